@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::sync::LazyLock;
+
+use apache_avro::types::Value;
+use apache_avro::{Codec, Reader, Schema, Writer};
+use tracing::error;
+
+use crate::consume::ConsumedMessage;
+use crate::error::PulsarCatError;
+use crate::format::format_message_id;
+use crate::produce::Record;
+
+/// Avro schema of the records in an archive, one record per message.
+pub const ARCHIVE_SCHEMA: &str = r#"{
+    "type": "record",
+    "name": "PulsarMessage",
+    "namespace": "pulsar_cat",
+    "fields": [
+        {"name": "topic", "type": "string"},
+        {"name": "message_id", "type": "string"},
+        {"name": "key", "type": ["null", "string"], "default": null},
+        {"name": "payload", "type": "bytes"},
+        {"name": "publish_time", "type": "long"},
+        {"name": "properties", "type": {"type": "map", "values": "string"}}
+    ]
+}"#;
+
+static SCHEMA: LazyLock<Schema> =
+    LazyLock::new(|| Schema::parse_str(ARCHIVE_SCHEMA).expect("archive schema is valid"));
+
+/// Writes consumed messages into an Avro object container file.
+///
+/// Appended messages are buffered in blocks, the last block is written when
+/// the archive is finished or dropped.
+pub struct ArchiveWriter {
+    writer: Option<Writer<'static, File>>,
+    path: String,
+    // Second handle to the file being written, to notice when it was moved away
+    file: File,
+}
+
+impl ArchiveWriter {
+    /// Create (or truncate) the archive at `path`.
+    pub fn create(path: &str) -> Result<Self, PulsarCatError> {
+        let file = File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create Avro archive '{}': {}", path, e))?;
+        let handle = file
+            .try_clone()
+            .map_err(|e| anyhow::anyhow!("Failed to create Avro archive '{}': {}", path, e))?;
+        Ok(ArchiveWriter {
+            writer: Some(Writer::with_codec(&SCHEMA, file, Codec::Deflate)),
+            path: path.to_owned(),
+            file: handle,
+        })
+    }
+
+    pub fn append(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        let record = Value::Record(vec![
+            ("topic".to_owned(), Value::String(msg.topic.to_owned())),
+            (
+                "message_id".to_owned(),
+                Value::String(format_message_id(msg.message_id)),
+            ),
+            (
+                "key".to_owned(),
+                match msg.key {
+                    Some(key) => Value::Union(1, Box::new(Value::String(key.to_owned()))),
+                    None => Value::Union(0, Box::new(Value::Null)),
+                },
+            ),
+            ("payload".to_owned(), Value::Bytes(msg.payload.to_vec())),
+            (
+                "publish_time".to_owned(),
+                Value::Long(msg.publish_time as i64),
+            ),
+            (
+                "properties".to_owned(),
+                Value::Map(
+                    msg.properties
+                        .iter()
+                        .map(|kv| (kv.key.clone(), Value::String(kv.value.clone())))
+                        .collect(),
+                ),
+            ),
+        ]);
+        if let Some(writer) = self.writer.as_mut() {
+            writer.append(record).map_err(|e| {
+                anyhow::anyhow!("Failed to write to Avro archive '{}': {}", self.path, e)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Finish the archive and start a new one at the same path if the file was
+    /// moved away, e.g. by logrotate. Returns whether a new file was started,
+    /// an archive still at its path is kept open.
+    pub fn reopen(&mut self) -> Result<bool, PulsarCatError> {
+        if self.is_at_path() {
+            return Ok(false);
+        }
+        self.close()?;
+        *self = ArchiveWriter::create(&self.path)?;
+        Ok(true)
+    }
+
+    // Whether the path still refers to the file being written
+    #[cfg(unix)]
+    fn is_at_path(&self) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        let Ok(at_path) = std::fs::metadata(&self.path) else {
+            return false;
+        };
+        self.file
+            .metadata()
+            .is_ok_and(|open| open.dev() == at_path.dev() && open.ino() == at_path.ino())
+    }
+
+    #[cfg(not(unix))]
+    fn is_at_path(&self) -> bool {
+        // Without file identities to compare, assume the file was not moved
+        true
+    }
+
+    /// Write the remaining buffered messages and close the archive.
+    pub fn finish(mut self) -> Result<(), PulsarCatError> {
+        self.close()
+    }
+
+    fn close(&mut self) -> Result<(), PulsarCatError> {
+        if let Some(writer) = self.writer.take() {
+            let write_error = |e: &dyn std::fmt::Display| {
+                anyhow::anyhow!("Failed to write to Avro archive '{}': {}", self.path, e)
+            };
+            let file = writer.into_inner().map_err(|e| write_error(&e))?;
+            file.sync_all().map_err(|e| write_error(&e))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ArchiveWriter {
+    fn drop(&mut self) {
+        // Consuming may be aborted, the buffered messages must still end up in the file
+        if let Err(e) = self.close() {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Read the messages of an archive written by [`ArchiveWriter`] as records to
+/// produce, keeping their key, payload and properties.
+pub fn read_archive(
+    path: &str,
+) -> Result<impl Iterator<Item = Result<Record, PulsarCatError>> + use<>, PulsarCatError> {
+    let file = File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open Avro archive '{}': {}", path, e))?;
+    read_archive_from(BufReader::new(file), path)
+}
+
+/// Like [`read_archive`], reading the archive from `reader`. `path` is only
+/// used in error messages.
+pub fn read_archive_from<R: Read>(
+    reader: R,
+    path: &str,
+) -> Result<impl Iterator<Item = Result<Record, PulsarCatError>> + use<R>, PulsarCatError> {
+    let reader = Reader::with_schema(&SCHEMA, reader)
+        .map_err(|e| anyhow::anyhow!("Failed to read Avro archive '{}': {}", path, e))?;
+    let path = path.to_owned();
+    Ok(reader.map(move |value| {
+        value
+            .map_err(|e| anyhow::anyhow!("Failed to read Avro archive '{}': {}", path, e).into())
+            .and_then(|value| {
+                record_from_value(value).ok_or_else(|| {
+                    anyhow::anyhow!("Unexpected record in Avro archive '{}'", path).into()
+                })
+            })
+    }))
+}
+
+fn record_from_value(value: Value) -> Option<Record> {
+    let Value::Record(fields) = value else {
+        return None;
+    };
+    let mut fields: HashMap<String, Value> = fields.into_iter().collect();
+    let key = match fields.remove("key")? {
+        Value::Union(_, key) => match *key {
+            Value::String(key) => Some(key),
+            _ => None,
+        },
+        _ => None,
+    };
+    let Value::Bytes(payload) = fields.remove("payload")? else {
+        return None;
+    };
+    let properties = match fields.remove("properties")? {
+        Value::Map(properties) => properties
+            .into_iter()
+            .filter_map(|(name, value)| match value {
+                Value::String(value) => Some((name, value)),
+                _ => None,
+            })
+            .collect(),
+        _ => BTreeMap::new(),
+    };
+    Some(Record {
+        key,
+        payload,
+        properties,
+        event_time: None,
+    })
+}
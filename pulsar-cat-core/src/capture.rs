@@ -0,0 +1,515 @@
+//! Captures of consumed messages, written by `consume --record` and replayed
+//! without a broker by `mock --replay`. A capture is a header line followed
+//! by a JSON line per message, holding everything a [`ConsumedMessage`]
+//! carries with the payload base64 encoded.
+
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::pin::pin;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures::FutureExt;
+use pulsar::proto::{KeyValue, MessageIdData};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::consume::{
+    ConsumeOptions, ConsumedMessage, ErrorPolicy, MessageFilter, process_message,
+};
+use crate::error::{MESSAGE_ERROR_TARGET, PulsarCatError};
+use crate::format::{format_message_id, parse_message_id};
+
+// Version of the capture format, captures of later versions are refused
+const FORMAT_VERSION: u32 = 1;
+
+// First line of a capture
+#[derive(Serialize, Deserialize)]
+struct Header {
+    pulsar_cat_capture: u32,
+}
+
+// A message as a line of a capture
+#[derive(Serialize, Deserialize)]
+struct CaptureLine {
+    topic: String,
+    message_id: String,
+    key: Option<String>,
+    payload: String,
+    publish_time: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    event_time: Option<u64>,
+    // As [key, value] pairs, keys may repeat
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    properties: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transaction_id: Option<(u64, u64)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    marker_type: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    replicated_from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compression: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uncompressed_size: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    batch_size: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schema_version: Option<u64>,
+}
+
+/// Writes consumed messages to a capture. Lines are buffered, the rest is
+/// written when the capture is finished or dropped.
+pub struct CaptureWriter {
+    writer: BufWriter<File>,
+    path: String,
+}
+
+impl CaptureWriter {
+    /// Create (or truncate) the capture at `path`.
+    pub fn create(path: &str) -> Result<Self, PulsarCatError> {
+        let file = File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create capture '{}': {}", path, e))?;
+        let mut writer = CaptureWriter {
+            writer: BufWriter::new(file),
+            path: path.to_owned(),
+        };
+        writer.write_line(&Header {
+            pulsar_cat_capture: FORMAT_VERSION,
+        })?;
+        Ok(writer)
+    }
+
+    pub fn append(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        self.write_line(&CaptureLine {
+            topic: msg.topic.to_owned(),
+            message_id: format_message_id(msg.message_id),
+            key: msg.key.map(ToOwned::to_owned),
+            payload: BASE64.encode(msg.payload),
+            publish_time: msg.publish_time,
+            event_time: msg.event_time,
+            properties: msg
+                .properties
+                .iter()
+                .map(|kv| (kv.key.clone(), kv.value.clone()))
+                .collect(),
+            transaction_id: msg.transaction_id,
+            marker_type: msg.marker_type,
+            replicated_from: msg.replicated_from.map(ToOwned::to_owned),
+            compression: msg.compression,
+            uncompressed_size: msg.uncompressed_size,
+            batch_size: msg.batch_size,
+            schema_version: msg.schema_version,
+        })
+    }
+
+    /// Write the buffered messages and close the capture.
+    pub fn finish(mut self) -> Result<(), PulsarCatError> {
+        self.writer.flush().map_err(|e| {
+            anyhow::anyhow!("Failed to write to capture '{}': {}", self.path, e).into()
+        })
+    }
+
+    fn write_line(&mut self, line: &impl Serialize) -> Result<(), PulsarCatError> {
+        serde_json::to_writer(&mut self.writer, line)
+            .map_err(std::io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"))
+            .map_err(|e| {
+                anyhow::anyhow!("Failed to write to capture '{}': {}", self.path, e).into()
+            })
+    }
+}
+
+/// A message read from a capture.
+#[derive(Debug, Clone)]
+pub struct CapturedMessage {
+    topic: String,
+    message_id: MessageIdData,
+    key: Option<String>,
+    payload: Vec<u8>,
+    publish_time: u64,
+    event_time: Option<u64>,
+    properties: Vec<KeyValue>,
+    transaction_id: Option<(u64, u64)>,
+    marker_type: Option<i32>,
+    replicated_from: Option<String>,
+    compression: Option<i32>,
+    uncompressed_size: Option<u32>,
+    batch_size: Option<i32>,
+    schema_version: Option<u64>,
+}
+
+impl CapturedMessage {
+    fn parse(line: &str) -> Result<Self, String> {
+        let line: CaptureLine = serde_json::from_str(line).map_err(|e| e.to_string())?;
+        Ok(CapturedMessage {
+            message_id: parse_message_id(&line.message_id)?,
+            payload: BASE64
+                .decode(&line.payload)
+                .map_err(|e| format!("invalid payload: {}", e))?,
+            properties: line
+                .properties
+                .into_iter()
+                .map(|(key, value)| KeyValue { key, value })
+                .collect(),
+            topic: line.topic,
+            key: line.key,
+            publish_time: line.publish_time,
+            event_time: line.event_time,
+            transaction_id: line.transaction_id,
+            marker_type: line.marker_type,
+            replicated_from: line.replicated_from,
+            compression: line.compression,
+            uncompressed_size: line.uncompressed_size,
+            batch_size: line.batch_size,
+            schema_version: line.schema_version,
+        })
+    }
+
+    /// The message as it was passed to the callback of
+    /// [`consume`](crate::consume::consume) when it was recorded.
+    pub fn as_consumed(&self) -> ConsumedMessage<'_> {
+        ConsumedMessage {
+            topic: &self.topic,
+            message_id: &self.message_id,
+            key: self.key.as_deref(),
+            payload: &self.payload,
+            publish_time: self.publish_time,
+            event_time: self.event_time,
+            properties: &self.properties,
+            transaction_id: self.transaction_id,
+            marker_type: self.marker_type,
+            replicated_from: self.replicated_from.as_deref(),
+            compression: self.compression,
+            uncompressed_size: self.uncompressed_size,
+            batch_size: self.batch_size,
+            schema_version: self.schema_version,
+        }
+    }
+}
+
+/// Keeps a consumed message beyond the callback it was passed to.
+impl From<&ConsumedMessage<'_>> for CapturedMessage {
+    fn from(msg: &ConsumedMessage) -> Self {
+        CapturedMessage {
+            topic: msg.topic.to_owned(),
+            message_id: msg.message_id.clone(),
+            key: msg.key.map(ToOwned::to_owned),
+            payload: msg.payload.to_vec(),
+            publish_time: msg.publish_time,
+            event_time: msg.event_time,
+            properties: msg.properties.to_vec(),
+            transaction_id: msg.transaction_id,
+            marker_type: msg.marker_type,
+            replicated_from: msg.replicated_from.map(ToOwned::to_owned),
+            compression: msg.compression,
+            uncompressed_size: msg.uncompressed_size,
+            batch_size: msg.batch_size,
+            schema_version: msg.schema_version,
+        }
+    }
+}
+
+/// The messages of the capture at `path` in the order they were recorded.
+/// A line that cannot be read is returned as an error, the following lines
+/// can still be read.
+pub fn read_capture(
+    path: &str,
+) -> Result<impl Iterator<Item = Result<CapturedMessage, PulsarCatError>>, PulsarCatError> {
+    let file = File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open capture '{}': {}", path, e))?;
+    let mut lines = BufReader::new(file).lines();
+    let header = lines
+        .next()
+        .transpose()
+        .map_err(|e| anyhow::anyhow!("Failed to read capture '{}': {}", path, e))?;
+    match header.and_then(|header| serde_json::from_str::<Header>(&header).ok()) {
+        Some(header) if header.pulsar_cat_capture <= FORMAT_VERSION => {}
+        Some(header) => {
+            return Err(anyhow::anyhow!(
+                "Capture '{}' has format version {}, this pulsar-cat reads up to version {}",
+                path,
+                header.pulsar_cat_capture,
+                FORMAT_VERSION
+            )
+            .into());
+        }
+        None => {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "'{}' is not a capture written by consume --record",
+                path
+            )));
+        }
+    }
+
+    let path = path.to_owned();
+    Ok(lines
+        .enumerate()
+        // The header is line 1
+        .map(|(number, line)| (number + 2, line))
+        .filter(|(_, line)| !line.as_ref().is_ok_and(|line| line.is_empty()))
+        .map(move |(number, line)| {
+            let line =
+                line.map_err(|e| anyhow::anyhow!("Failed to read capture '{}': {}", path, e))?;
+            CapturedMessage::parse(&line).map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid message in capture '{}' at line {}: {}",
+                    path,
+                    number,
+                    e
+                )
+                .into()
+            })
+        }))
+}
+
+/// Pass the messages of the capture at `path` to `on_message` like
+/// [`consume`](crate::consume::consume) does, without a broker. Markers, the
+/// trace ID, sampling, `count`, `max_bytes` and `on_error` apply as when
+/// consuming, the options of the subscription are ignored.
+///
+/// Stops when `shutdown` completes or the capture ends. Lines that cannot be
+/// read are skipped with [`ErrorPolicy::Skip`] and stop the replay otherwise.
+/// When messages were skipped, a partial failure is returned.
+pub async fn replay<S, F>(
+    path: &str,
+    opts: &ConsumeOptions,
+    shutdown: S,
+    mut on_message: F,
+) -> Result<(), PulsarCatError>
+where
+    S: Future<Output = ()>,
+    F: FnMut(&ConsumedMessage) -> Result<(), PulsarCatError>,
+{
+    let messages = read_capture(path)?;
+    let mut shutdown = pin!(shutdown);
+    let mut filter = MessageFilter::new(opts);
+    let mut consumed: u64 = 0;
+    let mut consumed_bytes: u64 = 0;
+    let mut failed: u64 = 0;
+
+    for message in messages {
+        if opts.count.is_some_and(|count| consumed >= count) {
+            info!("Consumed {} messages, exiting...", consumed);
+            break;
+        }
+        if opts
+            .max_bytes
+            .is_some_and(|max_bytes| consumed_bytes >= max_bytes)
+        {
+            info!(
+                "Consumed {} bytes in {} messages, exiting...",
+                consumed_bytes, consumed
+            );
+            break;
+        }
+        if shutdown.as_mut().now_or_never().is_some() {
+            info!("Shutting down replay...");
+            break;
+        }
+
+        let message = match message {
+            Ok(message) => message,
+            Err(e) if opts.on_error == ErrorPolicy::Skip => {
+                warn!(
+                    target: MESSAGE_ERROR_TARGET,
+                    kind = "receive",
+                    error = %e,
+                    "Failed to read message, skipping it"
+                );
+                failed += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let msg = message.as_consumed();
+        if filter.skips_marker(msg.marker_type) || !filter.samples(msg.properties) {
+            continue;
+        }
+        match process_message(&mut on_message, &msg, opts.on_error).await {
+            Ok(()) => {
+                consumed += 1;
+                consumed_bytes += msg.payload.len() as u64;
+            }
+            Err(e) if opts.on_error == ErrorPolicy::Skip => {
+                warn!(
+                    target: MESSAGE_ERROR_TARGET,
+                    kind = "process",
+                    topic = msg.topic,
+                    message_id = format_message_id(msg.message_id),
+                    error = %e,
+                    "Failed to process message, skipping it"
+                );
+                failed += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    info!("Replayed {} messages from {}", consumed, path);
+    if failed > 0 {
+        return Err(PulsarCatError::PartialFailure(anyhow::anyhow!(
+            "{} messages failed and were skipped",
+            failed
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consume::Sampling;
+
+    fn message<'a>(
+        message_id: &'a MessageIdData,
+        payload: &'a [u8],
+        properties: &'a [KeyValue],
+    ) -> ConsumedMessage<'a> {
+        ConsumedMessage {
+            topic: "persistent://tenant/ns/orders",
+            message_id,
+            key: Some("order-1"),
+            payload,
+            publish_time: 1_700_000_000_000,
+            event_time: None,
+            properties,
+            transaction_id: None,
+            marker_type: None,
+            replicated_from: None,
+            compression: None,
+            uncompressed_size: None,
+            batch_size: None,
+            schema_version: None,
+        }
+    }
+
+    // Record `count` messages, every third with the trace ID property
+    fn record(name: &str, count: u64) -> String {
+        let path = std::env::temp_dir()
+            .join(format!("pulsar-cat-{}-{}.pcatr", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let mut writer = CaptureWriter::create(&path).unwrap();
+        for entry_id in 0..count {
+            let message_id = MessageIdData {
+                ledger_id: 7,
+                entry_id,
+                partition: Some(-1),
+                ..Default::default()
+            };
+            let properties = if entry_id % 3 == 0 {
+                vec![KeyValue {
+                    key: "trace".to_owned(),
+                    value: "abc".to_owned(),
+                }]
+            } else {
+                Vec::new()
+            };
+            writer
+                .append(&message(&message_id, &[0, 159, 146, 150], &properties))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    // Entry IDs of the messages the replay passes on
+    async fn replayed(path: &str, opts: &ConsumeOptions) -> Vec<u64> {
+        let mut entry_ids = Vec::new();
+        replay(path, opts, std::future::pending(), |msg| {
+            entry_ids.push(msg.message_id.entry_id);
+            Ok(())
+        })
+        .await
+        .unwrap();
+        entry_ids
+    }
+
+    #[test]
+    fn keeps_messages_as_recorded() {
+        let path = record("roundtrip", 1);
+        let messages: Vec<_> = read_capture(&path).unwrap().collect();
+        std::fs::remove_file(&path).unwrap();
+
+        let [Ok(captured)] = messages.as_slice() else {
+            panic!("expected one message, got {:?}", messages);
+        };
+        let msg = captured.as_consumed();
+        assert_eq!(msg.topic, "persistent://tenant/ns/orders");
+        assert_eq!(format_message_id(msg.message_id), "7:0:-1");
+        assert_eq!(msg.key, Some("order-1"));
+        assert_eq!(msg.payload, [0, 159, 146, 150]);
+        assert_eq!(msg.publish_time, 1_700_000_000_000);
+        assert_eq!(msg.properties[0].value, "abc");
+    }
+
+    #[tokio::test]
+    async fn replays_with_filters_and_count() {
+        let path = record("filters", 10);
+        let all = replayed(&path, &ConsumeOptions::default()).await;
+        let counted = replayed(
+            &path,
+            &ConsumeOptions {
+                count: Some(4),
+                ..Default::default()
+            },
+        )
+        .await;
+        let traced = replayed(
+            &path,
+            &ConsumeOptions {
+                trace_id: Some("abc".to_owned()),
+                ..Default::default()
+            },
+        )
+        .await;
+        let traced_by_name = replayed(
+            &path,
+            &ConsumeOptions {
+                trace_id: Some("abc".to_owned()),
+                trace_id_property: Some("trace".to_owned()),
+                ..Default::default()
+            },
+        )
+        .await;
+        let traced_elsewhere = replayed(
+            &path,
+            &ConsumeOptions {
+                trace_id: Some("abc".to_owned()),
+                trace_id_property: Some("x-request-id".to_owned()),
+                ..Default::default()
+            },
+        )
+        .await;
+        let sampled = replayed(
+            &path,
+            &ConsumeOptions {
+                sampling: Some(Sampling::Ratio { n: 1, m: 2 }),
+                ..Default::default()
+            },
+        )
+        .await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(all, (0..10).collect::<Vec<_>>());
+        assert_eq!(counted, [0, 1, 2, 3]);
+        assert_eq!(traced, [0, 3, 6, 9]);
+        assert_eq!(traced_by_name, [0, 3, 6, 9]);
+        assert!(traced_elsewhere.is_empty());
+        assert_eq!(sampled, [0, 2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = std::env::temp_dir()
+            .join(format!("pulsar-cat-not-a-capture-{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        std::fs::write(&path, "{\"key\":null,\"payload\":\"hello\"}\n").unwrap();
+        let result = read_capture(&path).map(|_| ());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(PulsarCatError::Validation(_))));
+    }
+}
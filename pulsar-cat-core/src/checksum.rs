@@ -0,0 +1,63 @@
+use crc::{CRC_32_ISCSI, Crc};
+use sha2::{Digest, Sha256};
+
+/// Property holding the checksum of the payload, as `<algorithm>:<hex digest>`,
+/// e.g. `crc32c:e3069283`.
+pub const CHECKSUM_PROPERTY: &str = "pulsar-cat-checksum";
+
+const CRC32C: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumAlgorithm {
+    /// CRC-32C (Castagnoli), the checksum Pulsar itself uses for entries
+    #[default]
+    Crc32c,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32c => "crc32c",
+            ChecksumAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "crc32c" => Some(ChecksumAlgorithm::Crc32c),
+            "sha256" => Some(ChecksumAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+}
+
+/// Checksum of `payload` as lowercase hex.
+pub fn checksum(algorithm: ChecksumAlgorithm, payload: &[u8]) -> String {
+    match algorithm {
+        ChecksumAlgorithm::Crc32c => format!("{:08x}", CRC32C.checksum(payload)),
+        ChecksumAlgorithm::Sha256 => Sha256::digest(payload)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect(),
+    }
+}
+
+/// Value of [`CHECKSUM_PROPERTY`] for `payload`.
+pub fn checksum_property(algorithm: ChecksumAlgorithm, payload: &[u8]) -> String {
+    format!("{}:{}", algorithm.name(), checksum(algorithm, payload))
+}
+
+/// Check `payload` against a [`CHECKSUM_PROPERTY`] value, failing when the
+/// value is not a checksum pulsar-cat understands.
+pub fn verify_checksum(property: &str, payload: &[u8]) -> Result<bool, String> {
+    let (name, expected) = property.split_once(':').ok_or_else(|| {
+        format!(
+            "Invalid checksum '{}', expected 'algorithm:digest'",
+            property
+        )
+    })?;
+    let algorithm = ChecksumAlgorithm::from_name(name)
+        .ok_or_else(|| format!("Unknown checksum algorithm '{}'", name))?;
+    Ok(checksum(algorithm, payload).eq_ignore_ascii_case(expected))
+}
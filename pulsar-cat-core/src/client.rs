@@ -0,0 +1,174 @@
+use async_trait::async_trait;
+use pulsar::error::AuthenticationError;
+use pulsar::{Authentication, ConnectionRetryOptions, Pulsar, PulsarBuilder, TokioExecutor};
+use tracing::warn;
+
+use crate::error::PulsarCatError;
+
+// Connection retries per broker before failing over to the next one in the list
+const FAILOVER_CONNECT_RETRIES: u32 = 2;
+
+/// Authentication, proxy and TLS settings used to connect to a cluster.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+    pub token: Option<String>,
+    /// File to read the token from on every (re)connect, takes precedence over `token`
+    pub token_file: Option<String>,
+    /// Proxy service URL, replacing the broker URL for lookups and connections
+    pub proxy_url: Option<String>,
+    pub tls: TlsOptions,
+}
+
+/// Service URL list and settings to connect a new client with, when the
+/// broker the client was connected through goes away while a command runs.
+#[derive(Debug, Clone, Default)]
+pub struct Failover {
+    pub service_url: String,
+    pub options: ConnectOptions,
+}
+
+impl Failover {
+    /// A new client, connected through the first URL of the list that answers.
+    pub async fn connect(&self) -> Result<Pulsar<TokioExecutor>, PulsarCatError> {
+        connect(&self.service_url, &self.options).await
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// PEM encoded CA certificate chain used to verify the broker
+    pub ca_cert: Option<String>,
+    pub allow_insecure: bool,
+    pub no_hostname_verification: bool,
+}
+
+/// Token authentication reading the token from a file every time the client
+/// (re)connects, so rotated tokens are picked up without restarting.
+struct TokenFileAuthentication {
+    path: String,
+}
+
+#[async_trait]
+impl pulsar::authentication::Authentication for TokenFileAuthentication {
+    fn auth_method_name(&self) -> String {
+        "token".to_owned()
+    }
+
+    async fn initialize(&mut self) -> Result<(), AuthenticationError> {
+        Ok(())
+    }
+
+    async fn auth_data(&mut self) -> Result<Vec<u8>, AuthenticationError> {
+        read_token_file(&self.path)
+            .map(String::into_bytes)
+            .map_err(|e| AuthenticationError::Custom(e.to_string()))
+    }
+}
+
+/// Read a token from a file, ignoring surrounding whitespace.
+pub fn read_token_file(path: &str) -> Result<String, PulsarCatError> {
+    let token = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read token file '{}': {}", path, e))?;
+    Ok(token.trim().to_owned())
+}
+
+fn handle_auth(
+    mut builder: PulsarBuilder<TokioExecutor>,
+    options: &ConnectOptions,
+) -> Result<PulsarBuilder<TokioExecutor>, PulsarCatError> {
+    if let Some(path) = &options.token_file {
+        // Fail early on an unreadable file instead of on the first connection attempt
+        read_token_file(path)?;
+        builder =
+            builder.with_auth_provider(Box::new(TokenFileAuthentication { path: path.clone() }));
+    } else if let Some(token) = &options.token {
+        builder = builder.with_auth(Authentication {
+            name: "token".to_owned(),
+            data: Vec::from(token.as_str()),
+        });
+    }
+
+    Ok(builder)
+}
+
+fn handle_tls(
+    mut builder: PulsarBuilder<TokioExecutor>,
+    tls: &TlsOptions,
+) -> Result<PulsarBuilder<TokioExecutor>, PulsarCatError> {
+    if let Some(ca_cert) = &tls.ca_cert {
+        builder = builder.with_certificate_chain_file(ca_cert).map_err(|e| {
+            anyhow::anyhow!("Failed to read TLS CA certificate '{}': {}", ca_cert, e)
+        })?;
+    }
+    if tls.allow_insecure {
+        builder = builder.with_allow_insecure_connection(true);
+    }
+    if tls.no_hostname_verification {
+        builder = builder.with_tls_hostname_verification_enabled(false);
+    }
+
+    Ok(builder)
+}
+
+/// Split a comma-separated list of service URLs. Entries without a scheme
+/// inherit it from the previous entry, so both `pulsar://a:6650,b:6650` and
+/// `pulsar://a:6650,pulsar://b:6650` are accepted.
+pub fn split_service_urls(service_url: &str) -> Vec<String> {
+    let mut scheme = "pulsar";
+    let mut urls = Vec::new();
+    for entry in service_url
+        .split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+    {
+        match entry.split_once("://") {
+            Some((entry_scheme, _)) => {
+                scheme = entry_scheme;
+                urls.push(entry.to_owned());
+            }
+            None => urls.push(format!("{}://{}", scheme, entry)),
+        }
+    }
+    urls
+}
+
+/// Connect to a cluster, trying each URL of a comma-separated service URL
+/// list in order until a connection succeeds. The client stays bound to the
+/// URL it connected to, see [`Failover`] to move on from it later.
+pub async fn connect(
+    service_url: &str,
+    options: &ConnectOptions,
+) -> Result<Pulsar<TokioExecutor>, PulsarCatError> {
+    // With a proxy all lookups and connections go through the proxy's service URL
+    let service_url = options.proxy_url.as_deref().unwrap_or(service_url);
+    let candidates = split_service_urls(service_url);
+
+    // Try each service URL in order, only retrying briefly before moving on to the next one
+    let mut last_error = None;
+    for (i, url) in candidates.iter().enumerate() {
+        let mut builder = Pulsar::builder(url.as_str(), TokioExecutor);
+        if i + 1 < candidates.len() {
+            builder = builder.with_connection_retry_options(ConnectionRetryOptions {
+                max_retries: FAILOVER_CONNECT_RETRIES,
+                ..Default::default()
+            });
+        }
+        let builder = handle_auth(builder, options)?;
+        let builder = handle_tls(builder, &options.tls)?;
+
+        match builder.build().await {
+            Ok(pulsar) => return Ok(pulsar),
+            Err(e) => {
+                if i + 1 < candidates.len() {
+                    warn!("Failed to connect to {}: {}, trying next broker", url, e);
+                }
+                last_error = Some(e);
+            }
+        }
+    }
+
+    match last_error {
+        Some(e) => Err(e.into()),
+        None => Err(anyhow::anyhow!("No broker URL given").into()),
+    }
+}
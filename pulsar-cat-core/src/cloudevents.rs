@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde_json::{Map, Value};
+
+use crate::consume::ConsumedMessage;
+use crate::produce::Record;
+
+// Version of the CloudEvents specification events are created with
+const SPEC_VERSION: &str = "1.0";
+// Message property prefix of attributes in binary content mode
+const BINARY_ATTRIBUTE_PREFIX: &str = "ce_";
+// Message property carrying `datacontenttype` in binary content mode
+const BINARY_CONTENT_TYPE: &str = "content-type";
+// Content type of an event in structured content mode
+const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json";
+
+/// How an event is carried in a Pulsar message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentMode {
+    /// The whole event, attributes and data, is a JSON payload
+    Structured,
+    /// The payload is the event data, attributes are `ce_` prefixed properties
+    Binary,
+}
+
+/// A CloudEvent: its context attributes (`specversion`, `id`, `source`,
+/// `type`, optional attributes and extensions) and its data.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CloudEvent {
+    pub attributes: BTreeMap<String, String>,
+    pub data: Vec<u8>,
+}
+
+impl CloudEvent {
+    /// Create an event carrying `data`, with a random ID and the current time.
+    /// JSON data is marked as `application/json`, other data as text or bytes.
+    pub fn new(event_type: &str, source: &str, data: Vec<u8>) -> Self {
+        let content_type = if serde_json::from_slice::<Value>(&data).is_ok() {
+            "application/json"
+        } else if std::str::from_utf8(&data).is_ok() {
+            "text/plain"
+        } else {
+            "application/octet-stream"
+        };
+        let attributes = [
+            ("specversion", SPEC_VERSION.to_owned()),
+            ("id", uuid::Uuid::new_v4().to_string()),
+            ("source", source.to_owned()),
+            ("type", event_type.to_owned()),
+            (
+                "time",
+                humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            ),
+            ("datacontenttype", content_type.to_owned()),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_owned(), value))
+        .collect();
+        CloudEvent { attributes, data }
+    }
+
+    /// Parse the event carried by a message in either content mode, `None` if
+    /// the message is no CloudEvent.
+    pub fn from_message(msg: &ConsumedMessage) -> Option<Self> {
+        let binary_attributes: BTreeMap<String, String> = msg
+            .properties
+            .iter()
+            .filter_map(|kv| {
+                if kv.key == BINARY_CONTENT_TYPE {
+                    Some(("datacontenttype".to_owned(), kv.value.clone()))
+                } else {
+                    kv.key
+                        .strip_prefix(BINARY_ATTRIBUTE_PREFIX)
+                        .map(|name| (name.to_owned(), kv.value.clone()))
+                }
+            })
+            .collect();
+        if binary_attributes.contains_key("specversion") {
+            return Some(CloudEvent {
+                attributes: binary_attributes,
+                data: msg.payload.to_vec(),
+            });
+        }
+
+        let Ok(Value::Object(mut fields)) = serde_json::from_slice(msg.payload) else {
+            return None;
+        };
+        if !fields.get("specversion").is_some_and(Value::is_string) {
+            return None;
+        }
+        let data = match (fields.remove("data_base64"), fields.remove("data")) {
+            (Some(Value::String(encoded)), _) => BASE64.decode(encoded).ok()?,
+            (_, Some(Value::String(text))) => text.into_bytes(),
+            (_, Some(Value::Null)) | (_, None) => Vec::new(),
+            (_, Some(value)) => value.to_string().into_bytes(),
+        };
+        let attributes = fields
+            .into_iter()
+            .map(|(name, value)| match value {
+                Value::String(value) => (name, value),
+                value => (name, value.to_string()),
+            })
+            .collect();
+        Some(CloudEvent { attributes, data })
+    }
+
+    /// The event in JSON (structured content mode) format. JSON data is
+    /// embedded as is, text as a string and anything else base64 encoded.
+    pub fn to_json(&self) -> Value {
+        let mut event: Map<String, Value> = self
+            .attributes
+            .iter()
+            .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+            .collect();
+        let json_data = self
+            .attributes
+            .get("datacontenttype")
+            .is_none_or(|content_type| content_type.contains("json"));
+        if self.data.is_empty() {
+            return Value::Object(event);
+        }
+        match serde_json::from_slice::<Value>(&self.data) {
+            Ok(data) if json_data => {
+                event.insert("data".to_owned(), data);
+            }
+            _ => match std::str::from_utf8(&self.data) {
+                Ok(text) => {
+                    event.insert("data".to_owned(), Value::String(text.to_owned()));
+                }
+                Err(_) => {
+                    event.insert(
+                        "data_base64".to_owned(),
+                        Value::String(BASE64.encode(&self.data)),
+                    );
+                }
+            },
+        }
+        Value::Object(event)
+    }
+
+    /// Turn the event into a record to produce in the given content mode.
+    pub fn into_record(self, mode: ContentMode, key: Option<String>) -> Record {
+        match mode {
+            ContentMode::Structured => Record {
+                key,
+                payload: self.to_json().to_string().into_bytes(),
+                properties: BTreeMap::from([(
+                    BINARY_CONTENT_TYPE.to_owned(),
+                    STRUCTURED_CONTENT_TYPE.to_owned(),
+                )]),
+                event_time: None,
+            },
+            ContentMode::Binary => Record {
+                key,
+                properties: self
+                    .attributes
+                    .into_iter()
+                    .map(|(name, value)| {
+                        if name == "datacontenttype" {
+                            (BINARY_CONTENT_TYPE.to_owned(), value)
+                        } else {
+                            (format!("{}{}", BINARY_ATTRIBUTE_PREFIX, name), value)
+                        }
+                    })
+                    .collect(),
+                payload: self.data,
+                event_time: None,
+            },
+        }
+    }
+}
@@ -0,0 +1,1198 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::TryStreamExt;
+use pulsar::consumer::{Consumer, ConsumerOptions, InitialPosition, Message};
+use pulsar::error::{ConnectionError, ConsumerError, ServiceDiscoveryError};
+use pulsar::proto::command_get_topics_of_namespace::Mode;
+use pulsar::proto::{KeyValue, MessageIdData};
+use pulsar::{Pulsar, SubType, TokioExecutor};
+use regex::Regex;
+use tokio::time::timeout;
+use tracing::{debug, error, info, warn};
+
+use crate::client::Failover;
+use crate::error::{MESSAGE_ERROR_TARGET, PulsarCatError};
+use crate::format::{format_message_id, is_replication_marker};
+use crate::list::{is_non_persistent, namespace_topics};
+use crate::topic_name::TopicName;
+
+// Use a shorter timeout for faster detection of end of stream
+const TIMEOUT_DURATION: Duration = Duration::from_millis(300);
+// Idle time after the last message before considering the stream finished
+const MAX_IDLE_TIME: Duration = Duration::from_millis(500);
+// Wait between attempts to reconnect after a connection loss, doubling up to the maximum
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_millis(500);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+// How long to wait for the broker to confirm closing a disconnected consumer
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+// Wait before processing a message again with ErrorPolicy::Retry
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+// Wait before acknowledging again with AckFailurePolicy::Retry, doubling up to the maximum
+const ACK_RETRY_BACKOFF_MIN: Duration = Duration::from_millis(100);
+const ACK_RETRY_BACKOFF_MAX: Duration = Duration::from_secs(5);
+
+/// Where a new subscription starts reading.
+#[derive(Debug, Clone)]
+pub enum StartPosition {
+    Beginning,
+    End,
+    /// This many entries before the end of every partition, within the
+    /// partition's current ledger. A batch counts as a single entry.
+    BeforeEnd(u64),
+    /// The first message published at or after this time, in epoch milliseconds
+    Timestamp(u64),
+    /// This message ID. An ID of a partition only reads that partition of a
+    /// partitioned topic.
+    MessageId(MessageIdData),
+}
+
+/// When consumed messages are acknowledged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AckStrategy {
+    /// Each message right after it was processed
+    #[default]
+    Auto,
+    /// All processed messages when consuming stops
+    OnExit,
+    /// The processed messages after every this many messages
+    Interval(u64),
+    /// Never, the messages stay in the subscription's backlog
+    None,
+}
+
+/// Acknowledgements of [`AckStrategy::Auto`] held back and sent together once
+/// either limit is reached, trading fewer acknowledgement round trips for a
+/// larger window of messages that are redelivered after a crash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AckGrouping {
+    /// Longest time an acknowledgement is held back
+    pub max_delay: Duration,
+    /// Most acknowledgements held back
+    pub max_size: u64,
+}
+
+/// What happens when a message cannot be received (e.g. its payload fails to
+/// decompress) or the callback fails for it. Connection losses are not
+/// covered, consuming always reconnects after them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Stop consuming and return the error
+    #[default]
+    Exit,
+    /// Log the failure and continue with the next message, leaving the
+    /// failed one unacknowledged
+    Skip,
+    /// Try up to this many more times before stopping. A message that failed
+    /// to be received is received again by subscribing anew.
+    Retry(u32),
+}
+
+/// What happens when the acknowledgement of a processed message fails. The
+/// broker redelivers an unacknowledged message later, to this or another
+/// consumer of the subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AckFailurePolicy {
+    /// Log and count the failure and continue
+    #[default]
+    Ignore,
+    /// Stop consuming and return the error
+    Exit,
+    /// Try up to this many more times with a growing delay, then log and
+    /// count the failure and continue
+    Retry(u32),
+}
+
+/// Subset of the messages passed to the callback, to look at busy topics.
+#[derive(Debug, Clone, Copy)]
+pub enum Sampling {
+    /// The first `n` of every `m` messages
+    Ratio { n: u64, m: u64 },
+    /// Every message independently with this probability
+    Probability(f64),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConsumeOptions {
+    pub topic: String,
+    /// Consume these topics instead of `topic`, which then only names them in
+    /// log messages, e.g. the topics a template expands to
+    pub topics: Vec<String>,
+    /// Consume all topics of this namespace (`tenant/namespace`) instead of
+    /// `topic`, leaving out system topics
+    pub namespace: Option<String>,
+    /// With `namespace`, look for topics created while consuming this often
+    pub topic_refresh: Option<Duration>,
+    /// Initial position of the subscription, the client default when not given
+    pub start: Option<StartPosition>,
+    /// Stop once no new messages arrive instead of waiting for more
+    pub exit_at_end: bool,
+    /// Stop once every topic (partition) was read up to the message that was
+    /// its last one when consuming started, even while more are published
+    pub stop_at_last_message: bool,
+    /// Read the compacted view of the topic, holding only the latest message
+    /// of every key up to the last compaction. Needs an exclusive or failover
+    /// subscription.
+    pub read_compacted: bool,
+    /// Stop after this many messages
+    pub count: Option<u64>,
+    /// Stop once the payloads of the consumed messages add up to this many
+    /// bytes, the message crossing the limit is still passed on
+    pub max_bytes: Option<u64>,
+    /// Last processed message per topic (partition), consuming resumes after
+    /// these. Takes precedence over `start` for the topics it contains.
+    pub resume_from: BTreeMap<String, MessageIdData>,
+    /// Pass marker messages (e.g. transaction commit/abort markers) to the
+    /// callback instead of skipping them
+    pub show_markers: bool,
+    /// With `show_markers`, still skip the markers of replicated subscriptions
+    pub skip_replication_markers: bool,
+    /// Only pass a sample of the messages to the callback. `count` and
+    /// `max_bytes` only include sampled messages.
+    pub sampling: Option<Sampling>,
+    /// Leave messages that were not sampled unacknowledged
+    pub keep_unsampled: bool,
+    /// Only pass messages with this value in one of their properties to the
+    /// callback, e.g. a correlation ID to follow a single message. Counted
+    /// like messages left out by `sampling`.
+    pub trace_id: Option<String>,
+    /// Only compare the value of this property with `trace_id`, instead of
+    /// the values of all properties
+    pub trace_id_property: Option<String>,
+    /// Existing subscription to consume through instead of a fresh one
+    pub subscription: Option<String>,
+    pub subscription_type: SubType,
+    /// Dispatch priority within a shared or failover subscription, 0 is the highest
+    pub priority_level: Option<i32>,
+    /// Name of the consumer in the broker's stats, a generated one when not given
+    pub consumer_name: Option<String>,
+    /// Metadata properties of the consumer, shown in the broker's stats
+    pub consumer_metadata: BTreeMap<String, String>,
+    pub ack: AckStrategy,
+    /// Group the acknowledgements of [`AckStrategy::Auto`] instead of sending
+    /// each right away
+    pub ack_grouping: Option<AckGrouping>,
+    pub on_error: ErrorPolicy,
+    /// Negatively acknowledge messages skipped with [`ErrorPolicy::Skip`]
+    /// after this delay, so the broker redelivers them to the subscription.
+    /// Without it they stay unacknowledged: a named `subscription` gets them
+    /// again after reconnecting, a fresh one resumes after them and never does.
+    pub nack_redelivery_delay: Option<Duration>,
+    pub on_ack_failure: AckFailurePolicy,
+    /// Connect a new client this way when the connection to the broker is
+    /// lost, instead of reconnecting to the broker the client is bound to
+    pub failover: Option<Failover>,
+}
+
+/// A received message, borrowed from the client's buffer for the duration
+/// of the callback.
+#[derive(Debug, Clone, Copy)]
+pub struct ConsumedMessage<'a> {
+    pub topic: &'a str,
+    pub message_id: &'a MessageIdData,
+    pub key: Option<&'a str>,
+    pub payload: &'a [u8],
+    pub publish_time: u64,
+    /// Event time set by the producer, in epoch milliseconds
+    pub event_time: Option<u64>,
+    pub properties: &'a [KeyValue],
+    /// Transaction the message was produced in, as (most, least) significant bits
+    pub transaction_id: Option<(u64, u64)>,
+    /// Type of a marker written by the broker, `None` for regular messages
+    pub marker_type: Option<i32>,
+    /// Cluster a geo-replicated message was replicated from, `None` for
+    /// messages produced in the local cluster
+    pub replicated_from: Option<&'a str>,
+    /// Compression codec the producer applied to the entry, as a
+    /// `CompressionType` value, `None` when it was sent uncompressed
+    pub compression: Option<i32>,
+    /// Size of the entry before compression, as reported by the producer. The
+    /// entry holds the whole batch of a batched message.
+    pub uncompressed_size: Option<u32>,
+    /// Number of messages in the batch of a batched message
+    pub batch_size: Option<i32>,
+    /// Version of the registered schema the producer used, `None` for
+    /// messages produced without a schema
+    pub schema_version: Option<u64>,
+}
+
+impl<'a> ConsumedMessage<'a> {
+    pub fn from_message(msg: &'a Message<Vec<u8>>) -> Self {
+        let metadata = msg.metadata();
+        ConsumedMessage {
+            topic: &msg.topic,
+            message_id: msg.message_id(),
+            key: metadata.partition_key.as_deref(),
+            payload: &msg.payload.data,
+            publish_time: metadata.publish_time,
+            // Producers that do not set an event time may send 0
+            event_time: metadata.event_time.filter(|time| *time > 0),
+            properties: &metadata.properties,
+            transaction_id: metadata.txnid_most_bits.zip(metadata.txnid_least_bits),
+            marker_type: metadata.marker_type,
+            replicated_from: metadata.replicated_from.as_deref(),
+            compression: metadata.compression.filter(|compression| *compression != 0),
+            uncompressed_size: metadata.uncompressed_size,
+            batch_size: metadata.num_messages_in_batch,
+            // Brokers encode the version as a big-endian 64-bit number
+            schema_version: metadata
+                .schema_version
+                .as_deref()
+                .and_then(|version| <[u8; 8]>::try_from(version).ok())
+                .map(u64::from_be_bytes),
+        }
+    }
+}
+
+/// Consume a topic through a fresh subscription, or the given one, passing
+/// every message to `on_message` and acknowledging it according to `ack`.
+///
+/// Stops when `shutdown` completes, the stream ends, the count is reached or,
+/// with `exit_at_end`, no new messages arrive. Failures of single messages,
+/// including errors returned by the callback, are handled according to
+/// `on_error`. When messages were skipped, a partial failure is returned.
+///
+/// When the connection to the broker is lost, the subscription is re-created
+/// with exponential backoff and every partition resumes after the last message
+/// received from it, through a new client with `failover`. Loss and recovery are logged with an `event` field of
+/// `connection_lost` and `connection_restored`.
+pub async fn consume<S, F>(
+    client: &Pulsar<TokioExecutor>,
+    opts: &ConsumeOptions,
+    shutdown: S,
+    mut on_message: F,
+) -> Result<(), PulsarCatError>
+where
+    S: Future<Output = ()>,
+    F: FnMut(&ConsumedMessage) -> Result<(), PulsarCatError>,
+{
+    // Prepare consumer options with initial position
+    // Positions other than the ends are reached by seeking after subscribing
+    let consumer_options = match &opts.start {
+        Some(StartPosition::Beginning) => {
+            ConsumerOptions::default().with_initial_position(InitialPosition::Earliest)
+        }
+        Some(StartPosition::End | StartPosition::BeforeEnd(_)) => {
+            ConsumerOptions::default().with_initial_position(InitialPosition::Latest)
+        }
+        Some(StartPosition::Timestamp(_) | StartPosition::MessageId(_)) | None => {
+            ConsumerOptions::default()
+        }
+    };
+
+    let consumer_options = match opts.priority_level {
+        Some(priority_level) => consumer_options.with_priority_level(priority_level),
+        None => consumer_options,
+    }
+    .with_metadata(opts.consumer_metadata.clone());
+    let consumer_options = if opts.read_compacted {
+        consumer_options.read_compacted(true)
+    } else {
+        consumer_options
+    };
+
+    let (topics, topic_regex) = match &opts.namespace {
+        Some(namespace) => namespace_subscription(client, namespace, opts.topic_refresh).await?,
+        None => (opts.topics.clone(), None),
+    };
+
+    // A message ID belongs to a single partition, so only that partition is read
+    let topic = match &opts.start {
+        _ if opts.namespace.is_some() => opts.namespace.clone().unwrap_or_default(),
+        Some(StartPosition::MessageId(MessageIdData {
+            partition: Some(partition),
+            ..
+        })) if *partition >= 0 && !is_partition(&opts.topic) => {
+            format!("{}-partition-{}", opts.topic, partition)
+        }
+        _ => opts.topic.clone(),
+    };
+
+    let subscription = Subscription {
+        topic: topic.clone(),
+        topics,
+        topic_regex,
+        name: opts
+            .subscription
+            .clone()
+            .unwrap_or_else(|| format!("pulsar-cat-consumer-{}", generate_consumer_id())),
+        sub_type: opts.subscription_type,
+        consumer_name: opts
+            .consumer_name
+            .clone()
+            .unwrap_or_else(|| format!("pulsar-cat-{}", generate_consumer_id())),
+        options: consumer_options,
+        shared_cursor: opts.subscription.is_some(),
+    };
+    let mut consumer = subscription.subscribe(client).await?;
+
+    match &opts.start {
+        Some(StartPosition::MessageId(message_id)) => {
+            consumer
+                .seek(None, Some(message_id.clone()), None, client.clone())
+                .await?;
+        }
+        Some(StartPosition::Timestamp(timestamp)) => {
+            consumer
+                .seek(None, None, Some(*timestamp), client.clone())
+                .await?;
+        }
+        Some(StartPosition::BeforeEnd(entries)) => {
+            seek_before_end(client, &mut consumer, *entries).await?;
+        }
+        Some(StartPosition::Beginning | StartPosition::End) | None => {}
+    }
+
+    // Last message received per topic (partition), a reconnected consumer
+    // resumes after these
+    let mut positions = opts.resume_from.clone();
+    // Positions sought to, until the entry redelivered by seeking has passed
+    let mut seeks = resume(client, &mut consumer, &positions).await?;
+
+    // Topics (partitions) not yet read up to their last message
+    let mut end_ids = if opts.stop_at_last_message {
+        last_message_ids(&mut consumer, &positions).await?
+    } else {
+        BTreeMap::new()
+    };
+
+    match &opts.namespace {
+        Some(namespace) => info!("Started consuming from namespace: {}", namespace),
+        None => info!("Started consuming from topic: {}", topic),
+    }
+
+    tokio::pin!(shutdown);
+    let mut last_message_time = Instant::now();
+    let mut got_at_least_one_message = false;
+    let mut consumed: u64 = 0;
+    let mut consumed_bytes: u64 = 0;
+    let mut filter = MessageFilter::new(opts);
+    // Processed messages waiting for their acknowledgement, per topic (partition)
+    let mut unacked: BTreeMap<String, Vec<MessageIdData>> = BTreeMap::new();
+    let mut unacked_count: u64 = 0;
+    // When the first acknowledgement of the current group was held back
+    let mut unacked_since: Option<Instant> = None;
+    // Messages skipped with ErrorPolicy::Skip
+    let mut failed: u64 = 0;
+    // Acknowledgements that failed and were given up on
+    let mut failed_acks: u64 = 0;
+    // Attempts to receive the current message again with ErrorPolicy::Retry
+    let mut receive_retries: u32 = 0;
+    // Skipped messages waiting for their redelivery delay, in the order they are due
+    let mut nacks: VecDeque<(Instant, String, MessageIdData)> = VecDeque::new();
+    // Negatively acknowledged messages, processed again when they are redelivered
+    let mut redelivering: HashSet<(String, (u64, u64, i32))> = HashSet::new();
+
+    // Replaced when failing over to another broker
+    let mut client = client.clone();
+    let result = loop {
+        negatively_acknowledge_due(&mut consumer, &mut nacks, &mut redelivering).await;
+        if opts.stop_at_last_message && end_ids.is_empty() {
+            info!("Read up to the last message, exiting...");
+            break Ok(());
+        }
+        if opts.count.is_some_and(|count| consumed >= count) {
+            info!("Consumed {} messages, exiting...", consumed);
+            break Ok(());
+        }
+        if opts
+            .max_bytes
+            .is_some_and(|max_bytes| consumed_bytes >= max_bytes)
+        {
+            info!(
+                "Consumed {} bytes in {} messages, exiting...",
+                consumed_bytes, consumed
+            );
+            break Ok(());
+        }
+
+        // Always use a timeout when exiting at the end to detect end of stream
+        let next_message = async {
+            if opts.exit_at_end {
+                timeout(TIMEOUT_DURATION, consumer.try_next()).await
+            } else {
+                Ok(consumer.try_next().await)
+            }
+        };
+        // Grouped acknowledgements are also sent while no messages arrive
+        let ack_due = async {
+            match (opts.ack_grouping, unacked_since) {
+                (Some(grouping), Some(since)) => {
+                    tokio::time::sleep_until((since + grouping.max_delay).into()).await
+                }
+                _ => std::future::pending().await,
+            }
+        };
+        let nack_due = async {
+            match nacks.front() {
+                Some((due, ..)) => tokio::time::sleep_until((*due).into()).await,
+                None => std::future::pending().await,
+            }
+        };
+        let next_message = tokio::select! {
+            next = next_message => Some(next),
+            _ = ack_due => None,
+            _ = nack_due => continue,
+            _ = &mut shutdown => {
+                info!("Shutting down consumer...");
+                break Ok(());
+            }
+        };
+
+        let Some(next_message) = next_message else {
+            if let Err(e) = ack_all(
+                &mut consumer,
+                &mut unacked,
+                opts.on_ack_failure,
+                &mut failed_acks,
+            )
+            .await
+            {
+                break Err(e);
+            }
+            unacked_count = 0;
+            unacked_since = None;
+            continue;
+        };
+        match next_message {
+            // Timeout occurred, only possible when exiting at the end
+            Err(_) => {
+                if !got_at_least_one_message {
+                    info!("No messages available in topic (empty topic), exiting...");
+                    break Ok(());
+                }
+                // If we've already received messages and hit a timeout,
+                // it's likely we've reached the end
+                if last_message_time.elapsed() > MAX_IDLE_TIME {
+                    info!("No more messages available after timeout, exiting...");
+                    break Ok(());
+                }
+            }
+            Ok(Ok(Some(msg))) => {
+                got_at_least_one_message = true;
+                last_message_time = Instant::now();
+                if end_ids
+                    .get(&msg.topic)
+                    .is_some_and(|end_id| is_at_or_after(&msg, end_id))
+                {
+                    end_ids.remove(&msg.topic);
+                }
+
+                // A negatively acknowledged message comes again after later ones
+                let redelivered = !redelivering.is_empty()
+                    && redelivering
+                        .remove(&(msg.topic.clone(), message_id_order(msg.message_id())));
+                // Seeking redelivers the entry of the last processed message itself
+                let processed = !redelivered
+                    && seeks.get(&msg.topic).is_some_and(|resume_id| {
+                        let (ledger_id, entry_id, batch_index) = message_id_order(msg.message_id());
+                        let (resume_ledger_id, resume_entry_id, resume_batch_index) =
+                            message_id_order(resume_id);
+                        (ledger_id, entry_id) == (resume_ledger_id, resume_entry_id)
+                            && batch_index <= resume_batch_index
+                    });
+                // Already passed to the callback before seeking
+                if processed {
+                    continue;
+                }
+                if !seeks.is_empty() {
+                    seeks.remove(&msg.topic);
+                }
+                if !redelivered {
+                    // The topic is only copied for its first message
+                    match positions.get_mut(&msg.topic) {
+                        Some(position) => position.clone_from(msg.message_id()),
+                        None => {
+                            positions.insert(msg.topic.clone(), msg.message_id().clone());
+                        }
+                    }
+                }
+                receive_retries = 0;
+                let skipped_marker = filter.skips_marker(msg.metadata().marker_type);
+                let mut sampled = true;
+                if !skipped_marker {
+                    sampled = filter.samples(&msg.metadata().properties);
+                    if sampled {
+                        let consumed_msg = ConsumedMessage::from_message(&msg);
+                        match process_message(&mut on_message, &consumed_msg, opts.on_error).await {
+                            Ok(()) => {
+                                consumed += 1;
+                                consumed_bytes += msg.payload.data.len() as u64;
+                            }
+                            Err(e) if opts.on_error == ErrorPolicy::Skip => {
+                                warn!(
+                                    target: MESSAGE_ERROR_TARGET,
+                                    kind = "process",
+                                    topic = msg.topic,
+                                    message_id = format_message_id(msg.message_id()),
+                                    error = %e,
+                                    "Failed to process message, skipping it"
+                                );
+                                failed += 1;
+                                if let Some(delay) = opts.nack_redelivery_delay
+                                    && !is_non_persistent(&msg.topic)
+                                {
+                                    nacks.push_back((
+                                        Instant::now() + delay,
+                                        msg.topic.clone(),
+                                        msg.message_id().clone(),
+                                    ));
+                                }
+                                continue;
+                            }
+                            Err(e) => break Err(e),
+                        }
+                    }
+                }
+
+                // Acknowledge the message
+                if !sampled && opts.keep_unsampled {
+                    continue;
+                }
+                // Non-persistent topics keep no cursor, acknowledgements are not expected
+                if is_non_persistent(&msg.topic) {
+                    continue;
+                }
+                match opts.ack {
+                    AckStrategy::Auto if opts.ack_grouping.is_none() => {
+                        if let Err(e) = acknowledge(
+                            &mut consumer,
+                            &msg.topic,
+                            msg.message_id(),
+                            opts.on_ack_failure,
+                            &mut failed_acks,
+                        )
+                        .await
+                        {
+                            break Err(e);
+                        }
+                    }
+                    AckStrategy::Auto | AckStrategy::OnExit | AckStrategy::Interval(_) => {
+                        match unacked.get_mut(&msg.topic) {
+                            Some(ids) => ids.push(msg.message_id().clone()),
+                            None => {
+                                unacked.insert(msg.topic.clone(), vec![msg.message_id().clone()]);
+                            }
+                        }
+                        unacked_count += 1;
+                        unacked_since.get_or_insert_with(Instant::now);
+                        let group_size = match (opts.ack, opts.ack_grouping) {
+                            (AckStrategy::Interval(interval), _) => Some(interval),
+                            (AckStrategy::Auto, Some(grouping)) => Some(grouping.max_size),
+                            _ => None,
+                        };
+                        if group_size.is_some_and(|size| unacked_count >= size) {
+                            if let Err(e) = ack_all(
+                                &mut consumer,
+                                &mut unacked,
+                                opts.on_ack_failure,
+                                &mut failed_acks,
+                            )
+                            .await
+                            {
+                                break Err(e);
+                            }
+                            unacked_count = 0;
+                            unacked_since = None;
+                        }
+                    }
+                    AckStrategy::None => {}
+                }
+            }
+            Ok(Ok(None)) => {
+                info!("End of stream");
+                break Ok(());
+            }
+            Ok(Err(e)) => {
+                let connection_lost = !is_message_error(&e);
+                if connection_lost {
+                    warn!(
+                        event = "connection_lost",
+                        topic,
+                        error = %e,
+                        "Connection lost, reconnecting"
+                    );
+                } else {
+                    match opts.on_error {
+                        ErrorPolicy::Exit => break Err(e.into()),
+                        ErrorPolicy::Skip => {
+                            warn!(
+                                target: MESSAGE_ERROR_TARGET,
+                                kind = "receive",
+                                topic,
+                                error = %e,
+                                "Failed to receive message, skipping it"
+                            );
+                            failed += 1;
+                            continue;
+                        }
+                        ErrorPolicy::Retry(retries) => {
+                            if receive_retries >= retries {
+                                break Err(e.into());
+                            }
+                            receive_retries += 1;
+                            warn!(
+                                "Failed to receive message from {}: {}, subscribing again ({}/{})",
+                                topic, e, receive_retries, retries
+                            );
+                        }
+                    }
+                }
+                let lost_at = Instant::now();
+                // The old consumer may not get an answer from the broker anymore
+                let _ = timeout(CLOSE_TIMEOUT, consumer.close()).await;
+                let failover = opts.failover.as_ref().filter(|_| connection_lost);
+                match reconnect(
+                    &mut client,
+                    failover,
+                    &subscription,
+                    &positions,
+                    shutdown.as_mut(),
+                )
+                .await
+                {
+                    Ok(Some((reconnected, resumed))) => {
+                        consumer = reconnected;
+                        seeks = resumed;
+                        if !connection_lost {
+                            continue;
+                        }
+                        info!(
+                            event = "connection_restored",
+                            topic,
+                            downtime_ms = lost_at.elapsed().as_millis() as u64,
+                            "Connection restored after {}",
+                            humantime::format_duration(Duration::from_millis(
+                                lost_at.elapsed().as_millis() as u64
+                            ))
+                        );
+                    }
+                    Ok(None) => {
+                        info!("Shutting down consumer...");
+                        break Ok(());
+                    }
+                    Err(e) => {
+                        error!("Error reconnecting consumer: {}", e);
+                        break Err(e);
+                    }
+                }
+            }
+        }
+    };
+
+    let acked = if unacked_count > 0 {
+        debug!("Acknowledging {} processed messages", unacked_count);
+        ack_all(
+            &mut consumer,
+            &mut unacked,
+            opts.on_ack_failure,
+            &mut failed_acks,
+        )
+        .await
+    } else {
+        Ok(())
+    };
+
+    // Try to close consumer gracefully
+    if let Err(e) = consumer.close().await {
+        warn!("Error closing consumer: {}", e);
+    }
+
+    info!("Consumer shut down");
+    result?;
+    acked?;
+    let mut failures = Vec::new();
+    if failed > 0 {
+        failures.push(format!("{} messages failed and were skipped", failed));
+    }
+    if failed_acks > 0 {
+        failures.push(format!(
+            "{} acknowledgements failed, the broker will redeliver these messages",
+            failed_acks
+        ));
+    }
+    if !failures.is_empty() {
+        return Err(PulsarCatError::PartialFailure(anyhow::anyhow!(
+            "{}",
+            failures.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Which messages [`consume`] passes to its callback, by marker type, trace ID
+/// and sampling.
+pub struct MessageFilter<'a> {
+    opts: &'a ConsumeOptions,
+    // Messages looked at by Sampling::Ratio so far
+    sample_position: u64,
+}
+
+impl<'a> MessageFilter<'a> {
+    pub fn new(opts: &'a ConsumeOptions) -> Self {
+        MessageFilter {
+            opts,
+            sample_position: 0,
+        }
+    }
+
+    /// Whether a marker of `marker_type` is left out, `None` for regular
+    /// messages, which never are.
+    pub fn skips_marker(&self, marker_type: Option<i32>) -> bool {
+        marker_type.is_some_and(|marker_type| {
+            !self.opts.show_markers
+                || (self.opts.skip_replication_markers && is_replication_marker(marker_type))
+        })
+    }
+
+    /// Whether a message with `properties` has the trace ID and is sampled.
+    /// Every traced message counts towards the sampling ratio.
+    pub fn samples(&mut self, properties: &[KeyValue]) -> bool {
+        let traced = self.opts.trace_id.as_ref().is_none_or(|trace_id| {
+            properties.iter().any(|property| {
+                property.value == *trace_id
+                    && self
+                        .opts
+                        .trace_id_property
+                        .as_ref()
+                        .is_none_or(|name| property.key == *name)
+            })
+        });
+        traced
+            && match self.opts.sampling {
+                None => true,
+                Some(Sampling::Ratio { n, m }) => {
+                    let sampled = self.sample_position % m < n;
+                    self.sample_position += 1;
+                    sampled
+                }
+                Some(Sampling::Probability(probability)) => fastrand::f64() < probability,
+            }
+    }
+}
+
+// Pass the message to the callback, calling it again after a failure as
+// often as the policy allows
+pub(crate) async fn process_message<F>(
+    on_message: &mut F,
+    msg: &ConsumedMessage<'_>,
+    policy: ErrorPolicy,
+) -> Result<(), PulsarCatError>
+where
+    F: FnMut(&ConsumedMessage) -> Result<(), PulsarCatError>,
+{
+    let retries = match policy {
+        ErrorPolicy::Retry(retries) => retries,
+        ErrorPolicy::Exit | ErrorPolicy::Skip => 0,
+    };
+    let mut attempt = 0;
+    loop {
+        match on_message(msg) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                warn!(
+                    "Failed to process message {}: {}, retrying ({}/{})",
+                    format_message_id(msg.message_id),
+                    e,
+                    attempt,
+                    retries
+                );
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// What a consumer subscribes to, kept to subscribe again after a connection loss
+struct Subscription {
+    /// Topic, or the namespace when consuming a whole namespace
+    topic: String,
+    /// Topics of the namespace, `topic` itself is consumed when empty
+    topics: Vec<String>,
+    /// Pattern the client matches the topics of the namespace against, and
+    /// how often it looks for new ones
+    topic_regex: Option<(Regex, Duration)>,
+    name: String,
+    sub_type: SubType,
+    consumer_name: String,
+    options: ConsumerOptions,
+    /// The subscription may be used by other consumers, so its cursor must
+    /// not be moved when reconnecting
+    shared_cursor: bool,
+}
+
+impl Subscription {
+    async fn subscribe(
+        &self,
+        client: &Pulsar<TokioExecutor>,
+    ) -> Result<Consumer<Vec<u8>, TokioExecutor>, PulsarCatError> {
+        let builder = client.consumer();
+        let builder = match &self.topic_regex {
+            Some((regex, refresh)) => builder
+                .with_topic_regex(regex.clone())
+                .with_lookup_namespace(&self.topic)
+                .with_topic_refresh(*refresh),
+            None if !self.topics.is_empty() => builder.with_topics(&self.topics),
+            None => builder.with_topic(&self.topic),
+        };
+        let consumer = builder
+            .with_subscription_type(self.sub_type)
+            .with_subscription(&self.name)
+            .with_consumer_name(&self.consumer_name)
+            .with_options(self.options.clone())
+            .build::<Vec<u8>>()
+            .await?;
+        Ok(consumer)
+    }
+}
+
+// Topics of a namespace to subscribe to, without system topics (`__change_events`
+// and the like). With a refresh interval the client lists the namespace itself
+// and matches a pattern against it, picking up topics created later on.
+async fn namespace_subscription(
+    client: &Pulsar<TokioExecutor>,
+    namespace: &str,
+    refresh: Option<Duration>,
+) -> Result<(Vec<String>, Option<(Regex, Duration)>), PulsarCatError> {
+    if let Some(refresh) = refresh {
+        let regex = Regex::new(&format!(
+            "^(persistent|non-persistent)://{}/([^_]|_[^_])",
+            regex::escape(namespace)
+        ))
+        .map_err(|e| anyhow::anyhow!("Invalid namespace pattern: {}", e))?;
+        return Ok((Vec::new(), Some((regex, refresh))));
+    }
+
+    let topics: Vec<String> = namespace_topics(client, namespace, Mode::All, None)
+        .await?
+        .into_iter()
+        .filter(|topic| !topic.rsplit('/').next().unwrap_or(topic).starts_with("__"))
+        .collect();
+    if topics.is_empty() {
+        return Err(PulsarCatError::NotFound(anyhow::anyhow!(
+            "Namespace {} has no topics",
+            namespace
+        )));
+    }
+    info!(
+        "Consuming {} topics of namespace {}",
+        topics.len(),
+        namespace
+    );
+    Ok((topics, None))
+}
+
+// Seek the partitions with a known position to it and return the positions
+// sought to. Partitions are consumed by separate consumers, each seeks on its own.
+async fn resume(
+    client: &Pulsar<TokioExecutor>,
+    consumer: &mut Consumer<Vec<u8>, TokioExecutor>,
+    positions: &BTreeMap<String, MessageIdData>,
+) -> Result<BTreeMap<String, MessageIdData>, PulsarCatError> {
+    let topics = consumer.topics();
+    let mut seeks = BTreeMap::new();
+    for (resume_topic, message_id) in positions {
+        if !topics.contains(resume_topic) {
+            warn!(
+                "Not consuming {}, ignoring its saved position",
+                resume_topic
+            );
+            continue;
+        }
+        let consumer_ids = (topics.len() > 1).then(|| vec![resume_topic.clone()]);
+        consumer
+            .seek(consumer_ids, Some(message_id.clone()), None, client.clone())
+            .await?;
+        info!(
+            "Resuming {} after message {}",
+            resume_topic,
+            format_message_id(message_id)
+        );
+        seeks.insert(resume_topic.clone(), message_id.clone());
+    }
+    Ok(seeks)
+}
+
+// A consumer subscribed again, with the positions it was sought to
+type ResumedConsumer = (
+    Consumer<Vec<u8>, TokioExecutor>,
+    BTreeMap<String, MessageIdData>,
+);
+
+// Subscribe again with exponential backoff and resume after the last received
+// messages, returning the positions sought to with the consumer. With
+// `failover`, every attempt replaces `client` by a newly connected one first.
+// Returns None when `shutdown` completes first.
+async fn reconnect<S>(
+    client: &mut Pulsar<TokioExecutor>,
+    failover: Option<&Failover>,
+    subscription: &Subscription,
+    positions: &BTreeMap<String, MessageIdData>,
+    mut shutdown: Pin<&mut S>,
+) -> Result<Option<ResumedConsumer>, PulsarCatError>
+where
+    S: Future<Output = ()>,
+{
+    let mut backoff = RECONNECT_BACKOFF_MIN;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown.as_mut() => return Ok(None),
+        }
+        let attempt = async {
+            if let Some(failover) = failover {
+                *client = failover.connect().await?;
+            }
+            let mut consumer = subscription.subscribe(client).await?;
+            // The broker redelivers the unacknowledged messages of a shared subscription
+            let seeks = if subscription.shared_cursor {
+                BTreeMap::new()
+            } else {
+                resume(client, &mut consumer, positions).await?
+            };
+            Ok::<_, PulsarCatError>((consumer, seeks))
+        };
+        match attempt.await {
+            Ok(consumer) => return Ok(Some(consumer)),
+            Err(e) if is_permanent(&e) => return Err(e),
+            Err(e) => {
+                backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+                warn!(
+                    "Failed to reconnect to {}: {}, retrying in {}",
+                    subscription.topic,
+                    e,
+                    humantime::format_duration(backoff)
+                );
+            }
+        }
+    }
+}
+
+// Acknowledge the processed messages individually, as shared subscriptions do
+// not support cumulative acknowledgements
+async fn ack_all(
+    consumer: &mut Consumer<Vec<u8>, TokioExecutor>,
+    unacked: &mut BTreeMap<String, Vec<MessageIdData>>,
+    policy: AckFailurePolicy,
+    failed_acks: &mut u64,
+) -> Result<(), PulsarCatError> {
+    // Topics and lists are kept for the next messages
+    for (topic, message_ids) in unacked.iter_mut() {
+        for message_id in message_ids.drain(..) {
+            acknowledge(consumer, topic, &message_id, policy, failed_acks).await?;
+        }
+    }
+    Ok(())
+}
+
+// Negatively acknowledge the skipped messages whose redelivery delay passed,
+// so the broker sends them again
+async fn negatively_acknowledge_due(
+    consumer: &mut Consumer<Vec<u8>, TokioExecutor>,
+    nacks: &mut VecDeque<(Instant, String, MessageIdData)>,
+    redelivering: &mut HashSet<(String, (u64, u64, i32))>,
+) {
+    let now = Instant::now();
+    while nacks.front().is_some_and(|(due, ..)| *due <= now) {
+        let Some((_, topic, message_id)) = nacks.pop_front() else {
+            break;
+        };
+        let order = message_id_order(&message_id);
+        let formatted_id = format_message_id(&message_id);
+        match consumer.nack_with_id(&topic, message_id).await {
+            Ok(()) => {
+                debug!(
+                    "Negatively acknowledged message {} of {}",
+                    formatted_id, topic
+                );
+                redelivering.insert((topic, order));
+            }
+            Err(e) => warn!(
+                target: MESSAGE_ERROR_TARGET,
+                kind = "ack",
+                topic,
+                message_id = formatted_id,
+                error = %e,
+                "Failed to negatively acknowledge message"
+            ),
+        }
+    }
+}
+
+// Acknowledge a message, trying again after a failure as often as the policy
+// allows. A failure that is given up on is logged and counted, or returned
+// with AckFailurePolicy::Exit.
+async fn acknowledge(
+    consumer: &mut Consumer<Vec<u8>, TokioExecutor>,
+    topic: &str,
+    message_id: &MessageIdData,
+    policy: AckFailurePolicy,
+    failed_acks: &mut u64,
+) -> Result<(), PulsarCatError> {
+    let retries = match policy {
+        AckFailurePolicy::Retry(retries) => retries,
+        AckFailurePolicy::Ignore | AckFailurePolicy::Exit => 0,
+    };
+    let mut attempt = 0;
+    let mut backoff = ACK_RETRY_BACKOFF_MIN;
+    loop {
+        match consumer.ack_with_id(topic, message_id.clone()).await {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                warn!(
+                    "Failed to acknowledge message {}: {}, retrying ({}/{})",
+                    format_message_id(message_id),
+                    e,
+                    attempt,
+                    retries
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(ACK_RETRY_BACKOFF_MAX);
+            }
+            Err(e) if policy == AckFailurePolicy::Exit => {
+                return Err(anyhow::anyhow!(
+                    "Failed to acknowledge message {} on {}: {}",
+                    format_message_id(message_id),
+                    topic,
+                    e
+                )
+                .into());
+            }
+            Err(e) => {
+                warn!(
+                    target: MESSAGE_ERROR_TARGET,
+                    kind = "ack",
+                    topic,
+                    message_id = format_message_id(message_id),
+                    error = %e,
+                    "Failed to acknowledge message"
+                );
+                *failed_acks += 1;
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Errors of a single message received through an intact connection, e.g. a
+// payload that fails to decompress
+fn is_message_error(e: &pulsar::Error) -> bool {
+    matches!(
+        e,
+        pulsar::Error::Consumer(ConsumerError::Io(_) | ConsumerError::MissingPayload(_))
+    )
+}
+
+// Errors that reconnecting does not resolve
+fn is_permanent(e: &PulsarCatError) -> bool {
+    matches!(
+        e,
+        PulsarCatError::Pulsar(
+            pulsar::Error::Authentication(_)
+                | pulsar::Error::Connection(ConnectionError::Authentication(_))
+                | pulsar::Error::ServiceDiscovery(ServiceDiscoveryError::NotFound)
+        )
+    )
+}
+
+// Seek every partition to `entries` entries before its last one. Message IDs
+// only count up within a ledger, so earlier ledgers are not reached.
+async fn seek_before_end(
+    client: &Pulsar<TokioExecutor>,
+    consumer: &mut Consumer<Vec<u8>, TokioExecutor>,
+    entries: u64,
+) -> Result<(), PulsarCatError> {
+    // Partition consumers are kept and queried in topic order
+    let mut topics = consumer.topics();
+    topics.sort();
+    let last_ids = consumer.get_last_message_id().await?;
+    let single = topics.len() == 1;
+    for (topic, last_id) in topics.into_iter().zip(last_ids) {
+        // An empty ledger reports entry -1, there is nothing before the end
+        if last_id.entry_id == u64::MAX {
+            continue;
+        }
+        if last_id.entry_id + 1 < entries {
+            warn!(
+                "{} has only {} entries in its current ledger, starting at the first of them",
+                topic,
+                last_id.entry_id + 1
+            );
+        }
+        let message_id = MessageIdData {
+            ledger_id: last_id.ledger_id,
+            entry_id: (last_id.entry_id + 1).saturating_sub(entries),
+            partition: last_id.partition,
+            ..Default::default()
+        };
+        let consumer_ids = (!single).then(|| vec![topic.clone()]);
+        consumer
+            .seek(consumer_ids, Some(message_id), None, client.clone())
+            .await?;
+    }
+    Ok(())
+}
+
+// Last message of every topic (partition) of the consumer, leaving out the
+// empty ones and those already read up to it
+async fn last_message_ids(
+    consumer: &mut Consumer<Vec<u8>, TokioExecutor>,
+    positions: &BTreeMap<String, MessageIdData>,
+) -> Result<BTreeMap<String, MessageIdData>, PulsarCatError> {
+    // Partition consumers are kept and queried in topic order
+    let mut topics = consumer.topics();
+    topics.sort();
+    let last_ids = consumer.get_last_message_id().await?;
+    Ok(topics
+        .into_iter()
+        .zip(last_ids)
+        // An empty ledger reports entry -1
+        .filter(|(_, last_id)| last_id.entry_id != u64::MAX)
+        .filter(|(topic, last_id)| {
+            positions
+                .get(topic)
+                .is_none_or(|position| message_id_order(position) < message_id_order(last_id))
+        })
+        .collect())
+}
+
+// Whether `msg` is the message `end_id` or a later one. The last entry may be
+// a batch, which is only read completely with its last message.
+fn is_at_or_after(msg: &Message<Vec<u8>>, end_id: &MessageIdData) -> bool {
+    let id = msg.message_id();
+    match (id.ledger_id, id.entry_id).cmp(&(end_id.ledger_id, end_id.entry_id)) {
+        std::cmp::Ordering::Less => false,
+        std::cmp::Ordering::Greater => true,
+        std::cmp::Ordering::Equal => {
+            let batch_size = msg.metadata().num_messages_in_batch.unwrap_or(1);
+            id.batch_index
+                .filter(|index| *index >= 0)
+                .is_none_or(|index| index + 1 >= batch_size)
+        }
+    }
+}
+
+// Position of a message within its topic (partition)
+fn message_id_order(id: &MessageIdData) -> (u64, u64, i32) {
+    (id.ledger_id, id.entry_id, id.batch_index.unwrap_or(-1))
+}
+
+// Generate a unique consumer ID based on the current timestamp
+fn generate_consumer_id() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    format!("{}", now)
+}
+
+// Whether a topic addresses a single partition of a partitioned topic
+fn is_partition(topic: &str) -> bool {
+    TopicName::parse(topic).is_ok_and(|name| name.partition().is_some())
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use anyhow::Error as AnyhowError;
+use thiserror::Error;
+
+/// Tracing target of failures of individual messages, such as failed sends or
+/// acknowledgements, which do not stop the operation. Their events carry a
+/// `kind` field and, as far as known, the `topic`, `message_id`, `key` and
+/// input `line` of the message.
+pub const MESSAGE_ERROR_TARGET: &str = "pulsar_cat::message_error";
+
+#[derive(Debug, Error)]
+pub enum PulsarCatError {
+    #[error("Pulsar error: {0}")]
+    Pulsar(pulsar::Error),
+    #[error("Application error: {0}")]
+    Application(AnyhowError),
+    /// Invalid options or arguments given by the user
+    #[error("Invalid arguments: {0}")]
+    Validation(AnyhowError),
+    /// A request to the admin REST API failed, `status` is `None` when no
+    /// response was received
+    #[error("Admin error: {error}")]
+    Admin {
+        status: Option<u16>,
+        error: AnyhowError,
+    },
+    /// A topic or other resource the operation requires does not exist
+    #[error("Not found: {0}")]
+    NotFound(AnyhowError),
+    /// The operation completed, but some messages could not be processed
+    #[error("Partial failure: {0}")]
+    PartialFailure(AnyhowError),
+    /// The operation was stopped by a signal before it completed
+    #[error("Interrupted")]
+    Interrupted,
+    /// Shutdown was forced by a second signal or the end of the grace period,
+    /// in-flight messages may be lost
+    #[error("Shutdown aborted")]
+    Aborted,
+    /// The operation did not complete within the given time
+    #[error("Timed out after {}", humantime::format_duration(*.0))]
+    TimedOut(Duration),
+}
+
+impl From<pulsar::Error> for PulsarCatError {
+    fn from(e: pulsar::Error) -> Self {
+        PulsarCatError::Pulsar(e)
+    }
+}
+
+impl From<AnyhowError> for PulsarCatError {
+    fn from(e: AnyhowError) -> Self {
+        PulsarCatError::Application(e)
+    }
+}
@@ -0,0 +1,556 @@
+use std::borrow::Cow;
+use std::fmt::{self, Write as _};
+use std::io;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use pulsar::proto::{CompressionType, MessageIdData};
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+
+use crate::checksum::{ChecksumAlgorithm, checksum};
+use crate::consume::ConsumedMessage;
+use crate::error::PulsarCatError;
+
+/// Format a message ID as `ledger:entry:partition[:batch]`, the form accepted
+/// by `--from-message-id`. Non-partitioned topics have partition -1.
+pub fn format_message_id(id: &MessageIdData) -> String {
+    DisplayMessageId(id).to_string()
+}
+
+/// A message ID displayed like [`format_message_id`] without allocating.
+pub struct DisplayMessageId<'a>(pub &'a MessageIdData);
+
+impl fmt::Display for DisplayMessageId<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let id = self.0;
+        write!(
+            f,
+            "{}:{}:{}",
+            id.ledger_id,
+            id.entry_id,
+            id.partition.unwrap_or(-1)
+        )?;
+        if let Some(batch_index) = id.batch_index.filter(|i| *i >= 0) {
+            write!(f, ":{}", batch_index)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a message ID given as `ledger:entry[:partition[:batch]]`.
+pub fn parse_message_id(s: &str) -> Result<MessageIdData, String> {
+    let invalid = || {
+        format!(
+            "invalid message ID '{}', expected 'ledger:entry[:partition[:batch]]'",
+            s
+        )
+    };
+    let parts: Vec<&str> = s.trim().split(':').collect();
+    if !(2..=4).contains(&parts.len()) {
+        return Err(invalid());
+    }
+    let ledger_id = parts[0].parse::<u64>().map_err(|_| invalid())?;
+    let entry_id = parts[1].parse::<u64>().map_err(|_| invalid())?;
+    let optional = |i: usize| -> Result<Option<i32>, String> {
+        parts
+            .get(i)
+            .map(|part| part.parse::<i32>().map_err(|_| invalid()))
+            .transpose()
+    };
+    Ok(MessageIdData {
+        ledger_id,
+        entry_id,
+        partition: optional(2)?,
+        batch_index: optional(3)?,
+        ..Default::default()
+    })
+}
+
+/// Name of a broker written marker type, as used by the Java client.
+pub fn marker_name(marker_type: i32) -> String {
+    match marker_type {
+        10 => "REPLICATED_SUBSCRIPTION_SNAPSHOT_REQUEST".to_owned(),
+        11 => "REPLICATED_SUBSCRIPTION_SNAPSHOT_RESPONSE".to_owned(),
+        12 => "REPLICATED_SUBSCRIPTION_SNAPSHOT".to_owned(),
+        13 => "REPLICATED_SUBSCRIPTION_UPDATE".to_owned(),
+        20 => "TXN_COMMITTING".to_owned(),
+        21 => "TXN_COMMIT".to_owned(),
+        22 => "TXN_ABORT".to_owned(),
+        other => format!("UNKNOWN_MARKER_{}", other),
+    }
+}
+
+/// Name of a compression codec, `none` for uncompressed messages.
+pub fn compression_name(compression: Option<i32>) -> String {
+    let Some(compression) = compression else {
+        return "none".to_owned();
+    };
+    match CompressionType::try_from(compression) {
+        Ok(codec) => codec.as_str_name().to_lowercase(),
+        Err(_) => format!("unknown_{}", compression),
+    }
+}
+
+/// Whether a marker type belongs to replicated subscriptions, written by the
+/// broker to coordinate subscription positions across geo-replicated clusters.
+pub fn is_replication_marker(marker_type: i32) -> bool {
+    (10..=13).contains(&marker_type)
+}
+
+/// Format a transaction ID as `most:least` significant bits.
+pub fn format_transaction_id((most, least): (u64, u64)) -> String {
+    format!("{}:{}", most, least)
+}
+
+/// How line breaks inside printed values are handled, so that multi-line
+/// payloads do not break line oriented processing of the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NewlineMode {
+    #[default]
+    Keep,
+    /// Replace line breaks with `\n` and `\r` and backslashes with `\\`,
+    /// so the original text can be recovered
+    Escape,
+    /// Remove line breaks
+    Strip,
+}
+
+/// Apply `mode` to `text`, borrowing it when there is nothing to change.
+pub fn handle_newlines(text: &str, mode: NewlineMode) -> Cow<'_, str> {
+    match mode {
+        NewlineMode::Keep => Cow::Borrowed(text),
+        NewlineMode::Escape if text.contains(['\\', '\n', '\r']) => {
+            let mut escaped = String::with_capacity(text.len() + 8);
+            for c in text.chars() {
+                match c {
+                    '\\' => escaped.push_str("\\\\"),
+                    '\n' => escaped.push_str("\\n"),
+                    '\r' => escaped.push_str("\\r"),
+                    c => escaped.push(c),
+                }
+            }
+            Cow::Owned(escaped)
+        }
+        NewlineMode::Strip if text.contains(['\n', '\r']) => {
+            Cow::Owned(text.replace(['\n', '\r'], ""))
+        }
+        NewlineMode::Escape | NewlineMode::Strip => Cow::Borrowed(text),
+    }
+}
+
+/// Indent `text` by two spaces per level when it is a JSON object or array,
+/// keeping the order of object members unless `sort_keys` is set. Returns
+/// `None` for any other text.
+pub fn pretty_json(text: &str, sort_keys: bool) -> Option<String> {
+    if !text.trim_start().starts_with(['{', '[']) {
+        return None;
+    }
+    if sort_keys {
+        // Objects of a parsed value are ordered by member name
+        let value: Value = serde_json::from_str(text).ok()?;
+        return serde_json::to_string_pretty(&value).ok();
+    }
+    serde_json::from_str::<serde::de::IgnoredAny>(text).ok()?;
+
+    // The text is valid JSON, so only the structure outside of strings needs
+    // to be laid out again
+    let mut pretty = String::with_capacity(text.len() * 2);
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = text.chars().peekable();
+    let newline = |pretty: &mut String, depth: usize| {
+        pretty.push('\n');
+        pretty.extend(std::iter::repeat_n(' ', depth * 2));
+    };
+    while let Some(c) = chars.next() {
+        if in_string {
+            pretty.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                pretty.push(c);
+            }
+            '{' | '[' => {
+                pretty.push(c);
+                // Empty objects and arrays stay on one line
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                if let Some(close) = chars.next_if(|c| matches!(c, '}' | ']')) {
+                    pretty.push(close);
+                } else {
+                    depth += 1;
+                    newline(&mut pretty, depth);
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                newline(&mut pretty, depth);
+                pretty.push(c);
+            }
+            ',' => {
+                pretty.push(c);
+                newline(&mut pretty, depth);
+            }
+            ':' => pretty.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => pretty.push(c),
+        }
+    }
+    Some(pretty)
+}
+
+/// How payloads that are not valid UTF-8 are rendered as text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryMode {
+    /// Replace invalid sequences with U+FFFD
+    #[default]
+    Lossy,
+    /// Write invalid bytes as `\xNN`
+    Escape,
+    /// Base64 encode the whole payload
+    Base64,
+    /// Leave the message out of the output
+    Skip,
+    /// Stop with an error
+    Fail,
+}
+
+/// Render the payload of a message as text, applying `mode` when it is not
+/// valid UTF-8. Returns `None` when the message should be skipped.
+pub fn payload_text<'a>(
+    msg: &ConsumedMessage<'a>,
+    mode: BinaryMode,
+) -> Result<Option<Cow<'a, str>>, PulsarCatError> {
+    let payload = msg.payload;
+    if let Ok(text) = std::str::from_utf8(payload) {
+        return Ok(Some(Cow::Borrowed(text)));
+    }
+    match mode {
+        BinaryMode::Lossy => Ok(Some(String::from_utf8_lossy(payload))),
+        BinaryMode::Escape => {
+            let mut escaped = String::with_capacity(payload.len() + 16);
+            for chunk in payload.utf8_chunks() {
+                escaped.push_str(chunk.valid());
+                for byte in chunk.invalid() {
+                    escaped.push_str(&format!("\\x{:02x}", byte));
+                }
+            }
+            Ok(Some(Cow::Owned(escaped)))
+        }
+        BinaryMode::Base64 => Ok(Some(Cow::Owned(BASE64.encode(payload)))),
+        BinaryMode::Skip => Ok(None),
+        BinaryMode::Fail => Err(anyhow::anyhow!(
+            "Payload of message {} on {} is not valid UTF-8",
+            format_message_id(msg.message_id),
+            msg.topic
+        )
+        .into()),
+    }
+}
+
+/// Format a message according to a kcat style format string, with `payload`
+/// as the text of the payload and applying `newlines` to the key, payload and
+/// headers. `%C` is computed with `algorithm`.
+///
+/// Placeholders: %t=topic, %p=partition, %o=offset, %k=key, %s=payload,
+/// %S=size, %h=headers, %T=timestamp, %C=payload checksum, %v=schema version
+/// (empty without a schema), %%=literal percent sign
+pub fn format_message(
+    format_str: &str,
+    msg: &ConsumedMessage,
+    payload: &str,
+    newlines: NewlineMode,
+    algorithm: ChecksumAlgorithm,
+) -> String {
+    FormattedMessage {
+        format_str,
+        msg,
+        payload,
+        newlines,
+        algorithm,
+    }
+    .to_string()
+}
+
+/// A message formatted like [`format_message`], written straight to the
+/// output when displayed instead of being built as a string first.
+pub struct FormattedMessage<'a> {
+    pub format_str: &'a str,
+    pub msg: &'a ConsumedMessage<'a>,
+    pub payload: &'a str,
+    pub newlines: NewlineMode,
+    pub algorithm: ChecksumAlgorithm,
+}
+
+impl fmt::Display for FormattedMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = self.msg;
+        let newlines = self.newlines;
+        let mut chars = self.format_str.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                f.write_char(c)?;
+                continue;
+            }
+            let Some(c) = chars.next() else {
+                // Trailing %
+                return f.write_char('%');
+            };
+            match c {
+                't' => f.write_str(msg.topic)?,
+                // The message ID stands in for the partition and offset
+                'p' | 'o' => write!(f, "{}", DisplayMessageId(msg.message_id))?,
+                'k' => f.write_str(&handle_newlines(msg.key.unwrap_or(""), newlines))?,
+                's' => f.write_str(&handle_newlines(self.payload, newlines))?,
+                'S' => write!(f, "{}", msg.payload.len())?,
+                'h' => {
+                    for (i, h) in msg.properties.iter().enumerate() {
+                        if i > 0 {
+                            f.write_str(", ")?;
+                        }
+                        write!(
+                            f,
+                            "{}={}",
+                            handle_newlines(&h.key, newlines),
+                            handle_newlines(&h.value, newlines)
+                        )?;
+                    }
+                }
+                'T' => write!(f, "{}", msg.publish_time)?,
+                'C' => f.write_str(&checksum(self.algorithm, msg.payload))?,
+                'v' => {
+                    if let Some(version) = msg.schema_version {
+                        write!(f, "{}", version)?;
+                    }
+                }
+                '%' => f.write_char('%')?,
+                _ => {
+                    f.write_char('%')?;
+                    f.write_char(c)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// JSON representation of a message, as printed by `consume --json`.
+/// Messages with an event time carry `event_time`, messages produced with a
+/// schema `schema_version`, transactional messages and markers
+/// `transaction_id` and `marker`, geo-replicated messages `replicated_from`.
+pub fn message_to_json(msg: &ConsumedMessage) -> Value {
+    serde_json::to_value(MessageJson::new(msg)).expect("message JSON is serializable")
+}
+
+/// The JSON representation of [`message_to_json`], borrowing from the message.
+/// Displaying it writes the JSON straight to the output. Fields are in
+/// alphabetical order, like the keys of a [`Value`], unless `fields` selects
+/// the fields to display and their order.
+#[derive(Serialize)]
+pub struct MessageJson<'a> {
+    #[serde(skip)]
+    pub fields: Option<&'a [MessageField]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cloudevent: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_time: Option<u64>,
+    pub key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marker: Option<String>,
+    #[serde(serialize_with = "serialize_message_id")]
+    pub message_id: &'a MessageIdData,
+    pub payload: &'a str,
+    pub payload_size: usize,
+    pub publish_time: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replicated_from: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u64>,
+    pub topic: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<String>,
+}
+
+impl<'a> MessageJson<'a> {
+    pub fn new(msg: &ConsumedMessage<'a>) -> Self {
+        MessageJson {
+            fields: None,
+            checksum: None,
+            cloudevent: None,
+            event_time: msg.event_time,
+            key: msg.key,
+            marker: msg.marker_type.map(marker_name),
+            message_id: msg.message_id,
+            payload: std::str::from_utf8(msg.payload).unwrap_or("<binary data>"),
+            payload_size: msg.payload.len(),
+            publish_time: msg.publish_time,
+            replicated_from: msg.replicated_from,
+            schema_version: msg.schema_version,
+            topic: msg.topic,
+            transaction_id: msg.transaction_id.map(format_transaction_id),
+        }
+    }
+}
+
+impl fmt::Display for MessageJson<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.fields {
+            Some(fields) => {
+                serde_json::to_writer(FormatterWriter(f), &SelectedFields { json: self, fields })
+            }
+            None => serde_json::to_writer(FormatterWriter(f), self),
+        }
+        .map_err(|_| fmt::Error)
+    }
+}
+
+/// A field of [`MessageJson`], named as in the JSON output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageField {
+    Checksum,
+    Cloudevent,
+    EventTime,
+    Key,
+    Marker,
+    MessageId,
+    Payload,
+    PayloadSize,
+    PublishTime,
+    ReplicatedFrom,
+    SchemaVersion,
+    Topic,
+    TransactionId,
+}
+
+impl MessageField {
+    pub const ALL: [MessageField; 13] = [
+        MessageField::Checksum,
+        MessageField::Cloudevent,
+        MessageField::EventTime,
+        MessageField::Key,
+        MessageField::Marker,
+        MessageField::MessageId,
+        MessageField::Payload,
+        MessageField::PayloadSize,
+        MessageField::PublishTime,
+        MessageField::ReplicatedFrom,
+        MessageField::SchemaVersion,
+        MessageField::Topic,
+        MessageField::TransactionId,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MessageField::Checksum => "checksum",
+            MessageField::Cloudevent => "cloudevent",
+            MessageField::EventTime => "event_time",
+            MessageField::Key => "key",
+            MessageField::Marker => "marker",
+            MessageField::MessageId => "message_id",
+            MessageField::Payload => "payload",
+            MessageField::PayloadSize => "payload_size",
+            MessageField::PublishTime => "publish_time",
+            MessageField::ReplicatedFrom => "replicated_from",
+            MessageField::SchemaVersion => "schema_version",
+            MessageField::Topic => "topic",
+            MessageField::TransactionId => "transaction_id",
+        }
+    }
+}
+
+/// Parse the name of a [`MessageField`], e.g. `publish_time`.
+pub fn parse_message_field(s: &str) -> Result<MessageField, String> {
+    MessageField::ALL
+        .into_iter()
+        .find(|field| field.name() == s.trim())
+        .ok_or_else(|| {
+            let names: Vec<&str> = MessageField::ALL.iter().map(|field| field.name()).collect();
+            format!(
+                "unknown field '{}', expected one of {}",
+                s,
+                names.join(", ")
+            )
+        })
+}
+
+// The given fields of a message in the given order. Optional fields the
+// message does not have are left out, as in the full representation.
+struct SelectedFields<'a> {
+    json: &'a MessageJson<'a>,
+    fields: &'a [MessageField],
+}
+
+impl Serialize for SelectedFields<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let json = self.json;
+        let mut map = serializer.serialize_map(None)?;
+        for &field in self.fields {
+            let name = field.name();
+            match field {
+                MessageField::Checksum => entry(&mut map, name, &json.checksum)?,
+                MessageField::Cloudevent => entry(&mut map, name, &json.cloudevent)?,
+                MessageField::EventTime => entry(&mut map, name, &json.event_time)?,
+                MessageField::Key => map.serialize_entry(name, &json.key)?,
+                MessageField::Marker => entry(&mut map, name, &json.marker)?,
+                MessageField::MessageId => {
+                    map.serialize_entry(name, &format_message_id(json.message_id))?
+                }
+                MessageField::Payload => map.serialize_entry(name, json.payload)?,
+                MessageField::PayloadSize => map.serialize_entry(name, &json.payload_size)?,
+                MessageField::PublishTime => map.serialize_entry(name, &json.publish_time)?,
+                MessageField::ReplicatedFrom => entry(&mut map, name, &json.replicated_from)?,
+                MessageField::SchemaVersion => entry(&mut map, name, &json.schema_version)?,
+                MessageField::Topic => map.serialize_entry(name, json.topic)?,
+                MessageField::TransactionId => entry(&mut map, name, &json.transaction_id)?,
+            }
+        }
+        map.end()
+    }
+}
+
+// Serialize an optional field when it is present
+fn entry<M: SerializeMap, T: Serialize>(
+    map: &mut M,
+    name: &str,
+    value: &Option<T>,
+) -> Result<(), M::Error> {
+    match value {
+        Some(value) => map.serialize_entry(name, value),
+        None => Ok(()),
+    }
+}
+
+fn serialize_message_id<S: Serializer>(
+    id: &&MessageIdData,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&DisplayMessageId(id))
+}
+
+// Lets serde_json write to a formatter. It hands over whole strings or the
+// text between escapes, so every write is valid UTF-8.
+struct FormatterWriter<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl io::Write for FormatterWriter<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = std::str::from_utf8(buf).map_err(io::Error::other)?;
+        self.0.write_str(text).map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use serde_json::Value;
+
+/// A JSONPath selecting a single value, e.g. `$.order.id`, `$.items[0].sku` or
+/// `$['order-id']`. Only member and array index steps are supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Member(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("invalid JSON path '{}': {}", s, reason);
+        let mut rest = s
+            .trim()
+            .strip_prefix('$')
+            .ok_or_else(|| invalid("expected it to start with '$'"))?;
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+                if end == 0 {
+                    return Err(invalid("expected a member name after '.'"));
+                }
+                steps.push(Step::Member(after_dot[..end].to_owned()));
+                rest = &after_dot[end..];
+            } else if let Some(after_bracket) = rest.strip_prefix('[') {
+                let end = after_bracket
+                    .find(']')
+                    .ok_or_else(|| invalid("missing ']'"))?;
+                let selector = &after_bracket[..end];
+                let quoted = ['\'', '"'].iter().find_map(|quote| {
+                    selector
+                        .strip_prefix(*quote)
+                        .and_then(|name| name.strip_suffix(*quote))
+                });
+                steps.push(match quoted {
+                    Some(name) => Step::Member(name.to_owned()),
+                    None => Step::Index(selector.parse().map_err(|_| {
+                        invalid("expected an array index or a quoted member name in brackets")
+                    })?),
+                });
+                rest = &after_bracket[end + 1..];
+            } else {
+                return Err(invalid("expected '.' or '[' between steps"));
+            }
+        }
+        Ok(JsonPath { steps })
+    }
+
+    /// The value at the path, None when a step does not exist.
+    pub fn select<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.steps.iter().try_fold(value, |value, step| match step {
+            Step::Member(name) => value.get(name),
+            Step::Index(index) => value.get(index),
+        })
+    }
+}
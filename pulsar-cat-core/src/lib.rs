@@ -0,0 +1,26 @@
+//! Core of pulsar-cat: connecting to Apache Pulsar, consuming, producing and
+//! listing topics, and formatting messages. Results are handed to callbacks
+//! and returned as data rather than printed, so the functionality can be
+//! embedded in other tools and tests.
+
+pub mod archive;
+pub mod capture;
+pub mod checksum;
+pub mod client;
+pub mod cloudevents;
+pub mod consume;
+pub mod error;
+pub mod format;
+pub mod json_path;
+pub mod list;
+pub mod payload;
+pub mod produce;
+pub mod routing;
+pub mod state;
+#[cfg(feature = "testsupport")]
+pub mod testsupport;
+pub mod topic_name;
+pub mod topic_template;
+pub mod transform;
+
+pub use error::PulsarCatError;
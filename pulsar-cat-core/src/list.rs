@@ -0,0 +1,195 @@
+use pulsar::proto::command_get_topics_of_namespace::Mode;
+use pulsar::{Pulsar, TokioExecutor};
+use regex::Regex;
+
+use crate::error::PulsarCatError;
+use crate::topic_name::TopicName;
+
+/// A partition of a topic and the broker serving it. Non-partitioned topics
+/// have a single entry for the topic itself.
+#[derive(Debug, Clone)]
+pub struct TopicPartition {
+    pub partition: usize,
+    pub topic: String,
+    pub broker_url: String,
+    /// Whether the broker is reached through a proxy
+    pub proxy: bool,
+}
+
+/// Look up the partitions of a topic and their owning brokers. A single
+/// partition such as `orders-partition-2` has one entry with its index.
+pub async fn topic_partitions(
+    client: &Pulsar<TokioExecutor>,
+    topic: &str,
+) -> Result<Vec<TopicPartition>, PulsarCatError> {
+    let first = TopicName::parse(topic)
+        .ok()
+        .and_then(|name| name.partition())
+        .unwrap_or(0) as usize;
+    let partitions = client.lookup_partitioned_topic(topic).await?;
+    Ok(partitions
+        .into_iter()
+        .enumerate()
+        .map(|(partition, (topic, broker_address))| TopicPartition {
+            partition: first + partition,
+            topic,
+            broker_url: broker_address.url.to_string(),
+            proxy: broker_address.proxy,
+        })
+        .collect())
+}
+
+/// Whether a topic exists, judged by the topics listed for its namespace, so
+/// that checking does not auto-create it. A partitioned topic exists when one
+/// of its partitions does.
+pub async fn topic_exists(
+    client: &Pulsar<TokioExecutor>,
+    topic: &str,
+) -> Result<bool, PulsarCatError> {
+    let (namespace, mode) = topic_namespace(topic)?;
+    let topic = full_topic_name(topic);
+    let partition_prefix = format!("{}-partition-", topic);
+    let topics = namespace_topics(client, &namespace, mode, None).await?;
+    Ok(topics
+        .iter()
+        .any(|existing| *existing == topic || existing.starts_with(&partition_prefix)))
+}
+
+/// Number of partitions of a partitioned topic, judged by the partitions
+/// listed for its namespace, 0 when none are listed.
+pub async fn listed_partitions(
+    client: &Pulsar<TokioExecutor>,
+    topic: &str,
+) -> Result<u32, PulsarCatError> {
+    let (namespace, mode) = topic_namespace(topic)?;
+    let partition_prefix = format!("{}-partition-", full_topic_name(topic));
+    let topics = namespace_topics(client, &namespace, mode, None).await?;
+    Ok(topics
+        .iter()
+        .filter_map(|existing| {
+            existing
+                .strip_prefix(&partition_prefix)?
+                .parse::<u32>()
+                .ok()
+        })
+        .map(|partition| partition + 1)
+        .max()
+        .unwrap_or(0))
+}
+
+/// Up to `limit` topics of the topic's namespace whose names are close to
+/// it, e.g. differing by a typo, the closest first. Partitions are listed as
+/// their partitioned topic.
+pub async fn similar_topics(
+    client: &Pulsar<TokioExecutor>,
+    topic: &str,
+    limit: usize,
+) -> Result<Vec<String>, PulsarCatError> {
+    let (namespace, mode) = topic_namespace(topic)?;
+    // A missing partition is compared by its partitioned topic
+    let topic = match TopicName::parse(topic) {
+        Ok(name) => name.partitioned_topic().to_string(),
+        Err(_) => full_topic_name(topic),
+    };
+    let name = local_name(&topic).to_lowercase();
+    let max_distance = (name.chars().count() / 4).max(2);
+
+    let mut candidates: Vec<(usize, String)> = Vec::new();
+    for existing in namespace_topics(client, &namespace, mode, None).await? {
+        let existing = match TopicName::parse(&existing) {
+            Ok(name) if name.partition().is_some() => name.partitioned_topic().to_string(),
+            _ => existing,
+        };
+        if candidates
+            .iter()
+            .any(|(_, candidate)| *candidate == existing)
+        {
+            continue;
+        }
+        let existing_name = local_name(&existing).to_lowercase();
+        let distance = edit_distance(&name, &existing_name);
+        if distance <= max_distance
+            || existing_name.contains(&name)
+            || name.contains(&existing_name)
+        {
+            candidates.push((distance, existing));
+        }
+    }
+    candidates.sort();
+    Ok(candidates
+        .into_iter()
+        .take(limit)
+        .map(|(_, topic)| topic)
+        .collect())
+}
+
+/// List the topics of a namespace in the given domain. With a filter, only
+/// topics whose local name (the part after the namespace) matches are returned.
+pub async fn namespace_topics(
+    client: &Pulsar<TokioExecutor>,
+    namespace: &str,
+    mode: Mode,
+    filter: Option<&Regex>,
+) -> Result<Vec<String>, PulsarCatError> {
+    let topics = client
+        .get_topics_of_namespace(namespace.to_owned(), mode)
+        .await?;
+    Ok(topics
+        .into_iter()
+        .filter(|topic| {
+            let local_name = topic.rsplit('/').next().unwrap_or(topic);
+            filter.is_none_or(|f| f.is_match(local_name))
+        })
+        .collect())
+}
+
+/// Whether a topic is in the `non-persistent` domain. Such topics store no
+/// messages, so there is no backlog to read, seek in or acknowledge.
+pub fn is_non_persistent(topic: &str) -> bool {
+    topic.starts_with("non-persistent://")
+}
+
+/// `persistent://tenant/ns/topic` for topic names given without a domain.
+pub fn full_topic_name(topic: &str) -> String {
+    if topic.contains("://") {
+        topic.to_owned()
+    } else {
+        format!("persistent://{}", topic)
+    }
+}
+
+/// Namespace of a topic and the listing mode of its domain.
+pub fn topic_namespace(topic: &str) -> Result<(String, Mode), PulsarCatError> {
+    let (mode, rest) = match topic.split_once("://") {
+        Some(("non-persistent", rest)) => (Mode::NonPersistent, rest),
+        Some((_, rest)) => (Mode::Persistent, rest),
+        None => (Mode::Persistent, topic),
+    };
+    match rest.rsplit_once('/') {
+        Some((namespace, _)) => Ok((namespace.to_owned(), mode)),
+        None => Err(anyhow::anyhow!(
+            "Invalid topic '{}', should be in the format of 'tenant/namespace/topic'",
+            topic
+        )
+        .into()),
+    }
+}
+
+fn local_name(topic: &str) -> &str {
+    topic.rsplit('/').next().unwrap_or(topic)
+}
+
+// Levenshtein distance over characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,65 @@
+use std::borrow::Cow;
+use std::io::Read;
+
+use crate::error::PulsarCatError;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const LZ4_FRAME_MAGIC: &[u8] = &[0x04, 0x22, 0x4d, 0x18];
+
+/// Compression applied to payloads by the producing application, independent
+/// of Pulsar's own batch compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadCompression {
+    /// Detect the format from the payload's magic bytes
+    Auto,
+    Gzip,
+    Zstd,
+    /// LZ4 frame format
+    Lz4,
+}
+
+impl PayloadCompression {
+    /// Detect the compression of a payload from its magic bytes.
+    pub fn detect(payload: &[u8]) -> Option<Self> {
+        if payload.starts_with(GZIP_MAGIC) {
+            Some(PayloadCompression::Gzip)
+        } else if payload.starts_with(ZSTD_MAGIC) {
+            Some(PayloadCompression::Zstd)
+        } else if payload.starts_with(LZ4_FRAME_MAGIC) {
+            Some(PayloadCompression::Lz4)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decompress a payload. With [`PayloadCompression::Auto`] payloads without a
+/// known magic number are returned unchanged.
+pub fn decompress_payload(
+    payload: &[u8],
+    compression: PayloadCompression,
+) -> Result<Cow<'_, [u8]>, PulsarCatError> {
+    let compression = match compression {
+        PayloadCompression::Auto => match PayloadCompression::detect(payload) {
+            Some(compression) => compression,
+            None => return Ok(Cow::Borrowed(payload)),
+        },
+        compression => compression,
+    };
+
+    let mut decompressed = Vec::new();
+    let result = match compression {
+        PayloadCompression::Gzip => {
+            flate2::read::MultiGzDecoder::new(payload).read_to_end(&mut decompressed)
+        }
+        PayloadCompression::Zstd => zstd::stream::read::Decoder::new(payload)
+            .and_then(|mut d| d.read_to_end(&mut decompressed)),
+        PayloadCompression::Lz4 => {
+            lz4::Decoder::new(payload).and_then(|mut d| d.read_to_end(&mut decompressed))
+        }
+        PayloadCompression::Auto => unreachable!("auto detection resolved above"),
+    };
+    result.map_err(|e| anyhow::anyhow!("Failed to decompress {:?} payload: {}", compression, e))?;
+    Ok(Cow::Owned(decompressed))
+}
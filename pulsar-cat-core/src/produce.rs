@@ -0,0 +1,571 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use futures::{Stream, StreamExt};
+use pulsar::compression::Compression;
+use pulsar::error::ProducerError;
+use pulsar::producer::{Message, Producer};
+use pulsar::proto::{CommandSendReceipt, MessageIdData};
+use pulsar::{Pulsar, TokioExecutor};
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::client::Failover;
+use crate::error::{MESSAGE_ERROR_TARGET, PulsarCatError};
+use crate::json_path::JsonPath;
+use crate::routing::{HashingScheme, partition_for_key};
+use crate::topic_name::TopicName;
+
+/// Maximum number of sends awaiting their receipt before reading more records,
+/// unless set in [`ProduceOptions::max_pending`].
+pub const DEFAULT_MAX_PENDING: usize = 1000;
+
+/// Replication cluster list restricting a message to the local cluster.
+pub const LOCAL_CLUSTER_ONLY: &str = "__local__";
+
+#[derive(Debug, Clone, Default)]
+pub struct ProduceOptions {
+    pub topic: String,
+    /// Send every message to each of these topics instead of only to `topic`,
+    /// e.g. to the topics a template expands to. Every topic gets producers
+    /// of its own.
+    pub topics: Vec<String>,
+    pub compression: Option<Compression>,
+    /// Clusters to replicate every message to, overriding the namespace's
+    /// replication clusters. [`LOCAL_CLUSTER_ONLY`] disables replication.
+    pub replicate_to: Vec<String>,
+    /// Property whose value selects the partition of every message instead
+    /// of its key, hashed like keys by the Java client. Messages without it
+    /// are sent to the partitions in turn.
+    pub route_by_property: Option<String>,
+    /// Hash function applied to the value of `route_by_property`
+    pub hashing_scheme: HashingScheme,
+    /// Number of producers the messages are spread over in turn, at least one.
+    /// More producers send more messages at once.
+    pub producers: usize,
+    /// Attach every producer to a single partition of a partitioned topic
+    /// rather than to all of them, with the same number of producers for
+    /// every partition.
+    pub pin_partitions: bool,
+    /// Maximum number of sends awaiting their receipt before reading more
+    /// records, 0 for [`DEFAULT_MAX_PENDING`]. Large payloads need less to
+    /// keep the producers busy, small ones more.
+    pub max_pending: usize,
+    /// Property whose value every receipt carries as its `trace_id`, e.g. a
+    /// correlation ID to follow the message through other services
+    pub trace_id_property: Option<String>,
+    /// Connect a new client this way when a send fails because the connection
+    /// to the broker was lost, and send the message again through it
+    pub failover: Option<Failover>,
+}
+
+/// A message to be produced.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Record {
+    pub key: Option<String>,
+    pub payload: Vec<u8>,
+    pub properties: BTreeMap<String, String>,
+    /// Time of the event the message describes, in epoch milliseconds
+    pub event_time: Option<u64>,
+}
+
+impl Record {
+    /// Parse an input line, splitting it at the first occurrence of
+    /// `key_delimiter` into key and value. With `enforce_key`, lines without a
+    /// key are rejected instead of becoming messages without one.
+    pub fn from_line(
+        line: String,
+        key_delimiter: Option<&str>,
+        enforce_key: bool,
+    ) -> Result<Self, String> {
+        let Some(delimiter) = key_delimiter else {
+            if enforce_key {
+                return Err(
+                    "Message key is required but not provided, please use --key to set the delimiter."
+                        .to_owned(),
+                );
+            }
+            return Ok(Record::from_payload(line));
+        };
+
+        match line.find(delimiter) {
+            Some(delimiter_pos) => {
+                let (k, v) = line.split_at(delimiter_pos);
+                Ok(Record {
+                    key: Some(k.to_owned()),
+                    // Skip the delimiter
+                    payload: v.as_bytes()[delimiter.len()..].to_vec(),
+                    ..Default::default()
+                })
+            }
+            None if enforce_key => Err(format!(
+                "Key is enforced but delimiter '{}' not found in the message",
+                delimiter
+            )),
+            // No delimiter found, use whole line as data
+            None => Ok(Record::from_payload(line)),
+        }
+    }
+
+    /// Parse a message printed by `kcat -C -J`, e.g.
+    /// `{"topic":"t","partition":0,"offset":1,"key":"k","payload":"v","headers":["h","v"]}`.
+    /// Kafka headers become properties and the timestamp `ts` the event time,
+    /// topic, partition and offset are ignored.
+    pub fn from_kcat_json(line: &str) -> Result<Self, String> {
+        let envelope: Value =
+            serde_json::from_str(line).map_err(|e| format!("Invalid kcat JSON: {}", e))?;
+        let text = |field: &str| match &envelope[field] {
+            Value::Null => Ok(None),
+            Value::String(s) => Ok(Some(s.clone())),
+            other => Err(format!(
+                "Invalid kcat JSON: '{}' should be a string, found {}",
+                field, other
+            )),
+        };
+
+        // Headers are a flat [name, value, name, value, ...] array
+        let mut properties = BTreeMap::new();
+        match &envelope["headers"] {
+            Value::Null => {}
+            Value::Array(headers) => {
+                for pair in headers.chunks(2) {
+                    let [Value::String(name), value] = pair else {
+                        return Err(
+                            "Invalid kcat JSON: 'headers' should alternate names and values"
+                                .to_owned(),
+                        );
+                    };
+                    let value = match value {
+                        Value::String(s) => s.clone(),
+                        _ => String::new(),
+                    };
+                    properties.insert(name.clone(), value);
+                }
+            }
+            other => {
+                return Err(format!(
+                    "Invalid kcat JSON: 'headers' should be an array, found {}",
+                    other
+                ));
+            }
+        }
+
+        Ok(Record {
+            key: text("key")?,
+            payload: text("payload")?.unwrap_or_default().into_bytes(),
+            properties,
+            // kcat prints -1 for messages without a timestamp
+            event_time: envelope["ts"].as_u64().filter(|ts| *ts > 0),
+        })
+    }
+
+    /// Parse a message printed by `consume --json`, e.g.
+    /// `{"topic":"t","message_id":"1:2:-1:-1","key":"k","payload":"v","publish_time":1700000000000}`,
+    /// returning it with its publish time in epoch milliseconds. The event time
+    /// is kept when the message has one.
+    pub fn from_consumer_json(line: &str) -> Result<(Self, Option<u64>), String> {
+        let message: Value =
+            serde_json::from_str(line).map_err(|e| format!("Invalid message JSON: {}", e))?;
+        let key = match &message["key"] {
+            Value::Null => None,
+            Value::String(key) => Some(key.clone()),
+            other => {
+                return Err(format!(
+                    "Invalid message JSON: 'key' should be a string, found {}",
+                    other
+                ));
+            }
+        };
+        let Value::String(payload) = &message["payload"] else {
+            return Err("Invalid message JSON: 'payload' should be a string".to_owned());
+        };
+        let time = |field: &str| match &message[field] {
+            Value::Null => Ok(None),
+            value => value.as_u64().map(Some).ok_or_else(|| {
+                format!(
+                    "Invalid message JSON: '{}' should be epoch milliseconds, found {}",
+                    field, value
+                )
+            }),
+        };
+        let publish_time = time("publish_time")?;
+
+        let record = Record {
+            key,
+            payload: payload.clone().into_bytes(),
+            properties: BTreeMap::new(),
+            event_time: time("event_time")?,
+        };
+        Ok((record, publish_time))
+    }
+
+    /// Key taken from the value at `path` in the JSON payload. Strings are
+    /// used as they are, numbers and booleans as text. None when the payload
+    /// is not JSON or has no such value.
+    pub fn key_from_json(&self, path: &JsonPath) -> Option<String> {
+        let payload: Value = serde_json::from_slice(&self.payload).ok()?;
+        match path.select(&payload)? {
+            Value::String(key) => Some(key.clone()),
+            value @ (Value::Number(_) | Value::Bool(_)) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    fn from_payload(payload: String) -> Self {
+        Record {
+            payload: payload.into_bytes(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Outcome of [`produce`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProduceSummary {
+    /// Messages the broker confirmed
+    pub sent: u64,
+    /// Messages whose send failed
+    pub failed: u64,
+    /// Messages a non-persistent topic accepted but dropped instead of
+    /// dispatching them, e.g. when its broker is over its limit of pending
+    /// non-persistent messages
+    pub dropped: u64,
+}
+
+/// Result of sending a single record, passed to the receipt callback of
+/// [`produce`] in the order of the records.
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    /// Position of the record in the stream, from 0
+    pub index: u64,
+    /// Topic the message was sent to
+    pub topic: String,
+    pub key: Option<String>,
+    /// Value of the `trace_id_property` of the message
+    pub trace_id: Option<String>,
+    pub outcome: SendOutcome,
+    /// Time from handing the message to the client until the broker answered
+    pub latency: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub enum SendOutcome {
+    /// Stored by the broker under this message ID. The partition is only set
+    /// when the producer is attached to a single partition.
+    Sent(MessageIdData),
+    /// Accepted by a non-persistent topic but not dispatched
+    Dropped,
+    Failed(String),
+}
+
+// A send awaiting its receipt. The receipt is awaited on a task of its own, so
+// the latency is taken when the broker answers rather than when it is collected.
+struct PendingSend {
+    index: u64,
+    // Index of the topic in the topics sent to
+    topic: usize,
+    // Kept to report failed sends
+    key: Option<String>,
+    trace_id: Option<String>,
+    partition: Option<i32>,
+    receipt: JoinHandle<(Result<CommandSendReceipt, pulsar::Error>, Duration)>,
+}
+
+/// Send every record of `records` to the topic, or to each of the topics.
+/// Failed sends are logged and counted without stopping the stream. A lost
+/// connection fails the stream, unless `failover` connects a new client.
+/// `on_receipt` is called with the outcome of every send.
+pub async fn produce<S, F>(
+    client: &Pulsar<TokioExecutor>,
+    opts: &ProduceOptions,
+    records: S,
+    mut on_receipt: F,
+) -> Result<ProduceSummary, PulsarCatError>
+where
+    S: Stream<Item = Record>,
+    F: FnMut(Receipt),
+{
+    let topics = if opts.topics.is_empty() {
+        std::slice::from_ref(&opts.topic)
+    } else {
+        opts.topics.as_slice()
+    };
+    // Replaced when failing over to another broker
+    let mut client = client.clone();
+    let mut pools = Vec::with_capacity(topics.len());
+    for topic in topics {
+        pools.push(ProducerPool::new(&client, topic, opts).await?);
+    }
+
+    let max_pending = match opts.max_pending {
+        0 => DEFAULT_MAX_PENDING,
+        max_pending => max_pending,
+    };
+    let mut pending: VecDeque<PendingSend> = VecDeque::new();
+    let mut summary = ProduceSummary::default();
+    let mut records = std::pin::pin!(records);
+    let mut index = 0;
+    while let Some(mut record) = records.next().await {
+        let trace_id = opts
+            .trace_id_property
+            .as_ref()
+            .and_then(|property| record.properties.get(property).cloned());
+        for topic in 0..pools.len() {
+            // The last topic takes the contents of the record, the others a copy
+            let (payload, properties) = if topic + 1 == topics.len() {
+                (
+                    std::mem::take(&mut record.payload),
+                    std::mem::take(&mut record.properties),
+                )
+            } else {
+                (record.payload.clone(), record.properties.clone())
+            };
+            let message = Message {
+                payload,
+                properties: properties.into_iter().collect(),
+                partition_key: record.key.clone(),
+                replicate_to: opts.replicate_to.clone(),
+                event_time: record.event_time,
+                ..Default::default()
+            };
+            let (producer, partition) = pools[topic].producer(&message);
+            let started = Instant::now();
+            // Kept to send it again through a new client
+            let resend = opts.failover.is_some().then(|| message.clone());
+            let (receipt, partition) = match producer.send_non_blocking(message).await {
+                Ok(receipt) => (receipt, partition),
+                Err(e) => match (&opts.failover, resend) {
+                    (Some(failover), Some(message)) if is_connection_lost(&e) => {
+                        warn!(
+                            event = "connection_lost",
+                            topic = topics[topic],
+                            error = %e,
+                            "Connection lost, connecting to the brokers again"
+                        );
+                        client = failover.connect().await?;
+                        for (name, pool) in topics.iter().zip(pools.iter_mut()) {
+                            *pool = ProducerPool::new(&client, name, opts).await?;
+                        }
+                        info!(
+                            event = "connection_restored",
+                            topic = topics[topic],
+                            "Connection restored"
+                        );
+                        let (producer, partition) = pools[topic].producer(&message);
+                        (producer.send_non_blocking(message).await?, partition)
+                    }
+                    _ => return Err(e.into()),
+                },
+            };
+            pending.push_back(PendingSend {
+                index,
+                topic,
+                key: record.key.clone(),
+                trace_id: trace_id.clone(),
+                partition,
+                receipt: tokio::spawn(async move { (receipt.await, started.elapsed()) }),
+            });
+
+            // Collect the receipts already there, keeping their order
+            while pending.len() >= max_pending
+                || pending
+                    .front()
+                    .is_some_and(|send| send.receipt.is_finished())
+            {
+                complete_send(topics, &mut pending, &mut summary, &mut on_receipt).await;
+            }
+        }
+        index += 1;
+    }
+
+    info!("Finished reading input, waiting for messages to be sent...");
+    while !pending.is_empty() {
+        complete_send(topics, &mut pending, &mut summary, &mut on_receipt).await;
+    }
+
+    for producer in pools.iter_mut().flat_map(|pool| &mut pool.producers) {
+        if let Err(e) = producer.close().await {
+            error!("Error closing producer: {}", e);
+        }
+    }
+    Ok(summary)
+}
+
+// The client picks partitions in turn regardless of the key, so routing by a
+// property or pinning producers to partitions needs a producer of its own for
+// every partition. Messages not routed by a property go to the producers in turn.
+struct ProducerPool {
+    // Property selecting the partition, with one producer per partition
+    route_by: Option<String>,
+    hashing_scheme: HashingScheme,
+    producers: Vec<Producer<TokioExecutor>>,
+    // Partition every producer is attached to, if a single one
+    partitions: Vec<Option<i32>>,
+    next: usize,
+}
+
+impl ProducerPool {
+    async fn new(
+        client: &Pulsar<TokioExecutor>,
+        topic: &str,
+        opts: &ProduceOptions,
+    ) -> Result<Self, PulsarCatError> {
+        let count = opts.producers.max(1);
+        // A single partition is published to directly, its messages report it
+        let partition = TopicName::parse(topic)
+            .ok()
+            .and_then(|name| name.partition());
+        let partitions = if partition.is_some() {
+            0
+        } else if opts.route_by_property.is_some() || opts.pin_partitions {
+            client.lookup_partitioned_topic_number(topic).await?
+        } else {
+            0
+        };
+        let topics: Vec<(String, Option<i32>)> = if partitions == 0 {
+            if let Some(property) = &opts.route_by_property {
+                warn!(
+                    "Topic {} is not partitioned, there is nothing to route by property {}",
+                    topic, property
+                );
+            }
+            vec![
+                (
+                    topic.to_owned(),
+                    partition.map(|partition| partition as i32)
+                );
+                count
+            ]
+        } else {
+            // Every partition gets the same number of producers
+            let per_partition = count.div_ceil(partitions as usize);
+            if per_partition * partitions as usize != count {
+                info!(
+                    "Using {} producers, {} for each of the {} partitions",
+                    per_partition * partitions as usize,
+                    per_partition,
+                    partitions
+                );
+            }
+            (0..per_partition)
+                .flat_map(|_| {
+                    (0..partitions).map(|partition| {
+                        (
+                            format!("{}-partition-{}", topic, partition),
+                            Some(partition as i32),
+                        )
+                    })
+                })
+                .collect()
+        };
+
+        let mut producers = Vec::with_capacity(topics.len());
+        for (topic, _) in &topics {
+            producers.push(build_producer(client, topic, opts).await?);
+        }
+        Ok(ProducerPool {
+            route_by: opts.route_by_property.clone().filter(|_| partitions > 0),
+            hashing_scheme: opts.hashing_scheme,
+            producers,
+            partitions: topics.into_iter().map(|(_, partition)| partition).collect(),
+            next: 0,
+        })
+    }
+
+    fn producer(&mut self, message: &Message) -> (&mut Producer<TokioExecutor>, Option<i32>) {
+        let value = self
+            .route_by
+            .as_ref()
+            .and_then(|property| message.properties.get(property));
+        let index = match value {
+            Some(value) => {
+                partition_for_key(value, self.producers.len() as u32, self.hashing_scheme) as usize
+            }
+            None => {
+                self.next = (self.next + 1) % self.producers.len();
+                self.next
+            }
+        };
+        (&mut self.producers[index], self.partitions[index])
+    }
+}
+
+// Errors of a connection that went away, another broker may take over
+fn is_connection_lost(e: &pulsar::Error) -> bool {
+    matches!(
+        e,
+        pulsar::Error::Connection(_) | pulsar::Error::Producer(ProducerError::Connection(_))
+    )
+}
+
+async fn build_producer(
+    client: &Pulsar<TokioExecutor>,
+    topic: &str,
+    opts: &ProduceOptions,
+) -> Result<Producer<TokioExecutor>, PulsarCatError> {
+    Ok(client
+        .producer()
+        .with_topic(topic)
+        .with_options(pulsar::ProducerOptions {
+            compression: opts.compression.clone(),
+            ..Default::default()
+        })
+        .build()
+        .await?)
+}
+
+// Wait for the oldest pending send, count its outcome and report it
+async fn complete_send(
+    topics: &[String],
+    pending: &mut VecDeque<PendingSend>,
+    summary: &mut ProduceSummary,
+    on_receipt: &mut impl FnMut(Receipt),
+) {
+    let Some(send) = pending.pop_front() else {
+        return;
+    };
+    let topic = &topics[send.topic];
+    let (result, latency) = match send.receipt.await {
+        Ok((result, latency)) => (result.map_err(|e| e.to_string()), latency),
+        Err(e) => (Err(e.to_string()), Duration::ZERO),
+    };
+    let outcome = match result {
+        // Brokers confirm dropped non-persistent messages with ID -1:-1
+        Ok(receipt)
+            if receipt
+                .message_id
+                .as_ref()
+                .is_some_and(|id| id.ledger_id == u64::MAX && id.entry_id == u64::MAX) =>
+        {
+            summary.dropped += 1;
+            SendOutcome::Dropped
+        }
+        Ok(receipt) => {
+            summary.sent += 1;
+            let mut id = receipt.message_id.unwrap_or_default();
+            id.partition = send.partition.or(id.partition);
+            SendOutcome::Sent(id)
+        }
+        Err(e) => {
+            error!(
+                target: MESSAGE_ERROR_TARGET,
+                kind = "send",
+                topic,
+                key = send.key,
+                error = %e,
+                "Failed to send message"
+            );
+            summary.failed += 1;
+            SendOutcome::Failed(e)
+        }
+    };
+    on_receipt(Receipt {
+        index: send.index,
+        topic: topic.clone(),
+        key: send.key,
+        trace_id: send.trace_id,
+        outcome,
+        latency,
+    });
+}
@@ -0,0 +1,64 @@
+/// Hash function applied to message keys by the producer's partition router,
+/// as configured with `hashingScheme` in the Java client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashingScheme {
+    /// Java's `String.hashCode()`, the default of the Java and Go clients
+    #[default]
+    JavaString,
+    /// Murmur3 32-bit hash of the UTF-8 key
+    Murmur3,
+}
+
+/// Partition that the default router of the Java client sends messages with
+/// `key` to, for a topic with `partitions` partitions.
+///
+/// pulsar-cat itself (like the Rust client) distributes messages round-robin
+/// regardless of their key, unless it routes them by a property.
+pub fn partition_for_key(key: &str, partitions: u32, scheme: HashingScheme) -> u32 {
+    let hash = match scheme {
+        HashingScheme::JavaString => java_string_hash(key),
+        HashingScheme::Murmur3 => murmur3_32(key.as_bytes(), 0),
+    };
+    // Both schemes clear the sign bit before taking the modulo
+    (hash & i32::MAX as u32) % partitions
+}
+
+// String.hashCode(), computed over UTF-16 code units
+fn java_string_hash(s: &str) -> u32 {
+    s.encode_utf16().fold(0u32, |hash, unit| {
+        hash.wrapping_mul(31).wrapping_add(u32::from(unit))
+    })
+}
+
+// MurmurHash3 x86 32-bit
+fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mix = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+    let mut hash = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        hash = (hash ^ mix(k))
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe654_6b64);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let k = tail
+            .iter()
+            .rev()
+            .fold(0u32, |k, byte| (k << 8) | u32::from(*byte));
+        hash ^= mix(k);
+    }
+
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use pulsar::proto::MessageIdData;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::consume::ConsumedMessage;
+use crate::error::PulsarCatError;
+use crate::format::{format_message_id, parse_message_id};
+
+// Minimum time between two writes of the state file while consuming
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+// On disk format, message IDs are kept in their printed form to stay readable
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StateFile {
+    positions: BTreeMap<String, String>,
+}
+
+/// Last processed message per topic (partition), checkpointed to a JSON file
+/// so a consumer can resume where a previous run stopped.
+///
+/// The file is written at most once per second while messages are recorded,
+/// and once more when the state is saved or dropped.
+#[derive(Debug)]
+pub struct ConsumeState {
+    path: String,
+    positions: BTreeMap<String, MessageIdData>,
+    last_saved: Instant,
+    dirty: bool,
+}
+
+impl ConsumeState {
+    /// Load the state from `path`, starting empty if the file does not exist.
+    pub fn load(path: &str) -> Result<Self, PulsarCatError> {
+        let mut positions = BTreeMap::new();
+        match std::fs::read_to_string(path) {
+            Ok(content) => {
+                let file: StateFile = serde_json::from_str(&content)
+                    .map_err(|e| anyhow::anyhow!("Invalid state file '{}': {}", path, e))?;
+                for (topic, message_id) in file.positions {
+                    let message_id = parse_message_id(&message_id)
+                        .map_err(|e| anyhow::anyhow!("Invalid state file '{}': {}", path, e))?;
+                    positions.insert(topic, message_id);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(anyhow::anyhow!("Failed to read state file '{}': {}", path, e).into());
+            }
+        }
+        Ok(ConsumeState {
+            path: path.to_owned(),
+            positions,
+            last_saved: Instant::now(),
+            dirty: false,
+        })
+    }
+
+    /// Last processed message per topic (partition).
+    pub fn positions(&self) -> &BTreeMap<String, MessageIdData> {
+        &self.positions
+    }
+
+    /// Record a message as processed, writing the file if it has not been
+    /// written for a while.
+    pub fn record(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        match self.positions.get_mut(msg.topic) {
+            Some(position) => position.clone_from(msg.message_id),
+            None => {
+                self.positions
+                    .insert(msg.topic.to_owned(), msg.message_id.clone());
+            }
+        }
+        self.dirty = true;
+        if self.last_saved.elapsed() >= SAVE_INTERVAL {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Write the recorded positions to the state file.
+    pub fn save(&mut self) -> Result<(), PulsarCatError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let file = StateFile {
+            positions: self
+                .positions
+                .iter()
+                .map(|(topic, message_id)| (topic.clone(), format_message_id(message_id)))
+                .collect(),
+        };
+        let content = serde_json::to_string_pretty(&file)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize state: {}", e))?;
+        // Write a temporary file first, an interrupted write must not lose the old state
+        let tmp_path = format!("{}.tmp", self.path);
+        std::fs::write(&tmp_path, content)
+            .and_then(|_| std::fs::rename(&tmp_path, &self.path))
+            .map_err(|e| anyhow::anyhow!("Failed to write state file '{}': {}", self.path, e))?;
+        self.last_saved = Instant::now();
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl Drop for ConsumeState {
+    fn drop(&mut self) {
+        // Consuming may be aborted, the last positions must still be saved
+        if let Err(e) = self.save() {
+            error!("{}", e);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Standalone Pulsar in a container for integration tests, behind the
+//! `testsupport` feature. Starting the container needs a running Docker
+//! daemon and pulls the `apachepulsar/pulsar` image on first use.
+
+use std::time::Duration;
+
+use futures::stream;
+use pulsar::proto::command_get_topics_of_namespace::Mode;
+use pulsar::{Pulsar, TokioExecutor};
+use testcontainers_modules::pulsar::Pulsar as PulsarImage;
+use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use testcontainers_modules::testcontainers::{ContainerAsync, ImageExt};
+
+use crate::capture::CapturedMessage;
+use crate::client::{ConnectOptions, connect};
+use crate::consume::{ConsumeOptions, StartPosition, consume};
+use crate::error::PulsarCatError;
+use crate::list::namespace_topics;
+use crate::produce::{ProduceOptions, ProduceSummary, Record, produce};
+
+// A standalone broker initializes bookies, ZooKeeper and functions first
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// A running standalone Pulsar, removed when dropped. It has the `public`
+/// tenant with the `public/default` namespace, and topics are created on
+/// first use.
+pub struct PulsarContainer {
+    // Kept for its lifetime only
+    _container: ContainerAsync<PulsarImage>,
+    broker_url: String,
+    admin_url: String,
+}
+
+impl PulsarContainer {
+    /// Start a standalone Pulsar and wait until it serves requests.
+    pub async fn start() -> Result<Self, PulsarCatError> {
+        let container = PulsarImage::default()
+            .with_startup_timeout(STARTUP_TIMEOUT)
+            .start()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to start the Pulsar container: {}", e))?;
+        let address = async {
+            let host = container.get_host().await?;
+            let broker_port = container.get_host_port_ipv4(6650).await?;
+            let admin_port = container.get_host_port_ipv4(8080).await?;
+            Ok::<_, testcontainers_modules::testcontainers::TestcontainersError>((
+                host,
+                broker_port,
+                admin_port,
+            ))
+        };
+        let (host, broker_port, admin_port) = address
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to find the Pulsar container ports: {}", e))?;
+        Ok(PulsarContainer {
+            _container: container,
+            broker_url: format!("pulsar://{}:{}", host, broker_port),
+            admin_url: format!("http://{}:{}", host, admin_port),
+        })
+    }
+
+    /// Service URL of the broker, e.g. `pulsar://localhost:32768`.
+    pub fn broker_url(&self) -> &str {
+        &self.broker_url
+    }
+
+    /// URL of the admin REST API, e.g. `http://localhost:32769`.
+    pub fn admin_url(&self) -> &str {
+        &self.admin_url
+    }
+
+    /// A new client of the broker, without authentication.
+    pub async fn client(&self) -> Result<Pulsar<TokioExecutor>, PulsarCatError> {
+        connect(&self.broker_url, &ConnectOptions::default()).await
+    }
+}
+
+/// A topic named after `name` that no other test uses, in the `public/default`
+/// namespace of the standalone broker.
+pub fn unique_topic(name: &str) -> String {
+    format!(
+        "persistent://public/default/{}-{}",
+        name,
+        uuid::Uuid::new_v4()
+    )
+}
+
+/// A path in the temporary directory that no other test uses, with the file
+/// extension `extension`. Nothing is created there.
+pub fn temp_path(extension: &str) -> String {
+    std::env::temp_dir()
+        .join(format!(
+            "pulsar-cat-it-{}.{}",
+            uuid::Uuid::new_v4(),
+            extension
+        ))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Send `records` to `topic`, in order.
+pub async fn produce_records(
+    client: &Pulsar<TokioExecutor>,
+    topic: &str,
+    records: Vec<Record>,
+) -> Result<ProduceSummary, PulsarCatError> {
+    let opts = ProduceOptions {
+        topic: topic.to_owned(),
+        ..Default::default()
+    };
+    produce(client, &opts, stream::iter(records), |_| {}).await
+}
+
+/// All messages of `topic`, read from the beginning through a fresh
+/// subscription until no more arrive.
+pub async fn consume_all(
+    client: &Pulsar<TokioExecutor>,
+    topic: &str,
+) -> Result<Vec<CapturedMessage>, PulsarCatError> {
+    consume_with(
+        client,
+        &ConsumeOptions {
+            topic: topic.to_owned(),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// The messages [`consume`] passes on with `opts`, which are read from the
+/// beginning until no more arrive.
+pub async fn consume_with(
+    client: &Pulsar<TokioExecutor>,
+    opts: &ConsumeOptions,
+) -> Result<Vec<CapturedMessage>, PulsarCatError> {
+    let opts = ConsumeOptions {
+        start: Some(StartPosition::Beginning),
+        exit_at_end: true,
+        ..opts.clone()
+    };
+    let mut messages = Vec::new();
+    consume(client, &opts, std::future::pending(), |msg| {
+        messages.push(CapturedMessage::from(msg));
+        Ok(())
+    })
+    .await?;
+    Ok(messages)
+}
+
+/// The persistent topics of `namespace`, given as `tenant/namespace`.
+pub async fn list_topics(
+    client: &Pulsar<TokioExecutor>,
+    namespace: &str,
+) -> Result<Vec<String>, PulsarCatError> {
+    namespace_topics(client, namespace, Mode::Persistent, None).await
+}
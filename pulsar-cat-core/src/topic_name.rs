@@ -0,0 +1,294 @@
+//! Parsing of topic names such as `persistent://tenant/ns/orders`, forms
+//! without a domain, short names of a default namespace, and the partitions
+//! of partitioned topics, which are addressed as `tenant/ns/orders-partition-2`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+// Separates the name of a partitioned topic from the partition index
+const PARTITION_SUFFIX: &str = "-partition-";
+
+/// Why a topic name could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TopicNameError {
+    #[error("unknown domain '{0}', should be 'persistent' or 'non-persistent'")]
+    UnknownDomain(String),
+    /// A bare topic name without tenant and namespace
+    #[error("should be in the format of 'tenant/namespace/topic'")]
+    MissingNamespace,
+    #[error("should be in the format of '[persistent|non-persistent://]tenant/namespace/topic'")]
+    InvalidFormat,
+    #[error("tenant and namespace may only use letters, digits and '-', '_', '=', ':', '.'")]
+    InvalidName,
+}
+
+/// A parsed topic name. A partition of a partitioned topic keeps the name of
+/// its partitioned topic and the partition index apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicName {
+    persistent: bool,
+    // As `tenant/namespace`, None for a short name until it is resolved in
+    // the default namespace
+    namespace: Option<String>,
+    local_name: String,
+    partition: Option<u32>,
+}
+
+/// Whether a tenant or namespace name only uses the allowed characters.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '=' | ':' | '.'))
+}
+
+impl TopicName {
+    /// Parse `[persistent|non-persistent://]tenant/namespace/topic`, where a
+    /// topic ending in `-partition-N` is partition N of a partitioned topic.
+    /// A bare `topic` is a short name, see [`TopicName::with_default_namespace`].
+    pub fn parse(topic: &str) -> Result<Self, TopicNameError> {
+        let (persistent, domain, rest) = match topic.split_once("://") {
+            Some(("persistent", rest)) => (true, true, rest),
+            Some(("non-persistent", rest)) => (false, true, rest),
+            Some((domain, _)) => return Err(TopicNameError::UnknownDomain(domain.to_owned())),
+            None => (true, false, topic),
+        };
+        let (namespace, local_name) = match rest.split('/').collect::<Vec<_>>()[..] {
+            [tenant, namespace, local_name] if !local_name.is_empty() => {
+                if !is_valid_name(tenant) || !is_valid_name(namespace) {
+                    return Err(TopicNameError::InvalidName);
+                }
+                (Some(format!("{}/{}", tenant, namespace)), local_name)
+            }
+            [local_name] if !local_name.is_empty() && !domain => (None, local_name),
+            [_] => return Err(TopicNameError::MissingNamespace),
+            _ => return Err(TopicNameError::InvalidFormat),
+        };
+
+        let (local_name, partition) = match local_name.rsplit_once(PARTITION_SUFFIX) {
+            // Only canonical indexes, so that the name is kept as given
+            Some((partitioned, index))
+                if !partitioned.is_empty()
+                    && index.bytes().all(|b| b.is_ascii_digit())
+                    && (index == "0" || !index.starts_with('0')) =>
+            {
+                match index.parse() {
+                    Ok(index) => (partitioned, Some(index)),
+                    Err(_) => (local_name, None),
+                }
+            }
+            _ => (local_name, None),
+        };
+        Ok(TopicName {
+            persistent,
+            namespace,
+            local_name: local_name.to_owned(),
+            partition,
+        })
+    }
+
+    /// Resolve a short name into `namespace`, given as `tenant/namespace`.
+    /// Names with a namespace are kept as they are.
+    pub fn with_default_namespace(self, namespace: &str) -> TopicName {
+        TopicName {
+            namespace: self.namespace.or_else(|| Some(namespace.to_owned())),
+            ..self
+        }
+    }
+
+    /// Whether this is a short name without tenant and namespace.
+    pub fn is_short(&self) -> bool {
+        self.namespace.is_none()
+    }
+
+    /// Fail for a short name, which cannot be looked up without a namespace.
+    pub fn require_namespace(&self) -> Result<(), TopicNameError> {
+        match self.namespace {
+            Some(_) => Ok(()),
+            None => Err(TopicNameError::MissingNamespace),
+        }
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.persistent
+    }
+
+    /// The namespace as `tenant/namespace`, None for a short name.
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// The partition index when this names a partition of a partitioned topic.
+    pub fn partition(&self) -> Option<u32> {
+        self.partition
+    }
+
+    /// The partitioned topic a partition belongs to, the topic itself otherwise.
+    pub fn partitioned_topic(&self) -> TopicName {
+        TopicName {
+            partition: None,
+            ..self.clone()
+        }
+    }
+
+    /// Partition `partition` of this topic, which must be partitioned.
+    pub fn with_partition(&self, partition: u32) -> TopicName {
+        TopicName {
+            partition: Some(partition),
+            ..self.clone()
+        }
+    }
+}
+
+impl FromStr for TopicName {
+    type Err = TopicNameError;
+
+    fn from_str(topic: &str) -> Result<Self, Self::Err> {
+        TopicName::parse(topic)
+    }
+}
+
+// The client takes topics as `Into<String>`
+impl From<&TopicName> for String {
+    fn from(topic: &TopicName) -> Self {
+        topic.to_string()
+    }
+}
+
+/// The full name, e.g. `persistent://tenant/ns/orders-partition-2`, or the
+/// name as given for a short name.
+impl fmt::Display for TopicName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.namespace {
+            Some(namespace) => {
+                let domain = if self.persistent {
+                    "persistent"
+                } else {
+                    "non-persistent"
+                };
+                write!(f, "{}://{}/{}", domain, namespace, self.local_name)?;
+            }
+            None => write!(f, "{}", self.local_name)?,
+        }
+        if let Some(partition) = self.partition {
+            write!(f, "{}{}", PARTITION_SUFFIX, partition)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(topic: &str) -> TopicName {
+        TopicName::parse(topic).unwrap()
+    }
+
+    #[test]
+    fn parses_full_names() {
+        let topic = parse("non-persistent://tenant/ns/orders");
+        assert!(!topic.is_persistent());
+        assert_eq!(topic.namespace(), Some("tenant/ns"));
+        assert_eq!(topic.partition(), None);
+        assert_eq!(topic.to_string(), "non-persistent://tenant/ns/orders");
+    }
+
+    #[test]
+    fn defaults_to_the_persistent_domain() {
+        let topic = parse("tenant/ns/orders");
+        assert!(topic.is_persistent());
+        assert_eq!(topic, parse("persistent://tenant/ns/orders"));
+        assert_eq!(topic.to_string(), "persistent://tenant/ns/orders");
+    }
+
+    #[test]
+    fn parses_partition_suffixes() {
+        let topic = parse("tenant/ns/orders-partition-2");
+        assert_eq!(topic.partition(), Some(2));
+        assert_eq!(
+            topic.partitioned_topic().to_string(),
+            "persistent://tenant/ns/orders"
+        );
+        assert_eq!(
+            topic.to_string(),
+            "persistent://tenant/ns/orders-partition-2"
+        );
+        assert_eq!(
+            parse("tenant/ns/orders").with_partition(0).to_string(),
+            "persistent://tenant/ns/orders-partition-0"
+        );
+    }
+
+    #[test]
+    fn keeps_non_canonical_partition_suffixes_in_the_name() {
+        for name in [
+            "orders-partition-",
+            "orders-partition-02",
+            "orders-partition-+2",
+            "orders-partition-x",
+            "-partition-2",
+            "orders-partition-99999999999",
+        ] {
+            let topic = parse(&format!("tenant/ns/{}", name));
+            assert_eq!(topic.partition(), None, "{}", name);
+            assert_eq!(
+                topic.to_string(),
+                format!("persistent://tenant/ns/{}", name)
+            );
+        }
+        assert_eq!(parse("tenant/ns/orders-partition-0").partition(), Some(0));
+    }
+
+    #[test]
+    fn resolves_short_names_in_the_default_namespace() {
+        let topic = parse("orders-partition-1");
+        assert!(topic.is_short());
+        assert_eq!(topic.namespace(), None);
+        assert_eq!(topic.to_string(), "orders-partition-1");
+        assert_eq!(
+            topic.require_namespace(),
+            Err(TopicNameError::MissingNamespace)
+        );
+
+        let topic = topic.with_default_namespace("tenant/ns");
+        assert!(!topic.is_short());
+        assert_eq!(topic.require_namespace(), Ok(()));
+        assert_eq!(topic.partition(), Some(1));
+        assert_eq!(
+            topic.to_string(),
+            "persistent://tenant/ns/orders-partition-1"
+        );
+
+        // Names with a namespace keep it
+        let topic = parse("other/ns/orders").with_default_namespace("tenant/ns");
+        assert_eq!(topic.namespace(), Some("other/ns"));
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        let error = |topic: &str| TopicName::parse(topic).unwrap_err();
+        assert_eq!(
+            error("kafka://tenant/ns/orders"),
+            TopicNameError::UnknownDomain("kafka".to_owned())
+        );
+        assert_eq!(
+            error("persistent://orders"),
+            TopicNameError::MissingNamespace
+        );
+        assert_eq!(error(""), TopicNameError::MissingNamespace);
+        assert_eq!(error("ns/orders"), TopicNameError::InvalidFormat);
+        assert_eq!(error("tenant/ns/"), TopicNameError::InvalidFormat);
+        assert_eq!(error("a/b/c/d"), TopicNameError::InvalidFormat);
+        assert_eq!(error("ten ant/ns/orders"), TopicNameError::InvalidName);
+        assert_eq!(error("tenant//orders"), TopicNameError::InvalidName);
+    }
+
+    #[test]
+    fn parses_from_str() {
+        let topic: TopicName = "tenant/ns/orders".parse().unwrap();
+        assert_eq!(String::from(&topic), "persistent://tenant/ns/orders");
+    }
+}
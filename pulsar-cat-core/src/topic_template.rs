@@ -0,0 +1,273 @@
+//! Brace expansion of topic names, so a family of sharded topics can be named
+//! at once, e.g. `tenant/ns/events-{0..9}` or `tenant/ns/{orders,payments}-eu`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::topic_name::TopicName;
+
+/// Most topics a template may expand to.
+pub const MAX_TEMPLATE_TOPICS: usize = 10_000;
+
+/// A topic, or a template naming several topics, whose expansions were
+/// checked to be valid topic names when it was parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicTemplate {
+    template: String,
+    default_namespace: Option<String>,
+}
+
+impl TopicTemplate {
+    /// Resolve the short names the template expands to, as with
+    /// `events-{0..9}`, into `namespace`, given as `tenant/namespace`.
+    pub fn with_default_namespace(self, namespace: &str) -> TopicTemplate {
+        TopicTemplate {
+            default_namespace: Some(namespace.to_owned()),
+            ..self
+        }
+    }
+
+    /// Whether this has brace groups to expand.
+    pub fn is_template(&self) -> bool {
+        is_topic_template(&self.template)
+    }
+
+    /// The topics named by the template, in the order of its alternatives.
+    pub fn expand(&self) -> Vec<TopicName> {
+        // Both were checked when the template was parsed
+        expand_topic_template(&self.template)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|topic| TopicName::parse(topic).ok())
+            .map(|topic| match &self.default_namespace {
+                Some(namespace) => topic.with_default_namespace(namespace),
+                None => topic,
+            })
+            .collect()
+    }
+}
+
+impl FromStr for TopicTemplate {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let topics = expand_topic_template(template)
+            .map_err(|e| format!("invalid topic template: {}", e))?;
+        for topic in &topics {
+            TopicName::parse(topic).map_err(|e| match topics.len() {
+                1 => e.to_string(),
+                _ => format!("topic '{}' {}", topic, e),
+            })?;
+        }
+        Ok(TopicTemplate {
+            template: template.to_owned(),
+            default_namespace: None,
+        })
+    }
+}
+
+/// The template as given, in the default namespace when it is short.
+impl fmt::Display for TopicTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.default_namespace {
+            Some(namespace) if !self.template.contains('/') => {
+                write!(f, "persistent://{}/{}", namespace, self.template)
+            }
+            _ => f.write_str(&self.template),
+        }
+    }
+}
+
+/// Whether `topic` is a template with brace groups to expand.
+pub fn is_topic_template(topic: &str) -> bool {
+    topic.contains('{')
+}
+
+/// Expand the brace groups of `template` like a shell does: `{a,b}` lists
+/// alternatives and `{N..M}` an ascending or descending range of integers,
+/// zero-padded to the same width when an end has a leading zero (`{00..15}`).
+/// Groups may be nested and combined, the topics are returned in the order of
+/// their alternatives. A topic without groups expands to itself.
+pub fn expand_topic_template(template: &str) -> Result<Vec<String>, String> {
+    let mut topics = Vec::new();
+    expand(template, &mut topics)?;
+    Ok(topics)
+}
+
+fn expand(template: &str, topics: &mut Vec<String>) -> Result<(), String> {
+    let Some(open) = template.find('{') else {
+        if template.contains('}') {
+            return Err("unmatched '}'".to_owned());
+        }
+        if topics.len() == MAX_TEMPLATE_TOPICS {
+            return Err(format!(
+                "expands to more than {} topics",
+                MAX_TEMPLATE_TOPICS
+            ));
+        }
+        topics.push(template.to_owned());
+        return Ok(());
+    };
+    let (prefix, rest) = (&template[..open], &template[open + 1..]);
+    if prefix.contains('}') {
+        return Err("unmatched '}'".to_owned());
+    }
+    let close = matching_brace(rest).ok_or("unmatched '{'")?;
+    let (group, suffix) = (&rest[..close], &rest[close + 1..]);
+    for alternative in alternatives(group)? {
+        expand(&format!("{}{}{}", prefix, alternative, suffix), topics)?;
+    }
+    Ok(())
+}
+
+// Position of the '}' closing a group, in the text after its '{'
+fn matching_brace(rest: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+// The alternatives of the contents of a group, nested groups are left to expand later
+fn alternatives(group: &str) -> Result<Vec<String>, String> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in group.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(group[start..i].to_owned());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !parts.is_empty() {
+        parts.push(group[start..].to_owned());
+        return Ok(parts);
+    }
+    if let Some((first, last)) = group.split_once("..")
+        && let (Ok(from), Ok(to)) = (first.parse::<i64>(), last.parse::<i64>())
+    {
+        let count = from.abs_diff(to) + 1;
+        if count > MAX_TEMPLATE_TOPICS as u64 {
+            return Err(format!(
+                "range {{{}}} has more than {} values",
+                group, MAX_TEMPLATE_TOPICS
+            ));
+        }
+        let padded = |end: &str| {
+            end.trim_start_matches('-').len() > 1 && end.trim_start_matches('-').starts_with('0')
+        };
+        let width = if padded(first) || padded(last) {
+            first.len().max(last.len())
+        } else {
+            0
+        };
+        let values: Box<dyn Iterator<Item = i64>> = if from <= to {
+            Box::new(from..=to)
+        } else {
+            Box::new((to..=from).rev())
+        };
+        return Ok(values
+            .map(|value| format!("{:0width$}", value, width = width))
+            .collect());
+    }
+    Err(format!(
+        "{{{}}} is neither a list like {{a,b}} nor a range like {{0..9}}",
+        group
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(template: &TopicTemplate) -> Vec<String> {
+        template.expand().iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn expands_lists_and_ranges() {
+        assert_eq!(
+            expand_topic_template("t/ns/{a,b}-{1..2}").unwrap(),
+            ["t/ns/a-1", "t/ns/a-2", "t/ns/b-1", "t/ns/b-2"]
+        );
+        assert_eq!(
+            expand_topic_template("t/ns/e-{08..10}").unwrap(),
+            ["t/ns/e-08", "t/ns/e-09", "t/ns/e-10"]
+        );
+        assert_eq!(
+            expand_topic_template("t/ns/e-{2..0}").unwrap(),
+            ["t/ns/e-2", "t/ns/e-1", "t/ns/e-0"]
+        );
+        assert_eq!(expand_topic_template("t/ns/e").unwrap(), ["t/ns/e"]);
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        assert!(expand_topic_template("t/ns/e-{0..9").is_err());
+        assert!(expand_topic_template("t/ns/e-}").is_err());
+        assert!(expand_topic_template("t/ns/e-{x}").is_err());
+        assert!(expand_topic_template("t/ns/e-{0..100000}").is_err());
+    }
+
+    #[test]
+    fn parses_templates_into_topic_names() {
+        let template: TopicTemplate = "tenant/ns/orders-partition-{0..1}".parse().unwrap();
+        assert!(template.is_template());
+        let topics = template.expand();
+        assert_eq!(topics.len(), 2);
+        assert_eq!(topics[1].partition(), Some(1));
+        assert_eq!(
+            topics[1].to_string(),
+            "persistent://tenant/ns/orders-partition-1"
+        );
+    }
+
+    #[test]
+    fn rejects_templates_expanding_to_invalid_topics() {
+        assert_eq!(
+            "tenant/ns/e-{0..9".parse::<TopicTemplate>(),
+            Err("invalid topic template: unmatched '{'".to_owned())
+        );
+        assert_eq!(
+            "{tenant/ns,ns}/e".parse::<TopicTemplate>(),
+            Err("topic 'ns/e' should be in the format of \
+                '[persistent|non-persistent://]tenant/namespace/topic'"
+                .to_owned())
+        );
+    }
+
+    #[test]
+    fn resolves_short_templates_in_the_default_namespace() {
+        let template: TopicTemplate = "events-{0..1}".parse().unwrap();
+        assert!(template.expand().iter().all(TopicName::is_short));
+
+        let template = template.with_default_namespace("tenant/ns");
+        assert_eq!(template.to_string(), "persistent://tenant/ns/events-{0..1}");
+        assert_eq!(
+            names(&template),
+            [
+                "persistent://tenant/ns/events-0",
+                "persistent://tenant/ns/events-1"
+            ]
+        );
+
+        // Templates with a namespace keep it
+        let template = "other/ns/events"
+            .parse::<TopicTemplate>()
+            .unwrap()
+            .with_default_namespace("tenant/ns");
+        assert_eq!(template.to_string(), "other/ns/events");
+        assert_eq!(names(&template), ["persistent://other/ns/events"]);
+    }
+}
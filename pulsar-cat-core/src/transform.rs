@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use mlua::{Function, Lua, Table, Value};
+
+use crate::consume::ConsumedMessage;
+use crate::error::PulsarCatError;
+use crate::format::format_message_id;
+use crate::produce::Record;
+
+// Global function every transform script has to define
+const TRANSFORM_FUNCTION: &str = "transform";
+
+/// A Lua script rewriting or dropping messages.
+///
+/// The script defines a global `transform(msg)` function. `msg` is a table with
+/// `topic`, `key` (nil without one), `payload` (a byte string), `properties`
+/// (a table of strings) and `event_time` (epoch milliseconds, nil without
+/// one); consumed messages also carry `message_id` and
+/// `publish_time`. The function returns the (modified) table, a string
+/// replacing only the payload, or nil to drop the message:
+///
+/// ```lua
+/// function transform(msg)
+///   if msg.properties["internal"] == "true" then
+///     return nil
+///   end
+///   msg.payload = msg.payload:gsub("%d%d%d%d%-%d%d%d%d%-%d%d%d%d%-%d%d%d%d", "****")
+///   return msg
+/// end
+/// ```
+pub struct Transform {
+    // Lua states are not Sync, the lock lets a transform be shared across tasks
+    lua: Mutex<Lua>,
+    path: String,
+}
+
+impl fmt::Debug for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transform")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl Transform {
+    /// Load a transform script, failing if it does not define `transform`.
+    pub fn load(path: &str) -> Result<Self, PulsarCatError> {
+        let code = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read transform script '{}': {}", path, e))?;
+        let lua = Lua::new();
+        lua.load(code.as_str())
+            .set_name(path)
+            .exec()
+            .map_err(|e| anyhow::anyhow!("Failed to load transform script '{}': {}", path, e))?;
+        if lua
+            .globals()
+            .get::<_, Function>(TRANSFORM_FUNCTION)
+            .is_err()
+        {
+            return Err(anyhow::anyhow!(
+                "Transform script '{}' does not define a '{}(msg)' function",
+                path,
+                TRANSFORM_FUNCTION
+            )
+            .into());
+        }
+        Ok(Transform {
+            lua: Mutex::new(lua),
+            path: path.to_owned(),
+        })
+    }
+
+    /// Transform a record before it is sent to `topic`, `None` drops it.
+    pub fn apply_produce(
+        &self,
+        topic: &str,
+        record: Record,
+    ) -> Result<Option<Record>, PulsarCatError> {
+        self.call(|lua| {
+            let table = record_table(lua, &record)?;
+            table.set("topic", topic)?;
+            Ok(table)
+        })
+    }
+
+    /// Transform a consumed message before it is output, `None` drops it.
+    pub fn apply_consume(&self, msg: &ConsumedMessage) -> Result<Option<Record>, PulsarCatError> {
+        let record = Record {
+            key: msg.key.map(str::to_owned),
+            payload: msg.payload.to_vec(),
+            properties: msg
+                .properties
+                .iter()
+                .map(|kv| (kv.key.clone(), kv.value.clone()))
+                .collect(),
+            event_time: msg.event_time,
+        };
+        self.call(|lua| {
+            let table = record_table(lua, &record)?;
+            table.set("topic", msg.topic)?;
+            table.set("message_id", format_message_id(msg.message_id))?;
+            table.set("publish_time", msg.publish_time)?;
+            Ok(table)
+        })
+    }
+
+    fn call<F>(&self, build: F) -> Result<Option<Record>, PulsarCatError>
+    where
+        F: for<'lua> FnOnce(&'lua Lua) -> mlua::Result<Table<'lua>>,
+    {
+        let lua = self
+            .lua
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Transform script '{}' panicked", self.path))?;
+        let result = build(&lua)
+            .and_then(|table| {
+                let function: Function = lua.globals().get(TRANSFORM_FUNCTION)?;
+                function.call::<_, Value>(table)
+            })
+            .and_then(record_from_value)
+            .map_err(|e| anyhow::anyhow!("Transform script '{}' failed: {}", self.path, e))?;
+        Ok(result)
+    }
+}
+
+fn record_table<'lua>(lua: &'lua Lua, record: &Record) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set("key", record.key.as_deref())?;
+    table.set("payload", lua.create_string(&record.payload)?)?;
+    let properties = lua.create_table()?;
+    for (name, value) in &record.properties {
+        properties.set(name.as_str(), value.as_str())?;
+    }
+    table.set("properties", properties)?;
+    table.set("event_time", record.event_time)?;
+    Ok(table)
+}
+
+fn record_from_value(value: Value) -> mlua::Result<Option<Record>> {
+    match value {
+        Value::Nil => Ok(None),
+        Value::String(payload) => Ok(Some(Record {
+            payload: payload.as_bytes().to_vec(),
+            ..Default::default()
+        })),
+        Value::Table(table) => {
+            let payload = match table.get::<_, Value>("payload")? {
+                Value::String(payload) => payload.as_bytes().to_vec(),
+                Value::Nil => Vec::new(),
+                other => {
+                    return Err(mlua::Error::runtime(format!(
+                        "payload must be a string, got {}",
+                        other.type_name()
+                    )));
+                }
+            };
+            let properties = match table.get::<_, Option<Table>>("properties")? {
+                Some(properties) => properties
+                    .pairs::<String, String>()
+                    .collect::<mlua::Result<BTreeMap<_, _>>>()?,
+                None => BTreeMap::new(),
+            };
+            Ok(Some(Record {
+                key: table.get("key")?,
+                payload,
+                properties,
+                event_time: table.get("event_time")?,
+            }))
+        }
+        other => Err(mlua::Error::runtime(format!(
+            "transform must return a table, a string or nil, got {}",
+            other.type_name()
+        ))),
+    }
+}
@@ -0,0 +1,136 @@
+//! End-to-end tests against a standalone Pulsar in a container, run with
+//! `cargo test -p pulsar-cat-core --features testsupport`. Every test starts
+//! a broker of its own, which needs a running Docker daemon.
+
+use std::collections::BTreeMap;
+
+use pulsar_cat_core::capture::{CaptureWriter, read_capture, replay};
+use pulsar_cat_core::consume::ConsumeOptions;
+use pulsar_cat_core::produce::Record;
+use pulsar_cat_core::testsupport::{
+    PulsarContainer, consume_all, consume_with, list_topics, produce_records, temp_path,
+    unique_topic,
+};
+
+fn record(key: &str, payload: &str, properties: &[(&str, &str)]) -> Record {
+    Record {
+        key: Some(key.to_owned()),
+        payload: payload.as_bytes().to_vec(),
+        properties: properties
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect(),
+        event_time: None,
+    }
+}
+
+#[tokio::test]
+async fn consumes_what_was_produced() {
+    let pulsar = PulsarContainer::start().await.unwrap();
+    let client = pulsar.client().await.unwrap();
+    let topic = unique_topic("round-trip");
+
+    let records = vec![
+        record("a", "first", &[("source", "test")]),
+        Record {
+            event_time: Some(1_700_000_000_000),
+            ..record("b", "second", &[])
+        },
+        record("a", "third", &[]),
+    ];
+    let summary = produce_records(&client, &topic, records).await.unwrap();
+    assert_eq!((summary.sent, summary.failed), (3, 0));
+
+    let messages = consume_all(&client, &topic).await.unwrap();
+    let messages: Vec<_> = messages.iter().map(|msg| msg.as_consumed()).collect();
+    let payloads: Vec<_> = messages.iter().map(|msg| msg.payload).collect();
+    assert_eq!(payloads, [&b"first"[..], b"second", b"third"]);
+    let keys: Vec<_> = messages.iter().map(|msg| msg.key).collect();
+    assert_eq!(keys, [Some("a"), Some("b"), Some("a")]);
+    assert_eq!(messages[0].properties.len(), 1);
+    assert_eq!(messages[0].properties[0].key, "source");
+    assert_eq!(messages[1].event_time, Some(1_700_000_000_000));
+    assert!(messages.iter().all(|msg| msg.topic == topic));
+}
+
+#[tokio::test]
+async fn lists_produced_topics() {
+    let pulsar = PulsarContainer::start().await.unwrap();
+    let client = pulsar.client().await.unwrap();
+    let topic = unique_topic("listed");
+
+    produce_records(&client, &topic, vec![record("k", "v", &[])])
+        .await
+        .unwrap();
+    let topics = list_topics(&client, "public/default").await.unwrap();
+    assert!(topics.contains(&topic), "{:?}", topics);
+}
+
+#[tokio::test]
+async fn follows_a_trace_id() {
+    let pulsar = PulsarContainer::start().await.unwrap();
+    let client = pulsar.client().await.unwrap();
+    let topic = unique_topic("traced");
+
+    let records = (0..6)
+        .map(|i| {
+            let trace_id = if i % 2 == 0 { "abc" } else { "other" };
+            record("k", &i.to_string(), &[("trace", trace_id)])
+        })
+        .collect();
+    produce_records(&client, &topic, records).await.unwrap();
+
+    let opts = ConsumeOptions {
+        topic: topic.clone(),
+        trace_id: Some("abc".to_owned()),
+        count: Some(2),
+        ..Default::default()
+    };
+    let messages = consume_with(&client, &opts).await.unwrap();
+    let payloads: Vec<_> = messages
+        .iter()
+        .map(|msg| msg.as_consumed().payload)
+        .collect();
+    assert_eq!(payloads, [&b"0"[..], b"2"]);
+}
+
+#[tokio::test]
+async fn replays_a_recorded_capture() {
+    let pulsar = PulsarContainer::start().await.unwrap();
+    let client = pulsar.client().await.unwrap();
+    let topic = unique_topic("recorded");
+
+    let records = (0..5)
+        .map(|i| record(&format!("k{}", i), &format!("v{}", i), &[]))
+        .collect();
+    produce_records(&client, &topic, records).await.unwrap();
+    let consumed = consume_all(&client, &topic).await.unwrap();
+
+    let path = temp_path("pcatr");
+    let mut writer = CaptureWriter::create(&path).unwrap();
+    for msg in &consumed {
+        writer.append(&msg.as_consumed()).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let recorded: Vec<_> = read_capture(&path)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(recorded.len(), consumed.len());
+
+    let mut replayed = BTreeMap::new();
+    let opts = ConsumeOptions {
+        count: Some(3),
+        ..Default::default()
+    };
+    replay(&path, &opts, std::future::pending(), |msg| {
+        replayed.insert(msg.key.unwrap().to_owned(), msg.payload.to_vec());
+        Ok(())
+    })
+    .await
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(replayed.len(), 3);
+    assert_eq!(replayed["k2"], b"v2");
+}
@@ -0,0 +1,459 @@
+use reqwest::{Certificate, Client, Method, RequestBuilder, Response};
+use serde_json::{Value, json};
+
+use pulsar_cat_core::client::read_token_file;
+
+use crate::{cli_options::AuthOpts, error::PulsarCatError};
+
+/// Thin wrapper around the Pulsar admin REST API (`/admin/v2`).
+pub struct AdminClient {
+    http: Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl AdminClient {
+    pub fn new(admin_url: &str, auth_opts: &AuthOpts) -> Result<Self, PulsarCatError> {
+        let tls = &auth_opts.tls;
+        let mut builder = Client::builder()
+            .danger_accept_invalid_certs(tls.allow_insecure.unwrap_or(false))
+            .danger_accept_invalid_hostnames(tls.no_hostname_verification.unwrap_or(false));
+        if let Some(ca_cert) = &tls.ca_cert {
+            let pem = std::fs::read(ca_cert).map_err(|e| {
+                anyhow::anyhow!("Failed to read TLS CA certificate '{}': {}", ca_cert, e)
+            })?;
+            let certificate = Certificate::from_pem(&pem)
+                .map_err(|e| anyhow::anyhow!("Invalid TLS CA certificate '{}': {}", ca_cert, e))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        let http = builder
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to build admin HTTP client: {}", e))?;
+
+        let token = match &auth_opts.token_file {
+            Some(path) => Some(read_token_file(path)?),
+            None => auth_opts.token.clone(),
+        };
+
+        Ok(AdminClient {
+            http,
+            base_url: admin_url.trim_end_matches('/').to_owned(),
+            token,
+        })
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.request_at(
+            method,
+            &format!("admin/v2/{}", path.trim_start_matches('/')),
+        )
+    }
+
+    // Request relative to the service root, for endpoints outside of `/admin/v2`
+    fn request_at(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}/{}", self.base_url, path);
+        let mut builder = self.http.request(method, url);
+        if let Some(token) = &self.token {
+            builder = builder.bearer_auth(token);
+        }
+        builder
+    }
+
+    async fn send(&self, builder: RequestBuilder) -> Result<Response, PulsarCatError> {
+        let response = builder.send().await.map_err(|e| PulsarCatError::Admin {
+            status: None,
+            error: anyhow::anyhow!("Admin request failed: {}", e),
+        })?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let url = response.url().to_string();
+        let body = response.text().await.unwrap_or_default();
+        Err(PulsarCatError::Admin {
+            status: Some(status.as_u16()),
+            error: anyhow::anyhow!(
+                "Admin request to {} failed with status {}: {}",
+                url,
+                status,
+                extract_reason(&body)
+            ),
+        })
+    }
+
+    async fn text(&self, builder: RequestBuilder) -> Result<String, PulsarCatError> {
+        let response = self.send(builder).await?;
+        let body = response
+            .text()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read admin response: {}", e))?;
+        Ok(body.trim().trim_matches('"').to_owned())
+    }
+
+    async fn json(&self, builder: RequestBuilder) -> Result<Value, PulsarCatError> {
+        let response = self.send(builder).await?;
+        let body = response
+            .text()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read admin response: {}", e))?;
+        if body.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&body)
+            .map_err(|e| anyhow::anyhow!("Failed to parse admin response: {}", e).into())
+    }
+
+    /// Number of partitions of a topic, 0 for non-partitioned topics.
+    pub async fn get_partition_count(&self, topic: &str) -> Result<u64, PulsarCatError> {
+        let path = format!("{}/partitions", topic_path(topic)?);
+        let metadata = self.json(self.request(Method::GET, &path)).await?;
+        Ok(metadata["partitions"].as_u64().unwrap_or(0))
+    }
+
+    /// Create a partitioned topic with the given number of partitions.
+    pub async fn create_partitioned_topic(
+        &self,
+        topic: &str,
+        partitions: u32,
+    ) -> Result<(), PulsarCatError> {
+        let path = format!("{}/partitions", topic_path(topic)?);
+        self.send(self.request(Method::PUT, &path).json(&partitions))
+            .await?;
+        Ok(())
+    }
+
+    /// Internal stats of a non-partitioned topic or a single partition,
+    /// including the mark-delete position of every subscription cursor.
+    pub async fn get_internal_stats(&self, topic: &str) -> Result<Value, PulsarCatError> {
+        let path = format!("{}/internalStats", topic_path(topic)?);
+        self.json(self.request(Method::GET, &path)).await
+    }
+
+    /// Stats of a non-partitioned topic or a single partition, such as its
+    /// backlog and storage size in bytes and those of every subscription.
+    /// `earliest_time_in_backlog` adds the publish time of the oldest
+    /// unacknowledged message of every subscription, which the broker has to
+    /// read from storage.
+    pub async fn get_topic_stats(
+        &self,
+        topic: &str,
+        earliest_time_in_backlog: bool,
+    ) -> Result<Value, PulsarCatError> {
+        let path = format!("{}/stats", topic_path(topic)?);
+        self.json(
+            self.request(Method::GET, &path)
+                .query(&[("getEarliestTimeInBacklog", earliest_time_in_backlog)]),
+        )
+        .await
+    }
+
+    /// Stats of a partitioned topic, with the stats of every partition by
+    /// name under `partitions`.
+    pub async fn get_partitioned_topic_stats(&self, topic: &str) -> Result<Value, PulsarCatError> {
+        let path = format!("{}/partitioned-stats", topic_path(topic)?);
+        self.json(
+            self.request(Method::GET, &path)
+                .query(&[("perPartition", true)]),
+        )
+        .await
+    }
+
+    /// Retention policy in effect for a topic, set on the topic, its
+    /// namespace or the broker. Requires topic level policies to be enabled.
+    pub async fn get_applied_retention(&self, topic: &str) -> Result<Value, PulsarCatError> {
+        let path = format!("{}/retention", topic_path(topic)?);
+        self.json(self.request(Method::GET, &path).query(&[("applied", true)]))
+            .await
+    }
+
+    /// Backlog quotas in effect for a topic by type, e.g.
+    /// `{"destination_storage": {"limitSize": 1073741824, "policy": "producer_request_hold"}}`.
+    /// Requires topic level policies to be enabled.
+    pub async fn get_applied_backlog_quotas(&self, topic: &str) -> Result<Value, PulsarCatError> {
+        let path = format!("{}/backlogQuotaMap", topic_path(topic)?);
+        self.json(self.request(Method::GET, &path).query(&[("applied", true)]))
+            .await
+    }
+
+    /// Size of an entry's payload as stored by the broker, i.e. as sent by the
+    /// producer after compression. The entry holds the whole batch of a
+    /// batched message.
+    pub async fn get_entry_size(
+        &self,
+        topic: &str,
+        ledger_id: u64,
+        entry_id: u64,
+    ) -> Result<u64, PulsarCatError> {
+        let path = format!(
+            "{}/ledger/{}/entry/{}",
+            topic_path(topic)?,
+            ledger_id,
+            entry_id
+        );
+        let response = self.send(self.request(Method::GET, &path)).await?;
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read admin response: {}", e))?;
+        Ok(body.len() as u64)
+    }
+
+    /// Create a subscription positioned at a message ID (`ledger:entry`).
+    pub async fn create_subscription(
+        &self,
+        topic: &str,
+        subscription: &str,
+        (ledger_id, entry_id): (i64, i64),
+    ) -> Result<(), PulsarCatError> {
+        let path = format!(
+            "{}/subscription/{}",
+            topic_path(topic)?,
+            encode_path_segment(subscription)
+        );
+        let position = json!({ "ledgerId": ledger_id, "entryId": entry_id });
+        self.send(self.request(Method::PUT, &path).json(&position))
+            .await?;
+        Ok(())
+    }
+
+    /// Reset a subscription cursor to a message ID (`ledger:entry`), with
+    /// `excluded` the message itself counts as acknowledged.
+    pub async fn reset_cursor(
+        &self,
+        topic: &str,
+        subscription: &str,
+        (ledger_id, entry_id): (i64, i64),
+        excluded: bool,
+    ) -> Result<(), PulsarCatError> {
+        let path = format!(
+            "{}/subscription/{}/resetcursor",
+            topic_path(topic)?,
+            encode_path_segment(subscription)
+        );
+        let position = json!({ "ledgerId": ledger_id, "entryId": entry_id });
+        self.send(
+            self.request(Method::POST, &path)
+                .query(&[("isExcluded", excluded)])
+                .json(&position),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Unload a topic so that it is reassigned to a (possibly different) broker.
+    pub async fn unload_topic(&self, topic: &str) -> Result<(), PulsarCatError> {
+        let path = format!("{}/unload", topic_path(topic)?);
+        self.send(self.request(Method::PUT, &path)).await?;
+        Ok(())
+    }
+
+    /// Terminate a topic, returning the ID of the last message that was persisted.
+    pub async fn terminate_topic(&self, topic: &str) -> Result<Value, PulsarCatError> {
+        let path = format!("{}/terminate", topic_path(topic)?);
+        self.json(self.request(Method::POST, &path)).await
+    }
+
+    /// Terminate every partition of a partitioned topic.
+    pub async fn terminate_partitioned_topic(&self, topic: &str) -> Result<Value, PulsarCatError> {
+        let path = format!("{}/terminate/partitions", topic_path(topic)?);
+        self.json(self.request(Method::POST, &path)).await
+    }
+
+    /// Start compacting a topic, every partition of a partitioned topic.
+    pub async fn trigger_compaction(&self, topic: &str) -> Result<(), PulsarCatError> {
+        let path = format!("{}/compaction", topic_path(topic)?);
+        self.send(self.request(Method::PUT, &path)).await?;
+        Ok(())
+    }
+
+    /// Status of the latest compaction of a non-partitioned topic or a single
+    /// partition, e.g. `{"status": "ERROR", "lastError": "..."}`. The status is
+    /// one of `NOT_RUN`, `RUNNING`, `SUCCESS` and `ERROR`.
+    pub async fn compaction_status(&self, topic: &str) -> Result<Value, PulsarCatError> {
+        let path = format!("{}/compaction", topic_path(topic)?);
+        self.json(self.request(Method::GET, &path)).await
+    }
+
+    /// Look up the broker owning a topic, returning the addresses advertised
+    /// for `listener_name` when given.
+    pub async fn lookup_topic(
+        &self,
+        topic: &str,
+        listener_name: Option<&str>,
+    ) -> Result<Value, PulsarCatError> {
+        let path = format!("lookup/v2/topic/{}", topic_path(topic)?);
+        let mut builder = self.request_at(Method::GET, &path);
+        if let Some(listener_name) = listener_name {
+            builder = builder.query(&[("listenerName", listener_name)]);
+        }
+        self.json(builder).await
+    }
+
+    /// Version of the broker serving the admin API.
+    pub async fn broker_version(&self) -> Result<String, PulsarCatError> {
+        self.text(self.request(Method::GET, "brokers/version"))
+            .await
+    }
+
+    /// Run the broker health check, which produces and consumes a message on
+    /// the broker's heartbeat topic.
+    pub async fn health_check(&self) -> Result<String, PulsarCatError> {
+        self.text(self.request(Method::GET, "brokers/health")).await
+    }
+
+    /// Runtime configuration of the broker, with the values as strings.
+    /// Requires superuser permissions.
+    pub async fn get_runtime_configuration(&self) -> Result<Value, PulsarCatError> {
+        self.json(self.request(Method::GET, "brokers/configuration/runtime"))
+            .await
+    }
+
+    /// Largest message the broker accepts (`maxMessageSize`), from its
+    /// runtime configuration. None when it is not reported.
+    pub async fn get_max_message_size(&self) -> Result<Option<u64>, PulsarCatError> {
+        let config = self.get_runtime_configuration().await?;
+        Ok(config["maxMessageSize"]
+            .as_str()
+            .and_then(|size| size.parse().ok()))
+    }
+
+    /// Create a namespace, applying the given initial policies.
+    pub async fn create_namespace(
+        &self,
+        namespace: &str,
+        policies: &Value,
+    ) -> Result<(), PulsarCatError> {
+        let path = format!("namespaces/{}", namespace_path(namespace)?);
+        self.send(self.request(Method::PUT, &path).json(policies))
+            .await?;
+        Ok(())
+    }
+
+    /// Retention policy of a namespace, e.g.
+    /// `{"retentionTimeInMinutes": 60, "retentionSizeInMB": 1024}`, null
+    /// when the namespace uses the broker defaults.
+    pub async fn get_retention(&self, namespace: &str) -> Result<Value, PulsarCatError> {
+        let path = format!("namespaces/{}/retention", namespace_path(namespace)?);
+        self.json(self.request(Method::GET, &path)).await
+    }
+
+    /// Persistent topics of a namespace, with every partition of a
+    /// partitioned topic listed on its own.
+    pub async fn get_namespace_topics(
+        &self,
+        namespace: &str,
+    ) -> Result<Vec<String>, PulsarCatError> {
+        let path = format!("namespaces/{}/topics", namespace_path(namespace)?);
+        let topics = self.json(self.request(Method::GET, &path)).await?;
+        Ok(topics
+            .as_array()
+            .map(|topics| {
+                topics
+                    .iter()
+                    .filter_map(|topic| topic.as_str().map(str::to_owned))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Backlog quotas of a namespace by type, empty when the namespace uses
+    /// the broker defaults.
+    pub async fn get_backlog_quotas(&self, namespace: &str) -> Result<Value, PulsarCatError> {
+        let path = format!("namespaces/{}/backlogQuotaMap", namespace_path(namespace)?);
+        self.json(self.request(Method::GET, &path)).await
+    }
+
+    /// Message TTL of a namespace in seconds, None when it is not set.
+    pub async fn get_message_ttl(&self, namespace: &str) -> Result<Option<u64>, PulsarCatError> {
+        let path = format!("namespaces/{}/messageTTL", namespace_path(namespace)?);
+        Ok(self.json(self.request(Method::GET, &path)).await?.as_u64())
+    }
+
+    /// Delete a namespace, `force` also deletes all topics in it.
+    pub async fn delete_namespace(
+        &self,
+        namespace: &str,
+        force: bool,
+    ) -> Result<(), PulsarCatError> {
+        let path = format!("namespaces/{}", namespace_path(namespace)?);
+        self.send(
+            self.request(Method::DELETE, &path)
+                .query(&[("force", force)]),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// Derive the admin HTTP endpoint from a broker service URL,
+/// e.g. `pulsar://host:6650` -> `http://host:8080`.
+pub fn admin_url_from_broker(broker: &str) -> String {
+    let (scheme, rest) = match broker.split_once("://") {
+        Some(("pulsar+ssl", rest)) => ("https", rest),
+        Some((_, rest)) => ("http", rest),
+        None => ("http", broker),
+    };
+    // Only the first host of a multi-host service URL is used
+    let authority = rest.split(['/', ',']).next().unwrap_or_default();
+    let host = match authority.rsplit_once(':') {
+        Some((host, _port)) => host,
+        None => authority,
+    };
+    let port = if scheme == "https" { 8443 } else { 8080 };
+
+    format!("{}://{}:{}", scheme, host, port)
+}
+
+/// Map a topic name to its REST path, e.g.
+/// `persistent://tenant/ns/topic` -> `persistent/tenant/ns/topic`.
+fn topic_path(topic: &str) -> Result<String, PulsarCatError> {
+    let (domain, rest) = match topic.split_once("://") {
+        Some((domain, rest)) => (domain, rest),
+        None => ("persistent", topic),
+    };
+    if rest.split('/').count() != 3 {
+        return Err(anyhow::anyhow!(
+            "Invalid topic name '{}', should be in the format of 'tenant/namespace/topic'",
+            topic
+        )
+        .into());
+    }
+
+    Ok(format!("{}/{}", domain, rest))
+}
+
+// Percent-encode a name used as a single path segment, such as a subscription
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn namespace_path(namespace: &str) -> Result<&str, PulsarCatError> {
+    if namespace.split('/').count() != 2 {
+        return Err(anyhow::anyhow!(
+            "Invalid namespace '{}', should be in the format of 'tenant/namespace'",
+            namespace
+        )
+        .into());
+    }
+
+    Ok(namespace)
+}
+
+// The admin API reports errors as `{"reason": "..."}`, fall back to the raw body otherwise
+fn extract_reason(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v["reason"].as_str().map(|s| s.to_owned()))
+        .unwrap_or_else(|| body.to_owned())
+}
@@ -1,8 +1,25 @@
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::time::Duration;
+
 use clap::ValueEnum;
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgAction, Args, Parser, Subcommand};
+use pulsar::proto::MessageIdData;
+use pulsar_cat_core::checksum::{ChecksumAlgorithm, checksum};
+use pulsar_cat_core::consume::{
+    AckFailurePolicy, AckStrategy, ConsumedMessage, ErrorPolicy, StartPosition,
+};
+use pulsar_cat_core::format::{
+    BinaryMode, MessageField, MessageJson, NewlineMode, parse_message_field, parse_message_id,
+    pretty_json,
+};
+use pulsar_cat_core::json_path::JsonPath;
+use pulsar_cat_core::topic_name::TopicName;
+use pulsar_cat_core::topic_template::TopicTemplate;
 
 use crate::PulsarCatError;
-use crate::op::OpValidate;
+use crate::features::BrokerFeature;
+use crate::op::{OpValidate, expand_topics, validate_namespace, validate_topic};
 
 #[derive(Parser, Debug, Clone)]
 #[clap(version, about = "The DevOps tool that provides Kcat like experience for Pulsar", long_about = None)]
@@ -13,15 +30,287 @@ pub struct CliOpts {
     #[arg(
         short = 'b',
         long = "broker",
-        required = true,
-        help = "Pulsar broker URL"
+        required = false,
+        global = true,
+        env = "PULSAR_BROKER",
+        help = "Pulsar broker URL, required unless provided by the selected profile. A comma-separated list is tried in order until a connection succeeds, also when consuming or producing loses its connection"
+    )]
+    pub broker: Option<String>,
+
+    #[arg(
+        long = "profile",
+        required = false,
+        global = true,
+        env = "PULSAR_CAT_PROFILE",
+        help = "Named profile from the configuration file to take connection settings from"
+    )]
+    pub profile: Option<String>,
+
+    #[arg(
+        long = "config",
+        required = false,
+        global = true,
+        env = "PULSAR_CAT_CONFIG",
+        help = "Path to the configuration file [default: ~/.config/pulsar-cat/config.toml]"
+    )]
+    pub config: Option<String>,
+
+    #[arg(
+        long = "admin-url",
+        required = false,
+        global = true,
+        env = "PULSAR_ADMIN_URL",
+        help = "Pulsar admin REST URL, derived from the broker URL when not given (e.g. pulsar://host:6650 -> http://host:8080)"
+    )]
+    pub admin_url: Option<String>,
+
+    #[arg(
+        long = "default-namespace",
+        required = false,
+        global = true,
+        env = "PULSAR_DEFAULT_NAMESPACE",
+        help = "Namespace in the format of 'tenant/namespace' that short topic names (e.g. 'my-topic') resolve to"
+    )]
+    pub default_namespace: Option<String>,
+
+    #[arg(
+        short = 'v',
+        long = "verbose",
+        global = true,
+        action = ArgAction::Count,
+        help = "Increase log verbosity (-v, -vv, -vvv), logs are written to stderr"
+    )]
+    pub verbose: u8,
+
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        global = true,
+        conflicts_with = "verbose",
+        help = "Only log errors, suppressing status messages such as 'Started consuming'"
+    )]
+    pub quiet: bool,
+
+    #[arg(
+        long = "log-format",
+        global = true,
+        value_enum,
+        default_value = "text",
+        help = "Format of the log output on stderr"
+    )]
+    pub log_format: LogFormat,
+
+    #[arg(
+        long = "errors-json",
+        global = true,
+        help = "Report failures of individual messages (send, acknowledgement, decode and input errors) as JSON lines on stderr"
+    )]
+    pub errors_json: bool,
+
+    #[arg(
+        long = "log-file",
+        global = true,
+        help = "Also write the logs as JSON lines to this file, at least at info level even with --quiet. Message data is never written to it"
+    )]
+    pub log_file: Option<String>,
+
+    #[arg(
+        long = "log-file-max-size",
+        global = true,
+        value_parser = parse_size,
+        default_value = "10M",
+        help = "Rotate the log file once it exceeds this size (e.g. '512K', '10M')"
+    )]
+    pub log_file_max_size: u64,
+
+    #[arg(
+        long = "log-file-keep",
+        global = true,
+        default_value = "5",
+        help = "Number of rotated log files to keep as <log-file>.1 (newest) to <log-file>.N"
     )]
-    pub broker: String,
+    pub log_file_keep: u32,
+
+    #[arg(
+        long = "shutdown-grace",
+        global = true,
+        value_parser = parse_duration,
+        default_value = "5s",
+        help = "On Ctrl-C, how long to wait for in-flight sends and acknowledgements before exiting"
+    )]
+    pub shutdown_grace: Duration,
+
+    #[arg(
+        long = "timeout",
+        required = false,
+        global = true,
+        value_parser = parse_duration,
+        help = "Shut down and fail when the whole invocation, connecting included, takes longer than this"
+    )]
+    pub timeout: Option<Duration>,
 
     #[command(subcommand)]
     pub command: OpMode,
 }
 
+/// Rewrite kcat style invocations such as `pulsar-cat -C -t topic -b broker -e`
+/// into the subcommand form: the first `-P`, `-C`, `-L` or `-Q` flag is replaced
+/// by the matching subcommand in front of all other arguments. Arguments
+/// without such a flag are returned unchanged.
+pub fn kcat_compat_args<I>(args: I) -> Vec<OsString>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut args: Vec<OsString> = args.into_iter().collect();
+    let mode = args
+        .iter()
+        .skip(1)
+        .take_while(|arg| *arg != "--")
+        .position(|arg| matches!(arg.to_str(), Some("-P" | "-C" | "-L" | "-Q")));
+    if let Some(i) = mode {
+        let subcommand = match args.remove(i + 1).to_str() {
+            Some("-P") => "produce",
+            Some("-C") => "consume",
+            Some("-L") => "list",
+            _ => "query",
+        };
+        args.insert(1, OsString::from(subcommand));
+    }
+    args
+}
+
+impl OpMode {
+    pub fn auth_mut(&mut self) -> Option<&mut AuthOpts> {
+        let auth = match self {
+            OpMode::Producer(opts) => &mut opts.auth,
+            OpMode::Consumer(opts) => &mut opts.auth,
+            OpMode::List(opts) => &mut opts.auth,
+            OpMode::Copy(opts) => &mut opts.auth,
+            OpMode::RequeueDlq(opts) => &mut opts.auth,
+            OpMode::Query(opts) => &mut opts.auth,
+            OpMode::Lookup(opts) => &mut opts.auth,
+            OpMode::Fetch(opts) => &mut opts.auth,
+            OpMode::KeyPartition(opts) => &mut opts.auth,
+            OpMode::Perf(opts) => &mut opts.auth,
+            OpMode::Lag(opts) => &mut opts.auth,
+            OpMode::Admin(opts) => &mut opts.auth,
+            OpMode::Ping(opts) => &mut opts.auth,
+            OpMode::DedupTest(opts) => &mut opts.auth,
+            OpMode::SimulateConsumers(opts) => &mut opts.auth,
+            OpMode::Mock(_) | OpMode::Auth(_) => return None,
+        };
+        Some(auth)
+    }
+
+    pub fn auth(&self) -> Option<&AuthOpts> {
+        let auth = match self {
+            OpMode::Producer(opts) => &opts.auth,
+            OpMode::Consumer(opts) => &opts.auth,
+            OpMode::List(opts) => &opts.auth,
+            OpMode::Copy(opts) => &opts.auth,
+            OpMode::RequeueDlq(opts) => &opts.auth,
+            OpMode::Query(opts) => &opts.auth,
+            OpMode::Lookup(opts) => &opts.auth,
+            OpMode::Fetch(opts) => &opts.auth,
+            OpMode::KeyPartition(opts) => &opts.auth,
+            OpMode::Perf(opts) => &opts.auth,
+            OpMode::Lag(opts) => &opts.auth,
+            OpMode::Admin(opts) => &opts.auth,
+            OpMode::Ping(opts) => &opts.auth,
+            OpMode::DedupTest(opts) => &opts.auth,
+            OpMode::SimulateConsumers(opts) => &opts.auth,
+            OpMode::Mock(_) | OpMode::Auth(_) => return None,
+        };
+        Some(auth)
+    }
+
+    /// Broker features the operation cannot do without.
+    pub fn required_broker_features(&self) -> Vec<BrokerFeature> {
+        let subscription_type = match self {
+            OpMode::Consumer(opts) => &opts.subscription_type,
+            OpMode::SimulateConsumers(opts) => &opts.subscription_type,
+            _ => return Vec::new(),
+        };
+        match subscription_type {
+            SubscriptionTypeOpt::KeyShared => vec![BrokerFeature::KeyShared],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Expand short topic names to `persistent://<namespace>/<topic>`.
+    /// `list` without a topic or namespace lists the default namespace.
+    pub fn apply_default_namespace(&mut self, namespace: &str) {
+        let expand = |topic: &mut TopicName| {
+            *topic = topic.clone().with_default_namespace(namespace);
+        };
+        let expand_template = |topic: &mut TopicTemplate| {
+            *topic = topic.clone().with_default_namespace(namespace);
+        };
+        match self {
+            OpMode::Producer(opts) => expand_template(&mut opts.topic),
+            OpMode::Consumer(opts) => {
+                if let Some(topic) = opts.topic.as_mut() {
+                    expand_template(topic);
+                }
+            }
+            OpMode::Copy(opts) => {
+                expand(&mut opts.source_topic);
+                expand(&mut opts.destination_topic);
+            }
+            OpMode::RequeueDlq(opts) => {
+                expand(&mut opts.dlq_topic);
+                if let Some(target) = opts.target.as_mut() {
+                    expand(target);
+                }
+                if let Some(origin_topic) = opts.origin_topic.as_mut() {
+                    expand(origin_topic);
+                }
+            }
+            OpMode::Query(opts) => expand(&mut opts.topic),
+            OpMode::Lookup(opts) => expand(&mut opts.topic),
+            OpMode::Fetch(opts) => expand(&mut opts.topic),
+            OpMode::KeyPartition(opts) => expand(&mut opts.topic),
+            OpMode::Perf(opts) => expand(&mut opts.topic),
+            OpMode::Ping(opts) => expand(&mut opts.topic),
+            OpMode::DedupTest(opts) => expand(&mut opts.topic),
+            OpMode::SimulateConsumers(opts) => expand(&mut opts.topic),
+            OpMode::Mock(_) | OpMode::Auth(_) => {}
+            OpMode::List(opts) => {
+                if let Some(topic) = opts.topic.as_mut() {
+                    expand(topic);
+                } else if opts.namespace.is_none() {
+                    opts.namespace = Some(namespace.to_owned());
+                }
+            }
+            OpMode::Lag(opts) => match &mut opts.command {
+                LagCommand::Export(export_opts) => expand(&mut export_opts.topic),
+                LagCommand::Stale(_) => {}
+                LagCommand::Apply(apply_opts) => {
+                    if let Some(topic) = apply_opts.topic.as_mut() {
+                        expand(topic);
+                    }
+                }
+            },
+            OpMode::Admin(opts) => match &mut opts.command {
+                AdminCommand::Topic(
+                    AdminTopicCommand::Unload(topic_opts)
+                    | AdminTopicCommand::Terminate(topic_opts),
+                ) => expand_template(&mut topic_opts.topic),
+                AdminCommand::Topic(AdminTopicCommand::Stats(stats_opts)) => {
+                    expand_template(&mut stats_opts.topic)
+                }
+                AdminCommand::Namespace(_) => {}
+                AdminCommand::Compaction(AdminCompactionCommand::Run(run_opts)) => {
+                    expand_template(&mut run_opts.topic)
+                }
+                AdminCommand::Compaction(AdminCompactionCommand::Status(status_opts)) => {
+                    expand_template(&mut status_opts.topic)
+                }
+            },
+        }
+    }
+}
+
 #[derive(Subcommand, Debug, Clone)]
 pub enum OpMode {
     /// Producer mode: send messages to a topic
@@ -30,11 +319,67 @@ pub enum OpMode {
 
     /// Consumer mode: read messages from a topic
     #[command(name = "consume", alias = "C")]
-    Consumer(ConsumerOpts),
+    Consumer(Box<ConsumerOpts>),
 
     /// List mode: view metadata about clusters, brokers, and topics
     #[command(name = "list", alias = "L")]
     List(ListOpts),
+
+    /// Copy mode: copy messages from one topic to another, possibly on another cluster
+    #[command(name = "copy")]
+    Copy(CopyOpts),
+
+    /// Requeue DLQ mode: republish dead-lettered messages to the topic they came from
+    #[command(name = "requeue-dlq")]
+    RequeueDlq(RequeueDlqOpts),
+
+    /// Query mode: find the first message ID at or after a timestamp, per partition
+    #[command(name = "query", alias = "Q")]
+    Query(QueryOpts),
+
+    /// Lookup mode: show the broker owning each partition of a topic
+    #[command(name = "lookup")]
+    Lookup(LookupOpts),
+
+    /// Fetch mode: print specific messages by message ID
+    #[command(name = "fetch")]
+    Fetch(FetchOpts),
+
+    /// Key partition mode: show which partition the producers' default router sends a key to
+    #[command(name = "key-partition")]
+    KeyPartition(KeyPartitionOpts),
+
+    /// Perf mode: benchmark produce/consume throughput and end-to-end latency
+    #[command(name = "perf")]
+    Perf(PerfOpts),
+
+    /// Lag mode: export subscription cursor positions and restore them
+    #[command(name = "lag")]
+    Lag(LagOpts),
+
+    /// Admin mode: manage topics through the Pulsar admin REST API
+    #[command(name = "admin")]
+    Admin(AdminOpts),
+
+    /// Ping mode: check that the cluster is reachable and report round-trip times
+    #[command(name = "ping")]
+    Ping(PingOpts),
+
+    /// Dedup test mode: check that broker-side deduplication drops duplicate messages
+    #[command(name = "dedup-test")]
+    DedupTest(DedupTestOpts),
+
+    /// Simulate consumers mode: show how a subscription dispatches messages and keys across several consumers
+    #[command(name = "simulate-consumers")]
+    SimulateConsumers(SimulateConsumersOpts),
+
+    /// Mock mode: replay a capture of consume --record through the output options of consume, without a broker
+    #[command(name = "mock")]
+    Mock(Box<MockOpts>),
+
+    /// Auth mode: store the token of a profile in the OS keychain
+    #[command(name = "auth", subcommand)]
+    Auth(AuthCommand),
 }
 
 #[derive(ValueEnum, Debug, Clone)]
@@ -44,11 +389,16 @@ enum AuthMethod {
 }
 
 #[derive(ValueEnum, Debug, Clone)]
-pub enum OffsetPosition {
-    #[value(alias = "beginning")]
-    Beginning,
-    #[value(alias = "end")]
-    End,
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum TopicDomain {
+    Persistent,
+    NonPersistent,
+    All,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -57,7 +407,7 @@ pub struct DisplayOpts {
         short = 'f',
         long = "format",
         required = false,
-        help = "Format to display messages in. Placeholders: %t=topic, %p=partition, %o=offset, %k=key, %s=payload, %S=size, %h=headers, %T=timestamp"
+        help = "Format to display messages in. Placeholders: %t=topic, %p=partition, %o=offset, %k=key, %s=payload, %S=size, %h=headers, %T=timestamp, %C=payload checksum, %v=schema version"
     )]
     pub format: Option<String>,
 
@@ -69,16 +419,303 @@ pub struct DisplayOpts {
         default_value = "false"
     )]
     pub json: bool,
+
+    #[arg(
+        long = "fields",
+        required = false,
+        requires = "json",
+        value_delimiter = ',',
+        value_parser = parse_message_field,
+        help = "Only put these fields in --json output, in this order, e.g. 'topic,key,payload,publish_time'. Fields a message does not have are left out"
+    )]
+    pub fields: Vec<MessageField>,
+
+    #[arg(
+        long = "escape-newlines",
+        required = false,
+        conflicts_with_all = ["json", "strip_newlines"],
+        help = "Print line breaks in payloads, keys and headers as \\n and \\r (and backslashes as \\\\), keeping one message per line",
+        default_value = "false"
+    )]
+    pub escape_newlines: bool,
+
+    #[arg(
+        long = "strip-newlines",
+        required = false,
+        conflicts_with = "json",
+        help = "Remove line breaks from payloads, keys and headers, keeping one message per line",
+        default_value = "false"
+    )]
+    pub strip_newlines: bool,
+
+    #[arg(
+        long = "pretty",
+        required = false,
+        conflicts_with_all = ["escape_newlines", "strip_newlines"],
+        help = "Indent payloads that are JSON objects or arrays, and CloudEvents, in plain and --format output. --json output stays one message per line",
+        default_value = "false"
+    )]
+    pub pretty: bool,
+
+    #[arg(
+        long = "sort-keys",
+        required = false,
+        requires = "pretty",
+        help = "With --pretty, order the members of JSON objects by name",
+        default_value = "false"
+    )]
+    pub sort_keys: bool,
+
+    #[arg(
+        long = "on-binary",
+        required = false,
+        value_enum,
+        default_value = "lossy",
+        help = "How to print payloads that are not valid UTF-8 in plain and --format output: 'lossy' replaces invalid bytes with U+FFFD, 'escape' writes them as \\xNN, 'base64' encodes the whole payload, 'skip' leaves the message out, 'fail' stops with an error"
+    )]
+    pub on_binary: BinaryModeOpt,
+
+    #[arg(
+        long = "checksum",
+        required = false,
+        value_enum,
+        help = "Add a 'checksum' field with the checksum of the payload to --json output, and use this algorithm for %C (default crc32c)"
+    )]
+    pub checksum: Option<ChecksumAlgorithmOpt>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ChecksumAlgorithmOpt {
+    Crc32c,
+    Sha256,
+}
+
+impl From<ChecksumAlgorithmOpt> for ChecksumAlgorithm {
+    fn from(opt: ChecksumAlgorithmOpt) -> Self {
+        match opt {
+            ChecksumAlgorithmOpt::Crc32c => ChecksumAlgorithm::Crc32c,
+            ChecksumAlgorithmOpt::Sha256 => ChecksumAlgorithm::Sha256,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum BinaryModeOpt {
+    Lossy,
+    Escape,
+    Base64,
+    Skip,
+    Fail,
+}
+
+impl DisplayOpts {
+    pub fn newline_mode(&self) -> NewlineMode {
+        if self.escape_newlines {
+            NewlineMode::Escape
+        } else if self.strip_newlines {
+            NewlineMode::Strip
+        } else {
+            NewlineMode::Keep
+        }
+    }
+
+    pub fn binary_mode(&self) -> BinaryMode {
+        match self.on_binary {
+            BinaryModeOpt::Lossy => BinaryMode::Lossy,
+            BinaryModeOpt::Escape => BinaryMode::Escape,
+            BinaryModeOpt::Base64 => BinaryMode::Base64,
+            BinaryModeOpt::Skip => BinaryMode::Skip,
+            BinaryModeOpt::Fail => BinaryMode::Fail,
+        }
+    }
+
+    /// Payload text as printed in plain and --format output, indented when
+    /// --pretty is given and it is JSON
+    pub fn payload_display<'a>(&self, payload: Cow<'a, str>) -> Cow<'a, str> {
+        if !self.pretty {
+            return payload;
+        }
+        match pretty_json(&payload, self.sort_keys) {
+            Some(pretty) => Cow::Owned(pretty),
+            None => payload,
+        }
+    }
+
+    pub fn checksum_algorithm(&self) -> ChecksumAlgorithm {
+        self.checksum.map(Into::into).unwrap_or_default()
+    }
+
+    /// JSON representation of a message as printed with --json
+    pub fn message_json<'a>(&'a self, msg: &ConsumedMessage<'a>) -> MessageJson<'a> {
+        MessageJson {
+            fields: (!self.fields.is_empty()).then_some(self.fields.as_slice()),
+            checksum: self
+                .checksum
+                .map(|algorithm| checksum(algorithm.into(), msg.payload)),
+            ..MessageJson::new(msg)
+        }
+    }
 }
 
 #[derive(Args, Debug, Clone)]
+pub struct TableOpts {
+    #[arg(
+        long = "no-header",
+        required = false,
+        help = "Do not print the header row of tabular output",
+        default_value = "false"
+    )]
+    pub no_header: bool,
+}
+
+#[derive(Args, Debug, Clone, Default)]
 pub struct AuthOpts {
     #[arg(
         long = "auth_token",
         required = false,
+        env = "PULSAR_AUTH_TOKEN",
+        hide_env_values = true,
         help = "Token for authentication"
     )]
     pub token: Option<String>,
+
+    #[arg(
+        long = "auth-token-file",
+        required = false,
+        env = "PULSAR_AUTH_TOKEN_FILE",
+        help = "Path to a file containing the token for authentication, re-read on every reconnect. Takes precedence over --auth_token"
+    )]
+    pub token_file: Option<String>,
+
+    #[arg(
+        long = "proxy-url",
+        required = false,
+        env = "PULSAR_PROXY_URL",
+        help = "Connect through the Pulsar proxy at this URL instead of directly to the broker"
+    )]
+    pub proxy_url: Option<String>,
+
+    #[command(flatten)]
+    pub tls: TlsOpts,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct TlsOpts {
+    #[arg(
+        long = "tls-ca-cert",
+        required = false,
+        env = "PULSAR_TLS_CA_CERT",
+        help = "Path to a PEM encoded CA certificate chain used to verify the broker"
+    )]
+    pub ca_cert: Option<String>,
+
+    // Not given is None, so that a profile enabling them can be overridden
+    // with =false
+    #[arg(
+        long = "tls-allow-insecure",
+        required = false,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        help = "Accept untrusted TLS certificates from the broker, '=false' to override a profile enabling it"
+    )]
+    pub allow_insecure: Option<bool>,
+
+    #[arg(
+        long = "tls-no-hostname-verification",
+        required = false,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        help = "Do not verify that the broker certificate matches its hostname, '=false' to override a profile enabling it"
+    )]
+    pub no_hostname_verification: Option<bool>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ReceiptFormatOpt {
+    /// The record index, message ID and latency, separated by spaces
+    Text,
+    /// One JSON object per message, with the key and any send error
+    Json,
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum CloudEventsModeOpt {
+    /// The payload is a JSON envelope holding attributes and data
+    Structured,
+    /// The payload is the data, attributes are sent as `ce_` properties
+    Binary,
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum PayloadDecompressOpt {
+    /// Detect gzip, zstd and lz4 payloads from their magic bytes
+    Auto,
+    Gzip,
+    Zstd,
+    Lz4,
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum SubscriptionTypeOpt {
+    Exclusive,
+    Shared,
+    Failover,
+    KeyShared,
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum LatencyTimeOpt {
+    /// Time the broker received the message
+    Publish,
+    /// Time set by the producing application
+    Event,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MaxMessageSizeOpt {
+    Bytes(u64),
+    /// The maxMessageSize of the broker
+    Broker,
+}
+
+/// Correlation ID property stamped on every produced message.
+#[derive(Debug, Clone)]
+pub struct TraceIdOpt {
+    pub property: String,
+    /// The same ID for every message, a random UUID per message when not given
+    pub value: Option<String>,
+}
+
+/// Correlation ID consumed messages are filtered by.
+#[derive(Debug, Clone)]
+pub struct TraceIdFilterOpt {
+    /// Property holding the ID, any property when not given
+    pub property: Option<String>,
+    pub value: String,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum KeyViolationOpt {
+    FailFast,
+    SkipWarn,
+    SendKeyless,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum CompareByOpt {
+    /// Hash of the key and payload, for clusters that replicate to each other
+    Payload,
+    /// Message ID, which only agrees between clusters sharing their storage,
+    /// replication assigns new IDs
+    MessageId,
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum InputFormatOpt {
+    Lines,
+    KcatJson,
 }
 
 #[derive(ValueEnum, Debug, Clone)]
@@ -100,9 +737,9 @@ pub struct ProducerOpts {
         short = 't',
         long = "topic",
         required = true,
-        help = "Topic to produce messages to, should be in the format of 'tenant/namespace/topic'"
+        help = "Topic to produce messages to, should be in the format of 'tenant/namespace/topic'. A template such as 'tenant/ns/events-{0..9}' sends every message to each of its topics"
     )]
-    pub topic: String,
+    pub topic: TopicTemplate,
 
     #[arg(
         long = "compression",
@@ -130,80 +767,2041 @@ pub struct ProducerOpts {
     )]
     pub enforce_key: bool,
 
-    #[command(flatten)]
-    pub auth: AuthOpts,
-}
+    #[arg(
+        long = "on-key-violation",
+        required = false,
+        requires = "enforce_key",
+        value_enum,
+        default_value = "skip-warn",
+        help = "What to do with a message without a key under --enforce_key: 'fail-fast' (stop reading input), 'skip-warn' (skip it, exiting with code 6 in the end) or 'send-keyless' (send it without a key)"
+    )]
+    pub on_key_violation: KeyViolationOpt,
 
-impl OpValidate for ProducerOpts {
-    fn validate(&self) -> Result<(), PulsarCatError> {
-        Ok(())
-    }
-}
+    #[arg(
+        long = "key-from-json-path",
+        required = false,
+        value_parser = JsonPath::parse,
+        conflicts_with_all = ["key", "input_avro", "replay_file"],
+        help = "Take the key of each message from this field of its JSON payload, e.g. '$.order.id'. Messages without it are sent without a key, or rejected with --enforce_key"
+    )]
+    pub key_from_json_path: Option<JsonPath>,
 
-#[derive(Args, Debug, Clone)]
-pub struct ConsumerOpts {
     #[arg(
-        short = 't',
-        long = "topic",
-        required = true,
-        help = "Topic to consume messages from, should be in the format of 'tenant/namespace/topic'"
+        long = "route-by-property",
+        required = false,
+        help = "Select the partition of each message by hashing the value of this property instead of the key, like the default router of the Java client. Messages without it are sent to the partitions in turn"
     )]
-    pub topic: String,
+    pub route_by_property: Option<String>,
 
     #[arg(
-        short = 'o',
-        long = "offset",
+        long = "hashing-scheme",
         required = false,
-        help = "Offset to start consuming from: 'beginning' or 'end'"
+        value_enum,
+        default_value = "java-string",
+        requires = "route_by_property",
+        help = "Hashing scheme applied to the --route-by-property value, as configured on the Java producers"
     )]
-    pub offset: Option<OffsetPosition>,
+    pub hashing_scheme: HashingSchemeOpt,
 
     #[arg(
-        short = 'e',
-        long = "exit",
+        long = "producers",
         required = false,
-        help = "Exit after consuming the last message",
-        default_value = "false"
+        default_value = "1",
+        conflicts_with = "route_by_property",
+        help = "Number of producers to spread the messages over in turn, to reach the throughput of a partitioned topic when backfilling. Messages are not kept in order"
     )]
-    pub exit: bool,
+    pub producers: u32,
 
-    #[command(flatten)]
-    pub auth: AuthOpts,
+    #[arg(
+        long = "pin-partitions",
+        required = false,
+        default_value = "false",
+        help = "Attach every producer to a single partition of a partitioned topic instead of to all of them, rounding --producers up to the same number for every partition"
+    )]
+    pub pin_partitions: bool,
 
-    #[command(flatten)]
-    pub display: DisplayOpts,
-}
+    #[arg(
+        long = "pipeline-buffer",
+        required = false,
+        default_value = "100",
+        help = "Number of input records read ahead of the producers. Lower it for multi-megabyte payloads to bound memory, raise it for small messages at high rates"
+    )]
+    pub pipeline_buffer: usize,
 
-#[derive(Args, Debug, Clone)]
-pub struct ListOpts {
-    #[command(flatten)]
-    pub auth: AuthOpts,
+    #[arg(
+        long = "max-pending",
+        required = false,
+        default_value = "1000",
+        help = "Number of messages sent without a receipt yet before reading pauses"
+    )]
+    pub max_pending: usize,
 
     #[arg(
-        long = "namespace",
+        long = "max-message-size",
         required = false,
-        help = "Namespace to list topics from, should be in the format of 'tenant/namespace'"
+        value_parser = parse_max_message_size,
+        help = "Reject messages whose payload, key and properties together are larger than this, e.g. '1M', instead of sending them. 'broker' takes the limit from the broker's configuration through the admin API"
     )]
-    pub namespace: Option<String>,
+    pub max_message_size: Option<MaxMessageSizeOpt>,
 
     #[arg(
-        short = 't',
-        long = "topic",
+        short = 'H',
+        long = "header",
         required = false,
-        help = "Topic to list messages from, should be in the format of 'tenant/namespace/topic'"
+        value_parser = parse_property,
+        help = "Property (NAME=VALUE) to set on every message, can be given multiple times. Overrides --properties-file"
     )]
-    pub topic: Option<String>,
-}
+    pub headers: Vec<(String, String)>,
 
-impl OpValidate for ListOpts {
-    fn validate(&self) -> Result<(), PulsarCatError> {
-        if !(self.namespace.is_some() || self.topic.is_some()) {
-            return Err(PulsarCatError::Application(anyhow::anyhow!(
-                "You must provide either a topic or a namespace.
-                If you want to list all topics in a namespace, use the --namespace flag.
-                If you want to list all partitons in a topic, use the --topic flag."
-            )));
-        }
-        Ok(())
+    #[arg(
+        long = "trace-id-property",
+        required = false,
+        value_parser = parse_trace_id_property,
+        value_name = "NAME[=VALUE|auto-uuid]",
+        help = "Stamp a correlation ID property on every message, a random UUID per message unless a VALUE is given, and print it with --print-receipts. Messages that already carry the property keep their ID. Find them with consume --trace-id"
+    )]
+    pub trace_id_property: Option<TraceIdOpt>,
+
+    #[arg(
+        long = "properties-file",
+        required = false,
+        help = "JSON file with an object of properties to set on every message, e.g. {\"region\": \"eu\"}"
+    )]
+    pub properties_file: Option<String>,
+
+    #[arg(
+        long = "require-existing-topic",
+        required = false,
+        help = "Fail if the topic does not exist, suggesting similar topic names, instead of letting the broker auto-create it",
+        default_value = "false"
+    )]
+    pub require_existing_topic: bool,
+
+    #[arg(
+        long = "transform",
+        required = false,
+        help = "Lua script whose transform(msg) function rewrites or drops messages before they are sent"
+    )]
+    pub transform: Option<String>,
+
+    #[arg(
+        long = "input-format",
+        required = false,
+        value_enum,
+        default_value = "lines",
+        conflicts_with_all = ["key", "enforce_key", "input_avro"],
+        help = "Format of the input on stdin: 'lines' (one message per line) or 'kcat-json' (messages printed by kcat -J, with key and headers)"
+    )]
+    pub input_format: InputFormatOpt,
+
+    #[arg(
+        long = "input-avro",
+        required = false,
+        conflicts_with_all = ["key", "enforce_key"],
+        help = "Replay the messages of an Avro archive written by consume --output-avro instead of reading stdin"
+    )]
+    pub input_avro: Option<String>,
+
+    #[arg(
+        long = "checksum",
+        required = false,
+        value_enum,
+        help = "Set the checksum of the payload as 'pulsar-cat-checksum' property on every message, to verify it with consume --verify-checksum"
+    )]
+    pub checksum: Option<ChecksumAlgorithmOpt>,
+
+    #[arg(
+        long = "replay-file",
+        required = false,
+        conflicts_with_all = ["key", "enforce_key", "input_avro", "input_format"],
+        help = "Replay the messages of a capture written by consume --json (one JSON message per line) instead of reading stdin"
+    )]
+    pub replay_file: Option<String>,
+
+    #[arg(
+        long = "respect-timing",
+        required = false,
+        requires = "replay_file",
+        num_args = 0..=1,
+        default_missing_value = "1",
+        value_name = "SPEED",
+        help = "Reproduce the gaps between the publish times of the replayed messages, sped up by the given factor (default 1, e.g. 2 replays twice as fast)"
+    )]
+    pub respect_timing: Option<f64>,
+
+    #[arg(
+        long = "listen-socket",
+        required = false,
+        conflicts_with_all = ["input_avro", "replay_file"],
+        help = "Listen on this UNIX domain socket instead of reading stdin, publishing the lines sent over every connection as messages until stopped (Unix only)"
+    )]
+    pub listen_socket: Option<String>,
+
+    #[arg(
+        long = "print-receipts",
+        required = false,
+        num_args = 0..=1,
+        default_missing_value = "text",
+        value_name = "FORMAT",
+        help = "Print the message ID and ack latency of every message to stdout, as 'text' (default) or 'json', in input order. Producers are attached to single partitions so the IDs include the partition"
+    )]
+    pub print_receipts: Option<ReceiptFormatOpt>,
+
+    #[arg(
+        long = "stats-interval",
+        required = false,
+        value_parser = parse_duration,
+        help = "Log the throughput, totals and failed messages every interval, e.g. '10s' (as JSON with --log-format json)"
+    )]
+    pub stats_interval: Option<Duration>,
+
+    #[arg(
+        long = "cloudevents",
+        required = false,
+        num_args = 0..=1,
+        default_missing_value = "structured",
+        help = "Wrap payloads in a CloudEvents envelope, in 'structured' (default) or 'binary' content mode"
+    )]
+    pub cloudevents: Option<CloudEventsModeOpt>,
+
+    #[arg(
+        long = "ce-type",
+        required = false,
+        requires = "cloudevents",
+        default_value = "pulsar-cat.message",
+        help = "CloudEvents 'type' attribute of produced events"
+    )]
+    pub ce_type: String,
+
+    #[arg(
+        long = "ce-source",
+        required = false,
+        requires = "cloudevents",
+        help = "CloudEvents 'source' attribute of produced events, the topic when not given"
+    )]
+    pub ce_source: Option<String>,
+
+    #[arg(
+        long = "replicate-to",
+        required = false,
+        value_delimiter = ',',
+        help = "Comma-separated clusters to replicate the messages to, overriding the namespace's replication clusters"
+    )]
+    pub replicate_to: Vec<String>,
+
+    #[arg(
+        long = "disable-replication",
+        required = false,
+        conflicts_with = "replicate_to",
+        help = "Keep the messages in the local cluster instead of geo-replicating them",
+        default_value = "false"
+    )]
+    pub disable_replication: bool,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+}
+
+impl OpValidate for ProducerOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        expand_topics(&self.topic)?;
+        if self.enforce_key && self.key.is_none() && self.key_from_json_path.is_none() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--enforce_key requires a key delimiter to be set with --key, e.g. --key ':', or --key-from-json-path"
+            )));
+        }
+        if self.key.as_deref() == Some("") {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "The --key delimiter must not be empty"
+            )));
+        }
+        if let Some(speed) = self.respect_timing
+            && !(speed > 0.0 && speed.is_finite())
+        {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "The --respect-timing speed factor must be a positive number, got {}",
+                speed
+            )));
+        }
+        if self.producers == 0 {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--producers must be at least 1"
+            )));
+        }
+        if self.pipeline_buffer == 0 {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--pipeline-buffer must be at least 1"
+            )));
+        }
+        if self.max_pending == 0 {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--max-pending must be at least 1"
+            )));
+        }
+        if self
+            .stats_interval
+            .is_some_and(|interval| interval.is_zero())
+        {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--stats-interval must be greater than 0"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConsumerOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        help = "Topic to consume messages from, should be in the format of 'tenant/namespace/topic'. A template such as 'tenant/ns/events-{0..9}' consumes all of its topics"
+    )]
+    pub topic: Option<TopicTemplate>,
+
+    #[arg(
+        long = "namespace",
+        required = false,
+        conflicts_with_all = ["topic", "require_existing_topic"],
+        help = "Consume all topics of this namespace instead of a single topic, should be in the format of 'tenant/namespace'"
+    )]
+    pub namespace: Option<String>,
+
+    #[arg(
+        long = "topic-refresh",
+        required = false,
+        value_parser = parse_duration,
+        help = "With --namespace, also consume topics created while consuming, looking for them this often, e.g. '30s'"
+    )]
+    pub topic_refresh: Option<Duration>,
+
+    #[arg(
+        short = 'o',
+        long = "offset",
+        alias = "from-message-id",
+        required = false,
+        allow_negative_numbers = true,
+        value_parser = parse_offset,
+        help = "Position to start consuming from: 'beginning', 'end', '-N' (N entries before the end), a timestamp (RFC3339 or epoch milliseconds) or a message ID ('ledger:entry[:partition[:batch]]', as printed by 'query')"
+    )]
+    pub offset: Option<StartPosition>,
+
+    #[arg(
+        short = 'e',
+        long = "exit",
+        required = false,
+        help = "Exit after consuming the last message",
+        default_value = "false"
+    )]
+    pub exit: bool,
+
+    #[arg(
+        short = 'c',
+        long = "count",
+        required = false,
+        help = "Exit after consuming this many messages"
+    )]
+    pub count: Option<u64>,
+
+    #[arg(
+        long = "max-bytes",
+        required = false,
+        value_parser = parse_size,
+        help = "Exit once the consumed payloads add up to this size, e.g. '10M' or '1G'"
+    )]
+    pub max_bytes: Option<u64>,
+
+    #[arg(
+        long = "for",
+        required = false,
+        value_parser = parse_duration,
+        help = "Exit after consuming for this long, e.g. '30s' or '5m', regardless of message counts and idle time"
+    )]
+    pub for_duration: Option<Duration>,
+
+    #[arg(
+        long = "stats-interval",
+        required = false,
+        value_parser = parse_duration,
+        help = "Log the throughput, totals, failed messages and the lag behind the latest publish time every interval, e.g. '10s' (as JSON with --log-format json)"
+    )]
+    pub stats_interval: Option<Duration>,
+
+    #[arg(
+        long = "transform",
+        required = false,
+        help = "Lua script whose transform(msg) function rewrites or drops messages before they are output"
+    )]
+    pub transform: Option<String>,
+
+    #[arg(
+        long = "output-avro",
+        required = false,
+        conflicts_with_all = ["json", "format"],
+        help = "Archive consumed messages with their metadata to this Avro object container file instead of printing them"
+    )]
+    pub output_avro: Option<String>,
+
+    #[arg(
+        long = "record",
+        required = false,
+        conflicts_with = "compare_broker",
+        help = "Also record every consumed message as received, with its metadata, to this capture file (e.g. capture.pcatr) for mock --replay"
+    )]
+    pub record: Option<String>,
+
+    #[arg(
+        long = "output-dir-per-partition",
+        required = false,
+        conflicts_with_all = ["format", "output_avro", "cloudevents", "summarize_keys", "histogram", "post_url", "show_encoding"],
+        help = "Write the messages of every partition as JSON lines to its own file in this directory (e.g. 'orders-partition-3.ndjson') instead of printing them, appending to existing files"
+    )]
+    pub output_dir_per_partition: Option<String>,
+
+    #[arg(
+        long = "reopen-on-sighup",
+        required = false,
+        requires = "output_avro",
+        help = "On SIGHUP, start a new Avro archive if the current one was moved away (e.g. by logrotate) instead of shutting down (Unix only)",
+        default_value = "false"
+    )]
+    pub reopen_on_sighup: bool,
+
+    #[arg(
+        long = "cloudevents",
+        required = false,
+        conflicts_with_all = ["format", "output_avro"],
+        help = "Render CloudEvents (binary or structured content mode) as JSON events with their attributes",
+        default_value = "false"
+    )]
+    pub cloudevents: bool,
+
+    #[arg(
+        long = "payload-decompress",
+        required = false,
+        num_args = 0..=1,
+        default_missing_value = "auto",
+        help = "Decompress payloads compressed by the producing application before output: 'auto' (default), 'gzip', 'zstd' or 'lz4'"
+    )]
+    pub payload_decompress: Option<PayloadDecompressOpt>,
+
+    #[arg(
+        long = "state-file",
+        required = false,
+        help = "JSON file recording the last processed message per partition, consuming resumes from it on restart"
+    )]
+    pub state_file: Option<String>,
+
+    #[arg(
+        long = "show-markers",
+        required = false,
+        help = "Display transaction commit/abort and other markers written by the broker instead of skipping them",
+        default_value = "false"
+    )]
+    pub show_markers: bool,
+
+    #[arg(
+        long = "skip-replication-markers",
+        required = false,
+        requires = "show_markers",
+        help = "With --show-markers, keep skipping the snapshot markers of replicated subscriptions",
+        default_value = "false"
+    )]
+    pub skip_replication_markers: bool,
+
+    #[arg(
+        long = "show-replicated-from",
+        required = false,
+        conflicts_with = "json",
+        help = "Prefix messages replicated from another cluster with '[<cluster>] ', --json output always contains replicated_from",
+        default_value = "false"
+    )]
+    pub show_replicated_from: bool,
+
+    #[arg(
+        long = "show-encoding",
+        required = false,
+        conflicts_with_all = ["format", "transform", "output_avro", "cloudevents", "summarize_keys", "histogram", "post_url"],
+        help = "Instead of payloads, print the compression codec, batch membership and uncompressed and wire size of every message. The wire size is read from the admin API",
+        default_value = "false"
+    )]
+    pub show_encoding: bool,
+
+    #[arg(
+        long = "verify-checksum",
+        required = false,
+        help = "Verify payloads against the checksum set by produce --checksum, reporting mismatches. Messages without a checksum are passed through",
+        default_value = "false"
+    )]
+    pub verify_checksum: bool,
+
+    #[arg(
+        long = "require-existing-topic",
+        required = false,
+        help = "Fail if the topic does not exist, suggesting similar topic names, instead of letting the broker auto-create it",
+        default_value = "false"
+    )]
+    pub require_existing_topic: bool,
+
+    #[arg(
+        long = "wait-for-topic",
+        required = false,
+        value_parser = parse_duration,
+        conflicts_with = "namespace",
+        help = "Wait up to this long for the topic to be created, e.g. '30s', instead of subscribing right away, which fails or auto-creates it"
+    )]
+    pub wait_for_topic: Option<Duration>,
+
+    #[arg(
+        long = "flush-every",
+        required = false,
+        conflicts_with = "unbuffered",
+        help = "Flush stdout after every N messages, by default buffered output is flushed every 100ms"
+    )]
+    pub flush_every: Option<u64>,
+
+    #[arg(
+        short = 'u',
+        long = "unbuffered",
+        required = false,
+        help = "Flush stdout after every message, the default when stdout is a terminal",
+        default_value = "false"
+    )]
+    pub unbuffered: bool,
+
+    #[arg(
+        long = "latency",
+        required = false,
+        num_args = 0..=1,
+        default_missing_value = "publish",
+        help = "Measure the latency from each message's 'publish' (default) or 'event' time to its receipt, percentiles are logged at exit"
+    )]
+    pub latency: Option<LatencyTimeOpt>,
+
+    #[arg(
+        long = "latency-out",
+        required = false,
+        requires = "latency",
+        help = "Also write the latency distribution to this file, as CSV for a '.csv' file and in HdrHistogram's '.hgrm' format otherwise"
+    )]
+    pub latency_out: Option<String>,
+
+    #[arg(
+        long = "summarize-keys",
+        required = false,
+        num_args = 0..=1,
+        default_missing_value = "10",
+        conflicts_with_all = ["format", "output_avro", "cloudevents"],
+        help = "Instead of printing messages, report the message count and payload bytes of the top N keys (10 by default) at exit"
+    )]
+    pub summarize_keys: Option<usize>,
+
+    #[arg(
+        long = "histogram",
+        required = false,
+        value_parser = parse_duration,
+        conflicts_with_all = ["format", "output_avro", "cloudevents", "summarize_keys"],
+        help = "Instead of printing messages, count messages and payload bytes per interval of their publish time, e.g. '1m', and print them at exit"
+    )]
+    pub histogram: Option<Duration>,
+
+    #[arg(
+        long = "snapshot",
+        required = false,
+        conflicts_with_all = ["offset", "namespace", "state_file", "output_avro", "post_url", "output_dir_per_partition", "summarize_keys", "histogram", "show_encoding", "cloudevents"],
+        help = "Read the compacted view of the topic from the beginning up to its last message, then print the latest message of every key in key order, as 'key<TAB>payload' lines unless --format or --json is given. An empty payload deletes its key",
+        default_value = "false"
+    )]
+    pub snapshot: bool,
+
+    #[arg(
+        long = "compare-broker",
+        required = false,
+        conflicts_with_all = ["snapshot", "state_file", "transform", "output_avro", "post_url", "output_dir_per_partition", "summarize_keys", "histogram", "show_encoding", "cloudevents", "latency", "json", "format"],
+        help = "Consume the topic from this second cluster as well and print the messages only one of the clusters has, to verify geo-replication. Connects with the same authentication options"
+    )]
+    pub compare_broker: Option<String>,
+
+    #[arg(
+        long = "compare-by",
+        required = false,
+        requires = "compare_broker",
+        default_value = "payload",
+        help = "How messages of the two clusters are matched"
+    )]
+    pub compare_by: CompareByOpt,
+
+    #[arg(
+        long = "compare-window",
+        required = false,
+        requires = "compare_broker",
+        value_parser = parse_duration,
+        default_value = "30s",
+        help = "How long a message may be missing on the other cluster before it is reported, e.g. '30s' or '5m'"
+    )]
+    pub compare_window: Duration,
+
+    #[command(flatten)]
+    pub table: TableOpts,
+
+    #[arg(
+        long = "sample",
+        required = false,
+        value_parser = parse_sample_ratio,
+        help = "Only output N of every M messages, given as 'N/M' (e.g. '1/100')"
+    )]
+    pub sample: Option<(u64, u64)>,
+
+    #[arg(
+        long = "sample-probability",
+        required = false,
+        conflicts_with = "sample",
+        help = "Only output each message with this probability, e.g. 0.01"
+    )]
+    pub sample_probability: Option<f64>,
+
+    #[arg(
+        long = "sample-no-ack",
+        required = false,
+        help = "Leave messages that were not sampled unacknowledged instead of acknowledging them",
+        default_value = "false"
+    )]
+    pub sample_no_ack: bool,
+
+    #[arg(
+        long = "trace-id",
+        required = false,
+        value_parser = parse_trace_id,
+        help = "Only output messages with this value in property NAME, given as NAME=VALUE, e.g. the correlation ID stamped by produce --trace-id-property, to follow a single message. A VALUE without NAME matches the value of any property"
+    )]
+    pub trace_id: Option<TraceIdFilterOpt>,
+
+    #[arg(
+        short = 's',
+        long = "subscription",
+        required = false,
+        help = "Consume through this subscription, which is created when it does not exist, instead of a fresh one"
+    )]
+    pub subscription: Option<String>,
+
+    #[arg(
+        long = "subscription-type",
+        required = false,
+        value_enum,
+        default_value = "exclusive",
+        help = "Type of the subscription, 'shared' and 'key-shared' let pulsar-cat consume alongside other consumers"
+    )]
+    pub subscription_type: SubscriptionTypeOpt,
+
+    #[arg(
+        long = "priority-level",
+        required = false,
+        help = "Dispatch priority of this consumer within a shared or failover subscription, 0 is the highest. \
+            The broker only dispatches to consumers of a lower priority when those of a higher one have no permits left"
+    )]
+    pub priority_level: Option<i32>,
+
+    #[arg(
+        long = "consumer-name",
+        required = false,
+        help = "Name of the consumer, shown in the topic stats of the broker (default: pulsar-cat-<random>)"
+    )]
+    pub consumer_name: Option<String>,
+
+    #[arg(
+        long = "consumer-metadata",
+        required = false,
+        value_parser = parse_property,
+        help = "Metadata property (NAME=VALUE) of the consumer, shown in the topic stats of the broker, can be given multiple times"
+    )]
+    pub consumer_metadata: Vec<(String, String)>,
+
+    #[arg(
+        long = "ack",
+        required = false,
+        default_value = "auto",
+        value_parser = parse_ack_strategy,
+        help = "When to acknowledge messages: 'auto' (each after processing), 'on-exit', 'manual-interval=N' (after every N messages) or 'none' (leave them in the backlog)"
+    )]
+    pub ack: AckStrategy,
+
+    #[arg(
+        long = "ack-grouping-time-ms",
+        required = false,
+        help = "With --ack auto, hold acknowledgements back for up to this many milliseconds and send them together (default 100 when --ack-grouping-size is given)"
+    )]
+    pub ack_grouping_time_ms: Option<u64>,
+
+    #[arg(
+        long = "ack-grouping-size",
+        required = false,
+        help = "With --ack auto, hold up to this many acknowledgements back and send them together (default 1000 when --ack-grouping-time-ms is given)"
+    )]
+    pub ack_grouping_size: Option<u64>,
+
+    #[arg(
+        long = "on-error",
+        required = false,
+        default_value = "exit",
+        value_parser = parse_error_policy,
+        help = "What to do when a message cannot be received, decoded or formatted: 'exit', 'skip' (log it and continue) or 'retry=N' (try N more times, then exit). Connection losses are always reconnected"
+    )]
+    pub on_error: ErrorPolicy,
+
+    #[arg(
+        long = "nack-redelivery-delay",
+        required = false,
+        value_parser = parse_duration,
+        help = "With --on-error skip, negatively acknowledge skipped messages after this delay, e.g. '1s' or '1m', so the broker redelivers them. Without it they stay unacknowledged, and only a --subscription gets them again, after a reconnect or on its next run"
+    )]
+    pub nack_redelivery_delay: Option<Duration>,
+
+    #[arg(
+        long = "on-ack-failure",
+        required = false,
+        default_value = "ignore",
+        value_parser = parse_ack_failure_policy,
+        help = "What to do when acknowledging a message fails: 'ignore' (log it and continue), 'exit' or 'retry=N' (try N more times with a growing delay, then log it and continue). Failures given up on end consuming with exit code 6, the broker redelivers the messages later"
+    )]
+    pub on_ack_failure: AckFailurePolicy,
+
+    #[arg(
+        long = "post-url",
+        required = false,
+        conflicts_with_all = ["json", "format", "output_avro", "summarize_keys", "histogram", "cloudevents"],
+        help = "POST each message to this URL instead of printing it, with the payload as the body and the metadata as X-Pulsar-* headers"
+    )]
+    pub post_url: Option<String>,
+
+    #[arg(
+        long = "post-concurrency",
+        required = false,
+        requires = "post_url",
+        default_value = "1",
+        help = "Number of requests to --post-url in flight at once, more than 1 does not keep the message order"
+    )]
+    pub post_concurrency: u32,
+
+    #[arg(
+        long = "post-retries",
+        required = false,
+        requires = "post_url",
+        default_value = "3",
+        help = "Retries of a request to --post-url failing with a connection error, 429 or a 5xx status"
+    )]
+    pub post_retries: u32,
+
+    #[arg(
+        long = "post-timeout",
+        required = false,
+        requires = "post_url",
+        value_parser = parse_duration,
+        default_value = "10s",
+        help = "Timeout of a single request to --post-url"
+    )]
+    pub post_timeout: Duration,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+
+    #[command(flatten)]
+    pub display: DisplayOpts,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct MockOpts {
+    #[arg(
+        long = "replay",
+        required = true,
+        help = "Capture written by consume --record to replay"
+    )]
+    pub replay: String,
+
+    /// Output, filter and transform options as for consume. Options that
+    /// need a broker, such as --topic or --subscription, are rejected.
+    #[command(flatten)]
+    pub consume: ConsumerOpts,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListOpts {
+    #[command(flatten)]
+    pub auth: AuthOpts,
+
+    #[arg(
+        long = "namespace",
+        required = false,
+        help = "Namespace to list topics from, should be in the format of 'tenant/namespace'"
+    )]
+    pub namespace: Option<String>,
+
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = false,
+        help = "Topic to list messages from, should be in the format of 'tenant/namespace/topic'"
+    )]
+    pub topic: Option<TopicName>,
+
+    #[arg(
+        long = "domain",
+        required = false,
+        help = "Only list topics of the given domain when listing a namespace",
+        default_value = "all"
+    )]
+    pub domain: TopicDomain,
+
+    #[arg(
+        long = "filter",
+        required = false,
+        help = "Only list topics whose name matches this glob pattern (e.g. 'orders-*') when listing a namespace"
+    )]
+    pub filter: Option<String>,
+
+    #[arg(
+        long = "regex",
+        required = false,
+        help = "Interpret --filter as a regular expression instead of a glob pattern",
+        default_value = "false"
+    )]
+    pub regex: bool,
+
+    #[arg(
+        long = "show-listener",
+        required = false,
+        help = "Display the broker addresses advertised for this listener instead of the default ones, resolved through the admin API. Only changes what is displayed, connections still use the default listener"
+    )]
+    pub show_listener: Option<String>,
+
+    #[command(flatten)]
+    pub table: TableOpts,
+}
+
+impl OpValidate for ListOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        if !(self.namespace.is_some() || self.topic.is_some()) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "You must provide either a topic or a namespace.
+                If you want to list all topics in a namespace, use the --namespace flag.
+                If you want to list all partitons in a topic, use the --topic flag."
+            )));
+        }
+        if self.namespace.is_some() && self.topic.is_some() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "Use either --topic or --namespace, not both"
+            )));
+        }
+        if let Some(topic) = &self.topic {
+            validate_topic(topic)?;
+        }
+        if let Some(namespace) = &self.namespace {
+            validate_namespace(namespace)?;
+        }
+        if self.regex && self.filter.is_none() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--regex requires a pattern to be given with --filter"
+            )));
+        }
+        if self.topic.is_some() && self.filter.is_some() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--filter only applies when listing a namespace"
+            )));
+        }
+        if self.namespace.is_some() && self.show_listener.is_some() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--show-listener only applies when listing a topic"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CopyOpts {
+    #[arg(
+        short = 't',
+        long = "source-topic",
+        required = true,
+        help = "Topic to copy messages from, should be in the format of 'tenant/namespace/topic'"
+    )]
+    pub source_topic: TopicName,
+
+    #[arg(
+        short = 'd',
+        long = "destination-topic",
+        required = true,
+        help = "Topic to copy messages to, should be in the format of 'tenant/namespace/topic'"
+    )]
+    pub destination_topic: TopicName,
+
+    #[arg(
+        short = 's',
+        long = "subscription",
+        required = false,
+        help = "Durable subscription to read the source topic with. \
+            Without it, a non-durable subscription starting at the earliest message is used"
+    )]
+    pub subscription: Option<String>,
+
+    #[arg(
+        long = "destination-broker",
+        required = false,
+        help = "Broker URL of the destination cluster, defaults to the source broker"
+    )]
+    pub destination_broker: Option<String>,
+
+    #[arg(
+        long = "destination-profile",
+        required = false,
+        help = "Profile from the configuration file providing the destination broker and credentials"
+    )]
+    pub destination_profile: Option<String>,
+
+    // Credentials for the destination cluster, resolved from --destination-profile
+    #[arg(skip)]
+    pub destination_auth: Option<AuthOpts>,
+
+    #[arg(
+        long = "from-timestamp",
+        required = false,
+        value_parser = parse_timestamp,
+        help = "Only copy messages published at or after this time (RFC3339 or epoch milliseconds)"
+    )]
+    pub from_timestamp: Option<u64>,
+
+    #[arg(
+        long = "until",
+        required = false,
+        value_parser = parse_timestamp,
+        help = "Stop at the first message published after this time (RFC3339 or epoch milliseconds)"
+    )]
+    pub until: Option<u64>,
+
+    #[arg(
+        short = 'c',
+        long = "count",
+        required = false,
+        help = "Stop after copying this many messages"
+    )]
+    pub count: Option<u64>,
+
+    #[arg(
+        short = 'e',
+        long = "exit",
+        required = false,
+        help = "Exit once no new messages arrive on the source topic",
+        default_value = "false"
+    )]
+    pub exit: bool,
+
+    #[arg(
+        long = "filter",
+        required = false,
+        help = "Only copy messages whose payload matches this regular expression"
+    )]
+    pub filter: Option<String>,
+
+    #[arg(
+        long = "transform",
+        required = false,
+        help = "Lua script whose transform(msg) function rewrites or drops messages before they are copied"
+    )]
+    pub transform: Option<String>,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+}
+
+impl OpValidate for CopyOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        validate_topic(&self.source_topic)?;
+        validate_topic(&self.destination_topic)?;
+        let same_cluster = self.destination_broker.is_none() && self.destination_profile.is_none();
+        if same_cluster && self.source_topic == self.destination_topic {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "Source and destination topic are the same, \
+                use --destination-broker or --destination-profile to copy to another cluster"
+            )));
+        }
+        if let (Some(from), Some(until)) = (self.from_timestamp, self.until)
+            && from > until
+        {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--from-timestamp must not be later than --until"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RequeueDlqOpts {
+    #[arg(
+        short = 't',
+        long = "dlq-topic",
+        required = true,
+        help = "Dead letter topic to drain, should be in the format of 'tenant/namespace/topic'"
+    )]
+    pub dlq_topic: TopicName,
+
+    #[arg(
+        short = 'd',
+        long = "target",
+        required = false,
+        help = "Topic to republish all messages to, instead of the origin topic recorded in their REAL_TOPIC property"
+    )]
+    pub target: Option<TopicName>,
+
+    #[arg(
+        short = 's',
+        long = "subscription",
+        required = false,
+        default_value = "pulsar-cat-requeue-dlq",
+        help = "Durable subscription to read the dead letter topic with, requeued messages are acknowledged on it"
+    )]
+    pub subscription: String,
+
+    #[arg(
+        long = "origin-topic",
+        required = false,
+        help = "Only requeue messages dead-lettered from this topic (or one of its partitions)"
+    )]
+    pub origin_topic: Option<TopicName>,
+
+    #[arg(
+        short = 'k',
+        long = "key",
+        required = false,
+        help = "Only requeue messages with this key, can be given multiple times"
+    )]
+    pub keys: Vec<String>,
+
+    #[arg(
+        long = "property",
+        required = false,
+        value_parser = parse_property,
+        help = "Only requeue messages with this property (NAME=VALUE), can be given multiple times and all must match"
+    )]
+    pub properties: Vec<(String, String)>,
+
+    #[arg(
+        long = "until",
+        required = false,
+        value_parser = parse_timestamp,
+        help = "Stop at the first message dead-lettered after this time (RFC3339 or epoch milliseconds)"
+    )]
+    pub until: Option<u64>,
+
+    #[arg(
+        short = 'c',
+        long = "count",
+        required = false,
+        help = "Stop after requeuing this many messages"
+    )]
+    pub count: Option<u64>,
+
+    #[arg(
+        long = "dry-run",
+        required = false,
+        help = "Print the messages that would be requeued and their target without republishing or acknowledging them",
+        default_value = "false"
+    )]
+    pub dry_run: bool,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+
+    #[command(flatten)]
+    pub table: TableOpts,
+}
+
+impl OpValidate for RequeueDlqOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        validate_topic(&self.dlq_topic)?;
+        for topic in [&self.target, &self.origin_topic].into_iter().flatten() {
+            validate_topic(topic)?;
+        }
+        if self.target.as_ref() == Some(&self.dlq_topic) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--target must not be the dead letter topic itself"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct QueryOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Topic to query, should be in the format of 'tenant/namespace/topic'"
+    )]
+    pub topic: TopicName,
+
+    #[arg(
+        short = 'T',
+        long = "timestamp",
+        required = true,
+        value_parser = parse_timestamp,
+        help = "Publish time to look up (RFC3339 or epoch milliseconds)"
+    )]
+    pub timestamp: u64,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+
+    #[command(flatten)]
+    pub table: TableOpts,
+}
+
+impl OpValidate for QueryOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        validate_topic(&self.topic)
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LookupOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Topic to look up, should be in the format of 'tenant/namespace/topic'"
+    )]
+    pub topic: TopicName,
+
+    #[arg(
+        short = 'c',
+        long = "count",
+        required = false,
+        default_value = "1",
+        help = "Number of lookups per partition, reporting min/avg/max lookup time"
+    )]
+    pub count: u32,
+
+    #[arg(
+        short = 'J',
+        long = "json",
+        required = false,
+        help = "Output one JSON object per partition instead of a table",
+        default_value = "false"
+    )]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub table: TableOpts,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+}
+
+impl OpValidate for LookupOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        validate_topic(&self.topic)?;
+        if self.count == 0 {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--count must be at least 1"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct FetchOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Topic to fetch from, should be in the format of 'tenant/namespace/topic'"
+    )]
+    pub topic: TopicName,
+
+    #[arg(
+        short = 'm',
+        long = "message-id",
+        required = false,
+        value_parser = parse_message_id,
+        help = "Message ID to fetch (ledger:entry[:partition[:batch]]), can be given multiple times. Read one per line from stdin when not given"
+    )]
+    pub message_ids: Vec<MessageIdData>,
+
+    #[command(flatten)]
+    pub display: DisplayOpts,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+}
+
+impl OpValidate for FetchOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        validate_topic(&self.topic)?;
+        if self.display.json && self.display.format.is_some() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--json and --format cannot be used together"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum HashingSchemeOpt {
+    /// Java's String.hashCode(), the default of the Java and Go clients
+    JavaString,
+    /// Murmur3 32-bit hash
+    Murmur3,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct KeyPartitionOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Partitioned topic, should be in the format of 'tenant/namespace/topic'"
+    )]
+    pub topic: TopicName,
+
+    #[arg(
+        short = 'k',
+        long = "key",
+        required = true,
+        help = "Message key to map to a partition, can be given multiple times"
+    )]
+    pub keys: Vec<String>,
+
+    #[arg(
+        long = "hashing-scheme",
+        required = false,
+        value_enum,
+        default_value = "java-string",
+        help = "Hashing scheme of the producers' partition router"
+    )]
+    pub hashing_scheme: HashingSchemeOpt,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+
+    #[command(flatten)]
+    pub table: TableOpts,
+}
+
+impl OpValidate for KeyPartitionOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        validate_topic(&self.topic)?;
+        if self.topic.partition().is_some() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "Topic '{}' is a single partition, keys are routed across the partitions of its partitioned topic",
+                self.topic
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct PingOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = false,
+        default_value = "public/default/pulsar-cat-ping",
+        help = "Probe topic to look up, it does not need to exist but its namespace does"
+    )]
+    pub topic: TopicName,
+
+    #[arg(
+        short = 'c',
+        long = "count",
+        required = false,
+        default_value = "3",
+        help = "Number of topic lookups to time"
+    )]
+    pub count: u32,
+
+    #[arg(
+        long = "health-check",
+        required = false,
+        help = "Also run the broker health check, which produces and consumes on the broker's heartbeat topic",
+        default_value = "false"
+    )]
+    pub health_check: bool,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+
+    #[command(flatten)]
+    pub table: TableOpts,
+}
+
+impl OpValidate for PingOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        validate_topic(&self.topic)?;
+        if self.count == 0 {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--count must be at least 1"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DedupTestOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Topic to test, should be in the format of 'tenant/namespace/topic'. The test messages stay on it"
+    )]
+    pub topic: TopicName,
+
+    #[arg(
+        short = 'c',
+        long = "count",
+        required = false,
+        default_value = "10",
+        help = "Number of messages in the test sequence, each is sent twice"
+    )]
+    pub count: u64,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+}
+
+impl OpValidate for DedupTestOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        validate_topic(&self.topic)?;
+        if !self.topic.is_persistent() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "Deduplication only applies to persistent topics, {} is non-persistent",
+                self.topic
+            )));
+        }
+        if self.count == 0 {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--count must be at least 1"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SimulateConsumersOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Topic to consume from, should be in the format of 'tenant/namespace/topic'"
+    )]
+    pub topic: TopicName,
+
+    #[arg(
+        short = 's',
+        long = "subscription",
+        required = true,
+        help = "Subscription the consumers share, it is created when it does not exist. The consumers acknowledge what they receive"
+    )]
+    pub subscription: String,
+
+    #[arg(
+        short = 'n',
+        long = "instances",
+        required = false,
+        default_value = "2",
+        help = "Number of consumers to start"
+    )]
+    pub instances: u32,
+
+    #[arg(
+        long = "subscription-type",
+        required = false,
+        value_enum,
+        default_value = "shared",
+        help = "Type of the subscription"
+    )]
+    pub subscription_type: SubscriptionTypeOpt,
+
+    #[arg(
+        short = 'c',
+        long = "count",
+        required = false,
+        help = "Stop after the consumers received this many messages in total"
+    )]
+    pub count: Option<u64>,
+
+    #[arg(
+        long = "for",
+        required = false,
+        value_parser = parse_duration,
+        help = "Stop after consuming for this long, e.g. '30s'. Without --count or --for the simulation runs until interrupted"
+    )]
+    pub for_duration: Option<Duration>,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+
+    #[command(flatten)]
+    pub table: TableOpts,
+}
+
+impl OpValidate for SimulateConsumersOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        validate_topic(&self.topic)?;
+        if self.instances == 0 {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--instances must be at least 1"
+            )));
+        }
+        if self.instances > 1 && matches!(self.subscription_type, SubscriptionTypeOpt::Exclusive) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "An exclusive subscription admits a single consumer, use another --subscription-type"
+            )));
+        }
+        if self.count == Some(0) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--count must be at least 1"
+            )));
+        }
+        if self.for_duration.is_some_and(|duration| duration.is_zero()) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--for must be greater than zero"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AuthCommand {
+    /// Store the auth token of the selected profile in the OS keychain
+    #[command(name = "login")]
+    Login(AuthLoginOpts),
+    /// Remove the auth token of the selected profile from the OS keychain
+    #[command(name = "logout")]
+    Logout,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AuthLoginOpts {
+    #[arg(
+        long = "token-file",
+        required = false,
+        help = "Read the token from this file instead of prompting for it or reading it from stdin"
+    )]
+    pub token_file: Option<String>,
+}
+
+// Every perf message starts with its send time, so it can't be smaller than that
+pub const PERF_MIN_MESSAGE_SIZE: u64 = 8;
+
+#[derive(Args, Debug, Clone)]
+pub struct PerfOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Topic to benchmark, should be in the format of 'tenant/namespace/topic'"
+    )]
+    pub topic: TopicName,
+
+    #[arg(
+        short = 's',
+        long = "size",
+        required = false,
+        default_value = "1K",
+        value_parser = parse_size,
+        help = "Size of each message, e.g. '100', '1K' or '1M'"
+    )]
+    pub size: u64,
+
+    #[arg(
+        short = 'r',
+        long = "rate",
+        required = false,
+        default_value = "100",
+        help = "Messages produced per second, 0 for as fast as possible"
+    )]
+    pub rate: u64,
+
+    #[arg(
+        short = 'd',
+        long = "duration",
+        required = false,
+        default_value = "60s",
+        value_parser = parse_duration,
+        help = "How long to produce messages for, e.g. '30s' or '5m'"
+    )]
+    pub duration: Duration,
+
+    #[arg(
+        short = 'p',
+        long = "partitions",
+        required = false,
+        help = "Create the topic with this many partitions if it does not exist yet"
+    )]
+    pub partitions: Option<u32>,
+
+    #[arg(
+        long = "latency-out",
+        required = false,
+        help = "Write the end-to-end latency distribution to this file, as CSV for a '.csv' file and in HdrHistogram's '.hgrm' format otherwise"
+    )]
+    pub latency_out: Option<String>,
+
+    #[command(flatten)]
+    pub auth: AuthOpts,
+}
+
+impl OpValidate for PerfOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        validate_topic(&self.topic)?;
+        if self.size < PERF_MIN_MESSAGE_SIZE {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--size must be at least {} bytes",
+                PERF_MIN_MESSAGE_SIZE
+            )));
+        }
+        if self.duration.is_zero() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--duration must be greater than zero"
+            )));
+        }
+        if self.partitions == Some(0) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--partitions must be at least 1"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LagOpts {
+    #[command(flatten)]
+    pub auth: AuthOpts,
+
+    #[command(subcommand)]
+    pub command: LagCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum LagCommand {
+    /// Export the cursor positions of a topic's subscriptions to a JSON file
+    #[command(name = "export")]
+    Export(LagExportOpts),
+
+    /// Reset subscription cursors to the positions of an exported file
+    #[command(name = "apply")]
+    Apply(LagApplyOpts),
+
+    /// List subscriptions of a namespace without consumers that keep a backlog
+    #[command(name = "stale")]
+    Stale(LagStaleOpts),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LagExportOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Topic whose subscriptions to export, should be in the format of 'tenant/namespace/topic'"
+    )]
+    pub topic: TopicName,
+
+    #[arg(
+        short = 's',
+        long = "subscription",
+        required = false,
+        help = "Only export this subscription, can be given multiple times (default: all subscriptions)"
+    )]
+    pub subscriptions: Vec<String>,
+
+    #[arg(
+        short = 'o',
+        long = "output",
+        required = false,
+        help = "File to write the positions to (default: stdout)"
+    )]
+    pub output: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LagApplyOpts {
+    #[arg(help = "File with positions written by 'lag export'")]
+    pub file: String,
+
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = false,
+        help = "Apply the positions to this topic instead of the exported one, it must have the same number of partitions"
+    )]
+    pub topic: Option<TopicName>,
+
+    #[arg(
+        short = 's',
+        long = "subscription",
+        required = false,
+        help = "Only apply the positions of this subscription, can be given multiple times (default: all subscriptions)"
+    )]
+    pub subscriptions: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LagStaleOpts {
+    #[arg(
+        long = "namespace",
+        required = true,
+        help = "Namespace whose subscriptions to check, should be in the format of 'tenant/namespace'"
+    )]
+    pub namespace: String,
+
+    #[arg(
+        long = "older-than",
+        required = false,
+        value_parser = parse_duration,
+        default_value = "7d",
+        help = "Only list subscriptions that have not consumed or acknowledged for this long, e.g. '7d' or '12h'"
+    )]
+    pub older_than: Duration,
+
+    #[command(flatten)]
+    pub table: TableOpts,
+}
+
+impl OpValidate for LagOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        match &self.command {
+            LagCommand::Export(opts) => validate_topic(&opts.topic),
+            LagCommand::Apply(opts) => opts.topic.as_ref().map_or(Ok(()), validate_topic),
+            LagCommand::Stale(opts) => {
+                validate_namespace(&opts.namespace)?;
+                if opts.older_than.is_zero() {
+                    return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                        "--older-than must be greater than zero"
+                    )));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AdminOpts {
+    #[command(flatten)]
+    pub auth: AuthOpts,
+
+    #[command(subcommand)]
+    pub command: AdminCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AdminCommand {
+    /// Manage topics
+    #[command(name = "topic", subcommand)]
+    Topic(AdminTopicCommand),
+
+    /// Manage namespaces
+    #[command(name = "namespace", subcommand)]
+    Namespace(AdminNamespaceCommand),
+
+    /// Compact topics, keeping only the latest message of every key
+    #[command(name = "compaction", subcommand)]
+    Compaction(AdminCompactionCommand),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AdminTopicCommand {
+    /// Unload a topic so it gets reassigned to a broker
+    #[command(name = "unload")]
+    Unload(AdminTopicOpts),
+
+    /// Terminate a topic, no more messages can be published to it afterwards
+    #[command(name = "terminate")]
+    Terminate(AdminTopicOpts),
+
+    /// Show the backlog of a topic against its retention policy and backlog quota
+    #[command(name = "stats")]
+    Stats(AdminTopicStatsOpts),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AdminTopicOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Topic to operate on, should be in the format of 'tenant/namespace/topic', or a template such as 'tenant/ns/events-{0..9}'"
+    )]
+    pub topic: TopicTemplate,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AdminTopicStatsOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Topic to show the stats of, should be in the format of 'tenant/namespace/topic', or a template such as 'tenant/ns/events-{0..9}'"
+    )]
+    pub topic: TopicTemplate,
+
+    #[command(flatten)]
+    pub table: TableOpts,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AdminCompactionCommand {
+    /// Start compacting a topic, all partitions of a partitioned topic
+    #[command(name = "run")]
+    Run(AdminCompactionRunOpts),
+
+    /// Show the status of the latest compaction of every partition
+    #[command(name = "status")]
+    Status(AdminCompactionStatusOpts),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AdminCompactionRunOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Topic to compact, should be in the format of 'tenant/namespace/topic', or a template such as 'tenant/ns/events-{0..9}'"
+    )]
+    pub topic: TopicTemplate,
+
+    #[arg(
+        long = "wait",
+        help = "Wait until the compaction completed, failing if it failed",
+        default_value = "false"
+    )]
+    pub wait: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AdminCompactionStatusOpts {
+    #[arg(
+        short = 't',
+        long = "topic",
+        required = true,
+        help = "Topic to show the compaction status of, should be in the format of 'tenant/namespace/topic', or a template such as 'tenant/ns/events-{0..9}'"
+    )]
+    pub topic: TopicTemplate,
+
+    #[command(flatten)]
+    pub table: TableOpts,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AdminNamespaceCommand {
+    /// Create a namespace, optionally with initial policies
+    #[command(name = "create")]
+    Create(AdminNamespaceCreateOpts),
+
+    /// Delete a namespace
+    #[command(name = "delete")]
+    Delete(AdminNamespaceDeleteOpts),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AdminNamespaceCreateOpts {
+    #[arg(help = "Namespace to create, should be in the format of 'tenant/namespace'")]
+    pub namespace: String,
+
+    #[arg(
+        long = "retention-time",
+        required = false,
+        allow_negative_numbers = true,
+        value_parser = parse_retention_time,
+        help = "Retention time for acknowledged messages, e.g. '3h', '7d', or '-1' for infinite (the default with only --retention-size)"
+    )]
+    pub retention_time: Option<i64>,
+
+    #[arg(
+        long = "retention-size",
+        required = false,
+        allow_negative_numbers = true,
+        value_parser = parse_retention_size,
+        help = "Retention size for acknowledged messages, e.g. '500M', '10G', or '-1' for infinite (the default with only --retention-time)"
+    )]
+    pub retention_size: Option<i64>,
+
+    #[arg(
+        long = "clusters",
+        required = false,
+        value_delimiter = ',',
+        help = "Comma-separated list of replication clusters for the namespace"
+    )]
+    pub clusters: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AdminNamespaceDeleteOpts {
+    #[arg(help = "Namespace to delete, should be in the format of 'tenant/namespace'")]
+    pub namespace: String,
+
+    #[arg(
+        long = "force",
+        help = "Delete the namespace even if it still contains topics",
+        default_value = "false"
+    )]
+    pub force: bool,
+}
+
+impl OpValidate for AdminOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        match &self.command {
+            AdminCommand::Topic(
+                AdminTopicCommand::Unload(opts) | AdminTopicCommand::Terminate(opts),
+            ) => expand_topics(&opts.topic).map(|_| ()),
+            AdminCommand::Topic(AdminTopicCommand::Stats(opts)) => {
+                expand_topics(&opts.topic).map(|_| ())
+            }
+            AdminCommand::Namespace(AdminNamespaceCommand::Create(opts)) => {
+                validate_namespace(&opts.namespace)
+            }
+            AdminCommand::Namespace(AdminNamespaceCommand::Delete(opts)) => {
+                validate_namespace(&opts.namespace)
+            }
+            AdminCommand::Compaction(AdminCompactionCommand::Run(AdminCompactionRunOpts {
+                topic,
+                ..
+            }))
+            | AdminCommand::Compaction(AdminCompactionCommand::Status(
+                AdminCompactionStatusOpts { topic, .. },
+            )) => {
+                for topic in expand_topics(topic)? {
+                    if !topic.is_persistent() {
+                        return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                            "Non-persistent topic {} stores no messages and cannot be compacted",
+                            topic
+                        )));
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parse a byte size such as `1024`, `64K`, `10M` or `2G`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, multiplier) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1u64 << 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 1u64 << 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 1u64 << 30),
+        Some((i, 't' | 'T')) => (&s[..i], 1u64 << 40),
+        _ => (s, 1),
+    };
+    let n = number.parse::<u64>().map_err(|_| {
+        format!(
+            "invalid size '{}', expected e.g. '512', '64K', '10M' or '2G'",
+            s
+        )
+    })?;
+    n.checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' is too large", s))
+}
+
+// A byte size or 'broker'
+fn parse_max_message_size(s: &str) -> Result<MaxMessageSizeOpt, String> {
+    if s == "broker" {
+        return Ok(MaxMessageSizeOpt::Broker);
+    }
+    match parse_size(s)? {
+        0 => Err("the size must be at least 1".to_owned()),
+        size => Ok(MaxMessageSizeOpt::Bytes(size)),
+    }
+}
+
+// A property name with an optional 'auto-uuid' or fixed value
+fn parse_trace_id_property(s: &str) -> Result<TraceIdOpt, String> {
+    let (property, value) = match s.split_once('=') {
+        Some((property, "auto-uuid")) => (property, None),
+        Some((property, value)) => (property, Some(value.to_owned())),
+        None => (s, None),
+    };
+    if property.is_empty() {
+        return Err(format!(
+            "'{}' is not in the format NAME[=VALUE|auto-uuid]",
+            s
+        ));
+    }
+    Ok(TraceIdOpt {
+        property: property.to_owned(),
+        value,
+    })
+}
+
+// A correlation ID with an optional property name before it
+fn parse_trace_id(s: &str) -> Result<TraceIdFilterOpt, String> {
+    let (property, value) = match s.split_once('=') {
+        Some((property, value)) => (Some(property), value),
+        None => (None, s),
+    };
+    if property == Some("") || value.is_empty() {
+        return Err(format!("'{}' is not in the format [NAME=]VALUE", s));
+    }
+    Ok(TraceIdFilterOpt {
+        property: property.map(ToOwned::to_owned),
+        value: value.to_owned(),
+    })
+}
+
+// Retention time in whole minutes, -1 meaning infinite
+fn parse_retention_time(s: &str) -> Result<i64, String> {
+    if s == "-1" {
+        return Ok(-1);
+    }
+    let d = humantime::parse_duration(s)
+        .map_err(|e| format!("invalid retention time '{}': {}", s, e))?;
+    if d.subsec_nanos() != 0 || d.as_secs() % 60 != 0 {
+        return Err(format!(
+            "retention time '{}' is not a whole number of minutes",
+            s
+        ));
+    }
+    Ok((d.as_secs() / 60) as i64)
+}
+
+// Retention size in whole megabytes, -1 meaning infinite
+fn parse_retention_size(s: &str) -> Result<i64, String> {
+    if s == "-1" {
+        return Ok(-1);
+    }
+    let bytes = parse_size(s)?;
+    if bytes % (1 << 20) != 0 {
+        return Err(format!(
+            "retention size '{}' is not a whole number of megabytes",
+            s
+        ));
+    }
+    Ok((bytes >> 20) as i64)
+}
+
+// Sampling ratio such as '1/100'
+fn parse_sample_ratio(s: &str) -> Result<(u64, u64), String> {
+    let invalid = || format!("invalid sample '{}', expected 'N/M' with 0 < N <= M", s);
+    let (n, m) = s.split_once('/').ok_or_else(invalid)?;
+    let n = n.trim().parse::<u64>().map_err(|_| invalid())?;
+    let m = m.trim().parse::<u64>().map_err(|_| invalid())?;
+    if n == 0 || n > m {
+        return Err(invalid());
+    }
+    Ok((n, m))
+}
+
+// Duration such as '30s', '5m' or '1h 30m'
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    humantime::parse_duration(s).map_err(|e| format!("invalid duration '{}': {}", s, e))
+}
+
+/// Parse an acknowledgement strategy: `auto`, `on-exit`, `manual-interval=N`
+/// or `none`.
+pub fn parse_ack_strategy(s: &str) -> Result<AckStrategy, String> {
+    match s.trim() {
+        "auto" => Ok(AckStrategy::Auto),
+        "on-exit" => Ok(AckStrategy::OnExit),
+        "none" => Ok(AckStrategy::None),
+        other => match other
+            .strip_prefix("manual-interval=")
+            .map(|n| n.parse::<u64>())
+        {
+            Some(Ok(interval)) if interval > 0 => Ok(AckStrategy::Interval(interval)),
+            _ => Err(format!(
+                "invalid ack strategy '{}', expected 'auto', 'on-exit', 'manual-interval=N' or 'none'",
+                s
+            )),
+        },
+    }
+}
+
+/// Parse a policy for messages that fail to be processed: `exit`, `skip` or
+/// `retry=N`.
+pub fn parse_error_policy(s: &str) -> Result<ErrorPolicy, String> {
+    match s.trim() {
+        "exit" => Ok(ErrorPolicy::Exit),
+        "skip" => Ok(ErrorPolicy::Skip),
+        other => match other.strip_prefix("retry=").map(|n| n.parse::<u32>()) {
+            Some(Ok(retries)) if retries > 0 => Ok(ErrorPolicy::Retry(retries)),
+            _ => Err(format!(
+                "invalid error policy '{}', expected 'exit', 'skip' or 'retry=N'",
+                s
+            )),
+        },
+    }
+}
+
+/// Parse a policy for failed acknowledgements: `ignore`, `exit` or `retry=N`.
+pub fn parse_ack_failure_policy(s: &str) -> Result<AckFailurePolicy, String> {
+    match s.trim() {
+        "ignore" => Ok(AckFailurePolicy::Ignore),
+        "exit" => Ok(AckFailurePolicy::Exit),
+        other => match other.strip_prefix("retry=").map(|n| n.parse::<u32>()) {
+            Some(Ok(retries)) if retries > 0 => Ok(AckFailurePolicy::Retry(retries)),
+            _ => Err(format!(
+                "invalid ack failure policy '{}', expected 'ignore', 'exit' or 'retry=N'",
+                s
+            )),
+        },
+    }
+}
+
+/// Parse a consumer start position: `beginning`, `end`, `-N` (N entries
+/// before the end), a message ID or a timestamp.
+pub fn parse_offset(s: &str) -> Result<StartPosition, String> {
+    match s.trim() {
+        "beginning" => return Ok(StartPosition::Beginning),
+        "end" => return Ok(StartPosition::End),
+        _ => {}
+    }
+    if let Some(entries) = s.trim().strip_prefix('-') {
+        return match entries.parse::<u64>() {
+            Ok(entries) if entries > 0 => Ok(StartPosition::BeforeEnd(entries)),
+            _ => Err(format!(
+                "invalid offset '{}', expected '-N' with N at least 1",
+                s
+            )),
+        };
+    }
+    if let Ok(message_id) = parse_message_id(s) {
+        return Ok(StartPosition::MessageId(message_id));
+    }
+    parse_timestamp(s).map(StartPosition::Timestamp).map_err(|_| {
+        format!(
+            "invalid offset '{}', expected 'beginning', 'end', '-N', a timestamp or a message ID",
+            s
+        )
+    })
+}
+
+fn parse_property(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_owned(), value.to_owned())),
+        _ => Err(format!("'{}' is not in the format NAME=VALUE", s)),
+    }
+}
+
+/// Parse a timestamp given as RFC3339 (e.g. `2024-05-01T12:00:00Z`) or epoch
+/// milliseconds into epoch milliseconds.
+pub fn parse_timestamp(s: &str) -> Result<u64, String> {
+    if let Ok(millis) = s.parse::<u64>() {
+        return Ok(millis);
     }
+    humantime::parse_rfc3339_weak(s)
+        .map_err(|e| format!("invalid timestamp '{}': {}", s, e))
+        .and_then(|time| {
+            time.duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .map_err(|_| format!("timestamp '{}' is before the Unix epoch", s))
+        })
 }
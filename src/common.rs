@@ -1,28 +1,180 @@
-use pulsar::{Authentication, Pulsar, PulsarBuilder, TokioExecutor};
+use std::time::Duration;
 
-use crate::{cli_options::AuthOpts, error::PulsarCatError};
+use pulsar::{Pulsar, TokioExecutor};
+use pulsar_cat_core::client::{ConnectOptions, Failover, TlsOptions, connect, split_service_urls};
+use pulsar_cat_core::list::{listed_partitions, similar_topics, topic_exists};
+use pulsar_cat_core::topic_name::TopicName;
 
-fn handle_auth(
-    mut builder: PulsarBuilder<TokioExecutor>,
-    auth_opts: &AuthOpts,
-) -> Result<PulsarBuilder<TokioExecutor>, PulsarCatError> {
-    if let Some(token) = &auth_opts.token {
-        builder = builder.with_auth(Authentication {
-            name: "token".to_owned(),
-            data: Vec::from(token.as_str()),
-        });
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+use crate::{
+    cli_options::{AuthOpts, TlsOpts},
+    error::PulsarCatError,
+};
+
+// How often --wait-for-topic checks whether the topic was created
+const TOPIC_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+impl From<&TlsOpts> for TlsOptions {
+    fn from(opts: &TlsOpts) -> Self {
+        TlsOptions {
+            ca_cert: opts.ca_cert.clone(),
+            allow_insecure: opts.allow_insecure.unwrap_or(false),
+            no_hostname_verification: opts.no_hostname_verification.unwrap_or(false),
+        }
     }
+}
 
-    Ok(builder)
+impl From<&AuthOpts> for ConnectOptions {
+    fn from(opts: &AuthOpts) -> Self {
+        ConnectOptions {
+            token: opts.token.clone(),
+            token_file: opts.token_file.clone(),
+            proxy_url: opts.proxy_url.clone(),
+            tls: TlsOptions::from(&opts.tls),
+        }
+    }
 }
 
 pub async fn get_base_client(
     service_url: &str,
     auth_opts: &AuthOpts,
 ) -> Result<Pulsar<TokioExecutor>, PulsarCatError> {
-    let builder = Pulsar::builder(service_url, TokioExecutor);
-    let builder = handle_auth(builder, auth_opts)?;
+    connect(service_url, &ConnectOptions::from(auth_opts)).await
+}
+
+/// Failover through the brokers of `service_url` when it lists several, None
+/// for a single broker or proxy, which a new client would connect to again.
+pub fn failover(service_url: &str, auth_opts: &AuthOpts) -> Option<Failover> {
+    let urls = auth_opts.proxy_url.as_deref().unwrap_or(service_url);
+    (split_service_urls(urls).len() > 1).then(|| Failover {
+        service_url: service_url.to_owned(),
+        options: ConnectOptions::from(auth_opts),
+    })
+}
 
-    let pulsar = builder.build().await?;
-    Ok(pulsar)
+/// `client` when one is passed in, e.g. by an integration test, a new
+/// connection to `service_url` otherwise.
+pub async fn client_or_connect(
+    client: Option<Pulsar<TokioExecutor>>,
+    service_url: &str,
+    auth_opts: &AuthOpts,
+) -> Result<Pulsar<TokioExecutor>, PulsarCatError> {
+    match client {
+        Some(client) => Ok(client),
+        None => get_base_client(service_url, auth_opts).await,
+    }
+}
+
+/// Fail with a not found error naming similar topics of the namespace when
+/// `topic` does not exist, instead of letting the broker auto-create it.
+pub async fn require_existing_topic(
+    pulsar: &Pulsar<TokioExecutor>,
+    topic: &str,
+) -> Result<(), PulsarCatError> {
+    if topic_exists(pulsar, topic).await? {
+        return Ok(());
+    }
+    let similar = similar_topics(pulsar, topic, 5).await?;
+    let hint = if similar.is_empty() {
+        String::new()
+    } else {
+        format!(", did you mean {}?", similar.join(", "))
+    };
+    Err(PulsarCatError::NotFound(anyhow::anyhow!(
+        "Topic {} does not exist{}",
+        topic,
+        hint
+    )))
+}
+
+/// Fail with a not found error when `topic` addresses a partition, as in
+/// `orders-partition-2`, that its topic does not have, before the broker
+/// creates a topic by that name. A namespace that cannot be listed is not
+/// checked, and non-persistent partitions are only listed once in use.
+pub async fn require_existing_partition(
+    pulsar: &Pulsar<TokioExecutor>,
+    topic: &str,
+) -> Result<(), PulsarCatError> {
+    let Ok(name) = TopicName::parse(topic) else {
+        return Ok(());
+    };
+    let Some(partition) = name.partition().filter(|_| name.is_persistent()) else {
+        return Ok(());
+    };
+    let partitioned = name.partitioned_topic().to_string();
+    let partitions = match listed_partitions(pulsar, &partitioned).await {
+        Ok(partitions) => partitions,
+        Err(e) => {
+            debug!("Failed to list the partitions of {}: {}", partitioned, e);
+            return Ok(());
+        }
+    };
+    if partition < partitions {
+        return Ok(());
+    }
+    // A non-partitioned topic may have a name ending in a partition suffix
+    if partitions == 0 && topic_exists(pulsar, topic).await? {
+        return Ok(());
+    }
+    let reason = match partitions {
+        0 => "is not a partitioned topic".to_owned(),
+        1 => "only has partition 0".to_owned(),
+        partitions => format!("only has partitions 0 to {}", partitions - 1),
+    };
+    Err(PulsarCatError::NotFound(anyhow::anyhow!(
+        "Partition {} does not exist, {} {}",
+        topic,
+        partitioned,
+        reason
+    )))
+}
+
+/// Wait until `topic` exists, without auto-creating it. Returns false when
+/// `shutdown` is cancelled first, and a not found error when the topic still
+/// does not exist after `timeout`. Failing checks are retried, the namespace
+/// may not exist yet either.
+pub async fn wait_for_topic(
+    pulsar: &Pulsar<TokioExecutor>,
+    topic: &str,
+    timeout: Duration,
+    shutdown: &CancellationToken,
+) -> Result<bool, PulsarCatError> {
+    let deadline = Instant::now() + timeout;
+    let mut waiting = false;
+    loop {
+        let last_error = match topic_exists(pulsar, topic).await {
+            Ok(true) => return Ok(true),
+            Ok(false) => None,
+            Err(e) => {
+                debug!("Failed to check whether {} exists: {}", topic, e);
+                Some(e)
+            }
+        };
+        if Instant::now() >= deadline {
+            let reason = last_error
+                .map(|e| format!(", the last check failed: {}", e))
+                .unwrap_or_default();
+            return Err(PulsarCatError::NotFound(anyhow::anyhow!(
+                "Topic {} was not created within {}{}",
+                topic,
+                humantime::format_duration(timeout),
+                reason
+            )));
+        }
+        if !waiting {
+            info!(
+                "Waiting up to {} for topic {} to be created",
+                humantime::format_duration(timeout),
+                topic
+            );
+            waiting = true;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep_until((Instant::now() + TOPIC_POLL_INTERVAL).min(deadline)) => {}
+            _ = shutdown.cancelled() => return Ok(false),
+        }
+    }
 }
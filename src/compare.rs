@@ -0,0 +1,195 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use pulsar_cat_core::consume::ConsumedMessage;
+use pulsar_cat_core::format::format_message_id;
+use tracing::{info, warn};
+
+use crate::cli_options::CompareByOpt;
+use crate::error::PulsarCatError;
+
+/// Matches the messages consumed from the same topic on two clusters. A
+/// message not seen on the other cluster within the window is printed as
+/// `<broker>\t<topic>\t<message ID>\t<key>`, naming the cluster that has it.
+pub struct ReplicationComparator {
+    by: CompareByOpt,
+    window: Duration,
+    brokers: [String; 2],
+    // Messages of every cluster not seen on the other one yet, oldest first
+    pending: [HashMap<MessageKey, VecDeque<Seen>>; 2],
+    // Every pending message in the order it was seen, to find the ones the
+    // window expired for. Matched messages are skipped when they come up.
+    seen_order: VecDeque<(Instant, usize, MessageKey, u64)>,
+    next_sequence: u64,
+    matched: u64,
+    // Messages only found on the cluster of the same index
+    missing: [u64; 2],
+}
+
+#[derive(Hash, PartialEq, Eq, Clone)]
+enum MessageKey {
+    Payload(String, u64),
+    MessageId(String, u64, u64, i32),
+}
+
+struct Seen {
+    sequence: u64,
+    publish_time: u64,
+    message_id: String,
+    key: Option<String>,
+}
+
+impl ReplicationComparator {
+    pub fn new(by: CompareByOpt, window: Duration, brokers: [String; 2]) -> Self {
+        ReplicationComparator {
+            by,
+            window,
+            brokers,
+            pending: Default::default(),
+            seen_order: VecDeque::new(),
+            next_sequence: 0,
+            matched: 0,
+            missing: [0; 2],
+        }
+    }
+
+    /// Record a message consumed from the cluster of `index` in the brokers.
+    pub fn record(&mut self, index: usize, msg: &ConsumedMessage) {
+        let key = self.message_key(msg);
+        if let Some(queue) = self.pending[1 - index].get_mut(&key) {
+            queue.pop_front();
+            if queue.is_empty() {
+                self.pending[1 - index].remove(&key);
+            }
+            self.matched += 1;
+            return;
+        }
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.pending[index]
+            .entry(key.clone())
+            .or_default()
+            .push_back(Seen {
+                sequence,
+                publish_time: msg.publish_time,
+                message_id: format_message_id(msg.message_id),
+                key: msg.key.map(str::to_owned),
+            });
+        self.seen_order
+            .push_back((Instant::now(), index, key, sequence));
+    }
+
+    /// Report the messages still missing on the other cluster after the window.
+    pub fn expire(&mut self, now: Instant) {
+        while let Some((seen_at, _, _, _)) = self.seen_order.front() {
+            if now.duration_since(*seen_at) < self.window {
+                break;
+            }
+            if let Some((_, index, key, sequence)) = self.seen_order.pop_front() {
+                self.report_missing(index, &key, sequence, |_| true);
+            }
+        }
+    }
+
+    /// Report the messages missing for longer than the window and summarize.
+    /// With both clusters read to their end, a message published longer than
+    /// the window ago is missing as well. Messages seen more recently are
+    /// counted as unconfirmed.
+    pub fn finish(mut self, read_to_end: bool) -> Result<(), PulsarCatError> {
+        self.expire(Instant::now());
+        let published_before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.saturating_sub(self.window).as_millis() as u64)
+            .unwrap_or_default();
+        let mut unconfirmed = 0;
+        for (_, index, key, sequence) in std::mem::take(&mut self.seen_order) {
+            let reported = self.report_missing(index, &key, sequence, |seen| {
+                read_to_end && seen.publish_time < published_before
+            });
+            if !reported && self.is_pending(index, &key, sequence) {
+                unconfirmed += 1;
+            }
+        }
+        info!(
+            "{} messages on both clusters, {} only on {}, {} only on {}",
+            self.matched, self.missing[0], self.brokers[0], self.missing[1], self.brokers[1]
+        );
+        if unconfirmed > 0 {
+            warn!(
+                "{} messages were only seen on one cluster within the last {}, too recently to tell, and are left out",
+                unconfirmed,
+                humantime::format_duration(self.window)
+            );
+        }
+        let missing = self.missing[0] + self.missing[1];
+        if missing > 0 {
+            return Err(PulsarCatError::PartialFailure(anyhow::anyhow!(
+                "{} messages were only found on one of the clusters",
+                missing
+            )));
+        }
+        Ok(())
+    }
+
+    // Print a message that is still pending if `is_missing` says so,
+    // returning whether it was printed
+    fn report_missing(
+        &mut self,
+        index: usize,
+        key: &MessageKey,
+        sequence: u64,
+        is_missing: impl Fn(&Seen) -> bool,
+    ) -> bool {
+        // Matches remove the oldest message of a key, so an unmatched one is in front
+        let Some(queue) = self.pending[index].get_mut(key) else {
+            return false;
+        };
+        if queue
+            .front()
+            .is_none_or(|seen| seen.sequence != sequence || !is_missing(seen))
+        {
+            return false;
+        }
+        let seen = queue.pop_front().expect("queue has a front");
+        if queue.is_empty() {
+            self.pending[index].remove(key);
+        }
+        let topic = match key {
+            MessageKey::Payload(topic, _) | MessageKey::MessageId(topic, ..) => topic,
+        };
+        println!(
+            "{}\t{}\t{}\t{}",
+            self.brokers[index],
+            topic,
+            seen.message_id,
+            seen.key.as_deref().unwrap_or_default()
+        );
+        self.missing[index] += 1;
+        true
+    }
+
+    fn is_pending(&self, index: usize, key: &MessageKey, sequence: u64) -> bool {
+        self.pending[index]
+            .get(key)
+            .is_some_and(|queue| queue.iter().any(|seen| seen.sequence == sequence))
+    }
+
+    fn message_key(&self, msg: &ConsumedMessage) -> MessageKey {
+        let topic = msg.topic.to_owned();
+        match self.by {
+            CompareByOpt::Payload => {
+                let mut hasher = DefaultHasher::new();
+                msg.key.hash(&mut hasher);
+                msg.payload.hash(&mut hasher);
+                MessageKey::Payload(topic, hasher.finish())
+            }
+            CompareByOpt::MessageId => MessageKey::MessageId(
+                topic,
+                msg.message_id.ledger_id,
+                msg.message_id.entry_id,
+                msg.message_id.batch_index.unwrap_or(-1),
+            ),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    cli_options::{AuthOpts, CliOpts, OpMode},
+    error::PulsarCatError,
+    keychain,
+};
+
+/// Contents of the configuration file, e.g.
+///
+/// ```toml
+/// default_profile = "local"
+///
+/// [profiles.local]
+/// broker = "pulsar://localhost:6650"
+///
+/// [profiles.prod-eu]
+/// broker = "pulsar+ssl://pulsar.eu.example.com:6651"
+/// admin_url = "https://pulsar-admin.eu.example.com"
+/// auth_token_file = "/etc/pulsar/token"
+/// tls_ca_cert = "/etc/pulsar/ca.pem"
+///
+/// [profiles.staging]
+/// broker = "pulsar+ssl://pulsar.staging.example.com:6651"
+/// keyring = true
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+/// Connection settings of a named profile. Every field is optional and only
+/// used when the corresponding command line flag is not given.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub broker: Option<String>,
+    pub admin_url: Option<String>,
+    pub default_namespace: Option<String>,
+    pub auth_token: Option<String>,
+    pub auth_token_file: Option<String>,
+    /// Take the auth token from the OS keychain, where `auth login` stores it
+    #[serde(default)]
+    pub keyring: bool,
+    pub proxy_url: Option<String>,
+    pub tls_ca_cert: Option<String>,
+    #[serde(default)]
+    pub tls_allow_insecure: bool,
+    #[serde(default)]
+    pub tls_no_hostname_verification: bool,
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("pulsar-cat").join("config.toml"));
+    }
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| {
+            PathBuf::from(home)
+                .join(".config")
+                .join("pulsar-cat")
+                .join("config.toml")
+        })
+}
+
+fn load_config_file(path: &Path) -> Result<ConfigFile, PulsarCatError> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to read configuration file '{}': {}",
+            path.display(),
+            e
+        )
+    })?;
+    toml::from_str(&content).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to parse configuration file '{}': {}",
+            path.display(),
+            e
+        )
+        .into()
+    })
+}
+
+impl ConfigFile {
+    fn profile(&self, name: &str, path: &Path) -> Result<&Profile, PulsarCatError> {
+        self.profiles.get(name).ok_or_else(|| {
+            PulsarCatError::Validation(anyhow::anyhow!(
+                "Profile '{}' not found in configuration file '{}'",
+                name,
+                path.display()
+            ))
+        })
+    }
+}
+
+impl Profile {
+    /// Fill in the authentication and TLS settings not given explicitly.
+    /// `name` is the name of the profile, its token is stored under it in the
+    /// OS keychain.
+    pub fn apply_to_auth(&self, name: &str, auth: &mut AuthOpts) -> Result<(), PulsarCatError> {
+        // An explicitly given token of either kind replaces both token settings of the profile
+        if auth.token.is_none() && auth.token_file.is_none() {
+            auth.token = self.auth_token.clone();
+            auth.token_file = self.auth_token_file.clone();
+            if self.keyring && auth.token.is_none() && auth.token_file.is_none() {
+                let token = keychain::load_token(name)?.ok_or_else(|| {
+                    PulsarCatError::Validation(anyhow::anyhow!(
+                        "No token of profile '{}' in the OS keychain, store one with \
+                        'pulsar-cat --profile {} auth login'",
+                        name,
+                        name
+                    ))
+                })?;
+                auth.token = Some(token);
+            }
+        }
+        auth.proxy_url = auth.proxy_url.take().or(self.proxy_url.clone());
+        auth.tls.ca_cert = auth.tls.ca_cert.take().or(self.tls_ca_cert.clone());
+        auth.tls.allow_insecure = auth.tls.allow_insecure.or(Some(self.tls_allow_insecure));
+        auth.tls.no_hostname_verification = auth
+            .tls
+            .no_hostname_verification
+            .or(Some(self.tls_no_hostname_verification));
+        Ok(())
+    }
+}
+
+/// Fill in the options not given on the command line from the selected profile.
+///
+/// The profile is taken from `--profile`, falling back to `default_profile` of
+/// the configuration file. A missing configuration file is only an error when
+/// a profile or file was explicitly requested.
+pub fn apply_profile(cli_opts: &mut CliOpts) -> Result<(), PulsarCatError> {
+    let explicit_path = cli_opts.config.as_ref().map(PathBuf::from);
+    let path = match explicit_path.clone().or_else(default_config_path) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let destination_profile = match &cli_opts.command {
+        OpMode::Copy(opts) => opts.destination_profile.clone(),
+        _ => None,
+    };
+    let config = if path.exists()
+        || explicit_path.is_some()
+        || cli_opts.profile.is_some()
+        || destination_profile.is_some()
+    {
+        load_config_file(&path)?
+    } else {
+        ConfigFile::default()
+    };
+
+    if let (OpMode::Copy(opts), Some(name)) = (&mut cli_opts.command, destination_profile) {
+        let profile = config.profile(&name, &path)?;
+        opts.destination_broker = opts.destination_broker.take().or(profile.broker.clone());
+        let mut auth = AuthOpts::default();
+        profile.apply_to_auth(&name, &mut auth)?;
+        opts.destination_auth = Some(auth);
+    }
+
+    let profile_name = match cli_opts.profile.clone().or(config.default_profile.clone()) {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    let profile = config.profile(&profile_name, &path)?;
+
+    cli_opts.broker = cli_opts.broker.take().or(profile.broker.clone());
+    cli_opts.admin_url = cli_opts.admin_url.take().or(profile.admin_url.clone());
+    cli_opts.default_namespace = cli_opts
+        .default_namespace
+        .take()
+        .or(profile.default_namespace.clone());
+    if let Some(auth) = cli_opts.command.auth_mut() {
+        profile.apply_to_auth(&profile_name, auth)?;
+    }
+
+    Ok(())
+}
+
+/// The name and settings of the profile selected with `--profile` or
+/// `default_profile`, which has to exist.
+pub fn selected_profile(cli_opts: &CliOpts) -> Result<(String, Profile), PulsarCatError> {
+    let path = cli_opts
+        .config
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(default_config_path)
+        .ok_or_else(|| {
+            PulsarCatError::Validation(anyhow::anyhow!(
+                "No configuration file location, give one with --config"
+            ))
+        })?;
+    let config = load_config_file(&path)?;
+    let name = cli_opts
+        .profile
+        .clone()
+        .or(config.default_profile.clone())
+        .ok_or_else(|| {
+            PulsarCatError::Validation(anyhow::anyhow!(
+                "No profile selected, use --profile or set default_profile in '{}'",
+                path.display()
+            ))
+        })?;
+    let profile = config.profile(&name, &path)?.clone();
+    Ok((name, profile))
+}
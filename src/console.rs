@@ -0,0 +1,37 @@
+//! Console setup. Windows consoles decode output in the legacy code page
+//! unless told otherwise, and only understand ANSI colors once virtual
+//! terminal processing is enabled.
+
+/// Switch the console to UTF-8 and enable colors on stderr. Returns whether
+/// stderr understands ANSI escape sequences.
+#[cfg(windows)]
+pub fn init() -> bool {
+    use std::os::windows::io::AsRawHandle;
+
+    use windows_sys::Win32::Globalization::CP_UTF8;
+    use windows_sys::Win32::System::Console::{
+        ENABLE_VIRTUAL_TERMINAL_PROCESSING, GetConsoleMode, SetConsoleCP, SetConsoleMode,
+        SetConsoleOutputCP,
+    };
+
+    // Both fail without a console, e.g. when started as a service, and
+    // there is nothing to decode then
+    unsafe {
+        SetConsoleOutputCP(CP_UTF8);
+        SetConsoleCP(CP_UTF8);
+    }
+
+    let stderr = std::io::stderr().as_raw_handle();
+    let mut mode = 0;
+    // Not a console, e.g. redirected to a file, which gets no colors
+    if unsafe { GetConsoleMode(stderr, &mut mode) } == 0 {
+        return false;
+    }
+    mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+        || unsafe { SetConsoleMode(stderr, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) } != 0
+}
+
+#[cfg(not(windows))]
+pub fn init() -> bool {
+    true
+}
@@ -0,0 +1,187 @@
+use std::fmt;
+
+use tracing::debug;
+
+use crate::admin::AdminClient;
+use crate::cli_options::AuthOpts;
+use crate::error::PulsarCatError;
+
+/// Broker capabilities that not every supported Pulsar release has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrokerFeature {
+    KeyShared,
+    Chunking,
+    Transactions,
+}
+
+impl BrokerFeature {
+    pub const ALL: [BrokerFeature; 3] = [
+        BrokerFeature::KeyShared,
+        BrokerFeature::Chunking,
+        BrokerFeature::Transactions,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BrokerFeature::KeyShared => "key-shared",
+            BrokerFeature::Chunking => "chunking",
+            BrokerFeature::Transactions => "transactions",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            BrokerFeature::KeyShared => "Key_Shared subscriptions",
+            BrokerFeature::Chunking => "message chunking",
+            BrokerFeature::Transactions => "transactions",
+        }
+    }
+
+    // First Pulsar release with the feature
+    fn since(self) -> BrokerVersion {
+        match self {
+            BrokerFeature::KeyShared => BrokerVersion(2, 4, 0),
+            BrokerFeature::Chunking => BrokerVersion(2, 6, 0),
+            BrokerFeature::Transactions => BrokerVersion(2, 7, 0),
+        }
+    }
+}
+
+/// Major, minor and patch version of a broker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct BrokerVersion(u32, u32, u32);
+
+impl BrokerVersion {
+    // Accepts suffixes as in '3.0.0-SNAPSHOT' and a fourth component as in
+    // the '2.10.4.1' of vendor builds
+    fn parse(version: &str) -> Option<Self> {
+        let mut numbers = version.trim().split(['.', '-']).map(|part| {
+            let digits = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            part[..digits].parse::<u32>().ok()
+        });
+        let major = numbers.next()??;
+        let minor = numbers.next()??;
+        let patch = numbers.next().flatten().unwrap_or(0);
+        Some(BrokerVersion(major, minor, patch))
+    }
+}
+
+impl fmt::Display for BrokerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// What a broker reports about itself through the admin API.
+pub struct BrokerFeatures {
+    pub version: String,
+    parsed_version: Option<BrokerVersion>,
+    // None when the runtime configuration is not readable, which requires
+    // superuser permissions
+    transaction_coordinator: Option<bool>,
+}
+
+/// Whether a broker has a feature, with the reason when it has not.
+pub enum Support {
+    Yes,
+    No(String),
+    Unknown,
+}
+
+impl BrokerFeatures {
+    pub async fn detect(admin: &AdminClient) -> Result<Self, PulsarCatError> {
+        let version = admin.broker_version().await?;
+        let transaction_coordinator = match admin.get_runtime_configuration().await {
+            Ok(config) => config["transactionCoordinatorEnabled"]
+                .as_str()
+                .map(|enabled| enabled == "true"),
+            Err(e) => {
+                debug!("Could not read the broker configuration: {}", e);
+                None
+            }
+        };
+        Ok(BrokerFeatures {
+            parsed_version: BrokerVersion::parse(&version),
+            version,
+            transaction_coordinator,
+        })
+    }
+
+    pub fn support(&self, feature: BrokerFeature) -> Support {
+        let Some(version) = self.parsed_version else {
+            return Support::Unknown;
+        };
+        if version < feature.since() {
+            return Support::No(format!("requires {} or later", feature.since()));
+        }
+        match (feature, self.transaction_coordinator) {
+            (BrokerFeature::Transactions, Some(false)) => Support::No(
+                "the transaction coordinator is disabled (transactionCoordinatorEnabled)"
+                    .to_owned(),
+            ),
+            (BrokerFeature::Transactions, None) => Support::Unknown,
+            _ => Support::Yes,
+        }
+    }
+
+    /// Fail with an error naming the broker version when it lacks `feature`.
+    /// Features whose support cannot be told are assumed to be present.
+    pub fn require(&self, feature: BrokerFeature) -> Result<(), PulsarCatError> {
+        match self.support(feature) {
+            Support::No(reason) => Err(anyhow::anyhow!(
+                "Broker {} does not support {}, it {}",
+                self.version.trim(),
+                feature.description(),
+                reason
+            )
+            .into()),
+            Support::Yes | Support::Unknown => Ok(()),
+        }
+    }
+
+    /// Summary such as 'key-shared, chunking, transactions=no', with '?' for
+    /// features whose support cannot be told.
+    pub fn summary(&self) -> String {
+        BrokerFeature::ALL
+            .iter()
+            .map(|&feature| match self.support(feature) {
+                Support::Yes => feature.name().to_owned(),
+                Support::No(_) => format!("{}=no", feature.name()),
+                Support::Unknown => format!("{}=?", feature.name()),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Check that the broker has the `required` features before the operation
+/// uses them, and log what it supports at debug level. A broker whose admin
+/// API is unreachable is assumed to have them, the operation reports any
+/// failure itself.
+pub async fn check_broker_features(
+    admin_url: &str,
+    auth: &AuthOpts,
+    required: &[BrokerFeature],
+) -> Result<(), PulsarCatError> {
+    let detected = match AdminClient::new(admin_url, auth) {
+        Ok(admin) => BrokerFeatures::detect(&admin).await,
+        Err(e) => Err(e),
+    };
+    let features = match detected {
+        Ok(features) => features,
+        Err(e) => {
+            debug!("Could not detect the broker version: {}", e);
+            return Ok(());
+        }
+    };
+    debug!(
+        "Broker version {}, features: {}",
+        features.version.trim(),
+        features.summary()
+    );
+    required
+        .iter()
+        .try_for_each(|&feature| features.require(feature))
+}
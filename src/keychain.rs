@@ -0,0 +1,53 @@
+use keyring::Entry;
+
+use crate::error::PulsarCatError;
+
+// Service name the tokens are stored under, with the profile name as user
+const SERVICE: &str = "pulsar-cat";
+
+/// Store the auth token of a profile in the OS keychain (the macOS Keychain,
+/// the Windows Credential Manager or the Secret Service on Linux), replacing
+/// the one stored before.
+pub fn store_token(profile: &str, token: &str) -> Result<(), PulsarCatError> {
+    let profile = profile.to_owned();
+    let token = token.to_owned();
+    on_keychain_thread(move || Entry::new(SERVICE, &profile)?.set_password(&token))
+}
+
+/// The auth token of a profile stored in the OS keychain, None when there is none.
+pub fn load_token(profile: &str) -> Result<Option<String>, PulsarCatError> {
+    let profile = profile.to_owned();
+    on_keychain_thread(
+        move || match Entry::new(SERVICE, &profile)?.get_password() {
+            Ok(token) => Ok(Some(token)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e),
+        },
+    )
+}
+
+/// Remove the auth token of a profile from the OS keychain, returning whether
+/// there was one.
+pub fn delete_token(profile: &str) -> Result<bool, PulsarCatError> {
+    let profile = profile.to_owned();
+    on_keychain_thread(
+        move || match Entry::new(SERVICE, &profile)?.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(e),
+        },
+    )
+}
+
+// Keychain calls block, and the Secret Service client drives an async runtime
+// of its own, so they are kept off the threads of the tokio runtime
+fn on_keychain_thread<T, F>(call: F) -> Result<T, PulsarCatError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> keyring::Result<T> + Send + 'static,
+{
+    std::thread::spawn(call)
+        .join()
+        .map_err(|_| anyhow::anyhow!("Accessing the OS keychain panicked"))?
+        .map_err(|e| anyhow::anyhow!("Failed to access the OS keychain: {}", e).into())
+}
@@ -0,0 +1,91 @@
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+
+use crate::error::PulsarCatError;
+
+// Highest trackable latency in microseconds
+const MAX_LATENCY_MICROS: u64 = 3_600_000_000;
+
+/// Histogram of latencies in microseconds, values above an hour are clamped.
+pub fn latency_histogram() -> Result<Histogram<u64>, PulsarCatError> {
+    Histogram::new_with_bounds(1, MAX_LATENCY_MICROS, 3)
+        .map_err(|e| anyhow::anyhow!("Failed to create latency histogram: {}", e).into())
+}
+
+/// Format a round-trip time in milliseconds, e.g. `2.1ms`.
+pub fn format_rtt(rtt: Duration) -> String {
+    format!("{:.1}ms", rtt.as_secs_f64() * 1000.0)
+}
+
+/// Format the minimum, the given percentiles and the maximum of a latency
+/// histogram in milliseconds, e.g. `min 0.512, p50 1.024, max 9.871`.
+pub fn format_percentiles(histogram: &Histogram<u64>, percentiles: &[f64]) -> String {
+    if histogram.is_empty() {
+        return "-".to_owned();
+    }
+    let millis = |micros: u64| micros as f64 / 1000.0;
+    let mut parts = vec![format!("min {:.3}", millis(histogram.min()))];
+    for percentile in percentiles {
+        parts.push(format!(
+            "p{} {:.3}",
+            percentile,
+            millis(histogram.value_at_percentile(*percentile))
+        ));
+    }
+    parts.push(format!("max {:.3}", millis(histogram.max())));
+    parts.join(", ")
+}
+
+/// Write the percentile distribution of a latency histogram to `path`, in
+/// milliseconds. A `.csv` file gets `percentile,latency_ms,count` rows,
+/// anything else HdrHistogram's `.hgrm` text format, which its plotter reads.
+pub fn write_histogram(histogram: &Histogram<u64>, path: &str) -> Result<(), PulsarCatError> {
+    let millis = |micros: u64| micros as f64 / 1000.0;
+    let csv = path.to_ascii_lowercase().ends_with(".csv");
+    let mut content = String::new();
+    if csv {
+        content.push_str("percentile,latency_ms,count\n");
+    } else {
+        content.push_str("       Value     Percentile TotalCount 1/(1-Percentile)\n\n");
+    }
+
+    let mut total = 0;
+    for value in histogram.iter_quantiles(5) {
+        total += value.count_since_last_iteration();
+        let latency = millis(value.value_iterated_to());
+        let quantile = value.quantile_iterated_to();
+        let _ = if csv {
+            writeln!(content, "{:.6},{:.3},{}", quantile * 100.0, latency, total)
+        } else if quantile < 1.0 {
+            writeln!(
+                content,
+                "{:12.3} {:14.12} {:10} {:14.2}",
+                latency,
+                quantile,
+                total,
+                1.0 / (1.0 - quantile)
+            )
+        } else {
+            writeln!(content, "{:12.3} {:14.12} {:10}", latency, quantile, total)
+        };
+    }
+    if !csv {
+        let _ = writeln!(
+            content,
+            "#[Mean    = {:12.3}, StdDeviation   = {:12.3}]",
+            histogram.mean() / 1000.0,
+            histogram.stdev() / 1000.0
+        );
+        let _ = writeln!(
+            content,
+            "#[Max     = {:12.3}, Total count    = {:12}]",
+            millis(histogram.max()),
+            histogram.len()
+        );
+    }
+
+    std::fs::write(path, content)
+        .map_err(|e| anyhow::anyhow!("Failed to write latency histogram '{}': {}", path, e).into())
+}
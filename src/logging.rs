@@ -0,0 +1,187 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{EnvFilter, fmt};
+
+use crate::{
+    cli_options::LogFormat,
+    error::{MESSAGE_ERROR_TARGET, PulsarCatError},
+};
+
+/// Target of the effective configuration logged at startup with `-v`, which
+/// `errors_json` writes as a JSON line like the failures of messages.
+pub const CONFIG_TARGET: &str = "pulsar_cat::config";
+
+/// Set up the tracing subscriber writing to stderr, so stdout only carries
+/// message data. Log records of the Pulsar client are routed through it too.
+///
+/// `RUST_LOG` takes precedence over the verbosity given with `-v` or `-q`.
+/// With `errors_json`, failures of individual messages are written as JSON
+/// lines regardless of the log level and format, and so is the effective
+/// configuration at `-v` and above. With `log_file`, all records
+/// are also written to it as JSON lines, at least at info level. Without
+/// `ansi`, stderr gets no colors.
+pub fn init(
+    verbosity: u8,
+    quiet: bool,
+    format: &LogFormat,
+    ansi: bool,
+    errors_json: bool,
+    log_file: Option<RotatingFile>,
+) -> Result<(), PulsarCatError> {
+    let is_message_error = |metadata: &tracing::Metadata| metadata.target() == MESSAGE_ERROR_TARGET;
+    let is_json_error = move |metadata: &tracing::Metadata| {
+        is_message_error(metadata) || (verbosity > 0 && metadata.target() == CONFIG_TARGET)
+    };
+    let layer = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_target(verbosity > 0);
+    // Otherwise colors follow NO_COLOR
+    let layer = if ansi { layer } else { layer.with_ansi(false) };
+    let log_layer = match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+    .with_filter(filter_fn(move |metadata| {
+        !(errors_json && is_json_error(metadata))
+    }))
+    .with_filter(env_filter(verbosity, quiet));
+    // One flat object per failure, e.g.
+    // {"timestamp":"...","level":"ERROR","message":"...","kind":"send","topic":"...","error":"..."}
+    let errors_layer = errors_json.then(|| {
+        fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(false)
+            .with_target(false)
+            .with_writer(std::io::stderr)
+            .with_filter(filter_fn(is_json_error))
+    });
+    // The history of a long-running job, so --quiet does not apply to it
+    let file_layer = log_file.map(|file| {
+        fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_ansi(false)
+            .with_writer(Mutex::new(file))
+            .with_filter(env_filter(verbosity, false))
+    });
+
+    tracing_subscriber::registry()
+        .with(log_layer)
+        .with(errors_layer)
+        .with(file_layer)
+        .with(MessageErrorCounter.with_filter(filter_fn(is_message_error)))
+        .try_init()
+        .map_err(|e| anyhow::anyhow!("Failed to initialize logging: {}", e).into())
+}
+
+// Failures of individual messages reported so far, for --stats-interval
+static MESSAGE_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of failures of individual messages logged so far.
+pub fn message_errors() -> u64 {
+    MESSAGE_ERRORS.load(Ordering::Relaxed)
+}
+
+// Counts the events it is given, whatever the log level
+struct MessageErrorCounter;
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for MessageErrorCounter {
+    fn on_event(&self, _event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        MESSAGE_ERRORS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn env_filter(verbosity: u8, quiet: bool) -> EnvFilter {
+    match std::env::var("RUST_LOG") {
+        Ok(directives) if !directives.is_empty() => EnvFilter::new(directives),
+        _ if quiet => EnvFilter::new("off,pulsar_cat=error"),
+        _ => EnvFilter::new(match verbosity {
+            // The client logs every connection retry, only show it on request
+            0 => "off,pulsar_cat=info",
+            1 => "info,pulsar_cat=debug",
+            2 => "debug",
+            _ => "trace",
+        }),
+    }
+}
+
+/// Log file that is rotated once it would exceed `max_size`: the file is
+/// renamed to `<path>.1`, earlier rotations move up by one, and the ones
+/// beyond `keep` are removed.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: u32,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    pub fn open(path: &str, max_size: u64, keep: u32) -> Result<Self, PulsarCatError> {
+        let path = PathBuf::from(path);
+        let file = open_append(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to open log file '{}': {}", path.display(), e))?;
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        Ok(RotatingFile {
+            path,
+            max_size,
+            keep,
+            file,
+            size,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated_path(self.keep));
+            for index in (1..self.keep).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = open_append(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+}
+
+impl Write for RotatingFile {
+    // Every log record is written at once, so records are never split
+    // across files
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
@@ -1,57 +1,433 @@
+mod admin;
 mod cli_options;
 mod common;
+mod compare;
+mod config;
+mod console;
 mod error;
+mod features;
+mod keychain;
+mod latency;
+mod logging;
 mod op;
+mod output;
+mod sink;
+mod source;
+mod stats;
+mod table;
+mod webhook;
 
+use std::process::ExitCode;
+
+use pulsar::error::{ConnectionError, ConsumerError, ProducerError, ServiceDiscoveryError};
+use pulsar::proto::ServerError;
 use tokio::select;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 
+use admin::admin_url_from_broker;
 use clap::Parser;
-use cli_options::{CliOpts, OpMode};
+use cli_options::{CliOpts, OpMode, kcat_compat_args};
 use error::PulsarCatError;
+use features::check_broker_features;
+
+use crate::op::{
+    run_admin, run_auth, run_consume, run_copy, run_dedup_test, run_fetch, run_key_partition,
+    run_lag, run_list, run_lookup, run_mock, run_perf, run_ping, run_produce, run_query,
+    run_requeue_dlq, run_simulate_consumers,
+};
 
-use crate::op::{run_consume, run_list, run_produce};
+// Process exit codes, documented in the README. Usage errors detected by the
+// argument parser exit with EXIT_VALIDATION as well.
+const EXIT_FAILURE: u8 = 1;
+const EXIT_VALIDATION: u8 = 2;
+const EXIT_CONNECTION: u8 = 3;
+const EXIT_AUTHENTICATION: u8 = 4;
+const EXIT_NOT_FOUND: u8 = 5;
+const EXIT_PARTIAL_FAILURE: u8 = 6;
+// As timeout(1)
+const EXIT_TIMED_OUT: u8 = 124;
+const EXIT_INTERRUPTED: u8 = 130;
+// As a process killed with SIGKILL
+const EXIT_ABORTED: u8 = 137;
 
 #[tokio::main]
-async fn main() -> Result<(), PulsarCatError> {
-    let cli_opts = CliOpts::parse();
+async fn main() -> ExitCode {
+    match try_main().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(PulsarCatError::Interrupted) => ExitCode::from(EXIT_INTERRUPTED),
+        Err(PulsarCatError::Aborted) => ExitCode::from(EXIT_ABORTED),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+async fn try_main() -> Result<(), PulsarCatError> {
+    let mut cli_opts = CliOpts::parse_from(kcat_compat_args(std::env::args_os()));
+    let log_file = cli_opts
+        .log_file
+        .as_deref()
+        .map(|path| {
+            logging::RotatingFile::open(path, cli_opts.log_file_max_size, cli_opts.log_file_keep)
+        })
+        .transpose()?;
+    let ansi = console::init();
+    logging::init(
+        cli_opts.verbose,
+        cli_opts.quiet,
+        &cli_opts.log_format,
+        ansi,
+        cli_opts.errors_json,
+        log_file,
+    )?;
+    // Manages the credentials of a profile instead of connecting with them
+    if let OpMode::Auth(command) = &cli_opts.command {
+        return run_auth(&cli_opts, command);
+    }
+    // Replays a capture instead of connecting
+    if let OpMode::Mock(mock_opts) = &cli_opts.command {
+        return run_mock(mock_opts).await;
+    }
+    config::apply_profile(&mut cli_opts)?;
+    if let Some(namespace) = cli_opts.default_namespace.clone() {
+        cli_opts.command.apply_default_namespace(&namespace);
+    }
     run(&cli_opts).await?;
     Ok(())
 }
 
 async fn run(cli_opts: &CliOpts) -> Result<(), PulsarCatError> {
+    let broker = cli_opts.broker.clone().ok_or_else(|| {
+        PulsarCatError::Validation(anyhow::anyhow!(
+            "No broker URL given, use --broker or select a profile with --profile"
+        ))
+    })?;
+
+    let admin_url = cli_opts
+        .admin_url
+        .clone()
+        .unwrap_or_else(|| admin_url_from_broker(&broker));
+
+    // Only asks the admin API when a feature must be checked or -vv shows them
+    let required_features = cli_opts.command.required_broker_features();
+    if let Some(auth) = cli_opts.command.auth()
+        && (!required_features.is_empty() || cli_opts.verbose >= 2)
+    {
+        check_broker_features(&admin_url, auth, &required_features).await?;
+    }
+
+    // Cancelled on a signal or timeout, ops that send or acknowledge messages
+    // stop reading new ones and finish the in-flight ones
+    let shutdown = CancellationToken::new();
+    let graceful = matches!(
+        cli_opts.command,
+        OpMode::Producer(_)
+            | OpMode::Consumer(_)
+            | OpMode::Copy(_)
+            | OpMode::RequeueDlq(_)
+            | OpMode::Perf(_)
+    );
+
+    // SIGHUP shuts down like the other signals unless it rotates the output
+    let hangup_shuts_down =
+        !matches!(&cli_opts.command, OpMode::Consumer(opts) if opts.reopen_on_sighup);
+
     let mut work_join_handle = match &cli_opts.command {
         OpMode::List(list_opts) => {
-            let broker = cli_opts.broker.clone();
+            let broker = broker.clone();
+            let admin_url = admin_url.clone();
             let list_opts = list_opts.clone();
-            tokio::spawn(async move { run_list(broker, list_opts).await })
+            tokio::spawn(async move { run_list(broker, admin_url, None, list_opts).await })
         }
         OpMode::Producer(produce_opts) => {
-            let broker = cli_opts.broker.clone();
+            let broker = broker.clone();
+            let admin_url = admin_url.clone();
             let produce_opts = produce_opts.clone();
-            tokio::spawn(async move { run_produce(broker, &produce_opts).await })
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                run_produce(broker, admin_url, None, &produce_opts, shutdown).await
+            })
         }
         OpMode::Consumer(consume_opts) => {
-            let broker = cli_opts.broker.clone();
+            let broker = broker.clone();
+            let admin_url = admin_url.clone();
             let consume_opts = consume_opts.clone();
-            tokio::spawn(async move { run_consume(broker, &consume_opts).await })
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                run_consume(broker, admin_url, None, &consume_opts, shutdown).await
+            })
+        }
+        OpMode::Copy(copy_opts) => {
+            let broker = broker.clone();
+            let copy_opts = copy_opts.clone();
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move { run_copy(broker, &copy_opts, shutdown).await })
+        }
+        OpMode::RequeueDlq(requeue_opts) => {
+            let broker = broker.clone();
+            let requeue_opts = requeue_opts.clone();
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move { run_requeue_dlq(broker, &requeue_opts, shutdown).await })
+        }
+        OpMode::Query(query_opts) => {
+            let broker = broker.clone();
+            let query_opts = query_opts.clone();
+            tokio::spawn(async move { run_query(broker, query_opts).await })
         }
+        OpMode::Lookup(lookup_opts) => {
+            let broker = broker.clone();
+            let lookup_opts = lookup_opts.clone();
+            tokio::spawn(async move { run_lookup(broker, lookup_opts).await })
+        }
+        OpMode::Fetch(fetch_opts) => {
+            let broker = broker.clone();
+            let fetch_opts = fetch_opts.clone();
+            tokio::spawn(async move { run_fetch(broker, fetch_opts).await })
+        }
+        OpMode::KeyPartition(key_partition_opts) => {
+            let broker = broker.clone();
+            let key_partition_opts = key_partition_opts.clone();
+            tokio::spawn(async move { run_key_partition(broker, key_partition_opts).await })
+        }
+        OpMode::Perf(perf_opts) => {
+            let broker = broker.clone();
+            let admin_url = admin_url.clone();
+            let perf_opts = perf_opts.clone();
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move { run_perf(broker, admin_url, &perf_opts, shutdown).await })
+        }
+        OpMode::Ping(ping_opts) => {
+            let broker = broker.clone();
+            let admin_url = admin_url.clone();
+            let ping_opts = ping_opts.clone();
+            let time_limit = cli_opts.timeout;
+            tokio::spawn(async move { run_ping(broker, admin_url, &ping_opts, time_limit).await })
+        }
+        OpMode::DedupTest(dedup_test_opts) => {
+            let broker = broker.clone();
+            let dedup_test_opts = dedup_test_opts.clone();
+            tokio::spawn(async move { run_dedup_test(broker, dedup_test_opts).await })
+        }
+        OpMode::SimulateConsumers(simulate_opts) => {
+            let broker = broker.clone();
+            let simulate_opts = simulate_opts.clone();
+            let shutdown = shutdown.clone();
+            tokio::spawn(
+                async move { run_simulate_consumers(broker, &simulate_opts, shutdown).await },
+            )
+        }
+        OpMode::Lag(lag_opts) => {
+            let admin_url = admin_url.clone();
+            let lag_opts = lag_opts.clone();
+            tokio::spawn(async move { run_lag(admin_url, lag_opts).await })
+        }
+        OpMode::Admin(admin_opts) => {
+            let admin_url = admin_url.clone();
+            let admin_opts = admin_opts.clone();
+            tokio::spawn(async move { run_admin(admin_url, admin_opts).await })
+        }
+        OpMode::Auth(_) => unreachable!("auth mode is run before connecting"),
+        OpMode::Mock(_) => unreachable!("mock mode is run without a broker"),
     };
 
-    select! {
-        result = &mut work_join_handle => {
-            if result.is_err() {
-                return Err(anyhow::anyhow!(result.unwrap_err()).into());
+    // Ping enforces the timeout itself
+    let time_limit = cli_opts
+        .timeout
+        .filter(|_| !matches!(cli_opts.command, OpMode::Ping(_)));
+    let deadline = async {
+        match time_limit {
+            Some(time_limit) => tokio::time::sleep(time_limit).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let stop = async |reason: &str, mut work_join_handle: JoinHandle<_>| {
+        if !graceful {
+            info!("{}", reason);
+            work_join_handle.abort();
+            return Ok(());
+        }
+        info!(
+            "{}, waiting up to {} for in-flight messages, press Ctrl-C again to exit immediately",
+            reason,
+            humantime::format_duration(cli_opts.shutdown_grace)
+        );
+        shutdown.cancel();
+        // A second signal aborts the drain, e.g. when the broker does not
+        // answer and the grace period is long
+        select! {
+            result = timeout(cli_opts.shutdown_grace, &mut work_join_handle) => match result {
+                Ok(result) => join_result(result),
+                Err(_) => {
+                    warn!("Shutdown grace period expired, in-flight messages may be lost");
+                    work_join_handle.abort();
+                    Err(PulsarCatError::Aborted)
+                }
+            },
+            signal = shutdown_signal(hangup_shuts_down) => {
+                warn!("{} received during shutdown, exiting without waiting for in-flight messages", signal);
+                work_join_handle.abort();
+                Err(PulsarCatError::Aborted)
             }
+        }
+    };
 
-            match result.unwrap() {
-                Ok(_) => Ok(()),
-                Err(e) => Err(e)
+    select! {
+        result = &mut work_join_handle => join_result(result),
+        signal = shutdown_signal(hangup_shuts_down) => {
+            stop(&format!("{} received", signal), work_join_handle)
+                .await
+                .and(Err(PulsarCatError::Interrupted))
+        }
+        _ = deadline => {
+            let time_limit = time_limit.unwrap_or_default();
+            stop(
+                &format!("Timed out after {}", humantime::format_duration(time_limit)),
+                work_join_handle,
+            )
+            .await
+            .and(Err(PulsarCatError::TimedOut(time_limit)))
+        }
+    }
+}
+
+// Wait for a signal that stops pulsar-cat and return its name
+#[cfg(unix)]
+async fn shutdown_signal(hangup: bool) -> &'static str {
+    use tokio::signal::unix::{Signal, SignalKind, signal};
+
+    let listen = |kind: SignalKind, name: &str| {
+        signal(kind)
+            .inspect_err(|e| warn!("Failed to listen for {}: {}", name, e))
+            .ok()
+    };
+    async fn recv(signal: Option<Signal>) {
+        match signal {
+            Some(mut signal) => {
+                signal.recv().await;
             }
+            None => std::future::pending().await,
         }
-        _ = tokio::signal::ctrl_c() => {
-            println!("Ctrl-C pressed");
-            work_join_handle.abort();
-            Ok(())
+    }
+
+    let terminate = listen(SignalKind::terminate(), "SIGTERM");
+    let hangup = if hangup {
+        listen(SignalKind::hangup(), "SIGHUP")
+    } else {
+        None
+    };
+    select! {
+        _ = tokio::signal::ctrl_c() => "Ctrl-C",
+        _ = recv(terminate) => "SIGTERM",
+        _ = recv(hangup) => "SIGHUP",
+    }
+}
+
+// Ctrl-Break, closing the console window and logging off or shutting down
+// take the place of SIGTERM
+#[cfg(windows)]
+async fn shutdown_signal(_hangup: bool) -> &'static str {
+    use tokio::signal::windows::{ctrl_break, ctrl_close, ctrl_logoff, ctrl_shutdown};
+
+    let mut ctrl_break = ctrl_break()
+        .inspect_err(|e| warn!("Failed to listen for Ctrl-Break: {}", e))
+        .ok();
+    let mut close = ctrl_close()
+        .inspect_err(|e| warn!("Failed to listen for CTRL_CLOSE_EVENT: {}", e))
+        .ok();
+    let mut logoff = ctrl_logoff()
+        .inspect_err(|e| warn!("Failed to listen for CTRL_LOGOFF_EVENT: {}", e))
+        .ok();
+    let mut shutdown = ctrl_shutdown()
+        .inspect_err(|e| warn!("Failed to listen for CTRL_SHUTDOWN_EVENT: {}", e))
+        .ok();
+    // Events without a listener never complete
+    select! {
+        _ = tokio::signal::ctrl_c() => "Ctrl-C",
+        Some(()) = async { ctrl_break.as_mut()?.recv().await } => "Ctrl-Break",
+        Some(()) = async { close.as_mut()?.recv().await } => "CTRL_CLOSE_EVENT",
+        Some(()) = async { logoff.as_mut()?.recv().await } => "CTRL_LOGOFF_EVENT",
+        Some(()) = async { shutdown.as_mut()?.recv().await } => "CTRL_SHUTDOWN_EVENT",
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn shutdown_signal(_hangup: bool) -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "Ctrl-C"
+}
+
+fn join_result(
+    result: Result<Result<(), PulsarCatError>, tokio::task::JoinError>,
+) -> Result<(), PulsarCatError> {
+    match result {
+        Ok(result) => result,
+        Err(e) => Err(anyhow::anyhow!(e).into()),
+    }
+}
+
+fn exit_code(e: &PulsarCatError) -> u8 {
+    match e {
+        PulsarCatError::Pulsar(e) => pulsar_exit_code(e),
+        PulsarCatError::Validation(_) => EXIT_VALIDATION,
+        PulsarCatError::Admin { status: None, .. } => EXIT_CONNECTION,
+        PulsarCatError::Admin {
+            status: Some(401 | 403),
+            ..
+        } => EXIT_AUTHENTICATION,
+        PulsarCatError::Admin {
+            status: Some(404), ..
+        } => EXIT_NOT_FOUND,
+        PulsarCatError::NotFound(_) => EXIT_NOT_FOUND,
+        PulsarCatError::PartialFailure(_) => EXIT_PARTIAL_FAILURE,
+        PulsarCatError::Interrupted => EXIT_INTERRUPTED,
+        PulsarCatError::Aborted => EXIT_ABORTED,
+        PulsarCatError::TimedOut(_) => EXIT_TIMED_OUT,
+        PulsarCatError::Application(_) | PulsarCatError::Admin { .. } => EXIT_FAILURE,
+    }
+}
+
+fn pulsar_exit_code(e: &pulsar::Error) -> u8 {
+    match e {
+        pulsar::Error::Authentication(_) => EXIT_AUTHENTICATION,
+        pulsar::Error::Connection(e)
+        | pulsar::Error::Consumer(ConsumerError::Connection(e))
+        | pulsar::Error::Producer(ProducerError::Connection(e))
+        | pulsar::Error::ServiceDiscovery(ServiceDiscoveryError::Connection(e)) => {
+            connection_exit_code(e)
+        }
+        pulsar::Error::ServiceDiscovery(ServiceDiscoveryError::Query(Some(server_error), _)) => {
+            server_exit_code(*server_error)
         }
+        pulsar::Error::ServiceDiscovery(ServiceDiscoveryError::NotFound) => EXIT_NOT_FOUND,
+        pulsar::Error::ServiceDiscovery(ServiceDiscoveryError::DnsLookupError) => EXIT_CONNECTION,
+        _ => EXIT_FAILURE,
+    }
+}
+
+fn connection_exit_code(e: &ConnectionError) -> u8 {
+    match e {
+        ConnectionError::Authentication(_) => EXIT_AUTHENTICATION,
+        ConnectionError::PulsarError(Some(server_error), _) => server_exit_code(*server_error),
+        ConnectionError::NotFound => EXIT_NOT_FOUND,
+        ConnectionError::Io(_)
+        | ConnectionError::Disconnected
+        | ConnectionError::SocketAddr(_)
+        | ConnectionError::Tls(_)
+        | ConnectionError::Shutdown => EXIT_CONNECTION,
+        _ => EXIT_FAILURE,
+    }
+}
+
+fn server_exit_code(e: ServerError) -> u8 {
+    match e {
+        ServerError::AuthenticationError | ServerError::AuthorizationError => EXIT_AUTHENTICATION,
+        ServerError::TopicNotFound | ServerError::SubscriptionNotFound => EXIT_NOT_FOUND,
+        ServerError::ServiceNotReady => EXIT_CONNECTION,
+        _ => EXIT_FAILURE,
     }
 }
@@ -0,0 +1,353 @@
+use crate::admin::AdminClient;
+use crate::cli_options::{
+    AdminCommand, AdminCompactionCommand, AdminNamespaceCommand, AdminOpts, AdminTopicCommand,
+};
+use crate::error::PulsarCatError;
+use crate::table::Table;
+use pulsar_cat_core::list::{full_topic_name, topic_namespace};
+use pulsar_cat_core::topic_template::TopicTemplate;
+use serde_json::{Value, json};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::op::{OpValidate, expand_topics};
+
+pub async fn run_admin(admin_url: String, admin_opts: AdminOpts) -> Result<(), PulsarCatError> {
+    admin_opts.validate()?;
+    let admin = AdminClient::new(&admin_url, &admin_opts.auth)?;
+
+    match admin_opts.command {
+        AdminCommand::Topic(AdminTopicCommand::Unload(opts)) => {
+            for topic in admin_topics(&admin, &opts.topic).await? {
+                admin.unload_topic(&topic).await?;
+                info!("Topic {} unloaded", topic);
+            }
+        }
+        AdminCommand::Topic(AdminTopicCommand::Terminate(opts)) => {
+            for topic in admin_topics(&admin, &opts.topic).await? {
+                let partitions = admin.get_partition_count(&topic).await?;
+                if partitions > 0 {
+                    let last_ids = admin.terminate_partitioned_topic(&topic).await?;
+                    info!(
+                        "Topic {} terminated ({} partitions), last message IDs: {}",
+                        topic, partitions, last_ids
+                    );
+                } else {
+                    let last_id = admin.terminate_topic(&topic).await?;
+                    info!("Topic {} terminated, last message ID: {}", topic, last_id);
+                }
+            }
+        }
+        AdminCommand::Topic(AdminTopicCommand::Stats(opts)) => {
+            let mut table = Table::new([
+                "TOPIC",
+                "BACKLOG",
+                "STORAGE",
+                "RETENTION_TIME",
+                "RETENTION_SIZE",
+                "QUOTA",
+                "QUOTA_POLICY",
+                "QUOTA_USED",
+            ]);
+            for topic in admin_topics(&admin, &opts.topic).await? {
+                let limits = topic_limits(&admin, &topic).await;
+                for stats in partition_stats(&admin, &topic).await? {
+                    let used = limits.quota_used(stats.backlog);
+                    if let Some(used) = used.filter(|used| *used >= QUOTA_WARNING_RATIO) {
+                        warn!(
+                            "Backlog of {} is at {:.1}% of its quota, the broker applies {} once it is exceeded",
+                            stats.topic,
+                            used * 100.0,
+                            limits.quota_policy.as_deref().unwrap_or("the quota policy")
+                        );
+                    }
+                    table.add_row([
+                        stats.topic,
+                        stats.backlog.to_string(),
+                        stats.storage.to_string(),
+                        limits.retention_time(),
+                        limits.retention_size(),
+                        limits
+                            .quota_bytes
+                            .map(|bytes| bytes.to_string())
+                            .unwrap_or_default(),
+                        limits.quota_policy.clone().unwrap_or_default(),
+                        used.map(|used| format!("{:.1}%", used * 100.0))
+                            .unwrap_or_default(),
+                    ]);
+                }
+            }
+            table.print(&opts.table);
+        }
+        AdminCommand::Namespace(AdminNamespaceCommand::Create(opts)) => {
+            let mut policies = json!({});
+            if !opts.clusters.is_empty() {
+                policies["replication_clusters"] = json!(opts.clusters);
+            }
+            // The broker rejects a retention policy limited in only one dimension
+            // with 0 for the other, so the one not given is infinite
+            if opts.retention_time.is_some() || opts.retention_size.is_some() {
+                policies["retention_policies"] = json!({
+                    "retentionTimeInMinutes": opts.retention_time.unwrap_or(-1),
+                    "retentionSizeInMB": opts.retention_size.unwrap_or(-1),
+                });
+            }
+            admin.create_namespace(&opts.namespace, &policies).await?;
+            info!("Namespace {} created", opts.namespace);
+        }
+        AdminCommand::Namespace(AdminNamespaceCommand::Delete(opts)) => {
+            admin.delete_namespace(&opts.namespace, opts.force).await?;
+            info!("Namespace {} deleted", opts.namespace);
+        }
+        AdminCommand::Compaction(AdminCompactionCommand::Run(opts)) => {
+            let topics = admin_topics(&admin, &opts.topic).await?;
+            for topic in &topics {
+                admin.trigger_compaction(topic).await?;
+                info!("Compaction of {} started", topic);
+            }
+            if opts.wait {
+                for topic in &topics {
+                    wait_for_compaction(&admin, topic).await?;
+                }
+            }
+        }
+        AdminCommand::Compaction(AdminCompactionCommand::Status(opts)) => {
+            let mut table = Table::new(["TOPIC", "STATUS", "ERROR"]);
+            for topic in admin_topics(&admin, &opts.topic).await? {
+                for status in compaction_status(&admin, &topic).await? {
+                    table.add_row([status.topic, status.status, status.error]);
+                }
+            }
+            table.print(&opts.table);
+        }
+    }
+
+    Ok(())
+}
+
+// The full names of the topics named by `topic`, failing when one addresses
+// a partition its partitioned topic does not have
+async fn admin_topics(
+    admin: &AdminClient,
+    topic: &TopicTemplate,
+) -> Result<Vec<String>, PulsarCatError> {
+    let topics = expand_topics(topic)?;
+    for topic in &topics {
+        let Some(partition) = topic.partition() else {
+            continue;
+        };
+        let partitioned = topic.partitioned_topic().to_string();
+        let partitions = admin.get_partition_count(&partitioned).await?;
+        if partitions > 0 && u64::from(partition) >= partitions {
+            return Err(PulsarCatError::NotFound(anyhow::anyhow!(
+                "Partition {} does not exist, {} has {} partitions",
+                topic,
+                partitioned,
+                partitions
+            )));
+        }
+    }
+    Ok(topics.iter().map(ToString::to_string).collect())
+}
+
+// Share of the backlog quota from which a topic is reported as close to it
+const QUOTA_WARNING_RATIO: f64 = 0.9;
+
+struct PartitionStats {
+    topic: String,
+    backlog: u64,
+    storage: u64,
+}
+
+// Backlog and storage size of a topic or of every partition of a partitioned
+// topic, as the backlog quota applies to each partition on its own
+async fn partition_stats(
+    admin: &AdminClient,
+    topic: &str,
+) -> Result<Vec<PartitionStats>, PulsarCatError> {
+    let read = |topic: String, stats: &Value| PartitionStats {
+        topic,
+        backlog: stats["backlogSize"].as_u64().unwrap_or(0),
+        storage: stats["storageSize"].as_u64().unwrap_or(0),
+    };
+    let partitions = admin.get_partition_count(topic).await?;
+    if partitions == 0 {
+        let stats = admin.get_topic_stats(topic, false).await?;
+        return Ok(vec![read(topic.to_owned(), &stats)]);
+    }
+    let stats = admin.get_partitioned_topic_stats(topic).await?;
+    // Partitions are reported by their full name
+    let full_name = full_topic_name(topic);
+    Ok((0..partitions)
+        .map(|partition| {
+            let name = format!("{}-partition-{}", full_name, partition);
+            let partition_stats = &stats["partitions"][&name];
+            read(
+                format!("{}-partition-{}", topic, partition),
+                partition_stats,
+            )
+        })
+        .collect())
+}
+
+/// Retention policy and backlog quota in effect for a topic, `None` where
+/// they could not be determined.
+#[derive(Default)]
+struct TopicLimits {
+    // Negative means infinite
+    retention_minutes: Option<i64>,
+    retention_mb: Option<i64>,
+    // Only set when there is a size limit
+    quota_bytes: Option<i64>,
+    quota_policy: Option<String>,
+}
+
+impl TopicLimits {
+    fn retention_time(&self) -> String {
+        match self.retention_minutes {
+            Some(minutes) if minutes < 0 => "infinite".to_owned(),
+            Some(minutes) => {
+                humantime::format_duration(Duration::from_secs(minutes as u64 * 60)).to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    fn retention_size(&self) -> String {
+        match self.retention_mb {
+            Some(mb) if mb < 0 => "infinite".to_owned(),
+            Some(mb) => format!("{}M", mb),
+            None => String::new(),
+        }
+    }
+
+    // Share of the size quota a backlog takes up
+    fn quota_used(&self, backlog: u64) -> Option<f64> {
+        self.quota_bytes
+            .map(|quota| backlog as f64 / quota.max(1) as f64)
+    }
+}
+
+// The policies of the topic when topic level policies are enabled, those of
+// its namespace otherwise, and the broker defaults for what is not set there.
+// Best effort, the policies are left empty when they cannot be read.
+async fn topic_limits(admin: &AdminClient, topic: &str) -> TopicLimits {
+    let applied = tokio::try_join!(
+        admin.get_applied_retention(topic),
+        admin.get_applied_backlog_quotas(topic)
+    );
+    let policies = match applied {
+        Ok(policies) => Ok(policies),
+        Err(e) => {
+            debug!(
+                "Could not read the topic policies of {}, using those of its namespace: {}",
+                topic, e
+            );
+            namespace_policies(admin, topic).await
+        }
+    };
+    let (retention, quotas) = match policies {
+        Ok(policies) => policies,
+        Err(e) => {
+            warn!(
+                "Could not read the retention policy and backlog quota of {}: {}",
+                topic, e
+            );
+            return TopicLimits::default();
+        }
+    };
+
+    let quota = &quotas["destination_storage"];
+    let mut limits = TopicLimits {
+        retention_minutes: retention["retentionTimeInMinutes"].as_i64(),
+        retention_mb: retention["retentionSizeInMB"].as_i64(),
+        // Brokers before 2.9 call the size limit 'limit'
+        quota_bytes: quota["limitSize"].as_i64().or(quota["limit"].as_i64()),
+        quota_policy: quota["policy"].as_str().map(str::to_owned),
+    };
+    if limits.retention_minutes.is_none() || limits.quota_policy.is_none() {
+        // The configuration values are strings
+        match admin.get_runtime_configuration().await {
+            Ok(config) => {
+                let number = |name: &str| config[name].as_str().and_then(|v| v.parse().ok());
+                if limits.retention_minutes.is_none() {
+                    limits.retention_minutes = number("defaultRetentionTimeInMinutes");
+                    limits.retention_mb = number("defaultRetentionSizeInMB");
+                }
+                if limits.quota_policy.is_none() {
+                    limits.quota_bytes = number("backlogQuotaDefaultLimitBytes");
+                    limits.quota_policy = config["backlogQuotaDefaultRetentionPolicy"]
+                        .as_str()
+                        .map(str::to_owned);
+                }
+            }
+            Err(e) => debug!("Could not read the broker defaults: {}", e),
+        }
+    }
+    limits.quota_bytes = limits.quota_bytes.filter(|bytes| *bytes >= 0);
+    limits
+}
+
+async fn namespace_policies(
+    admin: &AdminClient,
+    topic: &str,
+) -> Result<(Value, Value), PulsarCatError> {
+    let (namespace, _) = topic_namespace(topic)?;
+    tokio::try_join!(
+        admin.get_retention(&namespace),
+        admin.get_backlog_quotas(&namespace)
+    )
+}
+
+// How often the status of a running compaction is checked
+const COMPACTION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+struct CompactionStatus {
+    topic: String,
+    status: String,
+    error: String,
+}
+
+// Compaction status of a topic or of every partition of a partitioned topic,
+// which the broker only reports per partition
+async fn compaction_status(
+    admin: &AdminClient,
+    topic: &str,
+) -> Result<Vec<CompactionStatus>, PulsarCatError> {
+    let partitions = admin.get_partition_count(topic).await?;
+    let topics = if partitions == 0 {
+        vec![topic.to_owned()]
+    } else {
+        (0..partitions)
+            .map(|partition| format!("{}-partition-{}", topic, partition))
+            .collect()
+    };
+    let mut statuses = Vec::with_capacity(topics.len());
+    for topic in topics {
+        let status = admin.compaction_status(&topic).await?;
+        statuses.push(CompactionStatus {
+            status: status["status"].as_str().unwrap_or("UNKNOWN").to_owned(),
+            error: status["lastError"].as_str().unwrap_or_default().to_owned(),
+            topic,
+        });
+    }
+    Ok(statuses)
+}
+
+// Wait until no partition of the topic is compacting anymore
+async fn wait_for_compaction(admin: &AdminClient, topic: &str) -> Result<(), PulsarCatError> {
+    loop {
+        let statuses = compaction_status(admin, topic).await?;
+        if statuses.iter().any(|status| status.status == "RUNNING") {
+            tokio::time::sleep(COMPACTION_POLL_INTERVAL).await;
+            continue;
+        }
+        if let Some(failed) = statuses.iter().find(|status| status.status == "ERROR") {
+            return Err(
+                anyhow::anyhow!("Compaction of {} failed: {}", failed.topic, failed.error).into(),
+            );
+        }
+        info!("Compaction of {} completed", topic);
+        return Ok(());
+    }
+}
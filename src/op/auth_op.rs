@@ -0,0 +1,68 @@
+use std::io::{self, BufRead, IsTerminal};
+
+use crate::cli_options::{AuthCommand, AuthLoginOpts, CliOpts};
+use crate::config::selected_profile;
+use crate::error::PulsarCatError;
+use crate::keychain;
+
+use tracing::{info, warn};
+
+pub fn run_auth(cli_opts: &CliOpts, command: &AuthCommand) -> Result<(), PulsarCatError> {
+    let (name, profile) = selected_profile(cli_opts)?;
+    match command {
+        AuthCommand::Login(opts) => {
+            let token = read_token(&name, opts)?;
+            keychain::store_token(&name, &token)?;
+            info!("Stored the token of profile '{}' in the OS keychain", name);
+            if !profile.keyring {
+                warn!(
+                    "Profile '{}' does not use the keychain, set 'keyring = true' in it",
+                    name
+                );
+            } else if profile.auth_token.is_some() || profile.auth_token_file.is_some() {
+                warn!(
+                    "Profile '{}' also sets auth_token or auth_token_file, which take precedence over the keychain",
+                    name
+                );
+            }
+        }
+        AuthCommand::Logout => {
+            if keychain::delete_token(&name)? {
+                info!(
+                    "Removed the token of profile '{}' from the OS keychain",
+                    name
+                );
+            } else {
+                warn!("No token of profile '{}' in the OS keychain", name);
+            }
+        }
+    }
+    Ok(())
+}
+
+// The token from --token-file, a prompt on a terminal or else the first line of stdin
+fn read_token(profile: &str, opts: &AuthLoginOpts) -> Result<String, PulsarCatError> {
+    let token = if let Some(path) = &opts.token_file {
+        std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read token file '{}': {}", path, e))?
+    } else if io::stdin().is_terminal() {
+        dialoguer::Password::new()
+            .with_prompt(format!("Token for profile '{}'", profile))
+            .interact()
+            .map_err(|e| anyhow::anyhow!("Failed to read the token: {}", e))?
+    } else {
+        let mut line = String::new();
+        io::stdin()
+            .lock()
+            .read_line(&mut line)
+            .map_err(|e| anyhow::anyhow!("Failed to read the token from stdin: {}", e))?;
+        line
+    };
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(PulsarCatError::Validation(anyhow::anyhow!(
+            "The token is empty"
+        )));
+    }
+    Ok(token.to_owned())
+}
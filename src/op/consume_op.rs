@@ -1,385 +1,925 @@
-use crate::common::get_base_client;
-use crate::op::OpValidate;
+use crate::admin::AdminClient;
+use crate::common::{
+    client_or_connect, failover, get_base_client, require_existing_partition,
+    require_existing_topic, wait_for_topic,
+};
+use crate::compare::ReplicationComparator;
+use crate::latency::{format_percentiles, latency_histogram, write_histogram};
+use crate::logging::CONFIG_TARGET;
+use crate::op::{OpValidate, expand_topics, validate_namespace};
+use crate::output::{Output, spawn_interval_flush};
+use crate::sink::{Sink, create_sink};
+use crate::stats::{SessionStats, spawn_stats_reporter};
 use crate::{
-    cli_options::{ConsumerOpts, OffsetPosition},
-    error::PulsarCatError,
+    cli_options::{ConsumerOpts, LatencyTimeOpt, PayloadDecompressOpt, SubscriptionTypeOpt},
+    error::{MESSAGE_ERROR_TARGET, PulsarCatError},
 };
 
-use futures::TryStreamExt;
+use hdrhistogram::Histogram;
 use pulsar::proto::KeyValue;
-use pulsar::{SubType, consumer::ConsumerOptions, consumer::InitialPosition};
-use serde_json::json;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use pulsar::{Pulsar, SubType, TokioExecutor};
+use pulsar_cat_core::capture::{CaptureWriter, replay};
+use pulsar_cat_core::checksum::{CHECKSUM_PROPERTY, verify_checksum};
+use pulsar_cat_core::consume::{
+    AckGrouping, AckStrategy, ConsumeOptions, ConsumedMessage, ErrorPolicy, Sampling,
+    StartPosition, consume,
+};
+use pulsar_cat_core::format::format_message_id;
+use pulsar_cat_core::list::is_non_persistent;
+use pulsar_cat_core::payload::{PayloadCompression, decompress_payload};
+use pulsar_cat_core::state::ConsumeState;
+use pulsar_cat_core::transform::Transform;
+use std::borrow::Cow;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+// Ack grouping defaults of the Java client
+const DEFAULT_ACK_GROUPING_TIME_MS: u64 = 100;
+const DEFAULT_ACK_GROUPING_SIZE: u64 = 1000;
+
+pub async fn run_consume(
+    broker: String,
+    admin_url: String,
+    client: Option<Pulsar<TokioExecutor>>,
+    opts: &ConsumerOpts,
+    shutdown: CancellationToken,
+) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+    // Not required by the parser, mock mode takes the same options without it
+    if opts.topic.is_none() && opts.namespace.is_none() {
+        return Err(PulsarCatError::Validation(anyhow::anyhow!(
+            "--topic or --namespace is required"
+        )));
+    }
 
-use std::str;
-use tokio::time::timeout;
+    let topics: Vec<String> = opts
+        .topic
+        .as_ref()
+        .map(expand_topics)
+        .transpose()?
+        .unwrap_or_default()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+
+    let state = opts
+        .state_file
+        .as_deref()
+        .map(ConsumeState::load)
+        .transpose()?;
+
+    let consume_opts = ConsumeOptions {
+        failover: failover(&broker, &opts.auth),
+        ..consume_options(opts, &topics, state.as_ref())
+    };
+    log_effective_config(&broker, opts, &consume_opts);
 
-pub async fn run_consume(broker: String, opts: &ConsumerOpts) -> Result<(), PulsarCatError> {
     // Create Pulsar client
-    let client = get_base_client(&broker, &opts.auth).await?;
-
-    // Prepare consumer options with initial position
-    let consumer_options = if let Some(offset) = &opts.offset {
-        match offset {
-            OffsetPosition::Beginning => {
-                ConsumerOptions::default().with_initial_position(InitialPosition::Earliest)
-            }
-            OffsetPosition::End => {
-                ConsumerOptions::default().with_initial_position(InitialPosition::Latest)
+    let client = client_or_connect(client, &broker, &opts.auth).await?;
+    if let Some(timeout) = opts.wait_for_topic {
+        for topic in &topics {
+            if !wait_for_topic(&client, topic, timeout, &shutdown).await? {
+                return Ok(());
             }
         }
-    } else {
-        ConsumerOptions::default()
-    };
+    }
+    if opts.require_existing_topic {
+        for topic in &topics {
+            require_existing_topic(&client, topic).await?;
+        }
+    }
+    for topic in &topics {
+        require_existing_partition(&client, topic).await?;
+    }
+    if topics.iter().any(|topic| is_non_persistent(topic)) {
+        warn_non_persistent(opts);
+    } else if matches!(
+        opts.offset,
+        Some(StartPosition::Beginning | StartPosition::Timestamp(_))
+    ) {
+        warn_retention(&admin_url, opts).await;
+    }
 
-    // Create consumer with topic and options
-    let mut consumer = client
-        .consumer()
-        .with_topic(&opts.topic)
-        .with_subscription_type(SubType::Exclusive)
-        .with_subscription(format!("pulsar-cat-consumer-{}", generate_consumer_id()))
-        .with_consumer_name(format!("pulsar-cat-{}", generate_consumer_id()))
-        .with_options(consumer_options)
-        .build::<Vec<u8>>()
-        .await?;
-
-    if !opts.display.json {
-        println!("Started consuming from topic: {}", opts.topic);
-        println!("Press Ctrl+C to exit");
+    if let Some(compare_broker) = &opts.compare_broker {
+        return compare_clusters(
+            &client,
+            &broker,
+            compare_broker,
+            opts,
+            &consume_opts,
+            shutdown,
+        )
+        .await;
     }
 
-    let early_exit = opts.exit;
-
-    // Add time tracking for early exit detection
-    let mut last_message_time = SystemTime::now();
-    let mut got_at_least_one_message = false;
-
-    // Use a shorter timeout for faster detection of end of stream
-    const TIMEOUT_DURATION: Duration = Duration::from_millis(300);
-    // Define the maximum idle time before considering the stream finished
-    const MAX_IDLE_TIME: Duration = Duration::from_millis(1000);
-
-    // For empty topics with early exit, check for messages immediately with a timeout
-    if early_exit {
-        // Use a short timeout for the first message check
-        match timeout(TIMEOUT_DURATION, consumer.try_next()).await {
-            // Timeout on first attempt indicates an empty topic
-            Err(_) => {
-                if !opts.display.json {
-                    println!("No messages available in topic (empty topic), exiting...");
-                }
+    process_messages(
+        Messages::Broker(&client),
+        opts,
+        &consume_opts,
+        &admin_url,
+        state,
+        shutdown,
+    )
+    .await
+}
 
-                // Try to close consumer gracefully
-                if let Err(e) = consumer.close().await {
-                    eprintln!("Error closing consumer: {}", e);
-                }
+/// Where [`process_messages`] takes the messages from.
+pub enum Messages<'a> {
+    Broker(&'a Pulsar<TokioExecutor>),
+    /// A capture written with --record
+    Capture(&'a str),
+}
 
-                if !opts.display.json {
-                    println!("Consumer shut down");
-                }
-                return Ok(());
-            }
-            // Got a result from the first attempt
-            Ok(result) => match result {
-                // Found a message, process normally
-                Ok(Some(msg)) => {
-                    got_at_least_one_message = true;
-                    last_message_time = SystemTime::now();
-
-                    // Process the message
-                    let payload = msg.payload.data.as_ref();
-                    let message_id = msg.message_id.clone();
-                    let topic = msg.topic.clone();
-                    let key = msg.key().map(|k| k.to_string());
-                    let publish_time = msg.metadata().publish_time;
-                    let headers = msg.metadata().properties.clone();
-
-                    // Format message according to options
-                    if opts.display.json {
-                        // Output in JSON format
-                        let json_output = json!({
-                            "topic": topic,
-                            "message_id": format!("{:?}", message_id),
-                            "key": key,
-                            "payload": str::from_utf8(payload).unwrap_or("<binary data>"),
-                            "payload_size": payload.len(),
-                            "publish_time": publish_time,
-                        });
-                        println!("{}", serde_json::to_string(&json_output).unwrap());
-                    } else if let Some(format_str) = &opts.display.format {
-                        // Custom format
-                        let formatted = format_message(
-                            format_str,
-                            &topic,
-                            format!("{:?}", message_id).as_str(),
-                            key.as_deref(),
-                            payload,
-                            publish_time,
-                            &headers,
-                        );
-                        println!("{}", formatted);
-                    } else {
-                        // Default format - just the payload
-                        let content = String::from_utf8_lossy(payload);
-                        println!("{}", content);
-                    }
-
-                    // Acknowledge the message
-                    if let Err(e) = consumer.ack(&msg).await {
-                        eprintln!("Failed to acknowledge message: {}", e);
-                    }
-                }
-                // No messages (empty topic) or end of stream
-                Ok(None) => {
-                    if !opts.display.json {
-                        println!("No messages in topic (empty topic), exiting...");
-                    }
-
-                    // Try to close consumer gracefully
-                    if let Err(e) = consumer.close().await {
-                        eprintln!("Error closing consumer: {}", e);
-                    }
-
-                    if !opts.display.json {
-                        println!("Consumer shut down");
-                    }
-                    return Ok(());
-                }
-                // Error consuming
-                Err(e) => {
-                    eprintln!("Error receiving message: {}", e);
+/// The consume options of the command line options, consuming `topics`
+/// and resuming from the positions in `state`.
+pub fn consume_options(
+    opts: &ConsumerOpts,
+    topics: &[String],
+    state: Option<&ConsumeState>,
+) -> ConsumeOptions {
+    ConsumeOptions {
+        // A template names the topics it expands to
+        topic: match topics {
+            [topic] => topic.clone(),
+            _ => opts
+                .topic
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        },
+        topics: if topics.len() > 1 {
+            topics.to_vec()
+        } else {
+            Vec::new()
+        },
+        namespace: opts.namespace.clone(),
+        topic_refresh: opts.topic_refresh,
+        start: if opts.snapshot {
+            Some(StartPosition::Beginning)
+        } else {
+            opts.offset.clone()
+        },
+        exit_at_end: opts.exit || opts.snapshot,
+        stop_at_last_message: opts.snapshot,
+        read_compacted: opts.snapshot,
+        count: opts.count,
+        max_bytes: opts.max_bytes,
+        resume_from: state
+            .map(|state| state.positions().clone())
+            .unwrap_or_default(),
+        show_markers: opts.show_markers,
+        skip_replication_markers: opts.skip_replication_markers,
+        sampling: match (opts.sample, opts.sample_probability) {
+            (Some((n, m)), _) => Some(Sampling::Ratio { n, m }),
+            (None, Some(probability)) => Some(Sampling::Probability(probability)),
+            (None, None) => None,
+        },
+        keep_unsampled: opts.sample_no_ack,
+        trace_id: opts
+            .trace_id
+            .as_ref()
+            .map(|trace_id| trace_id.value.clone()),
+        trace_id_property: opts
+            .trace_id
+            .as_ref()
+            .and_then(|trace_id| trace_id.property.clone()),
+        subscription: opts.subscription.clone(),
+        subscription_type: match opts.subscription_type {
+            SubscriptionTypeOpt::Exclusive => SubType::Exclusive,
+            SubscriptionTypeOpt::Shared => SubType::Shared,
+            SubscriptionTypeOpt::Failover => SubType::Failover,
+            SubscriptionTypeOpt::KeyShared => SubType::KeyShared,
+        },
+        priority_level: opts.priority_level,
+        consumer_name: opts.consumer_name.clone(),
+        consumer_metadata: opts.consumer_metadata.iter().cloned().collect(),
+        ack: opts.ack,
+        ack_grouping: match (opts.ack_grouping_time_ms, opts.ack_grouping_size) {
+            (None, None) => None,
+            (time_ms, size) => Some(AckGrouping {
+                max_delay: Duration::from_millis(time_ms.unwrap_or(DEFAULT_ACK_GROUPING_TIME_MS)),
+                max_size: size.unwrap_or(DEFAULT_ACK_GROUPING_SIZE),
+            }),
+        },
+        on_error: opts.on_error,
+        nack_redelivery_delay: opts.nack_redelivery_delay,
+        on_ack_failure: opts.on_ack_failure,
+        failover: None,
+    }
+}
 
-                    // Try to close consumer gracefully
-                    if let Err(e) = consumer.close().await {
-                        eprintln!("Error closing consumer: {}", e);
-                    }
+/// Decompress, verify and transform every message of `messages`, writing it
+/// to the sink of the output options and recording it with --record.
+pub async fn process_messages(
+    messages: Messages<'_>,
+    opts: &ConsumerOpts,
+    consume_opts: &ConsumeOptions,
+    admin_url: &str,
+    mut state: Option<ConsumeState>,
+    shutdown: CancellationToken,
+) -> Result<(), PulsarCatError> {
+    let transform = opts.transform.as_deref().map(Transform::load).transpose()?;
+    let mut latency = opts
+        .latency
+        .as_ref()
+        .map(|time| LatencyStats::new(matches!(time, LatencyTimeOpt::Event)))
+        .transpose()?;
+
+    let mut checksums = ChecksumVerifier::default();
+
+    let reopen = Arc::new(AtomicBool::new(false));
+    if opts.reopen_on_sighup {
+        request_reopen_on_hangup(reopen.clone())?;
+    }
 
-                    return Err(e.into());
+    // Interactive output shows up immediately, a pipe or files get batched writes
+    let interactive = opts.output_dir_per_partition.is_none() && std::io::stdout().is_terminal();
+    let flush_every = if opts.unbuffered || interactive {
+        Some(1)
+    } else {
+        opts.flush_every
+    };
+    let mut output = Output::new(flush_every);
+    if let Some(dir) = &opts.output_dir_per_partition {
+        output = output.with_partition_dir(dir)?;
+    }
+    let output = Arc::new(Mutex::new(output));
+    let flusher = flush_every
+        .is_none()
+        .then(|| spawn_interval_flush(output.clone()));
+    let mut sink = create_sink(opts, admin_url, output)?;
+    let mut recording = opts
+        .record
+        .as_deref()
+        .map(CaptureWriter::create)
+        .transpose()?;
+
+    let stats = Arc::new(SessionStats::default());
+    let reporter = opts
+        .stats_interval
+        .map(|interval| spawn_stats_reporter(stats.clone(), "Consumed", interval));
+
+    if let Messages::Broker(_) = messages {
+        info!("Press Ctrl+C to exit");
+    }
+    let decompression = opts.payload_decompress.as_ref().map(|opt| match opt {
+        PayloadDecompressOpt::Auto => PayloadCompression::Auto,
+        PayloadDecompressOpt::Gzip => PayloadCompression::Gzip,
+        PayloadDecompressOpt::Zstd => PayloadCompression::Zstd,
+        PayloadDecompressOpt::Lz4 => PayloadCompression::Lz4,
+    });
+    // Stop on Ctrl+C or once consuming --for the given time
+    let stop = async {
+        match opts.for_duration {
+            Some(duration) => tokio::select! {
+                _ = shutdown.cancelled() => {}
+                _ = tokio::time::sleep(duration) => {
+                    info!("Consumed for {}, exiting...", humantime::format_duration(duration));
                 }
             },
+            None => shutdown.cancelled().await,
+        }
+    };
+    let on_message = |msg: &ConsumedMessage| {
+        if let Some(recording) = recording.as_mut() {
+            recording.append(msg)?;
+        }
+        if let Some(latency) = latency.as_mut() {
+            latency.record(msg);
         }
+        stats.record(msg.payload.len());
+        stats.record_publish_time(msg.publish_time);
+        if reopen.swap(false, Ordering::Relaxed) {
+            sink.reopen()?;
+        }
+        // The encoding is shown as received, before any decompression
+        if opts.show_encoding {
+            sink.write(msg)?;
+            return match state.as_mut() {
+                Some(state) => state.record(msg),
+                None => Ok(()),
+            };
+        }
+        if opts.verify_checksum {
+            checksums.verify(msg);
+        }
+        let payload = match decompression {
+            Some(compression) => decompress_payload(msg.payload, compression).unwrap_or_else(|e| {
+                // Show the payload as received rather than skipping the message
+                warn!(
+                    target: MESSAGE_ERROR_TARGET,
+                    kind = "decode",
+                    topic = msg.topic,
+                    message_id = format_message_id(msg.message_id),
+                    "{}",
+                    e
+                );
+                Cow::Borrowed(msg.payload)
+            }),
+            None => Cow::Borrowed(msg.payload),
+        };
+        process_message(
+            transform.as_ref(),
+            sink.as_mut(),
+            &ConsumedMessage {
+                payload: &payload,
+                ..*msg
+            },
+        )?;
+        match state.as_mut() {
+            Some(state) => state.record(msg),
+            None => Ok(()),
+        }
+    };
+    let result = match messages {
+        Messages::Broker(client) => consume(client, consume_opts, stop, on_message).await,
+        Messages::Capture(path) => replay(path, consume_opts, stop, on_message).await,
+    };
+
+    if let Some(flusher) = flusher {
+        flusher.abort();
     }
+    if let Some(reporter) = reporter {
+        reporter.abort();
+    }
+    let finished = sink.finish();
+    let recorded = recording.map_or(Ok(()), CaptureWriter::finish);
+    if let Some(latency) = &latency {
+        latency.log_summary();
+        if let Some(path) = &opts.latency_out {
+            write_histogram(&latency.histogram, path)?;
+            info!("Wrote the latency distribution to {}", path);
+        }
+    }
+    result?;
 
-    // Main consumption loop with a way to exit on Ctrl+C
-    loop {
-        // Always use a timeout when early_exit is enabled to detect end of stream
-        let next_message = if early_exit {
-            timeout(TIMEOUT_DURATION, consumer.try_next()).await
-        } else {
-            Ok(consumer.try_next().await)
-        };
+    if let Some(state) = state.as_mut() {
+        state.save()?;
+    }
+    finished?;
+    recorded?;
+    if opts.verify_checksum {
+        checksums.finish()?;
+    }
+    Ok(())
+}
 
-        // Check if we've been idle too long and should exit
-        if early_exit && got_at_least_one_message {
-            let idle_time = SystemTime::now()
-                .duration_since(last_message_time)
-                .unwrap_or(Duration::from_secs(0));
-            if idle_time > MAX_IDLE_TIME {
-                if !opts.display.json {
-                    println!(
-                        "No new messages received for {} ms, exiting...",
-                        idle_time.as_millis()
-                    );
-                }
-                break;
+// Consume the topic from both clusters at the same time, printing the
+// messages only one of them has
+async fn compare_clusters(
+    client: &Pulsar<TokioExecutor>,
+    broker: &str,
+    compare_broker: &str,
+    opts: &ConsumerOpts,
+    consume_opts: &ConsumeOptions,
+    shutdown: CancellationToken,
+) -> Result<(), PulsarCatError> {
+    let compare_client = get_base_client(compare_broker, &opts.auth).await?;
+    let comparator = Mutex::new(ReplicationComparator::new(
+        opts.compare_by,
+        opts.compare_window,
+        [broker.to_owned(), compare_broker.to_owned()],
+    ));
+
+    // Both stop on Ctrl+C, once consuming --for the given time or when the
+    // other one fails
+    let stop = shutdown.child_token();
+    if let Some(duration) = opts.for_duration {
+        let stop = stop.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            info!(
+                "Consumed for {}, exiting...",
+                humantime::format_duration(duration)
+            );
+            stop.cancel();
+        });
+    }
+    let compare_opts = ConsumeOptions {
+        failover: failover(compare_broker, &opts.auth),
+        ..consume_opts.clone()
+    };
+    let consume_from = |client, consume_opts, index| {
+        let stop = stop.clone();
+        let comparator = &comparator;
+        async move {
+            let result = consume(client, consume_opts, stop.cancelled(), |msg| {
+                comparator.lock().unwrap().record(index, msg);
+                Ok(())
+            })
+            .await;
+            if result.is_err() {
+                stop.cancel();
             }
+            result
         }
+    };
 
-        tokio::select! {
-            // Handle the next message (potentially with timeout)
-            result = async { next_message } => {
-                match result {
-                    // Timeout occurred - check if we should exit
-                    Err(_) if early_exit => {
-                        // If we've already received at least one message and hit a timeout,
-                        // it's likely we've reached the end
-                        if got_at_least_one_message {
-                            let idle_time = SystemTime::now().duration_since(last_message_time).unwrap_or(Duration::from_secs(0));
-                            if idle_time > MAX_IDLE_TIME / 2 {
-                                if !opts.display.json {
-                                    println!("No more messages available after timeout, exiting...");
-                                }
-                                break;
-                            }
-                        } else {
-                            // If we haven't received any messages yet, exit after a few timeouts
-                            if !opts.display.json {
-                                println!("No messages available after timeout, exiting...");
-                            }
-                            break;
-                        }
-                    },
-                    // Got a result from the consumer
-                    Ok(consumer_result) => {
-                        match consumer_result {
-                            Ok(Some(msg)) => {
-                                // Update time tracking for early exit detection
-                                got_at_least_one_message = true;
-                                last_message_time = SystemTime::now();
-
-                                // Access message data
-                                let payload = msg.payload.data.as_ref();
-                                let message_id = msg.message_id.clone();
-                                let topic = msg.topic.clone();
-                                let headers = msg.metadata().properties.clone();
-                                let key = msg.key().map(|k| k.to_string());
-                                // Get publish time - may need to use event time or other timestamp
-                                let publish_time = msg.metadata().publish_time;
-
-                                // Format message according to options
-                                if opts.display.json {
-                                    // Output in JSON format
-                                    let json_output = json!({
-                                        "topic": topic,
-                                        "message_id": format!("{:?}", message_id),
-                                        "key": key,
-                                        "payload": str::from_utf8(payload).unwrap_or("<binary data>"),
-                                        "payload_size": payload.len(),
-                                        "publish_time": publish_time,
-                                    });
-                                    println!("{}", serde_json::to_string(&json_output).unwrap());
-                                } else if let Some(format_str) = &opts.display.format {
-                                    // Custom format
-                                    let formatted = format_message(
-                                        format_str,
-                                        &topic,
-                                        format!("{:?}", message_id).as_str(),
-                                        key.as_deref(),
-                                        payload,
-                                        publish_time,
-                                        &headers
-                                    );
-                                    println!("{}", formatted);
-                                } else {
-                                    // Default format - just the payload
-                                    let content = String::from_utf8_lossy(payload);
-                                    println!("{}", content);
-                                }
-
-                                // Acknowledge the message
-                                if let Err(e) = consumer.ack(&msg).await {
-                                    eprintln!("Failed to acknowledge message: {}", e);
-                                }
-                            },
-                            Ok(None) => {
-                                if !opts.display.json {
-                                    println!("End of stream");
-                                }
-                                if early_exit {
-                                    break;
-                                }
-                            },
-                            Err(e) => {
-                                eprintln!("Error receiving message: {}", e);
-                                break;
-                            }
-                        }
-                    },
-                    // This shouldn't happen since select will choose available branches
-                    _ => {
-                        if early_exit {
-                            break;
-                        }
-                    },
-                }
-            },
+    info!(
+        "Comparing the messages of {} and {}, press Ctrl+C to exit",
+        broker, compare_broker
+    );
+    let consumed = async {
+        tokio::join!(
+            consume_from(client, consume_opts, 0),
+            consume_from(&compare_client, &compare_opts, 1)
+        )
+    };
+    let expire = async {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            comparator.lock().unwrap().expire(Instant::now());
+        }
+    };
+    let (first, second) = tokio::select! {
+        results = consumed => results,
+        _ = expire => unreachable!("expiring runs until consuming ends"),
+    };
+    first?;
+    second?;
+    // Without interruption --exit read both clusters to their end
+    let read_to_end = consume_opts.exit_at_end && !stop.is_cancelled();
+    comparator.into_inner().unwrap().finish(read_to_end)
+}
 
-            _ = tokio::signal::ctrl_c() => {
-                if !opts.display.json {
-                    println!("Received Ctrl+C, shutting down consumer...");
-                }
-                break;
-            }
+// Payload checksums of the consumed messages compared to the ones they were
+// produced with
+#[derive(Default)]
+struct ChecksumVerifier {
+    verified: u64,
+    failed: u64,
+    // Messages produced without a checksum
+    missing: u64,
+}
 
-            // Add a periodic timeout check to ensure we exit if no progress
-            _ = tokio::time::sleep(Duration::from_millis(500)), if early_exit && got_at_least_one_message => {
-                let idle_time = SystemTime::now().duration_since(last_message_time).unwrap_or(Duration::from_secs(0));
-                if idle_time > MAX_IDLE_TIME {
-                    if !opts.display.json {
-                        println!("No new messages received for {} ms, exiting...", idle_time.as_millis());
-                    }
-                    break;
-                }
+impl ChecksumVerifier {
+    fn verify(&mut self, msg: &ConsumedMessage) {
+        let Some(property) = msg
+            .properties
+            .iter()
+            .find(|property| property.key == CHECKSUM_PROPERTY)
+        else {
+            self.missing += 1;
+            return;
+        };
+        let error = match verify_checksum(&property.value, msg.payload) {
+            Ok(true) => {
+                self.verified += 1;
+                return;
             }
-        }
+            Ok(false) => format!("Payload does not match checksum {}", property.value),
+            Err(e) => e,
+        };
+        warn!(
+            target: MESSAGE_ERROR_TARGET,
+            kind = "checksum",
+            topic = msg.topic,
+            message_id = format_message_id(msg.message_id),
+            "{}",
+            error
+        );
+        self.failed += 1;
     }
 
-    // Try to close consumer gracefully
-    if let Err(e) = consumer.close().await {
-        eprintln!("Error closing consumer: {}", e);
+    fn finish(&self) -> Result<(), PulsarCatError> {
+        info!(
+            "Verified checksums of {} messages, {} failed, {} had no checksum",
+            self.verified + self.failed,
+            self.failed,
+            self.missing
+        );
+        if self.failed > 0 {
+            return Err(PulsarCatError::PartialFailure(anyhow::anyhow!(
+                "{} messages failed checksum verification",
+                self.failed
+            )));
+        }
+        Ok(())
     }
+}
 
-    if !opts.display.json {
-        println!("Consumer shut down");
-    }
-    Ok(())
+// End-to-end latency of consumed messages, from their publish or event time
+// to their receipt
+struct LatencyStats {
+    from_event_time: bool,
+    histogram: Histogram<u64>,
+    // Messages timestamped after their receipt, a sign of clock skew
+    ahead: u64,
+    max_ahead_millis: u64,
+    // Messages without an event time
+    missing: u64,
 }
 
-// Format a message according to the format string
-// Placeholders: %t=topic, %p=partition, %o=offset, %k=key, %s=payload, %S=size, %h=headers, %T=timestamp
-fn format_message(
-    format_str: &str,
-    topic: &str,
-    message_id: &str,
-    key: Option<&str>,
-    payload: &[u8],
-    timestamp: u64,
-    headers: &[KeyValue],
-) -> String {
-    let mut result = String::new();
-    let mut in_placeholder = false;
-
-    for c in format_str.chars() {
-        if in_placeholder {
-            match c {
-                't' => result.push_str(topic),
-                'p' => result.push_str(message_id), // Using message_id as the partition equivalent
-                'o' => result.push_str(message_id), // Using message_id as the offset equivalent
-                'k' => result.push_str(key.unwrap_or("")),
-                's' => result.push_str(&String::from_utf8_lossy(payload)),
-                'S' => result.push_str(&payload.len().to_string()),
-                'h' => result.push_str(
-                    &headers
-                        .iter()
-                        .map(|h| format!("{}={}", h.key, h.value))
-                        .collect::<Vec<String>>()
-                        .join(", "),
-                ),
-                'T' => result.push_str(&timestamp.to_string()),
-                '%' => result.push('%'),
-                _ => {
-                    result.push('%');
-                    result.push(c);
+impl LatencyStats {
+    fn new(from_event_time: bool) -> Result<Self, PulsarCatError> {
+        Ok(LatencyStats {
+            from_event_time,
+            histogram: latency_histogram()?,
+            ahead: 0,
+            max_ahead_millis: 0,
+            missing: 0,
+        })
+    }
+
+    fn record(&mut self, msg: &ConsumedMessage) {
+        let time = if self.from_event_time {
+            match msg.event_time {
+                Some(event_time) => event_time,
+                None => {
+                    self.missing += 1;
+                    return;
                 }
             }
-            in_placeholder = false;
-        } else if c == '%' {
-            in_placeholder = true;
         } else {
-            result.push(c);
+            msg.publish_time
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_millis() as u64)
+            .unwrap_or_default();
+        match now.checked_sub(time) {
+            Some(millis) => self.histogram.saturating_record((millis * 1000).max(1)),
+            None => {
+                self.ahead += 1;
+                self.max_ahead_millis = self.max_ahead_millis.max(time - now);
+            }
         }
     }
 
-    // Handle trailing % if any
-    if in_placeholder {
-        result.push('%');
+    fn log_summary(&self) {
+        info!(
+            "End-to-end latency ms of {} messages: {}",
+            self.histogram.len(),
+            format_percentiles(&self.histogram, &[50.0, 95.0, 99.0])
+        );
+        if self.ahead > 0 {
+            warn!(
+                "{} messages were timestamped up to {}ms after their receipt, the clocks are skewed",
+                self.ahead, self.max_ahead_millis
+            );
+        }
+        if self.missing > 0 {
+            warn!(
+                "{} messages without an event time were left out",
+                self.missing
+            );
+        }
     }
+}
+
+// Set `reopen` on every SIGHUP, the archive is reopened before the next message
+#[cfg(unix)]
+fn request_reopen_on_hangup(reopen: Arc<AtomicBool>) -> Result<(), PulsarCatError> {
+    use tokio::signal::unix::{SignalKind, signal};
 
-    result
+    let mut hangup = signal(SignalKind::hangup())
+        .map_err(|e| anyhow::anyhow!("Failed to listen for SIGHUP: {}", e))?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            reopen.store(true, Ordering::Relaxed);
+        }
+    });
+    Ok(())
 }
 
-// Generate a unique consumer ID based on the current timestamp
-fn generate_consumer_id() -> String {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis();
+#[cfg(not(unix))]
+fn request_reopen_on_hangup(_reopen: Arc<AtomicBool>) -> Result<(), PulsarCatError> {
+    Err(PulsarCatError::Validation(anyhow::anyhow!(
+        "--reopen-on-sighup is only supported on Unix"
+    )))
+}
 
-    format!("{}", now)
+fn process_message(
+    transform: Option<&Transform>,
+    sink: &mut dyn Sink,
+    msg: &ConsumedMessage,
+) -> Result<(), PulsarCatError> {
+    let Some(transform) = transform else {
+        return sink.write(msg);
+    };
+    // A message dropped by the transform is still acknowledged
+    if let Some(record) = transform.apply_consume(msg)? {
+        let properties: Vec<KeyValue> = record
+            .properties
+            .into_iter()
+            .map(|(key, value)| KeyValue { key, value })
+            .collect();
+        sink.write(&ConsumedMessage {
+            key: record.key.as_deref(),
+            payload: &record.payload,
+            properties: &properties,
+            ..*msg
+        })?;
+    }
+    Ok(())
+}
+
+// Options that have no effect on a non-persistent topic, which only passes on
+// messages published while consuming and keeps no cursor
+fn warn_non_persistent(opts: &ConsumerOpts) {
+    if matches!(opts.offset, Some(StartPosition::Beginning)) {
+        warn!("Non-persistent topics store no messages, only new messages are consumed");
+    }
+    if opts.ack != AckStrategy::Auto
+        || opts.ack_grouping_time_ms.is_some()
+        || opts.ack_grouping_size.is_some()
+    {
+        warn!("Non-persistent topics are not acknowledged, ignoring the --ack options");
+    }
+}
+
+// Log what the consumer subscribes to and how, once short topic names are
+// resolved, so a misconfiguration shows before messages flow
+fn log_effective_config(broker: &str, opts: &ConsumerOpts, consume_opts: &ConsumeOptions) {
+    let topics = match (&consume_opts.namespace, consume_opts.topics.as_slice()) {
+        (Some(namespace), _) => format!("all topics of {}", namespace),
+        (None, []) => consume_opts.topic.clone(),
+        (None, topics) => topics.join(", "),
+    };
+    let auth = match (&opts.auth.token_file, &opts.auth.token) {
+        (Some(path), _) => format!("token from {}", path),
+        (None, Some(_)) => "token".to_owned(),
+        (None, None) => "none".to_owned(),
+    };
+    let subscription_type = match opts.subscription_type {
+        SubscriptionTypeOpt::Exclusive => "exclusive",
+        SubscriptionTypeOpt::Shared => "shared",
+        SubscriptionTypeOpt::Failover => "failover",
+        SubscriptionTypeOpt::KeyShared => "key-shared",
+    };
+    let start = match &consume_opts.start {
+        None | Some(StartPosition::End) => "end".to_owned(),
+        Some(StartPosition::Beginning) => "beginning".to_owned(),
+        Some(StartPosition::BeforeEnd(entries)) => format!("{} entries before the end", entries),
+        Some(StartPosition::Timestamp(millis)) => {
+            humantime::format_rfc3339_millis(UNIX_EPOCH + Duration::from_millis(*millis))
+                .to_string()
+        }
+        Some(StartPosition::MessageId(id)) => format!("message {}", format_message_id(id)),
+    };
+    let initial_position = match consume_opts.resume_from.len() {
+        0 if consume_opts.start.is_none() && consume_opts.subscription.is_some() => {
+            "the subscription's cursor".to_owned()
+        }
+        0 => start,
+        topics => format!(
+            "after the positions of {} topics in the state file, {} for others",
+            topics, start
+        ),
+    };
+    debug!(
+        target: CONFIG_TARGET,
+        broker,
+        proxy = opts.auth.proxy_url.as_deref().unwrap_or("none"),
+        auth,
+        tls_ca_cert = opts.auth.tls.ca_cert.as_deref().unwrap_or("system"),
+        topics,
+        subscription = consume_opts
+            .subscription
+            .as_deref()
+            .unwrap_or("generated"),
+        subscription_type,
+        initial_position,
+        consumer_name = consume_opts.consumer_name.as_deref().unwrap_or("generated"),
+        "Effective consumer configuration"
+    );
+}
+
+// Reading old data returns fewer messages than expected once the broker
+// deleted them: acknowledged messages are kept for the retention time of the
+// namespace, unacknowledged ones expire after its message TTL. This is best
+// effort, nothing is reported when the admin API cannot be queried.
+async fn warn_retention(admin_url: &str, opts: &ConsumerOpts) {
+    let namespace = match (&opts.namespace, &opts.topic) {
+        (Some(namespace), _) => namespace.clone(),
+        (None, Some(topic)) => match topic.expand().first().and_then(|t| t.namespace()) {
+            Some(namespace) => namespace.to_owned(),
+            None => return,
+        },
+        (None, None) => return,
+    };
+    let policies = async {
+        let admin = AdminClient::new(admin_url, &opts.auth)?;
+        let retention = admin.get_retention(&namespace).await?;
+        let ttl = admin.get_message_ttl(&namespace).await?;
+        Ok::<_, PulsarCatError>((retention, ttl))
+    };
+    let (retention, ttl) = match policies.await {
+        Ok(policies) => policies,
+        Err(e) => {
+            debug!(
+                "Could not read the retention policy of {}: {}",
+                namespace, e
+            );
+            return;
+        }
+    };
+    // Negative means infinite, null means the broker defaults which are unknown here
+    let Some(minutes) = retention["retentionTimeInMinutes"]
+        .as_i64()
+        .filter(|minutes| *minutes >= 0)
+    else {
+        return;
+    };
+    let retention = Duration::from_secs(minutes as u64 * 60);
+    let ttl = ttl
+        .filter(|seconds| *seconds > 0)
+        .map(|seconds| {
+            format!(
+                ", unacknowledged messages expire after {}",
+                humantime::format_duration(Duration::from_secs(seconds))
+            )
+        })
+        .unwrap_or_default();
+
+    match opts.offset {
+        Some(StartPosition::Timestamp(timestamp)) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0);
+            let age = Duration::from_millis(now.saturating_sub(timestamp));
+            if age > retention {
+                warn!(
+                    "Namespace {} keeps acknowledged messages for {}{}: messages published {} ago may already be deleted, fewer messages than expected may be returned",
+                    namespace,
+                    humantime::format_duration(retention),
+                    ttl,
+                    humantime::format_duration(Duration::from_secs(age.as_secs()))
+                );
+            }
+        }
+        _ => warn!(
+            "Namespace {} keeps acknowledged messages for {}{}: the beginning is the oldest message not deleted yet",
+            namespace,
+            humantime::format_duration(retention),
+            ttl
+        ),
+    }
 }
 
 impl OpValidate for ConsumerOpts {
     fn validate(&self) -> Result<(), PulsarCatError> {
+        let topics = self
+            .topic
+            .as_ref()
+            .map(expand_topics)
+            .transpose()?
+            .unwrap_or_default();
+        // Seeking needs a stored backlog
+        if let Some(topic) = topics.iter().find(|topic| !topic.is_persistent()) {
+            let seek = match self.offset {
+                Some(StartPosition::Beginning | StartPosition::End) | None => None,
+                Some(_) => Some("a timestamp, message ID or '-N' --offset"),
+            };
+            if let Some(option) = seek.or(self.state_file.as_ref().map(|_| "--state-file")) {
+                return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                    "Non-persistent topic {} stores no messages, {} cannot be used with it",
+                    topic,
+                    option
+                )));
+            }
+        }
+        if topics.len() > 1 && matches!(self.offset, Some(StartPosition::MessageId(_))) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "A message ID --offset belongs to a single topic, it cannot be used with a topic template"
+            )));
+        }
+        if let Some(namespace) = &self.namespace {
+            validate_namespace(namespace)?;
+            if matches!(self.offset, Some(StartPosition::MessageId(_))) {
+                return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                    "--namespace cannot be used with a message ID --offset"
+                )));
+            }
+            if self.topic_refresh.is_some_and(|refresh| refresh.is_zero()) {
+                return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                    "--topic-refresh must be greater than 0"
+                )));
+            }
+        } else if self.topic_refresh.is_some() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--topic-refresh requires --namespace"
+            )));
+        }
+        if self.display.json && self.display.format.is_some() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--json and --format cannot be used together"
+            )));
+        }
+        if self.state_file.is_some() && matches!(self.offset, Some(StartPosition::MessageId(_))) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--state-file cannot be used with a message ID --offset"
+            )));
+        }
+        if let Some(priority_level) = self.priority_level {
+            if matches!(self.subscription_type, SubscriptionTypeOpt::Exclusive) {
+                return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                    "--priority-level requires --subscription-type shared, key-shared or failover"
+                )));
+            }
+            if priority_level < 0 {
+                return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                    "--priority-level must not be negative"
+                )));
+            }
+        }
+        if (self.ack_grouping_time_ms.is_some() || self.ack_grouping_size.is_some())
+            && self.ack != AckStrategy::Auto
+        {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--ack-grouping-time-ms and --ack-grouping-size require --ack auto"
+            )));
+        }
+        if self.ack_grouping_time_ms == Some(0) || self.ack_grouping_size == Some(0) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--ack-grouping-time-ms and --ack-grouping-size must be at least 1"
+            )));
+        }
+        if self.snapshot {
+            if topics.iter().any(|topic| !topic.is_persistent()) {
+                return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                    "--snapshot needs a persistent topic, non-persistent topics are not compacted"
+                )));
+            }
+            if matches!(
+                self.subscription_type,
+                SubscriptionTypeOpt::Shared | SubscriptionTypeOpt::KeyShared
+            ) {
+                return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                    "--snapshot reads the compacted topic, which requires --subscription-type exclusive or failover"
+                )));
+            }
+        }
+        if self.count == Some(0) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--count must be at least 1"
+            )));
+        }
+        if self
+            .sample_probability
+            .is_some_and(|probability| !(probability > 0.0 && probability <= 1.0))
+        {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--sample-probability must be greater than 0 and at most 1"
+            )));
+        }
+        if self.sample_no_ack && self.sample.is_none() && self.sample_probability.is_none() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--sample-no-ack requires --sample or --sample-probability"
+            )));
+        }
+        if self.summarize_keys == Some(0) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--summarize-keys must be at least 1"
+            )));
+        }
+        if self
+            .histogram
+            .is_some_and(|interval| interval.as_millis() == 0)
+        {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--histogram must be at least 1ms"
+            )));
+        }
+        if self.max_bytes == Some(0) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--max-bytes must be at least 1"
+            )));
+        }
+        if self.post_concurrency == 0 {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--post-concurrency must be at least 1"
+            )));
+        }
+        if self.nack_redelivery_delay.is_some() && self.on_error != ErrorPolicy::Skip {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--nack-redelivery-delay requires --on-error skip"
+            )));
+        }
+        if self
+            .stats_interval
+            .is_some_and(|interval| interval.is_zero())
+        {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--stats-interval must be greater than 0"
+            )));
+        }
+        if self.compare_broker.is_some() && self.compare_window.is_zero() {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--compare-window must be greater than 0"
+            )));
+        }
+        if self.flush_every == Some(0) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--flush-every must be at least 1"
+            )));
+        }
         Ok(())
     }
 }
@@ -0,0 +1,264 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use crate::cli_options::CopyOpts;
+use crate::common::get_base_client;
+use crate::error::{MESSAGE_ERROR_TARGET, PulsarCatError};
+use crate::op::OpValidate;
+
+use futures::TryStreamExt;
+use pulsar::consumer::{ConsumerOptions, InitialPosition, Message};
+use pulsar::producer::{self, SendFuture};
+use pulsar::{Consumer, SubType, TokioExecutor};
+use pulsar_cat_core::consume::ConsumedMessage;
+use pulsar_cat_core::format::format_message_id;
+use pulsar_cat_core::transform::Transform;
+use regex::bytes::Regex;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+// Maximum number of sends awaiting their receipt before reading more messages
+const MAX_IN_FLIGHT: usize = 1000;
+// Time without new source messages after which --exit stops the copy
+const IDLE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+pub async fn run_copy(
+    broker: String,
+    opts: &CopyOpts,
+    shutdown: CancellationToken,
+) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+    let filter = opts
+        .filter
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .map_err(|e| {
+            PulsarCatError::Validation(anyhow::anyhow!("Invalid --filter pattern: {}", e))
+        })?;
+    let transform = opts.transform.as_deref().map(Transform::load).transpose()?;
+
+    let source_client = get_base_client(&broker, &opts.auth).await?;
+    let destination_client = if opts.destination_broker.is_none() && opts.destination_auth.is_none()
+    {
+        source_client.clone()
+    } else {
+        let destination_broker = opts.destination_broker.as_deref().unwrap_or(&broker);
+        let destination_auth = opts.destination_auth.as_ref().unwrap_or(&opts.auth);
+        get_base_client(destination_broker, destination_auth).await?
+    };
+
+    // Without an explicit subscription, read through a non-durable one so no
+    // subscription is left behind on the source topic
+    let consumer_options = ConsumerOptions::default()
+        .with_initial_position(InitialPosition::Earliest)
+        .durable(opts.subscription.is_some());
+    let subscription = opts
+        .subscription
+        .clone()
+        .unwrap_or_else(|| format!("pulsar-cat-copy-{}", std::process::id()));
+
+    let mut consumer: Consumer<Vec<u8>, TokioExecutor> = source_client
+        .consumer()
+        .with_topic(&opts.source_topic)
+        .with_subscription_type(SubType::Exclusive)
+        .with_subscription(subscription)
+        .with_options(consumer_options)
+        .build()
+        .await?;
+
+    if let Some(from_timestamp) = opts.from_timestamp {
+        consumer
+            .seek(None, None, Some(from_timestamp), source_client.clone())
+            .await?;
+    }
+
+    let mut producer = destination_client
+        .producer()
+        .with_topic(&opts.destination_topic)
+        .build()
+        .await?;
+
+    info!(
+        "Copying messages from {} to {}",
+        opts.source_topic, opts.destination_topic
+    );
+
+    let mut copied: u64 = 0;
+    let mut confirmed: u64 = 0;
+    // Messages not matching the filter or dropped by the transform
+    let mut skipped: u64 = 0;
+    // Messages the transform failed for, left unacknowledged
+    let mut failed: u64 = 0;
+    let mut in_flight: VecDeque<(SendFuture, Message<Vec<u8>>)> = VecDeque::new();
+    let result = loop {
+        if opts.count.is_some_and(|count| copied >= count) {
+            break Ok(());
+        }
+
+        let next_message = tokio::select! {
+            next = async {
+                if opts.exit {
+                    timeout(IDLE_TIMEOUT, consumer.try_next()).await
+                } else {
+                    Ok(consumer.try_next().await)
+                }
+            } => next,
+            _ = shutdown.cancelled() => {
+                info!("Stopping copy, waiting for in-flight messages...");
+                break Ok(());
+            }
+        };
+
+        let msg = match next_message {
+            Ok(Ok(Some(msg))) => msg,
+            Ok(Ok(None)) => break Ok(()),
+            Ok(Err(e)) => break Err(e.into()),
+            // Idle timeout with --exit: the source topic is drained
+            Err(_) => break Ok(()),
+        };
+
+        let metadata = msg.metadata();
+        if opts
+            .until
+            .is_some_and(|until| metadata.publish_time > until)
+        {
+            break Ok(());
+        }
+
+        if filter
+            .as_ref()
+            .is_some_and(|filter| !filter.is_match(&msg.payload.data))
+        {
+            skip(&mut consumer, &msg).await;
+            skipped += 1;
+            continue;
+        }
+
+        let mut message = producer::Message {
+            payload: msg.payload.data.clone(),
+            properties: metadata
+                .properties
+                .iter()
+                .map(|kv| (kv.key.clone(), kv.value.clone()))
+                .collect::<HashMap<_, _>>(),
+            partition_key: metadata.partition_key.clone(),
+            ordering_key: metadata.ordering_key.clone(),
+            event_time: metadata.event_time,
+            ..Default::default()
+        };
+        if let Some(transform) = &transform {
+            match transform.apply_consume(&ConsumedMessage::from_message(&msg)) {
+                Ok(Some(record)) => {
+                    message.payload = record.payload;
+                    message.partition_key = record.key;
+                    message.properties = record.properties.into_iter().collect();
+                }
+                Ok(None) => {
+                    skip(&mut consumer, &msg).await;
+                    skipped += 1;
+                    continue;
+                }
+                Err(e) => {
+                    warn!(
+                        target: MESSAGE_ERROR_TARGET,
+                        kind = "transform",
+                        topic = msg.topic,
+                        message_id = format_message_id(msg.message_id()),
+                        "{}",
+                        e
+                    );
+                    failed += 1;
+                    continue;
+                }
+            }
+        }
+        let receipt = match producer.send_non_blocking(message).await {
+            Ok(receipt) => receipt,
+            Err(e) => break Err(e.into()),
+        };
+        in_flight.push_back((receipt, msg));
+        copied += 1;
+
+        if in_flight.len() >= MAX_IN_FLIGHT {
+            match complete_send(&mut consumer, &mut in_flight).await {
+                Ok(()) => confirmed += 1,
+                Err(e) => break Err(e),
+            }
+        }
+    };
+
+    // Source messages are only acknowledged once the destination confirmed them
+    let mut drain_result = Ok(());
+    while !in_flight.is_empty() {
+        match complete_send(&mut consumer, &mut in_flight).await {
+            Ok(()) => confirmed += 1,
+            Err(e) => {
+                drain_result = Err(e);
+                break;
+            }
+        }
+    }
+
+    if let Err(e) = consumer.close().await {
+        warn!("Error closing consumer: {}", e);
+    }
+    if let Err(e) = producer.close().await {
+        warn!("Error closing producer: {}", e);
+    }
+
+    info!(
+        "Copied {} messages from {} to {}",
+        confirmed, opts.source_topic, opts.destination_topic
+    );
+    if skipped > 0 {
+        info!(
+            "Skipped {} messages filtered out or dropped by the transform",
+            skipped
+        );
+    }
+    result.and(drain_result)?;
+    if failed > 0 {
+        return Err(PulsarCatError::PartialFailure(anyhow::anyhow!(
+            "The transform failed for {} messages, they were not copied",
+            failed
+        )));
+    }
+    Ok(())
+}
+
+// Acknowledge a message that is not copied, so it is not read again
+async fn skip(consumer: &mut Consumer<Vec<u8>, TokioExecutor>, msg: &Message<Vec<u8>>) {
+    if let Err(e) = consumer.ack(msg).await {
+        warn!(
+            target: MESSAGE_ERROR_TARGET,
+            kind = "ack",
+            topic = msg.topic,
+            message_id = format_message_id(msg.message_id()),
+            error = %e,
+            "Failed to acknowledge message"
+        );
+    }
+}
+
+// Wait for the oldest in-flight send and acknowledge its source message
+async fn complete_send(
+    consumer: &mut Consumer<Vec<u8>, TokioExecutor>,
+    in_flight: &mut VecDeque<(SendFuture, Message<Vec<u8>>)>,
+) -> Result<(), PulsarCatError> {
+    if let Some((receipt, msg)) = in_flight.pop_front() {
+        receipt.await?;
+        if let Err(e) = consumer.ack(&msg).await {
+            warn!(
+                target: MESSAGE_ERROR_TARGET,
+                kind = "ack",
+                topic = msg.topic,
+                message_id = format_message_id(msg.message_id()),
+                error = %e,
+                "Failed to acknowledge message"
+            );
+        }
+    }
+    Ok(())
+}
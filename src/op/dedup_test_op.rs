@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cli_options::DedupTestOpts;
+use crate::common::get_base_client;
+use crate::error::PulsarCatError;
+use crate::op::OpValidate;
+
+use futures::TryStreamExt;
+use pulsar::consumer::ConsumerOptions;
+use pulsar::producer::Message;
+use pulsar::{Consumer, Pulsar, SubType, TokioExecutor};
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+// Property tagging the messages of a test run, so other traffic on the topic is ignored
+const RUN_PROPERTY: &str = "pulsar-cat-dedup-test";
+// Property with the position of a message in the test sequence
+const SEQUENCE_PROPERTY: &str = "pulsar-cat-dedup-sequence";
+// How long to wait for further messages before the topic is considered read
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Outcome of sending the test sequence a second time.
+#[derive(Default)]
+struct SendCounts {
+    // Stored by the broker
+    accepted: u64,
+    // Confirmed by the broker without storing them
+    duplicates: u64,
+    failed: u64,
+}
+
+pub async fn run_dedup_test(broker: String, opts: DedupTestOpts) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+    let client = get_base_client(&broker, &opts.auth).await?;
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let run = format!("{}-{}", std::process::id(), millis);
+    // Deduplication is tracked per producer name
+    let producer_name = format!("pulsar-cat-dedup-test-{}", run);
+
+    // Subscribe before producing so that every stored message is received
+    let mut consumer: Consumer<Vec<u8>, TokioExecutor> = client
+        .consumer()
+        .with_topic(&opts.topic)
+        .with_subscription_type(SubType::Exclusive)
+        .with_subscription(format!("pulsar-cat-dedup-test-{}", run))
+        .with_options(ConsumerOptions::default().durable(false))
+        .build()
+        .await?;
+
+    // A new producer starts its sequence IDs at 0 again, so the second
+    // producer of the same name sends the sequence IDs of the first one
+    info!(
+        "Sending {} messages to {} as producer {}, then again with the same sequence IDs",
+        opts.count, opts.topic, producer_name
+    );
+    let first = send_sequence(&client, &opts, &producer_name, &run).await?;
+    let second = send_sequence(&client, &opts, &producer_name, &run).await?;
+
+    let copies = read_sequence(&mut consumer, &run, opts.count * 2).await?;
+    if let Err(e) = consumer.close().await {
+        warn!("Error closing consumer: {}", e);
+    }
+
+    let stored: u64 = copies.values().sum();
+    let missing = (0..opts.count)
+        .filter(|sequence| !copies.contains_key(sequence))
+        .count() as u64;
+    let duplicates_stored = copies.values().map(|n| n.saturating_sub(1)).sum::<u64>();
+    println!(
+        "First send:  {} accepted, {} rejected as duplicates, {} failed",
+        first.accepted, first.duplicates, first.failed
+    );
+    println!(
+        "Second send: {} accepted, {} rejected as duplicates, {} failed",
+        second.accepted, second.duplicates, second.failed
+    );
+    println!(
+        "Consumed {} messages: {} of {} sequence IDs, {} stored more than once",
+        stored,
+        copies.len(),
+        opts.count,
+        duplicates_stored
+    );
+
+    if first.failed + second.failed > 0 || missing > 0 {
+        return Err(PulsarCatError::PartialFailure(anyhow::anyhow!(
+            "The test was inconclusive, {} sends failed and {} messages were not consumed",
+            first.failed + second.failed,
+            missing
+        )));
+    }
+    if duplicates_stored > 0 {
+        return Err(PulsarCatError::PartialFailure(anyhow::anyhow!(
+            "Deduplication is not effective on {}: {} of {} duplicates were stored",
+            opts.topic,
+            duplicates_stored,
+            opts.count
+        )));
+    }
+    println!("Deduplication is effective on {}", opts.topic);
+    Ok(())
+}
+
+// Send the test sequence with a producer of the given name, waiting for every receipt
+async fn send_sequence(
+    client: &Pulsar<TokioExecutor>,
+    opts: &DedupTestOpts,
+    producer_name: &str,
+    run: &str,
+) -> Result<SendCounts, PulsarCatError> {
+    let mut producer = client
+        .producer()
+        .with_topic(&opts.topic)
+        .with_name(producer_name)
+        .build()
+        .await?;
+
+    let mut receipts = Vec::with_capacity(opts.count as usize);
+    for sequence in 0..opts.count {
+        let message = Message {
+            payload: format!("dedup-test {} {}", run, sequence).into_bytes(),
+            properties: [
+                (RUN_PROPERTY.to_owned(), run.to_owned()),
+                (SEQUENCE_PROPERTY.to_owned(), sequence.to_string()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        receipts.push(producer.send_non_blocking(message).await?);
+    }
+
+    let mut counts = SendCounts::default();
+    for receipt in receipts {
+        match receipt.await {
+            // Brokers confirm duplicates without storing them, with ID -1:-1
+            Ok(receipt)
+                if receipt
+                    .message_id
+                    .as_ref()
+                    .is_some_and(|id| id.ledger_id == u64::MAX && id.entry_id == u64::MAX) =>
+            {
+                counts.duplicates += 1
+            }
+            Ok(_) => counts.accepted += 1,
+            Err(e) => {
+                warn!("Failed to send message: {}", e);
+                counts.failed += 1;
+            }
+        }
+    }
+    if let Err(e) = producer.close().await {
+        warn!("Error closing producer: {}", e);
+    }
+    Ok(counts)
+}
+
+// Number of stored copies of every sequence ID of the run, reading until
+// `max` messages of the run arrived or no more arrive
+async fn read_sequence(
+    consumer: &mut Consumer<Vec<u8>, TokioExecutor>,
+    run: &str,
+    max: u64,
+) -> Result<BTreeMap<u64, u64>, PulsarCatError> {
+    let mut copies = BTreeMap::new();
+    let mut received = 0;
+    while received < max {
+        let msg = match timeout(READ_TIMEOUT, consumer.try_next()).await {
+            Ok(Ok(Some(msg))) => msg,
+            Ok(Ok(None)) | Err(_) => break,
+            Ok(Err(e)) => return Err(e.into()),
+        };
+        let property = |name: &str| {
+            msg.metadata()
+                .properties
+                .iter()
+                .find(|property| property.key == name)
+                .map(|property| property.value.clone())
+        };
+        if property(RUN_PROPERTY).as_deref() == Some(run)
+            && let Some(sequence) = property(SEQUENCE_PROPERTY).and_then(|s| s.parse().ok())
+        {
+            *copies.entry(sequence).or_default() += 1;
+            received += 1;
+        }
+        if let Err(e) = consumer.ack(&msg).await {
+            warn!("Failed to acknowledge message: {}", e);
+        }
+    }
+    Ok(copies)
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::time::Duration;
+
+use crate::cli_options::FetchOpts;
+use crate::common::get_base_client;
+use crate::error::{MESSAGE_ERROR_TARGET, PulsarCatError};
+use futures::TryStreamExt;
+use pulsar::consumer::{ConsumerOptions, InitialPosition};
+use pulsar::proto::MessageIdData;
+use pulsar::reader::Reader;
+use pulsar::{Pulsar, TokioExecutor};
+use pulsar_cat_core::consume::ConsumedMessage;
+use pulsar_cat_core::format::{
+    format_message, format_message_id, handle_newlines, parse_message_id, payload_text,
+};
+use tokio::time::timeout;
+use tracing::warn;
+
+use crate::op::OpValidate;
+
+// How long to wait for the message after seeking before assuming it does not exist
+const READ_TIMEOUT: Duration = Duration::from_millis(2000);
+
+pub async fn run_fetch(broker: String, opts: FetchOpts) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+    let message_ids = if opts.message_ids.is_empty() {
+        read_message_ids()?
+    } else {
+        opts.message_ids.clone()
+    };
+
+    let pulsar = get_base_client(&broker, &opts.auth).await?;
+    let partitions = pulsar.lookup_partitioned_topic_number(&opts.topic).await?;
+
+    // One reader per partition, seeking for every message
+    let mut readers: HashMap<String, Reader<Vec<u8>, TokioExecutor>> = HashMap::new();
+    let mut missing = 0;
+    for message_id in &message_ids {
+        let topic = match (partitions, message_id.partition) {
+            (0, None | Some(-1)) => opts.topic.to_string(),
+            (0, Some(_)) => {
+                return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                    "Message ID {} has a partition, but {} is not partitioned",
+                    format_message_id(message_id),
+                    opts.topic
+                )));
+            }
+            (_, Some(partition)) if (0..partitions as i32).contains(&partition) => {
+                opts.topic.with_partition(partition as u32).to_string()
+            }
+            _ => {
+                return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                    "Message ID {} does not name one of the {} partitions of {}",
+                    format_message_id(message_id),
+                    partitions,
+                    opts.topic
+                )));
+            }
+        };
+
+        let reader = match readers.get_mut(&topic) {
+            Some(reader) => reader,
+            None => {
+                let reader = create_reader(&pulsar, &topic, readers.len()).await?;
+                readers.entry(topic.clone()).or_insert(reader)
+            }
+        };
+        if !fetch(reader, message_id, &opts).await? {
+            warn!(
+                target: MESSAGE_ERROR_TARGET,
+                kind = "fetch",
+                topic = topic,
+                message_id = format_message_id(message_id),
+                "Message not found"
+            );
+            missing += 1;
+        }
+    }
+
+    if missing > 0 {
+        return Err(PulsarCatError::PartialFailure(anyhow::anyhow!(
+            "{} of {} messages not found",
+            missing,
+            message_ids.len()
+        )));
+    }
+    Ok(())
+}
+
+async fn create_reader(
+    pulsar: &Pulsar<TokioExecutor>,
+    topic: &str,
+    index: usize,
+) -> Result<Reader<Vec<u8>, TokioExecutor>, PulsarCatError> {
+    let reader = pulsar
+        .reader()
+        .with_topic(topic)
+        .with_subscription(format!("pulsar-cat-fetch-{}-{}", std::process::id(), index))
+        .with_options(ConsumerOptions::default().with_initial_position(InitialPosition::Earliest))
+        .into_reader()
+        .await?;
+    Ok(reader)
+}
+
+// Seek to the entry of the message and print it, returning false when the
+// topic has no message with that ID (e.g. it was removed by retention)
+async fn fetch(
+    reader: &mut Reader<Vec<u8>, TokioExecutor>,
+    message_id: &MessageIdData,
+    opts: &FetchOpts,
+) -> Result<bool, PulsarCatError> {
+    // Batched messages share the entry, so the reader is positioned at the
+    // entry and the batch is read up to the requested index
+    let entry_id = MessageIdData {
+        batch_index: None,
+        ..message_id.clone()
+    };
+    reader.seek(Some(entry_id), None).await?;
+
+    let target = message_id_order(message_id);
+    loop {
+        let msg = match timeout(READ_TIMEOUT, reader.try_next()).await {
+            Ok(Ok(Some(msg))) => msg,
+            Ok(Ok(None)) | Err(_) => return Ok(false),
+            Ok(Err(e)) => return Err(e.into()),
+        };
+        let order = message_id_order(msg.message_id());
+        if order < target {
+            continue;
+        }
+        if order > target {
+            return Ok(false);
+        }
+
+        let msg = ConsumedMessage::from_message(&msg);
+        let newlines = opts.display.newline_mode();
+        if opts.display.json {
+            println!("{}", opts.display.message_json(&msg));
+        } else if let Some(payload) = payload_text(&msg, opts.display.binary_mode())? {
+            let payload = opts.display.payload_display(payload);
+            match &opts.display.format {
+                Some(format_str) => {
+                    println!(
+                        "{}",
+                        format_message(
+                            format_str,
+                            &msg,
+                            &payload,
+                            newlines,
+                            opts.display.checksum_algorithm()
+                        )
+                    )
+                }
+                None => println!("{}", handle_newlines(&payload, newlines)),
+            }
+        }
+        return Ok(true);
+    }
+}
+
+// Position of a message within its partition, a message without batch index
+// matches the first message of a batch
+fn message_id_order(id: &MessageIdData) -> (u64, u64, i32) {
+    (
+        id.ledger_id,
+        id.entry_id,
+        id.batch_index.filter(|i| *i >= 0).unwrap_or(0),
+    )
+}
+
+// One message ID per line, empty lines are ignored
+fn read_message_ids() -> Result<Vec<MessageIdData>, PulsarCatError> {
+    let mut message_ids = Vec::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.map_err(|e| anyhow::anyhow!("Failed to read stdin: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message_id =
+            parse_message_id(&line).map_err(|e| PulsarCatError::Validation(anyhow::anyhow!(e)))?;
+        message_ids.push(message_id);
+    }
+    if message_ids.is_empty() {
+        return Err(PulsarCatError::Validation(anyhow::anyhow!(
+            "No message IDs given, use --message-id or pass them on stdin"
+        )));
+    }
+    Ok(message_ids)
+}
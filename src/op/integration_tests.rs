@@ -0,0 +1,197 @@
+//! The ops against a standalone Pulsar in a container, run with
+//! `cargo test --features testsupport`. Every test starts a broker of its
+//! own, which needs a running Docker daemon.
+
+use std::fs;
+
+use clap::Parser;
+use pulsar_cat_core::capture::read_capture;
+use pulsar_cat_core::testsupport::{PulsarContainer, list_topics, temp_path, unique_topic};
+use tokio_util::sync::CancellationToken;
+
+use crate::cli_options::{CliOpts, OpMode};
+use crate::error::PulsarCatError;
+use crate::op::list_op::list_table;
+use crate::op::{run_consume, run_produce};
+
+fn parse(args: &[&str]) -> OpMode {
+    CliOpts::parse_from([&["pulsar-cat"], args].concat()).command
+}
+
+// Send the messages of a `consume --json` capture to `topic`
+async fn produce(pulsar: &PulsarContainer, topic: &str, lines: &[&str]) {
+    let path = temp_path("jsonl");
+    fs::write(&path, lines.join("\n")).unwrap();
+    let OpMode::Producer(opts) = parse(&["produce", "-t", topic, "--replay-file", &path]) else {
+        unreachable!()
+    };
+    let client = pulsar.client().await.unwrap();
+    let result = run_produce(
+        pulsar.broker_url().to_owned(),
+        pulsar.admin_url().to_owned(),
+        Some(client),
+        &opts,
+        CancellationToken::new(),
+    )
+    .await;
+    fs::remove_file(&path).unwrap();
+    result.unwrap();
+}
+
+async fn consume(pulsar: &PulsarContainer, args: &[&str]) -> Result<(), PulsarCatError> {
+    let OpMode::Consumer(opts) = parse(&[&["consume"], args].concat()) else {
+        unreachable!()
+    };
+    let client = pulsar.client().await.unwrap();
+    run_consume(
+        pulsar.broker_url().to_owned(),
+        pulsar.admin_url().to_owned(),
+        Some(client),
+        &opts,
+        CancellationToken::new(),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn consumes_produced_messages_into_a_capture() {
+    let pulsar = PulsarContainer::start().await.unwrap();
+    let topic = unique_topic("produce-consume");
+    produce(
+        &pulsar,
+        &topic,
+        &[
+            r#"{"key":"a","payload":"first"}"#,
+            r#"{"key":null,"payload":"second","event_time":1700000000000}"#,
+            r#"{"key":"a","payload":"third"}"#,
+        ],
+    )
+    .await;
+
+    let capture = temp_path("pcatr");
+    let args = [
+        "-t",
+        &topic,
+        "-o",
+        "beginning",
+        "--exit",
+        "--record",
+        &capture,
+    ];
+    consume(&pulsar, &args).await.unwrap();
+    let messages: Vec<_> = read_capture(&capture)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    fs::remove_file(&capture).unwrap();
+
+    let messages: Vec<_> = messages.iter().map(|msg| msg.as_consumed()).collect();
+    let payloads: Vec<_> = messages.iter().map(|msg| msg.payload).collect();
+    assert_eq!(payloads, [&b"first"[..], b"second", b"third"]);
+    let keys: Vec<_> = messages.iter().map(|msg| msg.key).collect();
+    assert_eq!(keys, [Some("a"), None, Some("a")]);
+    assert_eq!(messages[1].event_time, Some(1_700_000_000_000));
+}
+
+#[tokio::test]
+async fn stops_consuming_after_count() {
+    let pulsar = PulsarContainer::start().await.unwrap();
+    let topic = unique_topic("count");
+    let lines: Vec<_> = (0..5)
+        .map(|i| format!(r#"{{"key":"k","payload":"{}"}}"#, i))
+        .collect();
+    produce(
+        &pulsar,
+        &topic,
+        &lines.iter().map(String::as_str).collect::<Vec<_>>(),
+    )
+    .await;
+
+    let capture = temp_path("pcatr");
+    let args = [
+        "-t",
+        &topic,
+        "-o",
+        "beginning",
+        "-c",
+        "2",
+        "--record",
+        &capture,
+    ];
+    consume(&pulsar, &args).await.unwrap();
+    let count = read_capture(&capture).unwrap().count();
+    fs::remove_file(&capture).unwrap();
+    assert_eq!(count, 2);
+}
+
+#[tokio::test]
+async fn fails_for_a_missing_topic_when_it_is_required() {
+    let pulsar = PulsarContainer::start().await.unwrap();
+    let topic = unique_topic("missing");
+
+    let args = ["-t", &topic, "--exit", "--require-existing-topic"];
+    let result = consume(&pulsar, &args).await;
+    assert!(
+        matches!(result, Err(PulsarCatError::NotFound(_))),
+        "{:?}",
+        result
+    );
+
+    // Not created by looking it up
+    let client = pulsar.client().await.unwrap();
+    let topics = list_topics(&client, "public/default").await.unwrap();
+    assert!(!topics.contains(&topic));
+}
+
+#[tokio::test]
+async fn lists_a_topic_and_its_namespace() {
+    let pulsar = PulsarContainer::start().await.unwrap();
+    let topic = unique_topic("listed");
+    let other = unique_topic("unlisted");
+    produce(&pulsar, &topic, &[r#"{"key":"k","payload":"v"}"#]).await;
+    produce(&pulsar, &other, &[r#"{"key":"k","payload":"v"}"#]).await;
+    let client = pulsar.client().await.unwrap();
+
+    // The lines `list` prints, without the header
+    let list = async |args: &[&str]| {
+        let OpMode::List(opts) = parse(&[&["list", "--no-header"], args].concat()) else {
+            unreachable!()
+        };
+        let table = list_table(pulsar.broker_url(), pulsar.admin_url(), &client, &opts)
+            .await
+            .unwrap();
+        table
+            .render(&opts.table)
+            .lines()
+            .map(|line| line.split_whitespace().map(ToOwned::to_owned).collect())
+            .collect::<Vec<Vec<String>>>()
+    };
+
+    // A non-partitioned topic is listed as its only partition
+    let partitions = list(&["-t", &topic]).await;
+    assert_eq!(partitions.len(), 1, "{:?}", partitions);
+    assert_eq!(partitions[0][1], topic);
+
+    let local_name = topic.rsplit('/').next().unwrap();
+    let topics = list(&["--namespace", "public/default", "--filter", local_name]).await;
+    assert_eq!(topics, [[topic.clone()]]);
+    let topics = list(&["--namespace", "public/default"]).await;
+    assert!(topics.contains(&vec![other]), "{:?}", topics);
+}
+
+#[tokio::test]
+async fn fails_to_list_a_missing_topic() {
+    let pulsar = PulsarContainer::start().await.unwrap();
+    let topic = unique_topic("missing");
+    let client = pulsar.client().await.unwrap();
+
+    let OpMode::List(opts) = parse(&["list", "-t", &topic]) else {
+        unreachable!()
+    };
+    let result = list_table(pulsar.broker_url(), pulsar.admin_url(), &client, &opts).await;
+    assert!(
+        matches!(result, Err(PulsarCatError::NotFound(_))),
+        "{:?}",
+        result.map(|_| ())
+    );
+}
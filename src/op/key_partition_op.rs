@@ -0,0 +1,40 @@
+use crate::cli_options::{HashingSchemeOpt, KeyPartitionOpts};
+use crate::common::get_base_client;
+use crate::error::PulsarCatError;
+use crate::op::OpValidate;
+use crate::table::Table;
+use pulsar_cat_core::routing::{HashingScheme, partition_for_key};
+use tracing::warn;
+
+pub async fn run_key_partition(
+    broker: String,
+    opts: KeyPartitionOpts,
+) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+    let pulsar = get_base_client(&broker, &opts.auth).await?;
+
+    let partitions = pulsar.lookup_partitioned_topic_number(&opts.topic).await?;
+    if partitions == 0 {
+        warn!(
+            "Topic {} is not partitioned, all keys are sent to the topic itself",
+            opts.topic
+        );
+        return Ok(());
+    }
+
+    let scheme = match opts.hashing_scheme {
+        HashingSchemeOpt::JavaString => HashingScheme::JavaString,
+        HashingSchemeOpt::Murmur3 => HashingScheme::Murmur3,
+    };
+    let mut table = Table::new(["KEY", "PARTITION", "TOPIC"]);
+    for key in &opts.keys {
+        let partition = partition_for_key(key, partitions, scheme);
+        table.add_row([
+            key.clone(),
+            partition.to_string(),
+            format!("{}-partition-{}", opts.topic, partition),
+        ]);
+    }
+    table.print(&opts.table);
+    Ok(())
+}
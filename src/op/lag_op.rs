@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use pulsar_cat_core::topic_name::TopicName;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, info};
+
+use crate::admin::AdminClient;
+use crate::cli_options::{LagApplyOpts, LagCommand, LagExportOpts, LagOpts, LagStaleOpts};
+use crate::error::PulsarCatError;
+use crate::op::OpValidate;
+use crate::table::Table;
+
+/// Cursor positions of a topic's subscriptions, as written by `lag export`.
+#[derive(Serialize, Deserialize, Debug)]
+struct CursorPositions {
+    topic: String,
+    /// Number of partitions, 0 for a non-partitioned topic
+    partitions: u64,
+    subscriptions: BTreeMap<String, Vec<PartitionPosition>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PartitionPosition {
+    /// Partition index, -1 for a non-partitioned topic
+    partition: i64,
+    /// Last message acknowledged in order, as `ledger:entry`
+    mark_delete_position: String,
+}
+
+pub async fn run_lag(admin_url: String, opts: LagOpts) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+    let admin = AdminClient::new(&admin_url, &opts.auth)?;
+
+    match &opts.command {
+        LagCommand::Export(export_opts) => export(&admin, export_opts).await,
+        LagCommand::Apply(apply_opts) => apply(&admin, apply_opts).await,
+        LagCommand::Stale(stale_opts) => stale(&admin, stale_opts).await,
+    }
+}
+
+async fn export(admin: &AdminClient, opts: &LagExportOpts) -> Result<(), PulsarCatError> {
+    let topic = opts.topic.to_string();
+    let partitions = admin.get_partition_count(&topic).await?;
+    let mut positions = CursorPositions {
+        topic: topic.clone(),
+        partitions,
+        subscriptions: BTreeMap::new(),
+    };
+
+    for (partition, topic) in partition_topics(&topic, partitions) {
+        let stats = admin.get_internal_stats(&topic).await?;
+        let Some(cursors) = stats["cursors"].as_object() else {
+            continue;
+        };
+        for (subscription, cursor) in cursors {
+            if !opts.subscriptions.is_empty() && !opts.subscriptions.contains(subscription) {
+                continue;
+            }
+            let Some(position) = cursor["markDeletePosition"].as_str() else {
+                continue;
+            };
+            positions
+                .subscriptions
+                .entry(subscription.clone())
+                .or_default()
+                .push(PartitionPosition {
+                    partition,
+                    mark_delete_position: position.to_owned(),
+                });
+        }
+    }
+
+    let content = serde_json::to_string_pretty(&positions)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize cursor positions: {}", e))?;
+    match &opts.output {
+        Some(path) => {
+            std::fs::write(path, content + "\n")
+                .map_err(|e| anyhow::anyhow!("Failed to write '{}': {}", path, e))?;
+            info!(
+                "Exported cursor positions of {} subscriptions on {} to {}",
+                positions.subscriptions.len(),
+                opts.topic,
+                path
+            );
+        }
+        None => println!("{}", content),
+    }
+    Ok(())
+}
+
+async fn apply(admin: &AdminClient, opts: &LagApplyOpts) -> Result<(), PulsarCatError> {
+    let content = std::fs::read_to_string(&opts.file)
+        .map_err(|e| anyhow::anyhow!("Failed to read '{}': {}", opts.file, e))?;
+    let positions: CursorPositions = serde_json::from_str(&content).map_err(|e| {
+        PulsarCatError::Validation(anyhow::anyhow!(
+            "Invalid cursor positions file '{}': {}",
+            opts.file,
+            e
+        ))
+    })?;
+
+    let topic = opts
+        .topic
+        .as_ref()
+        .map_or_else(|| positions.topic.clone(), ToString::to_string);
+    let topic = topic.as_str();
+    let partitions = admin.get_partition_count(topic).await?;
+    if partitions != positions.partitions {
+        return Err(PulsarCatError::Validation(anyhow::anyhow!(
+            "Topic {} has {} partitions, but the positions were exported from {} with {}",
+            topic,
+            partitions,
+            positions.topic,
+            positions.partitions
+        )));
+    }
+
+    let mut applied = 0;
+    for (subscription, partition_positions) in &positions.subscriptions {
+        if !opts.subscriptions.is_empty() && !opts.subscriptions.contains(subscription) {
+            continue;
+        }
+        for position in partition_positions {
+            let partition_topic = match position.partition {
+                -1 => topic.to_owned(),
+                partition => format!("{}-partition-{}", topic, partition),
+            };
+            let message_id = parse_position(&position.mark_delete_position)?;
+            reset_cursor(admin, &partition_topic, subscription, message_id).await?;
+            debug!(
+                "Reset {} on {} to {}",
+                subscription, partition_topic, position.mark_delete_position
+            );
+        }
+        applied += 1;
+    }
+
+    info!(
+        "Applied cursor positions of {} subscriptions to {}",
+        applied, topic
+    );
+    Ok(())
+}
+
+/// A subscription summed up over the partitions of its topic.
+#[derive(Default)]
+struct SubscriptionActivity {
+    backlog: u64,
+    consumers: usize,
+    // Epoch milliseconds of the latest consume or acknowledgement, None when
+    // the broker recorded none
+    last_active: Option<u64>,
+}
+
+async fn stale(admin: &AdminClient, opts: &LagStaleOpts) -> Result<(), PulsarCatError> {
+    let mut subscriptions: BTreeMap<(String, String), SubscriptionActivity> = BTreeMap::new();
+    for partition_topic in admin.get_namespace_topics(&opts.namespace).await? {
+        let stats = admin.get_topic_stats(&partition_topic, true).await?;
+        let Some(topic_subscriptions) = stats["subscriptions"].as_object() else {
+            continue;
+        };
+        let topic = match TopicName::parse(&partition_topic) {
+            Ok(name) => name.partitioned_topic().to_string(),
+            Err(_) => partition_topic.clone(),
+        };
+        for (subscription, stats) in topic_subscriptions {
+            // Non-durable subscriptions go away with their consumers
+            if stats["isDurable"].as_bool() == Some(false) {
+                continue;
+            }
+            let activity = subscriptions
+                .entry((topic.clone(), subscription.clone()))
+                .or_default();
+            activity.backlog += stats["msgBacklog"].as_u64().unwrap_or(0);
+            activity.consumers += stats["consumers"].as_array().map_or(0, Vec::len);
+            activity.last_active = activity.last_active.max(last_active(stats));
+        }
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let mut table = Table::new(["TOPIC", "SUBSCRIPTION", "BACKLOG", "IDLE"]);
+    let mut stale = 0;
+    for ((topic, subscription), activity) in subscriptions {
+        if activity.consumers > 0 || activity.backlog == 0 {
+            continue;
+        }
+        let Some(last_active) = activity.last_active else {
+            debug!(
+                "Skipping subscription {} on {}, the broker has no record of its activity",
+                subscription, topic
+            );
+            continue;
+        };
+        let idle = Duration::from_secs(now.saturating_sub(last_active) / 1000);
+        if idle < opts.older_than {
+            continue;
+        }
+        table.add_row([
+            topic,
+            subscription,
+            activity.backlog.to_string(),
+            humantime::format_duration(idle).to_string(),
+        ]);
+        stale += 1;
+    }
+    table.print(&opts.table);
+    info!(
+        "Found {} subscriptions in {} without consumers that were idle for {} or longer",
+        stale,
+        opts.namespace,
+        humantime::format_duration(opts.older_than)
+    );
+    Ok(())
+}
+
+// Epoch milliseconds of the latest activity of a subscription on a partition.
+// The broker keeps consume and acknowledgement times in memory only, so after
+// the topic was loaded again the publish time of the oldest unacknowledged
+// message stands in for them.
+fn last_active(stats: &Value) -> Option<u64> {
+    let timestamp = |name: &str| stats[name].as_u64().filter(|timestamp| *timestamp > 0);
+    [
+        "lastConsumedTimestamp",
+        "lastAckedTimestamp",
+        "lastMarkDeleteAdvancedTimestamp",
+    ]
+    .into_iter()
+    .filter_map(timestamp)
+    .max()
+    .or_else(|| timestamp("earliestMsgPublishTimeInBacklog"))
+}
+
+// Position the cursor right after the mark-delete position, creating the
+// subscription when it does not exist on the topic yet (e.g. after a migration)
+async fn reset_cursor(
+    admin: &AdminClient,
+    topic: &str,
+    subscription: &str,
+    message_id: (i64, i64),
+) -> Result<(), PulsarCatError> {
+    match admin
+        .reset_cursor(topic, subscription, message_id, true)
+        .await
+    {
+        Err(PulsarCatError::Admin {
+            status: Some(404), ..
+        }) => {
+            info!("Creating subscription {} on {}", subscription, topic);
+            admin
+                .create_subscription(topic, subscription, message_id)
+                .await?;
+            admin
+                .reset_cursor(topic, subscription, message_id, true)
+                .await
+        }
+        result => result,
+    }
+}
+
+// (partition index, topic name) of every partition, -1 for a non-partitioned topic
+fn partition_topics(topic: &str, partitions: u64) -> Vec<(i64, String)> {
+    if partitions == 0 {
+        return vec![(-1, topic.to_owned())];
+    }
+    (0..partitions as i64)
+        .map(|partition| (partition, format!("{}-partition-{}", topic, partition)))
+        .collect()
+}
+
+fn parse_position(position: &str) -> Result<(i64, i64), PulsarCatError> {
+    position
+        .split_once(':')
+        .and_then(|(ledger, entry)| Some((ledger.parse().ok()?, entry.parse().ok()?)))
+        .ok_or_else(|| {
+            PulsarCatError::Validation(anyhow::anyhow!(
+                "Invalid position '{}', expected 'ledger:entry'",
+                position
+            ))
+        })
+}
@@ -1,77 +1,124 @@
-use crate::cli_options::ListOpts;
-use crate::common::get_base_client;
+use crate::admin::AdminClient;
+use crate::cli_options::{ListOpts, TopicDomain};
+use crate::common::{client_or_connect, require_existing_partition};
 use crate::error::PulsarCatError;
-use pulsar::BrokerAddress;
+use crate::table::Table;
 use pulsar::proto::command_get_topics_of_namespace::Mode;
+use pulsar::{Pulsar, TokioExecutor};
+use pulsar_cat_core::list::{namespace_topics, topic_partitions};
+use regex::Regex;
 
 use crate::op::OpValidate;
 
-pub async fn run_list(broker: String, list_opts: ListOpts) -> Result<(), PulsarCatError> {
+pub async fn run_list(
+    broker: String,
+    admin_url: String,
+    client: Option<Pulsar<TokioExecutor>>,
+    list_opts: ListOpts,
+) -> Result<(), PulsarCatError> {
     list_opts.validate()?;
-    let pulsar = get_base_client(&broker, &list_opts.auth).await?;
+    let pulsar = client_or_connect(client, &broker, &list_opts.auth).await?;
+    let table = list_table(&broker, &admin_url, &pulsar, &list_opts).await?;
+    table.print(&list_opts.table);
+    Ok(())
+}
 
-    match (list_opts.topic, list_opts.namespace) {
+/// The partitions of the topic or the topics of the namespace, a not found
+/// error when the topic does not exist.
+pub(crate) async fn list_table(
+    broker: &str,
+    admin_url: &str,
+    pulsar: &Pulsar<TokioExecutor>,
+    list_opts: &ListOpts,
+) -> Result<Table, PulsarCatError> {
+    match (&list_opts.topic, &list_opts.namespace) {
         (Some(topic), None) => {
-            println!(
-                "Printing partition info for topic: {}, service url: {}",
-                &topic, &broker
-            );
-            let partitions = pulsar.lookup_partitioned_topic(&topic).await?;
-            let format_partition_info = |broker_address: &BrokerAddress| -> String {
-                format!(
-                    "{}:{}",
-                    broker_address.url,
-                    if broker_address.proxy {
-                        "(via proxy)"
-                    } else {
-                        ""
-                    }
-                )
+            let topic = topic.to_string();
+            require_existing_partition(pulsar, &topic).await?;
+            let partitions = topic_partitions(pulsar, &topic).await?;
+            if partitions.is_empty() {
+                return Err(PulsarCatError::NotFound(anyhow::anyhow!(
+                    "Topic {} not found",
+                    topic
+                )));
+            }
+
+            // The client cannot select a listener itself, so listener specific
+            // addresses are only displayed, resolved through the admin lookup
+            // endpoint
+            let admin = match &list_opts.show_listener {
+                Some(_) => Some(AdminClient::new(admin_url, &list_opts.auth)?),
+                None => None,
             };
-            match partitions.len() {
-                0 => println!("Topic {} not found", topic),
-                1 => {
-                    println!("Topic {} is a non-partitioned topic", topic);
-                    println!(
-                        "    Partition 0: {}",
-                        format_partition_info(&partitions[0].1)
-                    );
-                }
-                _ => {
-                    println!("Topic {} has {} partitions", topic, partitions.len());
-                    for (i, partition) in partitions.iter().enumerate() {
-                        println!(
-                            "    Partition {}: {}",
-                            i,
-                            format_partition_info(&partition.1)
-                        );
+
+            let mut table = Table::new(["PARTITION", "TOPIC", "BROKER", "PROXY"]);
+            for partition in partitions {
+                let broker_url = match &admin {
+                    Some(admin) => {
+                        let lookup = admin
+                            .lookup_topic(&partition.topic, list_opts.show_listener.as_deref())
+                            .await?;
+                        let key = if broker.starts_with("pulsar+ssl://") {
+                            "brokerUrlTls"
+                        } else {
+                            "brokerUrl"
+                        };
+                        lookup[key].as_str().unwrap_or_default().to_owned()
                     }
-                }
+                    None => partition.broker_url,
+                };
+                table.add_row([
+                    partition.partition.to_string(),
+                    partition.topic,
+                    broker_url,
+                    partition.proxy.to_string(),
+                ]);
             }
+            Ok(table)
         }
         (None, Some(namespace)) => {
-            let topics = pulsar
-                .get_topics_of_namespace(namespace.clone(), Mode::All)
-                .await?;
-            println!(
-                "Printing topics in namespace: {}, service url: {}",
-                namespace, broker
-            );
+            let mode = match list_opts.domain {
+                TopicDomain::Persistent => Mode::Persistent,
+                TopicDomain::NonPersistent => Mode::NonPersistent,
+                TopicDomain::All => Mode::All,
+            };
+
+            let filter = match &list_opts.filter {
+                Some(pattern) if list_opts.regex => Some(Regex::new(pattern)),
+                Some(pattern) => Some(Regex::new(&glob_to_regex(pattern))),
+                None => None,
+            }
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("Invalid --filter pattern: {}", e))?;
+
+            let topics = namespace_topics(pulsar, namespace, mode, filter.as_ref()).await?;
+            let mut table = Table::new(["TOPIC"]);
             for topic in topics {
-                println!("    {}", topic);
+                table.add_row([topic]);
             }
+            Ok(table)
         }
-        _ => {
-            return Err(anyhow::anyhow!(
-                "
+        _ => Err(anyhow::anyhow!(
+            "
             You must provide either a topic or a namespace.
             If you want to list all topics in a namespace, use the --namespace flag.
             If you want to list all partitions in a topic, use the --topic flag.
         "
-            )
-            .into());
-        }
+        )
+        .into()),
     }
+}
 
-    Ok(())
+// Translate a glob pattern (`*`, `?`) into an anchored regular expression
+fn glob_to_regex(pattern: &str) -> String {
+    let mut regex = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
 }
@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+use crate::cli_options::LookupOpts;
+use crate::common::get_base_client;
+use crate::error::PulsarCatError;
+use crate::latency::format_rtt;
+use crate::table::Table;
+use serde_json::json;
+
+use crate::op::OpValidate;
+
+pub async fn run_lookup(broker: String, opts: LookupOpts) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+    let pulsar = get_base_client(&broker, &opts.auth).await?;
+
+    let partitions = pulsar.lookup_partitioned_topic_number(&opts.topic).await?;
+    let topics: Vec<String> = match partitions {
+        0 => vec![opts.topic.to_string()],
+        n => (0..n)
+            .map(|partition| opts.topic.with_partition(partition).to_string())
+            .collect(),
+    };
+
+    let mut table = Table::new([
+        "PARTITION",
+        "TOPIC",
+        "BROKER",
+        "CONNECT_URL",
+        "PROXY",
+        "LOOKUP_TIME",
+    ]);
+    for (partition, topic) in topics.into_iter().enumerate() {
+        let mut rtts = Vec::new();
+        let mut address = None;
+        for _ in 0..opts.count {
+            let start = Instant::now();
+            address = Some(pulsar.lookup_topic(topic.as_str()).await?);
+            rtts.push(start.elapsed());
+        }
+        let Some(address) = address else {
+            continue;
+        };
+
+        let min = rtts.iter().min().copied().unwrap_or_default();
+        let max = rtts.iter().max().copied().unwrap_or_default();
+        let avg = rtts.iter().sum::<Duration>() / rtts.len() as u32;
+        if opts.json {
+            let millis = |rtt: Duration| rtt.as_secs_f64() * 1000.0;
+            println!(
+                "{}",
+                json!({
+                    "partition": partition,
+                    "topic": topic,
+                    "broker_url": address.broker_url,
+                    "connect_url": address.url.to_string(),
+                    "proxy": address.proxy,
+                    "lookup_ms": {
+                        "min": millis(min),
+                        "avg": millis(avg),
+                        "max": millis(max),
+                    },
+                })
+            );
+        } else {
+            let lookup_time = if rtts.len() == 1 {
+                format_rtt(avg)
+            } else {
+                format!(
+                    "{}/{}/{}",
+                    format_rtt(min),
+                    format_rtt(avg),
+                    format_rtt(max)
+                )
+            };
+            table.add_row([
+                partition.to_string(),
+                topic,
+                address.broker_url,
+                address.url.to_string(),
+                address.proxy.to_string(),
+                lookup_time,
+            ]);
+        }
+    }
+    if !opts.json {
+        table.print(&opts.table);
+    }
+
+    Ok(())
+}
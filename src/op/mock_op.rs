@@ -0,0 +1,55 @@
+use tokio_util::sync::CancellationToken;
+
+use crate::cli_options::MockOpts;
+use crate::error::PulsarCatError;
+use crate::op::OpValidate;
+use crate::op::consume_op::{Messages, consume_options, process_messages};
+
+/// Replay a capture through the output pipeline of consume, e.g. to try
+/// formats and transforms without a broker.
+pub async fn run_mock(opts: &MockOpts) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+    let consume_opts = consume_options(&opts.consume, &[], None);
+    // --show-encoding, the only output asking the admin API, is rejected
+    process_messages(
+        Messages::Capture(&opts.replay),
+        &opts.consume,
+        &consume_opts,
+        "",
+        None,
+        CancellationToken::new(),
+    )
+    .await
+}
+
+impl OpValidate for MockOpts {
+    fn validate(&self) -> Result<(), PulsarCatError> {
+        let consume = &self.consume;
+        let broker_option = [
+            (consume.topic.is_some(), "--topic"),
+            (consume.namespace.is_some(), "--namespace"),
+            (consume.offset.is_some(), "--offset"),
+            (consume.snapshot, "--snapshot"),
+            (consume.state_file.is_some(), "--state-file"),
+            (consume.subscription.is_some(), "--subscription"),
+            (consume.wait_for_topic.is_some(), "--wait-for-topic"),
+            (consume.require_existing_topic, "--require-existing-topic"),
+            (consume.compare_broker.is_some(), "--compare-broker"),
+            (consume.show_encoding, "--show-encoding"),
+        ]
+        .into_iter()
+        .find_map(|(given, option)| given.then_some(option));
+        if let Some(option) = broker_option {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "{} needs a broker, mock only replays the messages of --replay",
+                option
+            )));
+        }
+        if consume.record.as_deref() == Some(self.replay.as_str()) {
+            return Err(PulsarCatError::Validation(anyhow::anyhow!(
+                "--record must not overwrite the capture given with --replay"
+            )));
+        }
+        consume.validate()
+    }
+}
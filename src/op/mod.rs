@@ -1,13 +1,80 @@
+mod admin_op;
+mod auth_op;
 mod consume_op;
+mod copy_op;
+mod dedup_test_op;
+mod fetch_op;
+#[cfg(all(test, feature = "testsupport"))]
+mod integration_tests;
+mod key_partition_op;
+mod lag_op;
 mod list_op;
+mod lookup_op;
+mod mock_op;
+mod perf_op;
+mod ping_op;
 mod produce_op;
+mod query_op;
+mod requeue_dlq_op;
+mod simulate_consumers_op;
 
 pub use crate::error::PulsarCatError;
+use pulsar_cat_core::topic_name::{TopicName, is_valid_name};
+use pulsar_cat_core::topic_template::TopicTemplate;
 
 pub trait OpValidate {
     fn validate(&self) -> Result<(), PulsarCatError>;
 }
 
+/// Check that a namespace is in the format of `tenant/namespace`.
+pub fn validate_namespace(namespace: &str) -> Result<(), PulsarCatError> {
+    match namespace.split_once('/') {
+        Some((tenant, ns)) if is_valid_name(tenant) && is_valid_name(ns) => Ok(()),
+        _ => Err(PulsarCatError::Validation(anyhow::anyhow!(
+            "Invalid namespace '{}', should be in the format of 'tenant/namespace' \
+            using only letters, digits and '-', '_', '=', ':', '.'",
+            namespace
+        ))),
+    }
+}
+
+/// Check that a topic names its namespace, short names are resolved with
+/// --default-namespace before.
+pub fn validate_topic(topic: &TopicName) -> Result<(), PulsarCatError> {
+    topic.require_namespace().map_err(|e| {
+        PulsarCatError::Validation(anyhow::anyhow!(
+            "Invalid topic '{}', {}. To use short topic names, set a namespace with --default-namespace",
+            topic,
+            e
+        ))
+    })
+}
+
+/// The topics named by `topic`, which may be a template with brace groups
+/// such as `tenant/ns/events-{0..9}`. Every topic is checked like
+/// [`validate_topic`].
+pub fn expand_topics(topic: &TopicTemplate) -> Result<Vec<TopicName>, PulsarCatError> {
+    let topics = topic.expand();
+    for topic in &topics {
+        validate_topic(topic)?;
+    }
+    Ok(topics)
+}
+
+pub use admin_op::run_admin;
+pub use auth_op::run_auth;
 pub use consume_op::run_consume;
+pub use copy_op::run_copy;
+pub use dedup_test_op::run_dedup_test;
+pub use fetch_op::run_fetch;
+pub use key_partition_op::run_key_partition;
+pub use lag_op::run_lag;
 pub use list_op::run_list;
+pub use lookup_op::run_lookup;
+pub use mock_op::run_mock;
+pub use perf_op::run_perf;
+pub use ping_op::run_ping;
 pub use produce_op::run_produce;
+pub use query_op::run_query;
+pub use requeue_dlq_op::run_requeue_dlq;
+pub use simulate_consumers_op::run_simulate_consumers;
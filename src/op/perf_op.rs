@@ -0,0 +1,315 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::admin::AdminClient;
+use crate::cli_options::{PERF_MIN_MESSAGE_SIZE, PerfOpts};
+use crate::common::get_base_client;
+use crate::error::PulsarCatError;
+use crate::latency::{format_percentiles, latency_histogram, write_histogram};
+use crate::op::OpValidate;
+
+use futures::TryStreamExt;
+use hdrhistogram::Histogram;
+use pulsar::consumer::ConsumerOptions;
+use pulsar::producer::SendFuture;
+use pulsar::{Consumer, Producer, SubType, TokioExecutor};
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+// Maximum number of sends awaiting their receipt before producing more messages
+const MAX_PENDING: usize = 1000;
+// How long to wait for outstanding messages once producing stopped
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+// How often the consumer checks whether it can stop while no messages arrive
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Counters and latency histograms shared by the producer, consumer and reporter.
+struct PerfStats {
+    start: Instant,
+    produced: AtomicU64,
+    send_errors: AtomicU64,
+    consumed: AtomicU64,
+    // Set by the producer when it stopped sending
+    producer_done: Mutex<Option<Instant>>,
+    // Set once the consumer stopped, which ends the benchmark
+    finished: AtomicBool,
+    // Interval and total end-to-end latency in microseconds
+    latency: Mutex<(Histogram<u64>, Histogram<u64>)>,
+}
+
+impl PerfStats {
+    fn new() -> Result<Self, PulsarCatError> {
+        Ok(PerfStats {
+            start: Instant::now(),
+            produced: AtomicU64::new(0),
+            send_errors: AtomicU64::new(0),
+            consumed: AtomicU64::new(0),
+            producer_done: Mutex::new(None),
+            finished: AtomicBool::new(false),
+            latency: Mutex::new((latency_histogram()?, latency_histogram()?)),
+        })
+    }
+
+    // Microseconds since the benchmark started, embedded in every message
+    fn elapsed_micros(&self) -> u64 {
+        self.start.elapsed().as_micros() as u64
+    }
+
+    fn record_latency(&self, micros: u64) {
+        let mut latency = self.latency.lock().unwrap();
+        latency.0.saturating_record(micros.max(1));
+        latency.1.saturating_record(micros.max(1));
+    }
+}
+
+pub async fn run_perf(
+    broker: String,
+    admin_url: String,
+    opts: &PerfOpts,
+    shutdown: CancellationToken,
+) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+
+    if let Some(partitions) = opts.partitions {
+        ensure_partitions(&admin_url, opts, partitions).await?;
+    }
+
+    let client = get_base_client(&broker, &opts.auth).await?;
+
+    // Subscribe before producing so that every produced message is received
+    let consumer: Consumer<Vec<u8>, TokioExecutor> = client
+        .consumer()
+        .with_topic(&opts.topic)
+        .with_subscription_type(SubType::Exclusive)
+        .with_subscription(format!("pulsar-cat-perf-{}", std::process::id()))
+        .with_options(ConsumerOptions::default().durable(false))
+        .build()
+        .await?;
+    let producer = client.producer().with_topic(&opts.topic).build().await?;
+
+    info!(
+        "Benchmarking {} for {} with {} byte messages at {}",
+        opts.topic,
+        humantime::format_duration(opts.duration),
+        opts.size,
+        match opts.rate {
+            0 => "maximum rate".to_owned(),
+            rate => format!("{} msg/s", rate),
+        }
+    );
+
+    let stats = PerfStats::new()?;
+    let (produce_result, consume_result, _) = tokio::join!(
+        produce(producer, opts, &stats, &shutdown),
+        consume(consumer, &stats),
+        report(&stats, opts.size),
+    );
+
+    print_summary(&stats, opts.size);
+    if let Some(path) = &opts.latency_out {
+        write_histogram(&stats.latency.lock().unwrap().1, path)?;
+        info!("Wrote the end-to-end latency distribution to {}", path);
+    }
+    produce_result.and(consume_result)
+}
+
+// Create the topic with the requested partitions, or check an existing one has them
+async fn ensure_partitions(
+    admin_url: &str,
+    opts: &PerfOpts,
+    partitions: u32,
+) -> Result<(), PulsarCatError> {
+    let admin = AdminClient::new(admin_url, &opts.auth)?;
+    let topic = opts.topic.to_string();
+    match admin.get_partition_count(&topic).await? {
+        0 => {
+            admin.create_partitioned_topic(&topic, partitions).await?;
+            info!(
+                "Created topic {} with {} partitions",
+                opts.topic, partitions
+            );
+            Ok(())
+        }
+        existing if existing == u64::from(partitions) => Ok(()),
+        existing => Err(anyhow::anyhow!(
+            "Topic {} already exists with {} partitions",
+            opts.topic,
+            existing
+        )
+        .into()),
+    }
+}
+
+async fn produce(
+    mut producer: Producer<TokioExecutor>,
+    opts: &PerfOpts,
+    stats: &PerfStats,
+    shutdown: &CancellationToken,
+) -> Result<(), PulsarCatError> {
+    let result = produce_messages(&mut producer, opts, stats, shutdown).await;
+    *stats.producer_done.lock().unwrap() = Some(Instant::now());
+    if let Err(e) = producer.close().await {
+        warn!("Error closing producer: {}", e);
+    }
+    result
+}
+
+async fn produce_messages(
+    producer: &mut Producer<TokioExecutor>,
+    opts: &PerfOpts,
+    stats: &PerfStats,
+    shutdown: &CancellationToken,
+) -> Result<(), PulsarCatError> {
+    let start = Instant::now();
+    let deadline = start + opts.duration;
+    let mut payload = vec![0u8; opts.size as usize];
+    let mut pending: VecDeque<SendFuture> = VecDeque::new();
+    let mut sent: u64 = 0;
+
+    while !stats.finished.load(Ordering::Relaxed) {
+        // Pace sends so that message n goes out n / rate seconds after the start
+        let due = match opts.rate {
+            0 => Instant::now(),
+            rate => start + Duration::from_secs_f64(sent as f64 / rate as f64),
+        };
+        if due >= deadline {
+            break;
+        }
+        // Stopping early still waits for the receipts of sent messages
+        tokio::select! {
+            _ = tokio::time::sleep_until(due.into()) => {}
+            _ = shutdown.cancelled() => break,
+        }
+
+        payload[..PERF_MIN_MESSAGE_SIZE as usize]
+            .copy_from_slice(&stats.elapsed_micros().to_be_bytes());
+        pending.push_back(producer.send_non_blocking(payload.clone()).await?);
+        sent += 1;
+
+        if pending.len() >= MAX_PENDING {
+            complete_send(&mut pending, stats).await;
+        }
+    }
+
+    while !pending.is_empty() {
+        complete_send(&mut pending, stats).await;
+    }
+    Ok(())
+}
+
+async fn complete_send(pending: &mut VecDeque<SendFuture>, stats: &PerfStats) {
+    if let Some(receipt) = pending.pop_front() {
+        match receipt.await {
+            Ok(_) => stats.produced.fetch_add(1, Ordering::Relaxed),
+            Err(e) => {
+                error!("Failed to send message: {}", e);
+                stats.send_errors.fetch_add(1, Ordering::Relaxed)
+            }
+        };
+    }
+}
+
+async fn consume(
+    mut consumer: Consumer<Vec<u8>, TokioExecutor>,
+    stats: &PerfStats,
+) -> Result<(), PulsarCatError> {
+    let result = consume_messages(&mut consumer, stats).await;
+    stats.finished.store(true, Ordering::Relaxed);
+    if let Err(e) = consumer.close().await {
+        warn!("Error closing consumer: {}", e);
+    }
+    result
+}
+
+async fn consume_messages(
+    consumer: &mut Consumer<Vec<u8>, TokioExecutor>,
+    stats: &PerfStats,
+) -> Result<(), PulsarCatError> {
+    loop {
+        // Stop once everything produced arrived, or nothing more is expected to
+        if let Some(done_at) = *stats.producer_done.lock().unwrap() {
+            let consumed = stats.consumed.load(Ordering::Relaxed);
+            if consumed >= stats.produced.load(Ordering::Relaxed)
+                || done_at.elapsed() >= DRAIN_TIMEOUT
+            {
+                return Ok(());
+            }
+        }
+
+        let msg = match timeout(POLL_INTERVAL, consumer.try_next()).await {
+            Ok(Ok(Some(msg))) => msg,
+            Ok(Ok(None)) => return Ok(()),
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => continue,
+        };
+
+        let now = stats.elapsed_micros();
+        if let Some(sent_at) = msg.payload.data.get(..PERF_MIN_MESSAGE_SIZE as usize) {
+            let sent_at = u64::from_be_bytes(sent_at.try_into().unwrap());
+            stats.record_latency(now.saturating_sub(sent_at));
+        }
+        stats.consumed.fetch_add(1, Ordering::Relaxed);
+
+        if let Err(e) = consumer.ack(&msg).await {
+            warn!("Failed to acknowledge message: {}", e);
+        }
+    }
+}
+
+// Print throughput and latency of every interval until the benchmark finished
+async fn report(stats: &PerfStats, size: u64) {
+    let mut interval = tokio::time::interval(REPORT_INTERVAL);
+    // The first tick completes immediately
+    interval.tick().await;
+    let mut last_tick = Instant::now();
+    let mut last_produced = 0;
+    let mut last_consumed = 0;
+
+    while !stats.finished.load(Ordering::Relaxed) {
+        interval.tick().await;
+        let seconds = last_tick.elapsed().as_secs_f64();
+        last_tick = Instant::now();
+
+        let produced = stats.produced.load(Ordering::Relaxed);
+        let consumed = stats.consumed.load(Ordering::Relaxed);
+        let produce_rate = (produced - last_produced) as f64 / seconds;
+        let consume_rate = (consumed - last_consumed) as f64 / seconds;
+        last_produced = produced;
+        last_consumed = consumed;
+
+        let mut latency = stats.latency.lock().unwrap();
+        println!(
+            "Produced {:.1} msg/s ({:.2} MiB/s), consumed {:.1} msg/s, latency ms: {}",
+            produce_rate,
+            produce_rate * size as f64 / (1 << 20) as f64,
+            consume_rate,
+            format_percentiles(&latency.0, &[50.0, 99.0])
+        );
+        latency.0.reset();
+    }
+}
+
+fn print_summary(stats: &PerfStats, size: u64) {
+    let seconds = stats.start.elapsed().as_secs_f64();
+    let produced = stats.produced.load(Ordering::Relaxed);
+    let consumed = stats.consumed.load(Ordering::Relaxed);
+    let send_errors = stats.send_errors.load(Ordering::Relaxed);
+    let latency = stats.latency.lock().unwrap();
+
+    println!(
+        "Produced {} messages ({:.1} msg/s, {:.2} MiB/s), consumed {}, send errors {}",
+        produced,
+        produced as f64 / seconds,
+        (produced * size) as f64 / seconds / (1 << 20) as f64,
+        consumed,
+        send_errors
+    );
+    println!(
+        "End-to-end latency ms: {}",
+        format_percentiles(&latency.1, &[50.0, 95.0, 99.0, 99.9])
+    );
+}
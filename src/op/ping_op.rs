@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use crate::admin::AdminClient;
+use crate::cli_options::PingOpts;
+use crate::common::get_base_client;
+use crate::error::PulsarCatError;
+use crate::features::{BrokerFeature, BrokerFeatures, Support};
+use crate::latency::format_rtt;
+use crate::op::OpValidate;
+use crate::table::Table;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+// Time limit of a ping without --timeout
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Ping enforces the global `--timeout` itself, to print the checks that
+/// completed in time.
+pub async fn run_ping(
+    broker: String,
+    admin_url: String,
+    opts: &PingOpts,
+    time_limit: Option<Duration>,
+) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+
+    // Checks that completed are printed even when a later one fails
+    let time_limit = time_limit.unwrap_or(DEFAULT_TIMEOUT);
+    let mut table = Table::new(["CHECK", "TARGET", "TIME", "RESULT"]);
+    let result = timeout(time_limit, ping(&broker, &admin_url, opts, &mut table)).await;
+    table.print(&opts.table);
+
+    result.unwrap_or(Err(PulsarCatError::TimedOut(time_limit)))
+}
+
+async fn ping(
+    broker: &str,
+    admin_url: &str,
+    opts: &PingOpts,
+    table: &mut Table,
+) -> Result<(), PulsarCatError> {
+    let start = Instant::now();
+    let client = get_base_client(broker, &opts.auth).await?;
+    table.add_row([
+        "connect".to_owned(),
+        broker.to_owned(),
+        format_rtt(start.elapsed()),
+        "ok".to_owned(),
+    ]);
+
+    let mut rtts = Vec::new();
+    for _ in 0..opts.count {
+        let start = Instant::now();
+        let address = client.lookup_topic(&opts.topic).await?;
+        let rtt = start.elapsed();
+        table.add_row([
+            "lookup".to_owned(),
+            opts.topic.to_string(),
+            format_rtt(rtt),
+            address.broker_url,
+        ]);
+        rtts.push(rtt);
+    }
+    if let (Some(min), Some(max)) = (rtts.iter().min(), rtts.iter().max()) {
+        let avg = rtts.iter().sum::<Duration>() / rtts.len() as u32;
+        info!(
+            "Lookup round-trip min/avg/max = {}/{}/{}",
+            format_rtt(*min),
+            format_rtt(avg),
+            format_rtt(*max)
+        );
+    }
+
+    let admin = AdminClient::new(admin_url, &opts.auth)?;
+    let start = Instant::now();
+    match BrokerFeatures::detect(&admin).await {
+        Ok(features) => {
+            table.add_row([
+                "version".to_owned(),
+                admin_url.to_owned(),
+                format_rtt(start.elapsed()),
+                features.version.trim().to_owned(),
+            ]);
+            for feature in BrokerFeature::ALL {
+                let support = match features.support(feature) {
+                    Support::Yes => "yes",
+                    Support::No(_) => "no",
+                    Support::Unknown => "unknown",
+                };
+                table.add_row([
+                    "feature".to_owned(),
+                    feature.name().to_owned(),
+                    String::new(),
+                    support.to_owned(),
+                ]);
+            }
+        }
+        // The binary protocol works without the admin API, so this is not a failure
+        Err(e) => warn!("Failed to get the broker version: {}", e),
+    }
+
+    if opts.health_check {
+        let start = Instant::now();
+        let result = admin.health_check().await?;
+        table.add_row([
+            "health-check".to_owned(),
+            admin_url.to_owned(),
+            format_rtt(start.elapsed()),
+            result,
+        ]);
+    }
+
+    Ok(())
+}
@@ -1,193 +1,403 @@
-use crate::{cli_options::ProducerOpts, error::PulsarCatError};
+use crate::{
+    cli_options::{
+        CloudEventsModeOpt, CompressionOpt, HashingSchemeOpt, KeyViolationOpt, MaxMessageSizeOpt,
+        ProducerOpts, ReceiptFormatOpt,
+    },
+    error::{MESSAGE_ERROR_TARGET, PulsarCatError},
+};
 
-use crate::common::get_base_client;
+use crate::admin::AdminClient;
+use crate::common::{
+    client_or_connect, failover, require_existing_partition, require_existing_topic,
+};
 
-use crate::op::OpValidate;
+use crate::op::{OpValidate, expand_topics};
+use crate::source::{open_source, spawn_source};
+use crate::stats::{SessionStats, spawn_stats_reporter};
 use flate2::Compression as Flate2Compression;
+use futures::StreamExt;
 use pulsar::compression::{
     Compression, CompressionLz4, CompressionSnappy, CompressionZlib, CompressionZstd,
 };
-use std::{
-    io::{self, BufRead},
-    sync::Arc,
-};
-use tokio::{
-    sync::{Mutex, broadcast, mpsc, oneshot},
-    task::JoinSet,
+use pulsar::{Pulsar, TokioExecutor};
+use pulsar_cat_core::checksum::{CHECKSUM_PROPERTY, checksum_property};
+use pulsar_cat_core::cloudevents::{CloudEvent, ContentMode};
+use pulsar_cat_core::format::format_message_id;
+use pulsar_cat_core::list::is_non_persistent;
+use pulsar_cat_core::produce::{
+    LOCAL_CLUSTER_ONLY, ProduceOptions, Receipt, Record, SendOutcome, produce,
 };
+use pulsar_cat_core::routing::HashingScheme;
+use pulsar_cat_core::transform::Transform;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
-pub async fn run_produce(broker: String, opts: &ProducerOpts) -> Result<(), PulsarCatError> {
+// Message size limit of brokers that do not configure one
+const DEFAULT_MAX_MESSAGE_SIZE: u64 = 5 * 1024 * 1024;
+
+pub async fn run_produce(
+    broker: String,
+    admin_url: String,
+    client: Option<Pulsar<TokioExecutor>>,
+    opts: &ProducerOpts,
+    shutdown: CancellationToken,
+) -> Result<(), PulsarCatError> {
     opts.validate()?;
+    let topics: Vec<String> = expand_topics(&opts.topic)?
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+
+    let client = client_or_connect(client, &broker, &opts.auth).await?;
+    if opts.require_existing_topic {
+        for topic in &topics {
+            require_existing_topic(&client, topic).await?;
+        }
+    }
+    for topic in &topics {
+        require_existing_partition(&client, topic).await?;
+    }
+    for topic in topics.iter().filter(|topic| is_non_persistent(topic)) {
+        info!(
+            "{} is non-persistent: messages are not stored, only consumers connected while producing receive them",
+            topic
+        );
+    }
+    let fan_out = topics.len() > 1;
+    if fan_out {
+        info!("Sending every message to each of {} topics", topics.len());
+    }
+
+    let compression = match opts.compression {
+        CompressionOpt::None => None,
+        CompressionOpt::Lz4 => Some(Compression::Lz4(CompressionLz4::default())),
+        CompressionOpt::Zlib => Some(Compression::Zlib(CompressionZlib {
+            level: Flate2Compression::default(),
+        })),
+        CompressionOpt::Zstd => Some(Compression::Zstd(CompressionZstd::default())),
+        CompressionOpt::Snappy => Some(Compression::Snappy(CompressionSnappy {})),
+    };
+    let produce_opts = ProduceOptions {
+        topic: match topics.as_slice() {
+            [topic] => topic.clone(),
+            _ => opts.topic.to_string(),
+        },
+        topics: if fan_out { topics } else { Vec::new() },
+        compression,
+        replicate_to: if opts.disable_replication {
+            vec![LOCAL_CLUSTER_ONLY.to_owned()]
+        } else {
+            opts.replicate_to.clone()
+        },
+        route_by_property: opts.route_by_property.clone(),
+        hashing_scheme: match opts.hashing_scheme {
+            HashingSchemeOpt::JavaString => HashingScheme::JavaString,
+            HashingSchemeOpt::Murmur3 => HashingScheme::Murmur3,
+        },
+        producers: opts.producers as usize,
+        // The client does not tell which partition a message went to
+        pin_partitions: opts.pin_partitions || opts.print_receipts.is_some(),
+        max_pending: opts.max_pending,
+        trace_id_property: opts
+            .trace_id_property
+            .as_ref()
+            .map(|trace_id| trace_id.property.clone()),
+        failover: failover(&broker, &opts.auth),
+    };
+    let transform = opts.transform.as_deref().map(Transform::load).transpose()?;
+    let max_message_size = match opts.max_message_size {
+        Some(MaxMessageSizeOpt::Bytes(size)) => Some(size),
+        Some(MaxMessageSizeOpt::Broker) => Some(broker_max_message_size(&admin_url, opts).await),
+        None => None,
+    };
 
-    let client = get_base_client(&broker, &opts.auth).await?;
-
-    // Configure producer builder with options
-    let mut producer_builder = client.producer().with_topic(&opts.topic);
-
-    // Set compression if specified
-    if let crate::cli_options::CompressionOpt::None = opts.compression {
-        // No compression
-    } else {
-        let compression = match opts.compression {
-            crate::cli_options::CompressionOpt::Lz4 => Compression::Lz4(CompressionLz4::default()),
-            crate::cli_options::CompressionOpt::Zlib => Compression::Zlib(CompressionZlib {
-                level: Flate2Compression::default(),
-            }),
-            crate::cli_options::CompressionOpt::Zstd => {
-                Compression::Zstd(CompressionZstd::default())
+    // Properties set on every message, -H taking precedence over the file
+    let mut properties = match &opts.properties_file {
+        Some(path) => load_properties_file(path)?,
+        None => BTreeMap::new(),
+    };
+    properties.extend(opts.headers.iter().cloned());
+
+    // Messages without a key although --enforce_key is set
+    let missing_keys = Arc::new(AtomicU64::new(0));
+    let (source, progress) = open_source(opts, missing_keys.clone())?;
+    let (record_sender, mut record_receiver) = mpsc::channel(opts.pipeline_buffer);
+    // Input that could not be turned into a message, counted as failed
+    let rejected = Arc::new(AtomicU64::new(0));
+    spawn_source(source, record_sender, rejected.clone());
+
+    let stats = Arc::new(SessionStats::default());
+    let reporter = opts
+        .stats_interval
+        .map(|interval| spawn_stats_reporter(stats.clone(), "Produced", interval));
+
+    let records = futures::stream::poll_fn(move |cx| record_receiver.poll_recv(cx))
+        .take_until(async {
+            shutdown.cancelled().await;
+            info!("Stopped reading input, waiting for in-flight messages...");
+        })
+        .map(|mut record| {
+            record.properties.extend(properties.clone());
+            if let Some(trace_id) = &opts.trace_id_property {
+                record
+                    .properties
+                    .entry(trace_id.property.clone())
+                    .or_insert_with(|| {
+                        trace_id
+                            .value
+                            .clone()
+                            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+                    });
             }
-            crate::cli_options::CompressionOpt::Snappy => Compression::Snappy(CompressionSnappy {}),
-            crate::cli_options::CompressionOpt::None => unreachable!(),
-        };
-        producer_builder = producer_builder.with_options(pulsar::ProducerOptions {
-            compression: Some(compression),
-            ..Default::default()
+            record
+        })
+        .filter_map(|record| {
+            let record = apply_transform(record, opts, transform.as_ref(), &rejected)
+                .map(|record| wrap_cloudevent(record, opts))
+                .map(|record| add_checksum(record, opts))
+                .filter(|record| within_size_limit(record, max_message_size, &rejected))
+                .inspect(|record| stats.record(record.payload.len()));
+            async move { record }
         });
+    let summary = produce(&client, &produce_opts, records, |receipt| {
+        if let Some(format) = opts.print_receipts {
+            print_receipt(&receipt, format, fan_out);
+        }
+    })
+    .await;
+    if let Some(reporter) = reporter {
+        reporter.abort();
+    }
+    if let Some(progress) = progress {
+        progress.finish_and_clear();
+    }
+    if let Some(path) = &opts.listen_socket {
+        let _ = std::fs::remove_file(path);
     }
+    let summary = summary?;
 
-    let producer = producer_builder.build().await?;
-    // Wrap producer in Arc<Mutex<>> for sharing between tasks
-    let producer = Arc::new(Mutex::new(producer));
-
-    // Create channels for message processing
-    let (line_sender, line_receiver) = mpsc::channel(100);
-    let (shutdown_sender, _) = broadcast::channel::<()>(1);
-    let (input_done_tx, input_done_rx) = oneshot::channel();
-    let mut shutdown_receiver = shutdown_sender.subscribe();
-
-    // Spawn a task to read from stdin
-    let stdin_reader = tokio::task::spawn_blocking(move || {
-        let stdin = io::stdin();
-        let reader = io::BufReader::new(stdin);
-        let lines = reader.lines();
-
-        // Read lines until EOF
-        for line_result in lines {
-            match line_result {
-                Ok(line) => {
-                    if line.is_empty() {
-                        continue;
-                    }
-
-                    // Try to send the line, if the channel is closed, stop reading
-                    if line_sender.blocking_send(line).is_err() {
-                        break;
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error reading from stdin: {}", e);
-                    break;
-                }
+    info!("Sent {} messages, shutting down", summary.sent);
+    let missing_keys = missing_keys.load(Ordering::Relaxed);
+    if missing_keys > 0 {
+        match opts.on_key_violation {
+            KeyViolationOpt::FailFast => {
+                return Err(anyhow::anyhow!(
+                    "Stopped reading input at a message without a key, --enforce_key is set"
+                )
+                .into());
             }
+            KeyViolationOpt::SkipWarn => {
+                warn!("Skipped {} messages without a key", missing_keys)
+            }
+            KeyViolationOpt::SendKeyless => warn!(
+                "Sent {} messages without a key although --enforce_key is set",
+                missing_keys
+            ),
         }
+    }
+    if summary.dropped > 0 {
+        warn!(
+            "The broker dropped {} messages to the non-persistent topic instead of dispatching them",
+            summary.dropped
+        );
+    }
+    let failed = summary.failed + rejected.load(Ordering::Relaxed);
+    if failed > 0 {
+        return Err(PulsarCatError::PartialFailure(anyhow::anyhow!(
+            "{} of {} messages could not be produced",
+            failed,
+            summary.sent + failed
+        )));
+    }
+    Ok(())
+}
 
-        // Signal that we've reached EOF (all input has been read)
-        let _ = input_done_tx.send(());
-    });
-
-    // Create a JoinSet to manage message processing tasks
-    let mut join_set = JoinSet::new();
-
-    // Clone references for the message processor
-    let producer_ref = producer.clone();
-    let key_delimiter = opts.key.clone();
-    let enforce_key = opts.enforce_key;
-
-    // Clone line_receiver for the message processor
-    let mut processor_line_receiver = line_receiver;
-
-    // Spawn message processor task
-    let message_processor = tokio::spawn(async move {
-        while let Some(line) = processor_line_receiver.recv().await {
-            let producer_task = producer_ref.clone();
-            let key_delim = key_delimiter.clone();
-
-            // Spawn a task for each message
-            join_set.spawn(async move {
-                // Get locked producer for this task
-                let mut producer = producer_task.lock().await;
-
-                // Parse key and value based on delimiter if provided
-                let (message_key, message_data) = if let Some(delimiter) = key_delim {
-                    // Split at the first occurrence of the delimiter
-                    if let Some(delimiter_pos) = line.find(&delimiter) {
-                        let (k, v) = line.split_at(delimiter_pos);
-                        let v = &v[delimiter.len()..]; // Skip the delimiter
-                        (Some(k.to_string()), v.to_string())
-                    } else if enforce_key {
-                        // If key is enforced but delimiter not found
-                        return Err(PulsarCatError::Application(anyhow::anyhow!(
-                            "Key is enforced but delimiter '{}' not found in the message", delimiter
-                        )));
-                    } else {
-                        // No delimiter found, use whole line as data
-                        (None, line)
-                    }
-                } else {
-                    // No delimiter specified
-                    (None, line)
-                };
-
-                // Create message builder
-                let mut message_builder = producer.create_message();
-
-                // Add key if available
-                if let Some(key) = message_key {
-                    message_builder = message_builder.with_key(key);
-                } else if enforce_key {
-                    return Err(PulsarCatError::Application(anyhow::anyhow!(
-                        "Message key is required but not provided, please use --key to set the delimiter."
-                    )));
-                }
-
-                // Set message content and send
-                let message = message_builder
-                    .with_content(message_data.as_bytes()).send_non_blocking()
-                    .await?;
-
-                // Wait for message to be acknowledged
-                message.await?;
-                Ok::<_, PulsarCatError>(())
+// Print the outcome of a send to stdout, naming the topic in text when every
+// message is sent to several, and ending with the trace ID when there is one
+fn print_receipt(receipt: &Receipt, format: ReceiptFormatOpt, with_topic: bool) {
+    let latency_ms = receipt.latency.as_secs_f64() * 1000.0;
+    let (status, message_id, error) = match &receipt.outcome {
+        SendOutcome::Sent(id) => ("sent", Some(format_message_id(id)), None),
+        SendOutcome::Dropped => ("dropped", None, None),
+        SendOutcome::Failed(e) => ("failed", None, Some(e)),
+    };
+    let trace_id = receipt
+        .trace_id
+        .as_ref()
+        .map(|trace_id| format!(" {}", trace_id))
+        .unwrap_or_default();
+    match format {
+        ReceiptFormatOpt::Text if with_topic => println!(
+            "{} {} {} {:.3}{}",
+            receipt.index,
+            receipt.topic,
+            message_id.as_deref().unwrap_or(status),
+            latency_ms,
+            trace_id
+        ),
+        ReceiptFormatOpt::Text => println!(
+            "{} {} {:.3}{}",
+            receipt.index,
+            message_id.as_deref().unwrap_or(status),
+            latency_ms,
+            trace_id
+        ),
+        ReceiptFormatOpt::Json => {
+            let mut json = serde_json::json!({
+                "index": receipt.index,
+                "key": receipt.key,
+                "topic": receipt.topic,
+                "status": status,
+                "message_id": message_id,
+                "latency_ms": (latency_ms * 1000.0).round() / 1000.0,
+                "error": error,
             });
+            if let Some(trace_id) = &receipt.trace_id {
+                json["trace_id"] = serde_json::json!(trace_id);
+            }
+            println!("{}", json);
         }
+    }
+}
 
-        // Wait for all message processing tasks to complete
-        while let Some(result) = join_set.join_next().await {
-            if let Err(e) = result {
-                eprintln!("Error in message processing task: {}", e);
+// Read a JSON object of properties, numbers and booleans are set as text
+fn load_properties_file(path: &str) -> Result<BTreeMap<String, String>, PulsarCatError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read properties file '{}': {}", path, e))?;
+    let invalid = |reason: String| {
+        PulsarCatError::Validation(anyhow::anyhow!(
+            "Invalid properties file '{}': {}",
+            path,
+            reason
+        ))
+    };
+    let json: serde_json::Value =
+        serde_json::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+    let Some(object) = json.as_object() else {
+        return Err(invalid("expected a JSON object".to_owned()));
+    };
+    object
+        .iter()
+        .map(|(name, value)| match value {
+            serde_json::Value::String(value) => Ok((name.clone(), value.clone())),
+            serde_json::Value::Number(_) | serde_json::Value::Bool(_) => {
+                Ok((name.clone(), value.to_string()))
             }
-        }
-    });
-
-    // Set up handler to wait for either:
-    // 1. Ctrl-C signal
-    // 2. Input done (EOF reached)
-    // 3. Shutdown signal
-    tokio::select! {
-        _ = tokio::signal::ctrl_c() => {
-            println!("Received Ctrl-C, shutting down gracefully...");
-        }
-        _ = input_done_rx => {
-            println!("Finished reading input, waiting for messages to be sent...");
-        }
-        _ = shutdown_receiver.recv() => {
-            println!("Shutdown signal received from task, shutting down...");
-        }
+            _ => Err(invalid(format!(
+                "the value of '{}' must be a string, number or boolean",
+                name
+            ))),
+        })
+        .collect()
+}
+
+// Wrap the payload in a CloudEvent when producing CloudEvents
+fn wrap_cloudevent(record: Record, opts: &ProducerOpts) -> Record {
+    let mode = match opts.cloudevents {
+        Some(CloudEventsModeOpt::Structured) => ContentMode::Structured,
+        Some(CloudEventsModeOpt::Binary) => ContentMode::Binary,
+        None => return record,
+    };
+    let topic = opts.topic.to_string();
+    let source = opts.ce_source.as_deref().unwrap_or(&topic);
+    let mut event = CloudEvent::new(&opts.ce_type, source, record.payload);
+    // Properties set by the input or a transform become event extensions
+    for (name, value) in record.properties {
+        event.attributes.entry(name).or_insert(value);
+    }
+    Record {
+        event_time: record.event_time,
+        ..event.into_record(mode, record.key)
     }
+}
 
-    // Trigger shutdown to clean up processing
-    let _ = shutdown_sender.send(());
+// Set the checksum of the final payload, as it will be consumed
+fn add_checksum(mut record: Record, opts: &ProducerOpts) -> Record {
+    if let Some(algorithm) = opts.checksum {
+        let checksum = checksum_property(algorithm.into(), &record.payload);
+        record
+            .properties
+            .insert(CHECKSUM_PROPERTY.to_owned(), checksum);
+    }
+    record
+}
 
-    // Wait for stdin reader to finish
-    if let Err(e) = stdin_reader.await {
-        eprintln!("Error joining stdin reader: {}", e);
+// The maxMessageSize of the broker, or the default when it cannot be read
+async fn broker_max_message_size(admin_url: &str, opts: &ProducerOpts) -> u64 {
+    let size = match AdminClient::new(admin_url, &opts.auth) {
+        Ok(admin) => admin.get_max_message_size().await,
+        Err(e) => Err(e),
+    };
+    match size {
+        Ok(Some(size)) => {
+            info!("The broker accepts messages of up to {} bytes", size);
+            size
+        }
+        Ok(None) => DEFAULT_MAX_MESSAGE_SIZE,
+        Err(e) => {
+            warn!(
+                "Could not read the message size limit of the broker, assuming the default of {} bytes: {}",
+                DEFAULT_MAX_MESSAGE_SIZE, e
+            );
+            DEFAULT_MAX_MESSAGE_SIZE
+        }
     }
+}
 
-    // Wait for message processor to finish
-    if let Err(e) = message_processor.await {
-        eprintln!("Error joining message processor: {}", e);
+// Whether a record is within the message size limit, logging and counting it
+// when it is not
+fn within_size_limit(record: &Record, limit: Option<u64>, rejected: &AtomicU64) -> bool {
+    let Some(limit) = limit else {
+        return true;
+    };
+    let size = record.payload.len()
+        + record.key.as_ref().map_or(0, String::len)
+        + record
+            .properties
+            .iter()
+            .map(|(name, value)| name.len() + value.len())
+            .sum::<usize>();
+    if size as u64 <= limit {
+        return true;
     }
+    error!(
+        target: MESSAGE_ERROR_TARGET,
+        kind = "size",
+        key = record.key,
+        "Message of {} bytes is larger than the limit of {} bytes, not sending it",
+        size,
+        limit
+    );
+    rejected.fetch_add(1, Ordering::Relaxed);
+    false
+}
 
-    println!("All tasks completed, shutting down");
-    Ok(())
+// Run the record through the transform script, None drops it
+fn apply_transform(
+    record: Record,
+    opts: &ProducerOpts,
+    transform: Option<&Transform>,
+    rejected: &AtomicU64,
+) -> Option<Record> {
+    match transform {
+        Some(transform) => match transform.apply_produce(&opts.topic.to_string(), record) {
+            Ok(record) => record,
+            Err(e) => {
+                error!(
+                    target: MESSAGE_ERROR_TARGET,
+                    kind = "transform",
+                    topic = %opts.topic,
+                    "{}",
+                    e
+                );
+                rejected.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        },
+        None => Some(record),
+    }
 }
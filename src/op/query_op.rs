@@ -0,0 +1,73 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::cli_options::QueryOpts;
+use crate::common::get_base_client;
+use crate::error::PulsarCatError;
+use crate::table::Table;
+use futures::TryStreamExt;
+use pulsar::TokioExecutor;
+use pulsar::consumer::{ConsumerOptions, InitialPosition};
+use pulsar::reader::Reader;
+use pulsar_cat_core::format::format_message_id;
+use tokio::time::timeout;
+use tracing::warn;
+
+use crate::op::OpValidate;
+
+// How long to wait for a message after seeking before assuming there is none
+const READ_TIMEOUT: Duration = Duration::from_millis(2000);
+
+pub async fn run_query(broker: String, opts: QueryOpts) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+    let pulsar = get_base_client(&broker, &opts.auth).await?;
+
+    let partitions = pulsar.lookup_partitioned_topic(&opts.topic).await?;
+    if partitions.is_empty() {
+        warn!("Topic {} not found", opts.topic);
+        return Ok(());
+    }
+
+    let mut table = Table::new(["PARTITION", "TOPIC", "MESSAGE_ID", "PUBLISH_TIME"]);
+    for (i, (partition_topic, _)) in partitions.iter().enumerate() {
+        let mut reader: Reader<Vec<u8>, TokioExecutor> = pulsar
+            .reader()
+            .with_topic(partition_topic)
+            .with_subscription(format!("pulsar-cat-query-{}-{}", std::process::id(), i))
+            .with_options(
+                ConsumerOptions::default().with_initial_position(InitialPosition::Earliest),
+            )
+            .into_reader()
+            .await?;
+
+        // The broker binary-searches the ledgers for the first entry published
+        // at or after the timestamp and positions the reader there
+        reader.seek(None, Some(opts.timestamp)).await?;
+        let found = match timeout(READ_TIMEOUT, reader.try_next()).await {
+            Ok(Ok(Some(msg))) => Some((
+                format_message_id(msg.message_id()),
+                msg.metadata().publish_time,
+            )),
+            Ok(Ok(None)) | Err(_) => None,
+            Ok(Err(e)) => return Err(e.into()),
+        };
+
+        // An empty message ID means no message was published at or after the timestamp
+        let (message_id, publish_time) = match found {
+            Some((message_id, publish_time)) => (
+                message_id,
+                humantime::format_rfc3339_millis(UNIX_EPOCH + Duration::from_millis(publish_time))
+                    .to_string(),
+            ),
+            None => (String::new(), String::new()),
+        };
+        table.add_row([
+            i.to_string(),
+            partition_topic.clone(),
+            message_id,
+            publish_time,
+        ]);
+    }
+    table.print(&opts.table);
+
+    Ok(())
+}
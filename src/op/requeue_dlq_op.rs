@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::cli_options::RequeueDlqOpts;
+use crate::common::get_base_client;
+use crate::error::{MESSAGE_ERROR_TARGET, PulsarCatError};
+use crate::op::OpValidate;
+use crate::table::Table;
+
+use futures::TryStreamExt;
+use pulsar::consumer::{ConsumerOptions, InitialPosition, Message};
+use pulsar::producer;
+use pulsar::{Consumer, Producer, Pulsar, SubType, TokioExecutor};
+use pulsar_cat_core::format::format_message_id;
+use pulsar_cat_core::topic_name::TopicName;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+// Properties the Java client adds when it sends a message to the retry or
+// dead letter topic, they are removed again when requeuing
+const REAL_TOPIC: &str = "REAL_TOPIC";
+const ORIGIN_MESSAGE_ID: &str = "ORIGIN_MESSAGE_ID";
+const DLQ_PROPERTIES: [&str; 4] = [
+    REAL_TOPIC,
+    ORIGIN_MESSAGE_ID,
+    "RECONSUMETIMES",
+    "DELAY_TIME",
+];
+// Time without new messages after which the dead letter topic is considered drained
+const IDLE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+pub async fn run_requeue_dlq(
+    broker: String,
+    opts: &RequeueDlqOpts,
+    shutdown: CancellationToken,
+) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+    let pulsar = get_base_client(&broker, &opts.auth).await?;
+
+    // A dry run reads through a non-durable subscription, leaving no trace on the DLQ
+    let subscription = if opts.dry_run {
+        format!("pulsar-cat-requeue-dlq-{}", std::process::id())
+    } else {
+        opts.subscription.clone()
+    };
+    let mut consumer: Consumer<Vec<u8>, TokioExecutor> = pulsar
+        .consumer()
+        .with_topic(&opts.dlq_topic)
+        .with_subscription_type(SubType::Exclusive)
+        .with_subscription(subscription)
+        .with_options(
+            ConsumerOptions::default()
+                .with_initial_position(InitialPosition::Earliest)
+                .durable(!opts.dry_run),
+        )
+        .build()
+        .await?;
+
+    info!("Requeuing messages from {}", opts.dlq_topic);
+
+    let mut producers: HashMap<String, Producer<TokioExecutor>> = HashMap::new();
+    let mut table = Table::new(["MESSAGE_ID", "KEY", "ORIGIN_MESSAGE_ID", "TARGET"]);
+    let mut requeued: u64 = 0;
+    let mut filtered: u64 = 0;
+    let mut without_origin: u64 = 0;
+    let result = loop {
+        if opts.count.is_some_and(|count| requeued >= count) {
+            break Ok(());
+        }
+
+        let next_message = tokio::select! {
+            next = timeout(IDLE_TIMEOUT, consumer.try_next()) => next,
+            _ = shutdown.cancelled() => {
+                info!("Stopping requeue");
+                break Ok(());
+            }
+        };
+        let msg = match next_message {
+            Ok(Ok(Some(msg))) => msg,
+            Ok(Ok(None)) | Err(_) => break Ok(()),
+            Ok(Err(e)) => break Err(e.into()),
+        };
+
+        let metadata = msg.metadata();
+        if opts
+            .until
+            .is_some_and(|until| metadata.publish_time > until)
+        {
+            break Ok(());
+        }
+
+        let properties: HashMap<String, String> = metadata
+            .properties
+            .iter()
+            .map(|kv| (kv.key.clone(), kv.value.clone()))
+            .collect();
+        if !matches_filters(opts, &msg, &properties) {
+            filtered += 1;
+            continue;
+        }
+
+        let Some(target) = opts
+            .target
+            .as_ref()
+            .map(ToString::to_string)
+            .or_else(|| properties.get(REAL_TOPIC).cloned())
+        else {
+            warn!(
+                target: MESSAGE_ERROR_TARGET,
+                kind = "requeue",
+                topic = msg.topic,
+                message_id = format_message_id(msg.message_id()),
+                "Message has no {} property, use --target to requeue it",
+                REAL_TOPIC
+            );
+            without_origin += 1;
+            continue;
+        };
+
+        if opts.dry_run {
+            table.add_row([
+                format_message_id(msg.message_id()),
+                metadata.partition_key.clone().unwrap_or_default(),
+                properties
+                    .get(ORIGIN_MESSAGE_ID)
+                    .cloned()
+                    .unwrap_or_default(),
+                target,
+            ]);
+            requeued += 1;
+            continue;
+        }
+
+        if let Err(e) = requeue(&pulsar, &mut producers, &target, &msg, properties).await {
+            break Err(e);
+        }
+        // Only acknowledged once the origin topic has the message again
+        if let Err(e) = consumer.ack(&msg).await {
+            warn!(
+                target: MESSAGE_ERROR_TARGET,
+                kind = "ack",
+                topic = msg.topic,
+                message_id = format_message_id(msg.message_id()),
+                error = %e,
+                "Failed to acknowledge message, it will be requeued again by the next run"
+            );
+        }
+        debug!(
+            "Requeued {} to {}",
+            format_message_id(msg.message_id()),
+            target
+        );
+        requeued += 1;
+    };
+
+    if let Err(e) = consumer.close().await {
+        warn!("Error closing consumer: {}", e);
+    }
+    for (_, mut producer) in producers {
+        if let Err(e) = producer.close().await {
+            warn!("Error closing producer: {}", e);
+        }
+    }
+
+    if opts.dry_run {
+        table.print(&opts.table);
+        info!(
+            "{} messages would be requeued from {}",
+            requeued, opts.dlq_topic
+        );
+    } else {
+        info!("Requeued {} messages from {}", requeued, opts.dlq_topic);
+    }
+    if filtered > 0 {
+        info!(
+            "{} messages did not match the filters and were left in the DLQ",
+            filtered
+        );
+    }
+    result?;
+
+    if without_origin > 0 {
+        return Err(PulsarCatError::PartialFailure(anyhow::anyhow!(
+            "{} messages without an origin topic were left in {}",
+            without_origin,
+            opts.dlq_topic
+        )));
+    }
+    Ok(())
+}
+
+// Republish the message with its key, properties and event time, waiting
+// for the receipt so the DLQ message is only acknowledged once stored
+async fn requeue(
+    pulsar: &Pulsar<TokioExecutor>,
+    producers: &mut HashMap<String, Producer<TokioExecutor>>,
+    target: &str,
+    msg: &Message<Vec<u8>>,
+    mut properties: HashMap<String, String>,
+) -> Result<(), PulsarCatError> {
+    let producer = match producers.get_mut(target) {
+        Some(producer) => producer,
+        None => {
+            info!("Requeuing to {}", target);
+            let producer = pulsar.producer().with_topic(target).build().await?;
+            producers.entry(target.to_owned()).or_insert(producer)
+        }
+    };
+
+    properties.retain(|name, _| !DLQ_PROPERTIES.contains(&name.as_str()));
+    let metadata = msg.metadata();
+    let message = producer::Message {
+        payload: msg.payload.data.clone(),
+        properties,
+        partition_key: metadata.partition_key.clone(),
+        ordering_key: metadata.ordering_key.clone(),
+        event_time: metadata.event_time,
+        ..Default::default()
+    };
+    producer.send_non_blocking(message).await?.await?;
+    Ok(())
+}
+
+fn matches_filters(
+    opts: &RequeueDlqOpts,
+    msg: &Message<Vec<u8>>,
+    properties: &HashMap<String, String>,
+) -> bool {
+    if let Some(origin_topic) = &opts.origin_topic {
+        // Messages of a partitioned topic name the partition they came from
+        let matches_origin = properties.get(REAL_TOPIC).is_some_and(|real_topic| {
+            TopicName::parse(real_topic).is_ok_and(|name| {
+                name == *origin_topic || name.partitioned_topic() == *origin_topic
+            })
+        });
+        if !matches_origin {
+            return false;
+        }
+    }
+    if !opts.keys.is_empty()
+        && !msg
+            .metadata()
+            .partition_key
+            .as_ref()
+            .is_some_and(|key| opts.keys.contains(key))
+    {
+        return false;
+    }
+    opts.properties
+        .iter()
+        .all(|(name, value)| properties.get(name) == Some(value))
+}
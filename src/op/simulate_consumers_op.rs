@@ -0,0 +1,187 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::cli_options::{SimulateConsumersOpts, SubscriptionTypeOpt};
+use crate::common::get_base_client;
+use crate::error::PulsarCatError;
+use crate::op::OpValidate;
+use crate::table::Table;
+
+use futures::TryStreamExt;
+use pulsar::{Consumer, Pulsar, SubType, TokioExecutor};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Messages and keys one simulated consumer received.
+#[derive(Default)]
+struct InstanceStats {
+    messages: u64,
+    keys: HashSet<String>,
+}
+
+pub async fn run_simulate_consumers(
+    broker: String,
+    opts: &SimulateConsumersOpts,
+    shutdown: CancellationToken,
+) -> Result<(), PulsarCatError> {
+    opts.validate()?;
+    let client = get_base_client(&broker, &opts.auth).await?;
+
+    // All consumers are connected before any of them reads, so the broker
+    // dispatches across the full set from the first message on
+    let mut consumers = Vec::with_capacity(opts.instances as usize);
+    for instance in 0..opts.instances {
+        consumers.push(subscribe(&client, opts, instance).await?);
+    }
+    info!(
+        "Started {} consumers on subscription {} of {}",
+        opts.instances, opts.subscription, opts.topic
+    );
+
+    // Each consumer reports the key of every message it receives
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let stop = shutdown.child_token();
+    let mut tasks = JoinSet::new();
+    for (instance, consumer) in consumers.into_iter().enumerate() {
+        tasks.spawn(receive(consumer, instance, sender.clone(), stop.clone()));
+    }
+    drop(sender);
+
+    let deadline = async {
+        match opts.for_duration {
+            Some(duration) => tokio::time::sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+
+    let mut stats: Vec<InstanceStats> = (0..opts.instances)
+        .map(|_| InstanceStats::default())
+        .collect();
+    let mut received = 0;
+    while opts.count.is_none_or(|count| received < count) {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = &mut deadline => break,
+            message = receiver.recv() => match message {
+                Some((instance, key)) => {
+                    let instance_stats = &mut stats[instance];
+                    instance_stats.messages += 1;
+                    if let Some(key) = key {
+                        instance_stats.keys.insert(key);
+                    }
+                    received += 1;
+                }
+                // Every consumer stopped
+                None => break,
+            },
+        }
+    }
+    stop.cancel();
+
+    let mut result = Ok(());
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => result = Err(e),
+            Err(e) => result = Err(anyhow::anyhow!("Consumer task failed: {}", e).into()),
+        }
+    }
+
+    print_distribution(&stats, received, opts);
+    result
+}
+
+async fn subscribe(
+    client: &Pulsar<TokioExecutor>,
+    opts: &SimulateConsumersOpts,
+    instance: u32,
+) -> Result<Consumer<Vec<u8>, TokioExecutor>, PulsarCatError> {
+    let consumer = client
+        .consumer()
+        .with_topic(&opts.topic)
+        .with_subscription(&opts.subscription)
+        .with_subscription_type(match opts.subscription_type {
+            SubscriptionTypeOpt::Exclusive => SubType::Exclusive,
+            SubscriptionTypeOpt::Shared => SubType::Shared,
+            SubscriptionTypeOpt::Failover => SubType::Failover,
+            SubscriptionTypeOpt::KeyShared => SubType::KeyShared,
+        })
+        .with_consumer_name(format!("pulsar-cat-simulated-{}", instance))
+        .build()
+        .await?;
+    Ok(consumer)
+}
+
+// Receive and acknowledge messages until stopped, reporting their keys
+async fn receive(
+    mut consumer: Consumer<Vec<u8>, TokioExecutor>,
+    instance: usize,
+    sender: mpsc::UnboundedSender<(usize, Option<String>)>,
+    stop: CancellationToken,
+) -> Result<(), PulsarCatError> {
+    let result = loop {
+        let msg = tokio::select! {
+            _ = stop.cancelled() => break Ok(()),
+            msg = consumer.try_next() => match msg {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e.into()),
+            },
+        };
+        if let Err(e) = consumer.ack(&msg).await {
+            warn!("Failed to acknowledge message: {}", e);
+        }
+        if sender
+            .send((instance, msg.metadata().partition_key.clone()))
+            .is_err()
+        {
+            break Ok(());
+        }
+    };
+    if let Err(e) = consumer.close().await {
+        warn!("Error closing consumer: {}", e);
+    }
+    result
+}
+
+// Print the messages and keys of every consumer, and how many keys went to
+// more than one of them
+fn print_distribution(stats: &[InstanceStats], received: u64, opts: &SimulateConsumersOpts) {
+    let mut consumers_of_key: HashMap<&str, u32> = HashMap::new();
+    for instance_stats in stats {
+        for key in &instance_stats.keys {
+            *consumers_of_key.entry(key).or_default() += 1;
+        }
+    }
+
+    let mut table = Table::new(["CONSUMER", "MESSAGES", "SHARE", "KEYS", "SPLIT_KEYS"]);
+    for (instance, instance_stats) in stats.iter().enumerate() {
+        let share = match received {
+            0 => 0.0,
+            received => instance_stats.messages as f64 * 100.0 / received as f64,
+        };
+        let split_keys = instance_stats
+            .keys
+            .iter()
+            .filter(|key| consumers_of_key[key.as_str()] > 1)
+            .count();
+        table.add_row([
+            format!("pulsar-cat-simulated-{}", instance),
+            instance_stats.messages.to_string(),
+            format!("{:.1}%", share),
+            instance_stats.keys.len().to_string(),
+            split_keys.to_string(),
+        ]);
+    }
+    table.print(&opts.table);
+
+    let split_keys = consumers_of_key.values().filter(|&&n| n > 1).count();
+    info!(
+        "Received {} messages with {} distinct keys, {} keys were delivered to more than one consumer",
+        received,
+        consumers_of_key.len(),
+        split_keys
+    );
+}
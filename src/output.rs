@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::error::PulsarCatError;
+
+// A full buffer is written regardless of the flush policy
+const BUFFER_SIZE: usize = 64 * 1024;
+// How often buffered lines are flushed when no line count is given
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Buffered message output on stdout.
+///
+/// Writing each message with its own system call limits the rate at which
+/// fast topics can be drained, so lines are collected and written in batches.
+pub struct Output {
+    // Stdout, another writer in tests
+    writer: BufWriter<Box<dyn Write + Send>>,
+    // Directory with a file per topic (partition), see `with_partition_dir`
+    partition_dir: Option<PathBuf>,
+    partition_writers: HashMap<String, BufWriter<File>>,
+    flush_every: Option<u64>,
+    unflushed: u64,
+}
+
+impl Output {
+    /// With `flush_every`, the output is flushed after that many lines,
+    /// otherwise only when the buffer is full or [`Output::flush`] is called.
+    pub fn new(flush_every: Option<u64>) -> Self {
+        Self::with_writer(io::stdout(), flush_every)
+    }
+
+    /// Like [`Output::new`], writing to `writer` instead of stdout.
+    pub(crate) fn with_writer(
+        writer: impl Write + Send + 'static,
+        flush_every: Option<u64>,
+    ) -> Self {
+        Output {
+            writer: BufWriter::with_capacity(BUFFER_SIZE, Box::new(writer)),
+            partition_dir: None,
+            partition_writers: HashMap::new(),
+            flush_every,
+            unflushed: 0,
+        }
+    }
+
+    /// Write the lines of every topic (partition) passed to
+    /// [`Output::write_partition_line`] to its own file in `dir`, named after
+    /// the topic, e.g. `orders-partition-3.ndjson`. Existing files are appended to.
+    pub fn with_partition_dir(mut self, dir: &str) -> Result<Self, PulsarCatError> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| anyhow::anyhow!("Failed to create output directory '{}': {}", dir, e))?;
+        self.partition_dir = Some(PathBuf::from(dir));
+        Ok(self)
+    }
+
+    /// Write a line to the file of `topic` in the partition directory.
+    pub fn write_partition_line(
+        &mut self,
+        topic: &str,
+        line: fmt::Arguments,
+    ) -> Result<(), PulsarCatError> {
+        let Some(dir) = &self.partition_dir else {
+            return self.write_line(line);
+        };
+        let writer = match self.partition_writers.get_mut(topic) {
+            Some(writer) => writer,
+            None => {
+                let path = dir.join(format!("{}.ndjson", file_name(topic)));
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|e| anyhow::anyhow!("Failed to open '{}': {}", path.display(), e))?;
+                self.partition_writers
+                    .entry(topic.to_owned())
+                    .or_insert(BufWriter::with_capacity(BUFFER_SIZE, file))
+            }
+        };
+        writeln!(writer, "{}", line)
+            .map_err(|e| anyhow::anyhow!("Failed to write output file of {}: {}", topic, e))?;
+        self.unflushed += 1;
+        if self.flush_every.is_some_and(|n| self.unflushed >= n) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn write_line(&mut self, line: fmt::Arguments) -> Result<(), PulsarCatError> {
+        writeln!(self.writer, "{}", line).map_err(write_error)?;
+        self.unflushed += 1;
+        if self.flush_every.is_some_and(|n| self.unflushed >= n) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), PulsarCatError> {
+        if self.unflushed > 0 {
+            self.writer.flush().map_err(write_error)?;
+            for (topic, writer) in &mut self.partition_writers {
+                writer.flush().map_err(|e| {
+                    anyhow::anyhow!("Failed to write output file of {}: {}", topic, e)
+                })?;
+            }
+            self.unflushed = 0;
+        }
+        Ok(())
+    }
+}
+
+/// Flush `output` periodically, so that lines do not linger in the buffer
+/// while no new messages arrive. The task runs until it is aborted.
+pub fn spawn_interval_flush(output: Arc<Mutex<Output>>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = output.lock().unwrap().flush() {
+                warn!("{}", e);
+                break;
+            }
+        }
+    })
+}
+
+// The local name of a topic, as a file name. Topic names may contain ':',
+// which Windows does not allow in file names.
+fn file_name(topic: &str) -> String {
+    let local_name = topic.rsplit('/').next().unwrap_or(topic);
+    if cfg!(windows) {
+        local_name.replace(':', "_")
+    } else {
+        local_name.to_owned()
+    }
+}
+
+fn write_error(e: io::Error) -> PulsarCatError {
+    anyhow::anyhow!("Failed to write to stdout: {}", e).into()
+}
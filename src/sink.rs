@@ -0,0 +1,787 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use pulsar::proto::{KeyValue, MessageIdData};
+use pulsar_cat_core::archive::ArchiveWriter;
+use pulsar_cat_core::cloudevents::CloudEvent;
+use pulsar_cat_core::consume::ConsumedMessage;
+use pulsar_cat_core::format::{
+    FormattedMessage, MessageJson, compression_name, format_message_id, format_transaction_id,
+    handle_newlines, marker_name, payload_text,
+};
+use serde_json::json;
+use tokio::runtime::Handle;
+use tracing::{debug, info, warn};
+
+use crate::admin::AdminClient;
+use crate::cli_options::{ConsumerOpts, TableOpts};
+use crate::error::PulsarCatError;
+use crate::output::Output;
+use crate::table::Table;
+use crate::webhook::Webhook;
+
+/// Destination of consumed messages.
+///
+/// `consume` decompresses, verifies and transforms every message, then hands
+/// it to the sink selected by the output options, see [`create_sink`]. Sinks
+/// are called from the synchronous message callback, so a sink that blocks
+/// slows down consuming rather than buffering without bound.
+pub trait Sink: Send {
+    /// Write one message. An error stops consuming, failures of single
+    /// messages are logged and counted by the sink instead.
+    fn write(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError>;
+
+    /// Start a new file if the current one was moved away, on SIGHUP with
+    /// `--reopen-on-sighup`.
+    fn reopen(&mut self) -> Result<(), PulsarCatError> {
+        Ok(())
+    }
+
+    /// Write what is still buffered once consuming stopped, failing when
+    /// messages were lost on the way.
+    fn finish(self: Box<Self>) -> Result<(), PulsarCatError>;
+}
+
+/// The sink of the output options of `opts`. Lines written to stdout or to
+/// the partition files go through `output`, which is flushed by the caller
+/// while messages arrive.
+pub fn create_sink<'a>(
+    opts: &'a ConsumerOpts,
+    admin_url: &str,
+    output: Arc<Mutex<Output>>,
+) -> Result<Box<dyn Sink + 'a>, PulsarCatError> {
+    if opts.show_encoding {
+        return Ok(Box::new(EncodingSink {
+            opts,
+            output,
+            admin: Some(AdminClient::new(admin_url, &opts.auth)?),
+            last_entry: None,
+        }));
+    }
+    if let Some(path) = &opts.output_avro {
+        return Ok(Box::new(AvroSink(ArchiveWriter::create(path)?)));
+    }
+    if let Some(url) = &opts.post_url {
+        return Ok(Box::new(WebhookSink {
+            webhook: Webhook::new(
+                url,
+                opts.post_concurrency,
+                opts.post_retries,
+                opts.post_timeout,
+            )?,
+            url,
+        }));
+    }
+    if opts.output_dir_per_partition.is_some() {
+        return Ok(Box::new(PartitionDirSink { opts, output }));
+    }
+    if opts.snapshot {
+        return Ok(Box::new(SnapshotSink {
+            output: StdoutSink {
+                opts,
+                output,
+                default_format: Some("%k\t%s"),
+            },
+            latest: BTreeMap::new(),
+            messages: 0,
+            without_key: 0,
+        }));
+    }
+    if let Some(top) = opts.summarize_keys {
+        return Ok(Box::new(KeySummarySink {
+            opts,
+            output,
+            top,
+            keys: HashMap::new(),
+            messages: 0,
+            bytes: 0,
+        }));
+    }
+    if let Some(interval) = opts.histogram {
+        return Ok(Box::new(HistogramSink {
+            opts,
+            output,
+            interval_ms: interval.as_millis() as u64,
+            buckets: BTreeMap::new(),
+        }));
+    }
+    Ok(Box::new(StdoutSink {
+        opts,
+        output,
+        default_format: None,
+    }))
+}
+
+// Messages as lines on stdout, formatted by the display options
+struct StdoutSink<'a> {
+    opts: &'a ConsumerOpts,
+    output: Arc<Mutex<Output>>,
+    // Format string used when --format is not given, instead of the bare payload
+    default_format: Option<&'static str>,
+}
+
+impl Sink for StdoutSink<'_> {
+    fn write(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        let opts = self.opts;
+        let mut out = self.output.lock().unwrap();
+        let event = opts
+            .cloudevents
+            .then(|| CloudEvent::from_message(msg))
+            .flatten();
+        if let Some(marker_type) = msg.marker_type.filter(|_| !opts.display.json) {
+            // Markers have no payload worth printing
+            match msg.transaction_id {
+                Some(transaction_id) => out.write_line(format_args!(
+                    "<{} marker, transaction {}>",
+                    marker_name(marker_type),
+                    format_transaction_id(transaction_id)
+                )),
+                None => out.write_line(format_args!("<{} marker>", marker_name(marker_type))),
+            }
+        } else if let Some(event) = event {
+            if opts.display.json {
+                let json = MessageJson {
+                    cloudevent: Some(event.to_json()),
+                    ..opts.display.message_json(msg)
+                };
+                out.write_line(format_args!("{}", json))
+            } else if opts.display.pretty {
+                let event = serde_json::to_string_pretty(&event.to_json())
+                    .map_err(|e| anyhow::anyhow!("Failed to render CloudEvent: {}", e))?;
+                out.write_line(format_args!("{}", event))
+            } else {
+                out.write_line(format_args!("{}", event.to_json()))
+            }
+        } else if opts.display.json {
+            out.write_line(format_args!("{}", opts.display.message_json(msg)))
+        } else {
+            let Some(payload) = payload_text(msg, opts.display.binary_mode())? else {
+                debug!(
+                    "Skipping message {} with a binary payload",
+                    format_message_id(msg.message_id)
+                );
+                return Ok(());
+            };
+            let payload = opts.display.payload_display(payload);
+            let origin = match msg.replicated_from.filter(|_| opts.show_replicated_from) {
+                Some(cluster) => format!("[{}] ", cluster),
+                None => String::new(),
+            };
+            let newlines = opts.display.newline_mode();
+            if let Some(format_str) = opts.display.format.as_deref().or(self.default_format) {
+                out.write_line(format_args!(
+                    "{}{}",
+                    origin,
+                    FormattedMessage {
+                        format_str,
+                        msg,
+                        payload: &payload,
+                        newlines,
+                        algorithm: opts.display.checksum_algorithm(),
+                    }
+                ))
+            } else {
+                // Default format - just the payload
+                out.write_line(format_args!(
+                    "{}{}",
+                    origin,
+                    handle_newlines(&payload, newlines)
+                ))
+            }
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), PulsarCatError> {
+        self.output.lock().unwrap().flush()
+    }
+}
+
+// Messages as JSON lines in a file per topic (partition)
+struct PartitionDirSink<'a> {
+    opts: &'a ConsumerOpts,
+    output: Arc<Mutex<Output>>,
+}
+
+impl Sink for PartitionDirSink<'_> {
+    fn write(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        self.output.lock().unwrap().write_partition_line(
+            msg.topic,
+            format_args!("{}", self.opts.display.message_json(msg)),
+        )
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), PulsarCatError> {
+        self.output.lock().unwrap().flush()
+    }
+}
+
+// Messages with their metadata in an Avro archive, see `--output-avro`
+struct AvroSink(ArchiveWriter);
+
+impl Sink for AvroSink {
+    fn write(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        self.0.append(msg)
+    }
+
+    fn reopen(&mut self) -> Result<(), PulsarCatError> {
+        if self.0.reopen()? {
+            info!("Started a new Avro archive");
+        } else {
+            warn!("Avro archive was not moved away, continuing to write to it");
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), PulsarCatError> {
+        self.0.finish()
+    }
+}
+
+// Messages posted to an HTTP endpoint, see `--post-url`
+struct WebhookSink<'a> {
+    webhook: Webhook,
+    url: &'a str,
+}
+
+impl Sink for WebhookSink<'_> {
+    fn write(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        self.webhook.post(msg)
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), PulsarCatError> {
+        // Wait for the requests still in flight
+        let (posted, failed) =
+            tokio::task::block_in_place(|| Handle::current().block_on(self.webhook.finish()));
+        info!("Posted {} messages to {}", posted, self.url);
+        if failed > 0 {
+            return Err(PulsarCatError::PartialFailure(anyhow::anyhow!(
+                "{} messages could not be posted",
+                failed
+            )));
+        }
+        Ok(())
+    }
+}
+
+// The latest message of every key, printed in key order once consuming
+// stopped. An empty payload is a tombstone deleting its key, as in compaction.
+struct SnapshotSink<'a> {
+    output: StdoutSink<'a>,
+    latest: BTreeMap<String, LatestMessage>,
+    messages: u64,
+    without_key: u64,
+}
+
+// The parts of a consumed message that are printed
+struct LatestMessage {
+    topic: String,
+    message_id: MessageIdData,
+    payload: Vec<u8>,
+    publish_time: u64,
+    event_time: Option<u64>,
+    properties: Vec<KeyValue>,
+    schema_version: Option<u64>,
+}
+
+impl Sink for SnapshotSink<'_> {
+    fn write(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        self.messages += 1;
+        let Some(key) = msg.key else {
+            self.without_key += 1;
+            return Ok(());
+        };
+        if msg.payload.is_empty() {
+            self.latest.remove(key);
+            return Ok(());
+        }
+        let latest = LatestMessage {
+            topic: msg.topic.to_owned(),
+            message_id: msg.message_id.clone(),
+            payload: msg.payload.to_vec(),
+            publish_time: msg.publish_time,
+            event_time: msg.event_time,
+            properties: msg.properties.to_vec(),
+            schema_version: msg.schema_version,
+        };
+        match self.latest.get_mut(key) {
+            Some(entry) => *entry = latest,
+            None => {
+                self.latest.insert(key.to_owned(), latest);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), PulsarCatError> {
+        for (key, latest) in &self.latest {
+            self.output.write(&ConsumedMessage {
+                topic: &latest.topic,
+                message_id: &latest.message_id,
+                key: Some(key),
+                payload: &latest.payload,
+                publish_time: latest.publish_time,
+                event_time: latest.event_time,
+                properties: &latest.properties,
+                transaction_id: None,
+                marker_type: None,
+                replicated_from: None,
+                compression: None,
+                uncompressed_size: None,
+                batch_size: None,
+                schema_version: latest.schema_version,
+            })?;
+        }
+        info!("{} keys from {} messages", self.latest.len(), self.messages);
+        if self.without_key > 0 {
+            warn!(
+                "Left out {} messages without a key, they have no place in the snapshot",
+                self.without_key
+            );
+        }
+        Box::new(self.output).finish()
+    }
+}
+
+// Message count and payload bytes per key, to find hot keys
+struct KeySummarySink<'a> {
+    opts: &'a ConsumerOpts,
+    output: Arc<Mutex<Output>>,
+    top: usize,
+    keys: HashMap<Option<String>, (u64, u64)>,
+    messages: u64,
+    bytes: u64,
+}
+
+impl Sink for KeySummarySink<'_> {
+    fn write(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        let bytes = msg.payload.len() as u64;
+        let (key_messages, key_bytes) = self.keys.entry(msg.key.map(str::to_owned)).or_default();
+        *key_messages += 1;
+        *key_bytes += bytes;
+        self.messages += 1;
+        self.bytes += bytes;
+        Ok(())
+    }
+
+    // Print the `top` keys with the most messages, as a table or JSON lines
+    fn finish(self: Box<Self>) -> Result<(), PulsarCatError> {
+        let mut keys: Vec<_> = self.keys.iter().collect();
+        keys.sort_by(|(a_key, a), (b_key, b)| b.cmp(a).then_with(|| a_key.cmp(b_key)));
+        keys.truncate(self.top);
+
+        let mut out = self.output.lock().unwrap();
+        if self.opts.display.json {
+            for (key, (messages, bytes)) in keys {
+                out.write_line(format_args!(
+                    "{}",
+                    json!({"key": key, "messages": messages, "bytes": bytes})
+                ))?;
+            }
+        } else {
+            let mut table = Table::new(["KEY", "MESSAGES", "BYTES", "SHARE"]);
+            for (key, (messages, bytes)) in keys {
+                table.add_row([
+                    key.clone().unwrap_or_default(),
+                    messages.to_string(),
+                    bytes.to_string(),
+                    format!("{:.1}%", *messages as f64 * 100.0 / self.messages as f64),
+                ]);
+            }
+            write_table(&mut out, &table, &self.opts.table)?;
+        }
+        out.flush()?;
+        info!(
+            "{} messages with {} payload bytes in {} distinct keys",
+            self.messages,
+            self.bytes,
+            self.keys.len()
+        );
+        Ok(())
+    }
+}
+
+// Message count and payload bytes per interval of publish time, to find
+// traffic spikes
+struct HistogramSink<'a> {
+    opts: &'a ConsumerOpts,
+    output: Arc<Mutex<Output>>,
+    interval_ms: u64,
+    // Start of the interval in epoch milliseconds to messages and bytes
+    buckets: BTreeMap<u64, (u64, u64)>,
+}
+
+impl Sink for HistogramSink<'_> {
+    fn write(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        let start = msg.publish_time - msg.publish_time % self.interval_ms;
+        let (messages, bytes) = self.buckets.entry(start).or_default();
+        *messages += 1;
+        *bytes += msg.payload.len() as u64;
+        Ok(())
+    }
+
+    // Print every interval from the first to the last message, including
+    // those without messages, as a table or JSON lines
+    fn finish(self: Box<Self>) -> Result<(), PulsarCatError> {
+        let (Some(&first), Some(&last)) = (self.buckets.keys().next(), self.buckets.keys().last())
+        else {
+            info!("No messages consumed");
+            return Ok(());
+        };
+        let mut out = self.output.lock().unwrap();
+        let mut table = Table::new(["START", "MESSAGES", "BYTES"]);
+        for start in (first..=last).step_by(self.interval_ms as usize) {
+            let (messages, bytes) = self.buckets.get(&start).copied().unwrap_or_default();
+            let time = humantime::format_rfc3339_millis(UNIX_EPOCH + Duration::from_millis(start));
+            if self.opts.display.json {
+                out.write_line(format_args!(
+                    "{}",
+                    json!({"start": time.to_string(), "messages": messages, "bytes": bytes})
+                ))?;
+            } else {
+                table.add_row([time.to_string(), messages.to_string(), bytes.to_string()]);
+            }
+        }
+        if !self.opts.display.json {
+            write_table(&mut out, &table, &self.opts.table)?;
+        }
+        out.flush()?;
+        let (messages, bytes) = self
+            .buckets
+            .values()
+            .fold((0, 0), |(m, b), (messages, bytes)| {
+                (m + messages, b + bytes)
+            });
+        info!(
+            "{} messages with {} payload bytes published over {} intervals of {}",
+            messages,
+            bytes,
+            (last - first) / self.interval_ms + 1,
+            humantime::format_duration(Duration::from_millis(self.interval_ms))
+        );
+        Ok(())
+    }
+}
+
+// A table as lines of the output
+fn write_table(out: &mut Output, table: &Table, opts: &TableOpts) -> Result<(), PulsarCatError> {
+    for line in table.render(opts).lines() {
+        out.write_line(format_args!("{}", line))?;
+    }
+    Ok(())
+}
+
+// Prints how messages were compressed and batched by their producer. The
+// client decompresses entries, so their wire size is read from the admin API.
+struct EncodingSink<'a> {
+    opts: &'a ConsumerOpts,
+    output: Arc<Mutex<Output>>,
+    admin: Option<AdminClient>,
+    // Wire size of the last entry, shared by the messages of a batch
+    last_entry: Option<((String, u64, u64), Option<u64>)>,
+}
+
+impl Sink for EncodingSink<'_> {
+    fn write(&mut self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        let payload_bytes = msg.payload.len() as u64;
+        let batch_index = msg.message_id.batch_index.filter(|index| *index >= 0);
+        let batch_size = msg.batch_size.filter(|_| batch_index.is_some());
+        let (entry_bytes, wire_bytes) = if msg.compression.is_none() && batch_size.is_none() {
+            // The payload is the entry, as sent
+            (Some(payload_bytes), Some(payload_bytes))
+        } else {
+            let wire_bytes = self.wire_size(msg);
+            let entry_bytes = msg
+                .uncompressed_size
+                .map(u64::from)
+                .or(wire_bytes.filter(|_| msg.compression.is_none()));
+            (entry_bytes, wire_bytes)
+        };
+        let ratio = entry_bytes
+            .zip(wire_bytes)
+            .filter(|(_, wire_bytes)| *wire_bytes > 0)
+            .map(|(entry_bytes, wire_bytes)| entry_bytes as f64 / wire_bytes as f64);
+        let codec = compression_name(msg.compression);
+
+        let mut out = self.output.lock().unwrap();
+        if self.opts.display.json {
+            out.write_line(format_args!(
+                "{}",
+                json!({
+                    "topic": msg.topic,
+                    "message_id": format_message_id(msg.message_id),
+                    "compression": codec,
+                    "batch_index": batch_index,
+                    "batch_size": batch_size,
+                    "payload_bytes": payload_bytes,
+                    "entry_bytes": entry_bytes,
+                    "wire_bytes": wire_bytes,
+                    "compression_ratio": ratio.map(|ratio| (ratio * 100.0).round() / 100.0),
+                })
+            ))
+        } else {
+            let unknown = || "-".to_owned();
+            out.write_line(format_args!(
+                "{} compression={} batch={} payload_bytes={} entry_bytes={} wire_bytes={} ratio={}",
+                format_message_id(msg.message_id),
+                codec,
+                batch_index
+                    .zip(batch_size)
+                    .map(|(index, size)| format!("{}/{}", index + 1, size))
+                    .unwrap_or_else(unknown),
+                payload_bytes,
+                entry_bytes
+                    .map(|bytes| bytes.to_string())
+                    .unwrap_or_else(unknown),
+                wire_bytes
+                    .map(|bytes| bytes.to_string())
+                    .unwrap_or_else(unknown),
+                ratio
+                    .map(|ratio| format!("{:.2}", ratio))
+                    .unwrap_or_else(unknown)
+            ))
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), PulsarCatError> {
+        self.output.lock().unwrap().flush()
+    }
+}
+
+impl EncodingSink<'_> {
+    fn wire_size(&mut self, msg: &ConsumedMessage) -> Option<u64> {
+        let entry = (
+            msg.topic.to_owned(),
+            msg.message_id.ledger_id,
+            msg.message_id.entry_id,
+        );
+        if let Some((last_entry, wire_bytes)) = &self.last_entry
+            && *last_entry == entry
+        {
+            return *wire_bytes;
+        }
+        let admin = self.admin.as_ref()?;
+        // The callback is synchronous, the lookup blocks this worker thread only
+        let result = tokio::task::block_in_place(|| {
+            Handle::current().block_on(admin.get_entry_size(&entry.0, entry.1, entry.2))
+        });
+        let wire_bytes = match result {
+            Ok(wire_bytes) => Some(wire_bytes),
+            Err(PulsarCatError::Admin {
+                status: None,
+                error,
+            }) => {
+                warn!(
+                    "Admin API not reachable, wire sizes are not shown: {}",
+                    error
+                );
+                self.admin = None;
+                None
+            }
+            Err(e) => {
+                debug!("Failed to read the wire size of {:?}: {}", entry, e);
+                None
+            }
+        };
+        self.last_entry = Some((entry, wire_bytes));
+        wire_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::cli_options::{CliOpts, OpMode};
+
+    // Collects what the sink writes, shared with the output it is given to
+    #[derive(Clone, Default)]
+    struct Written(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Written {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Written {
+        fn lines(&self) -> Vec<String> {
+            let written = self.0.lock().unwrap();
+            String::from_utf8_lossy(&written)
+                .lines()
+                .map(ToOwned::to_owned)
+                .collect()
+        }
+    }
+
+    fn consumer_opts(args: &[&str]) -> ConsumerOpts {
+        let args = [&["pulsar-cat", "consume", "-t", "tenant/ns/orders"], args].concat();
+        match CliOpts::parse_from(args).command {
+            OpMode::Consumer(opts) => *opts,
+            _ => unreachable!(),
+        }
+    }
+
+    fn message<'a>(
+        message_id: &'a MessageIdData,
+        key: Option<&'a str>,
+        payload: &'a [u8],
+        publish_time: u64,
+    ) -> ConsumedMessage<'a> {
+        ConsumedMessage {
+            topic: "persistent://tenant/ns/orders",
+            message_id,
+            key,
+            payload,
+            publish_time,
+            event_time: None,
+            properties: &[],
+            transaction_id: None,
+            marker_type: None,
+            replicated_from: None,
+            compression: None,
+            uncompressed_size: None,
+            batch_size: None,
+            schema_version: None,
+        }
+    }
+
+    // The lines the sink of `opts` writes for messages with these keys,
+    // payloads and publish times
+    fn written_lines(opts: &ConsumerOpts, messages: &[(Option<&str>, &str, u64)]) -> Vec<String> {
+        let written = Written::default();
+        let output = Arc::new(Mutex::new(Output::with_writer(written.clone(), None)));
+        let mut sink = create_sink(opts, "http://localhost:8080", output).unwrap();
+        for (entry_id, &(key, payload, publish_time)) in messages.iter().enumerate() {
+            let message_id = MessageIdData {
+                ledger_id: 7,
+                entry_id: entry_id as u64,
+                ..Default::default()
+            };
+            sink.write(&message(&message_id, key, payload.as_bytes(), publish_time))
+                .unwrap();
+        }
+        sink.finish().unwrap();
+        written.lines()
+    }
+
+    // Like `written_lines`, for messages published a second apart
+    fn sink_lines(opts: &ConsumerOpts, messages: &[(Option<&str>, &str)]) -> Vec<String> {
+        let messages: Vec<_> = (0..)
+            .zip(messages)
+            .map(|(i, &(key, payload))| (key, payload, 1_700_000_000_000 + i * 1000))
+            .collect();
+        written_lines(opts, &messages)
+    }
+
+    #[test]
+    fn stdout_sink_formats_messages() {
+        let messages = [(Some("order-1"), "created"), (None, "paid")];
+        assert_eq!(
+            sink_lines(&consumer_opts(&[]), &messages),
+            ["created", "paid"]
+        );
+        assert_eq!(
+            sink_lines(&consumer_opts(&["--format", "%k=%s"]), &messages),
+            ["order-1=created", "=paid"]
+        );
+
+        let lines = sink_lines(&consumer_opts(&["--json"]), &messages);
+        let json: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(json["key"], "order-1");
+        assert_eq!(json["payload"], "created");
+        assert_eq!(json["message_id"], "7:0:-1");
+    }
+
+    #[test]
+    fn snapshot_sink_keeps_the_latest_message_of_every_key() {
+        let messages = [
+            (Some("b"), "1"),
+            (Some("a"), "2"),
+            (Some("b"), "3"),
+            (Some("c"), "4"),
+            // Tombstone
+            (Some("a"), ""),
+            (None, "without key"),
+        ];
+        assert_eq!(
+            sink_lines(&consumer_opts(&["--snapshot"]), &messages),
+            ["b\t3", "c\t4"]
+        );
+    }
+
+    #[test]
+    fn key_summary_sink_orders_keys_by_messages() {
+        let messages = [
+            (Some("c"), "x"),
+            (Some("b"), "x"),
+            (Some("a"), "x"),
+            (Some("c"), "x"),
+            (Some("a"), "xx"),
+            (None, "x"),
+            (Some("c"), "x"),
+        ];
+        let lines = sink_lines(&consumer_opts(&["--summarize-keys", "3"]), &messages);
+        let rows: Vec<Vec<&str>> = lines
+            .iter()
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                vec!["KEY", "MESSAGES", "BYTES", "SHARE"],
+                vec!["c", "3", "3", "42.9%"],
+                vec!["a", "2", "3", "28.6%"],
+                // Ties are ordered by key, messages without one come first
+                vec!["-", "1", "1", "14.3%"],
+            ]
+        );
+
+        let lines = sink_lines(
+            &consumer_opts(&["--summarize-keys", "1", "--json"]),
+            &messages,
+        );
+        assert_eq!(lines, [r#"{"bytes":3,"key":"c","messages":3}"#]);
+    }
+
+    #[test]
+    fn histogram_sink_fills_intervals_without_messages() {
+        // Two intervals of two seconds with messages and the two between
+        // them without
+        let messages = [
+            (Some("a"), "x", 1_700_000_000_000),
+            (Some("a"), "xx", 1_700_000_001_000),
+            (Some("a"), "xxx", 1_700_000_006_000),
+            (Some("a"), "xxxx", 1_700_000_007_999),
+        ];
+        let opts = consumer_opts(&["--histogram", "2s", "--json"]);
+        let buckets: Vec<_> = written_lines(&opts, &messages)
+            .iter()
+            .map(|line| {
+                let json: serde_json::Value = serde_json::from_str(line).unwrap();
+                (
+                    json["start"].as_str().unwrap().to_owned(),
+                    json["messages"].as_u64().unwrap(),
+                    json["bytes"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            buckets,
+            [
+                ("2023-11-14T22:13:20.000Z".to_owned(), 2, 3),
+                ("2023-11-14T22:13:22.000Z".to_owned(), 0, 0),
+                ("2023-11-14T22:13:24.000Z".to_owned(), 0, 0),
+                ("2023-11-14T22:13:26.000Z".to_owned(), 2, 7),
+            ]
+        );
+    }
+}
@@ -0,0 +1,383 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use pulsar_cat_core::archive::read_archive_from;
+use pulsar_cat_core::json_path::JsonPath;
+use pulsar_cat_core::produce::Record;
+use tokio::sync::mpsc;
+use tracing::{Level, error, warn};
+
+use crate::cli_options::{InputFormatOpt, KeyViolationOpt, ProducerOpts};
+use crate::error::{MESSAGE_ERROR_TARGET, PulsarCatError};
+
+/// Input of `produce`, read into records with their key, properties and
+/// event time.
+///
+/// Every source runs on a plain thread of its own, a blocked read must not
+/// keep the runtime from shutting down after Ctrl-C. Records are handed over
+/// through a bounded channel, so reading pauses while sends are pending.
+pub trait Source: Send {
+    /// Read until the input ends or the receiving side is dropped. Input that
+    /// cannot be turned into a record is logged and counted in `rejected`.
+    fn run(self: Box<Self>, sender: mpsc::Sender<Record>, rejected: Arc<AtomicU64>);
+}
+
+/// The source of the input options of `opts`, with a progress bar over its
+/// input when the size of the input is known. Lines without a key although
+/// `--enforce_key` is set are counted in `missing_keys`.
+pub fn open_source(
+    opts: &ProducerOpts,
+    missing_keys: Arc<AtomicU64>,
+) -> Result<(Box<dyn Source>, Option<ProgressBar>), PulsarCatError> {
+    if let Some(path) = &opts.input_avro {
+        let (input, progress) = open_file(path, "Avro archive")?;
+        let archive = read_archive_from(BufReader::new(input), path)?;
+        return Ok((Box::new(AvroSource(archive)), progress));
+    }
+    if let Some(path) = &opts.replay_file {
+        let (input, progress) = open_file(path, "replay file")?;
+        let source = ReplaySource {
+            input: BufReader::new(input),
+            speed: opts.respect_timing,
+        };
+        return Ok((Box::new(source), progress));
+    }
+    if let Some(path) = &opts.listen_socket {
+        let parser = LineParser::new(opts, missing_keys);
+        return Ok((Box::new(SocketSource::bind(path, parser)?), None));
+    }
+    let progress = input_progress(stdin_file_size());
+    let input: Box<dyn BufRead + Send> = match &progress {
+        Some(progress) => Box::new(BufReader::new(progress.wrap_read(io::stdin()))),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+    let source = LineSource {
+        input,
+        parser: LineParser::new(opts, missing_keys),
+    };
+    Ok((Box::new(source), progress))
+}
+
+/// Run `source` on a thread of its own.
+pub fn spawn_source(
+    source: Box<dyn Source>,
+    sender: mpsc::Sender<Record>,
+    rejected: Arc<AtomicU64>,
+) {
+    std::thread::spawn(move || source.run(sender, rejected));
+}
+
+// Open an input file, `what` names it in errors
+fn open_file(
+    path: &str,
+    what: &str,
+) -> Result<(Box<dyn Read + Send>, Option<ProgressBar>), PulsarCatError> {
+    let file = File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {} '{}': {}", what, path, e))?;
+    let progress = input_progress(file.metadata().map(|metadata| metadata.len()).ok());
+    let input: Box<dyn Read + Send> = match &progress {
+        Some(progress) => Box::new(progress.wrap_read(file)),
+        None => Box::new(file),
+    };
+    Ok((input, progress))
+}
+
+// Progress bar over input of known size, on stderr like the status messages
+// and hidden with them by --quiet. Nothing is drawn when stderr is not a terminal.
+fn input_progress(size: Option<u64>) -> Option<ProgressBar> {
+    let size = size.filter(|_| tracing::enabled!(Level::INFO))?;
+    let style = ProgressStyle::with_template(
+        "{bar:40} {binary_bytes}/{binary_total_bytes} {binary_bytes_per_sec} ETA {eta}",
+    )
+    .expect("progress template is valid");
+    Some(ProgressBar::new(size).with_style(style))
+}
+
+// Size of the file stdin is redirected from, None when it is a pipe or terminal
+#[cfg(unix)]
+fn stdin_file_size() -> Option<u64> {
+    use std::os::fd::AsFd;
+
+    let stdin = io::stdin().as_fd().try_clone_to_owned().ok()?;
+    let metadata = File::from(stdin).metadata().ok()?;
+    metadata.is_file().then_some(metadata.len())
+}
+
+#[cfg(windows)]
+fn stdin_file_size() -> Option<u64> {
+    use std::os::windows::io::AsHandle;
+
+    let stdin = io::stdin().as_handle().try_clone_to_owned().ok()?;
+    let metadata = File::from(stdin).metadata().ok()?;
+    metadata.is_file().then_some(metadata.len())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn stdin_file_size() -> Option<u64> {
+    None
+}
+
+// Why a line was not turned into a record as it is
+enum LineError {
+    // The line is skipped
+    Invalid(String),
+    // The message has no key although --enforce_key is set
+    MissingKey(Record, String),
+}
+
+// Parser of input lines in the --input-format
+#[derive(Clone)]
+struct LineParser {
+    key_delimiter: Option<String>,
+    enforce_key: bool,
+    on_key_violation: KeyViolationOpt,
+    input_format: InputFormatOpt,
+    key_path: Option<JsonPath>,
+    missing_keys: Arc<AtomicU64>,
+}
+
+impl LineParser {
+    fn new(opts: &ProducerOpts, missing_keys: Arc<AtomicU64>) -> Self {
+        LineParser {
+            key_delimiter: opts.key.clone(),
+            enforce_key: opts.enforce_key,
+            on_key_violation: opts.on_key_violation,
+            input_format: opts.input_format.clone(),
+            key_path: opts.key_from_json_path.clone(),
+            missing_keys,
+        }
+    }
+
+    fn parse(&self, line: String) -> Result<Record, LineError> {
+        let mut record = match self.input_format {
+            InputFormatOpt::Lines => Record::from_line(line, self.key_delimiter.as_deref(), false)
+                .map_err(LineError::Invalid)?,
+            InputFormatOpt::KcatJson => {
+                Record::from_kcat_json(&line).map_err(LineError::Invalid)?
+            }
+        };
+        if let Some(key_path) = &self.key_path {
+            record.key = record.key_from_json(key_path);
+            if record.key.is_none() && self.enforce_key {
+                return Err(LineError::MissingKey(
+                    record,
+                    "Key is enforced but the JSON payload has no key at --key-from-json-path"
+                        .to_owned(),
+                ));
+            }
+        } else if record.key.is_none() && self.enforce_key {
+            let reason = format!(
+                "Key is enforced but delimiter '{}' not found in the message",
+                self.key_delimiter.as_deref().unwrap_or_default()
+            );
+            return Err(LineError::MissingKey(record, reason));
+        }
+        Ok(record)
+    }
+}
+
+// One message per line of stdin
+struct LineSource {
+    input: Box<dyn BufRead + Send>,
+    parser: LineParser,
+}
+
+impl Source for LineSource {
+    fn run(self: Box<Self>, sender: mpsc::Sender<Record>, rejected: Arc<AtomicU64>) {
+        read_lines(self.input, &self.parser, sender, &rejected)
+    }
+}
+
+// Turn input lines into records until the input ends or the channel is closed
+fn read_lines(
+    input: impl BufRead,
+    parser: &LineParser,
+    sender: mpsc::Sender<Record>,
+    rejected: &AtomicU64,
+) {
+    for (number, line_result) in input.lines().enumerate() {
+        let line = number + 1;
+        let record = match line_result {
+            // Skip empty lines
+            Ok(text) if text.is_empty() => continue,
+            Ok(text) => match parser.parse(text) {
+                Ok(record) => record,
+                Err(LineError::Invalid(e)) => {
+                    error!(target: MESSAGE_ERROR_TARGET, kind = "input", line, "{}", e);
+                    rejected.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                Err(LineError::MissingKey(record, e)) => {
+                    parser.missing_keys.fetch_add(1, Ordering::Relaxed);
+                    match parser.on_key_violation {
+                        KeyViolationOpt::SendKeyless => {
+                            warn!(target: MESSAGE_ERROR_TARGET, kind = "input", line, "{}, sending it without a key", e);
+                            record
+                        }
+                        KeyViolationOpt::SkipWarn => {
+                            warn!(target: MESSAGE_ERROR_TARGET, kind = "input", line, "{}, skipping it", e);
+                            rejected.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                        KeyViolationOpt::FailFast => {
+                            error!(target: MESSAGE_ERROR_TARGET, kind = "input", line, "{}", e);
+                            rejected.fetch_add(1, Ordering::Relaxed);
+                            break;
+                        }
+                    }
+                }
+            },
+            Err(e) => {
+                error!("Error reading input: {}", e);
+                break;
+            }
+        };
+        // Try to send the record, if the channel is closed, stop reading
+        if sender.blocking_send(record).is_err() {
+            break;
+        }
+    }
+}
+
+// Replays the messages of an Avro archive, stopping at the first unreadable block
+struct AvroSource<I>(I);
+
+impl<I> Source for AvroSource<I>
+where
+    I: Iterator<Item = Result<Record, PulsarCatError>> + Send,
+{
+    fn run(self: Box<Self>, sender: mpsc::Sender<Record>, rejected: Arc<AtomicU64>) {
+        for record in self.0 {
+            match record {
+                Ok(record) => {
+                    if sender.blocking_send(record).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!(target: MESSAGE_ERROR_TARGET, kind = "decode", "{}", e);
+                    rejected.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Replays the messages of a consume --json capture. With a speed factor, every
+// message is held back until its publish time, relative to the first message
+// and scaled by the factor, has passed. Timing is kept against the start rather
+// than the previous message, so slow sends and out-of-order publish times of
+// different partitions do not add up.
+struct ReplaySource<R> {
+    input: R,
+    speed: Option<f64>,
+}
+
+impl<R: BufRead + Send> Source for ReplaySource<R> {
+    fn run(self: Box<Self>, sender: mpsc::Sender<Record>, rejected: Arc<AtomicU64>) {
+        let mut start: Option<(Instant, u64)> = None;
+        for (number, line_result) in self.input.lines().enumerate() {
+            let line = number + 1;
+            let text = match line_result {
+                Ok(text) if text.is_empty() => continue,
+                Ok(text) => text,
+                Err(e) => {
+                    error!("Error reading replay file: {}", e);
+                    break;
+                }
+            };
+            let (record, publish_time) = match Record::from_consumer_json(&text) {
+                Ok(message) => message,
+                Err(e) => {
+                    error!(target: MESSAGE_ERROR_TARGET, kind = "input", line, "{}", e);
+                    rejected.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            };
+            if let (Some(speed), Some(publish_time)) = (self.speed, publish_time) {
+                let (started, first_publish_time) =
+                    *start.get_or_insert((Instant::now(), publish_time));
+                let offset = publish_time.saturating_sub(first_publish_time) as f64 / speed;
+                let due = started + Duration::from_secs_f64(offset / 1000.0);
+                std::thread::sleep(due.saturating_duration_since(Instant::now()));
+            }
+            if sender.blocking_send(record).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+// Accepts connections on a UNIX domain socket, reading the lines every client
+// sends like stdin. Clients may connect one after another or at the same time,
+// the socket is served until pulsar-cat is stopped.
+#[cfg(unix)]
+struct SocketSource {
+    listener: std::os::unix::net::UnixListener,
+    parser: LineParser,
+}
+
+#[cfg(unix)]
+impl SocketSource {
+    fn bind(path: &str, parser: LineParser) -> Result<Self, PulsarCatError> {
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let bind_error = |e: io::Error| anyhow::anyhow!("Failed to listen on '{}': {}", path, e);
+        let listener = match UnixListener::bind(path) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                // Left behind by a pulsar-cat that did not shut down cleanly
+                if UnixStream::connect(path).is_ok() {
+                    return Err(
+                        anyhow::anyhow!("Another process is listening on '{}'", path).into(),
+                    );
+                }
+                std::fs::remove_file(path).map_err(bind_error)?;
+                UnixListener::bind(path).map_err(bind_error)?
+            }
+            result => result.map_err(bind_error)?,
+        };
+        tracing::info!("Listening for messages on {}", path);
+        Ok(SocketSource { listener, parser })
+    }
+}
+
+#[cfg(unix)]
+impl Source for SocketSource {
+    fn run(self: Box<Self>, sender: mpsc::Sender<Record>, rejected: Arc<AtomicU64>) {
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let parser = self.parser.clone();
+                    let sender = sender.clone();
+                    let rejected = rejected.clone();
+                    std::thread::spawn(move || {
+                        read_lines(BufReader::new(stream), &parser, sender, &rejected)
+                    });
+                }
+                Err(e) => error!("Failed to accept a connection: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct SocketSource;
+
+#[cfg(not(unix))]
+impl SocketSource {
+    fn bind(_path: &str, _parser: LineParser) -> Result<Self, PulsarCatError> {
+        Err(PulsarCatError::Validation(anyhow::anyhow!(
+            "--listen-socket is only supported on Unix"
+        )))
+    }
+}
+
+#[cfg(not(unix))]
+impl Source for SocketSource {
+    fn run(self: Box<Self>, _sender: mpsc::Sender<Record>, _rejected: Arc<AtomicU64>) {}
+}
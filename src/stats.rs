@@ -0,0 +1,98 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tokio::task::JoinHandle;
+use tracing::info;
+
+use crate::logging::message_errors;
+
+/// Messages handled by a long-running consume or produce, reported every
+/// `--stats-interval`.
+#[derive(Default)]
+pub struct SessionStats {
+    messages: AtomicU64,
+    bytes: AtomicU64,
+    // Publish time of the latest consumed message, 0 before the first one
+    last_publish_time: AtomicU64,
+}
+
+impl SessionStats {
+    pub fn record(&self, bytes: usize) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_publish_time(&self, publish_time: u64) {
+        self.last_publish_time
+            .fetch_max(publish_time, Ordering::Relaxed);
+    }
+}
+
+/// Log the throughput of every interval, the totals, the failures of
+/// individual messages and, when consuming, how far the latest message lags
+/// behind its publish time. The fields are structured, so `--log-format json`
+/// prints them as JSON. The task runs until it is aborted.
+pub fn spawn_stats_reporter(
+    stats: Arc<SessionStats>,
+    action: &'static str,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        // The first tick completes immediately
+        interval.tick().await;
+        let mut last_tick = Instant::now();
+        let mut last_messages = 0;
+        let mut last_bytes = 0;
+        loop {
+            interval.tick().await;
+            let seconds = last_tick.elapsed().as_secs_f64();
+            last_tick = Instant::now();
+
+            let messages = stats.messages.load(Ordering::Relaxed);
+            let bytes = stats.bytes.load(Ordering::Relaxed);
+            let rate = (messages - last_messages) as f64 / seconds;
+            let byte_rate = (bytes - last_bytes) as f64 / seconds;
+            last_messages = messages;
+            last_bytes = bytes;
+            let errors = message_errors();
+
+            let lag_ms = match stats.last_publish_time.load(Ordering::Relaxed) {
+                0 => None,
+                publish_time => {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_millis() as u64)
+                        .unwrap_or(0);
+                    Some(now.saturating_sub(publish_time))
+                }
+            };
+            let lag = lag_ms
+                .map(|lag_ms| {
+                    format!(
+                        ", latest message published {} ago",
+                        humantime::format_duration(Duration::from_millis(lag_ms))
+                    )
+                })
+                .unwrap_or_default();
+
+            info!(
+                event = "stats",
+                messages,
+                bytes,
+                msg_per_sec = rate,
+                bytes_per_sec = byte_rate,
+                errors,
+                lag_ms,
+                "{} {} messages ({:.1} msg/s, {:.2} MiB/s), {} errors{}",
+                action,
+                messages,
+                rate,
+                byte_rate / (1 << 20) as f64,
+                errors,
+                lag
+            );
+        }
+    })
+}
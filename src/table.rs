@@ -0,0 +1,81 @@
+use std::fmt::Display;
+
+use crate::cli_options::TableOpts;
+
+// Columns are separated by at least this many spaces
+const COLUMN_GAP: usize = 2;
+
+/// Plain-text table with aligned columns, used by the metadata commands.
+///
+/// Cells never contain the column separator, so the output can be consumed
+/// with `awk`/`cut` as well as read by humans.
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new<I, S>(headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Table {
+            headers: headers.into_iter().map(Into::into).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn add_row<I, S>(&mut self, row: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Display,
+    {
+        self.rows.push(
+            row.into_iter()
+                .map(|cell| cell.to_string().replace(char::is_whitespace, "_"))
+                .map(|cell| {
+                    if cell.is_empty() {
+                        "-".to_owned()
+                    } else {
+                        cell
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    pub fn render(&self, opts: &TableOpts) -> String {
+        // Padding counts characters, topic names and values may be non-ASCII
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (i, cell) in row.iter().enumerate() {
+                let len = cell.chars().count();
+                match widths.get_mut(i) {
+                    Some(width) => *width = (*width).max(len),
+                    None => widths.push(len),
+                }
+            }
+        }
+
+        let mut output = String::new();
+        let header = (!opts.no_header).then_some(&self.headers);
+        for row in header.into_iter().chain(self.rows.iter()) {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                if i + 1 == row.len() {
+                    line.push_str(cell);
+                } else {
+                    line.push_str(&format!("{:<width$}", cell, width = widths[i] + COLUMN_GAP));
+                }
+            }
+            output.push_str(line.trim_end());
+            output.push('\n');
+        }
+        output
+    }
+
+    pub fn print(&self, opts: &TableOpts) {
+        print!("{}", self.render(opts));
+    }
+}
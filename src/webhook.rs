@@ -0,0 +1,180 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use pulsar_cat_core::consume::ConsumedMessage;
+use pulsar_cat_core::format::format_message_id;
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Request, StatusCode};
+use tokio::runtime::Handle;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+use crate::error::{MESSAGE_ERROR_TARGET, PulsarCatError};
+
+// Wait before the first retry of a failed request, doubling with every attempt
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Posts consumed messages to an HTTP endpoint, the payload as the body and
+/// the metadata as `X-Pulsar-*` headers.
+///
+/// Up to `concurrency` requests are in flight at once; [`Webhook::post`]
+/// waits for a free slot, so a slow endpoint slows down consuming. Requests
+/// failing with a connection error, 429 or a 5xx status are retried.
+pub struct Webhook {
+    http: Client,
+    url: String,
+    concurrency: u32,
+    retries: u32,
+    slots: Arc<Semaphore>,
+    posted: Arc<AtomicU64>,
+    failed: Arc<AtomicU64>,
+}
+
+impl Webhook {
+    pub fn new(
+        url: &str,
+        concurrency: u32,
+        retries: u32,
+        timeout: Duration,
+    ) -> Result<Self, PulsarCatError> {
+        let http = Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to build webhook HTTP client: {}", e))?;
+        Ok(Webhook {
+            http,
+            url: url.to_owned(),
+            concurrency,
+            retries,
+            slots: Arc::new(Semaphore::new(concurrency as usize)),
+            posted: Arc::new(AtomicU64::new(0)),
+            failed: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Start posting the message, waiting while `concurrency` requests are in
+    /// flight. Must be called on a multi-threaded runtime.
+    pub fn post(&self, msg: &ConsumedMessage) -> Result<(), PulsarCatError> {
+        let request = self
+            .http
+            .post(&self.url)
+            .headers(message_headers(msg))
+            .body(msg.payload.to_vec())
+            .build()
+            .map_err(|e| anyhow::anyhow!("Invalid webhook request: {}", e))?;
+        let slot = tokio::task::block_in_place(|| {
+            Handle::current().block_on(self.slots.clone().acquire_owned())
+        })
+        .expect("webhook slots are never closed");
+
+        let http = self.http.clone();
+        let retries = self.retries;
+        let posted = self.posted.clone();
+        let failed = self.failed.clone();
+        let topic = msg.topic.to_owned();
+        let message_id = format_message_id(msg.message_id);
+        tokio::spawn(async move {
+            match send(&http, request, retries).await {
+                Ok(()) => {
+                    posted.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    warn!(
+                        target: MESSAGE_ERROR_TARGET,
+                        kind = "webhook",
+                        topic,
+                        message_id,
+                        error = %e,
+                        "Failed to post message"
+                    );
+                    failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            drop(slot);
+        });
+        Ok(())
+    }
+
+    /// Wait for the requests in flight, returning the number of posted and
+    /// failed messages.
+    pub async fn finish(&self) -> (u64, u64) {
+        let _ = self.slots.acquire_many(self.concurrency).await;
+        (
+            self.posted.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+        )
+    }
+}
+
+async fn send(http: &Client, request: Request, retries: u32) -> Result<(), String> {
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        // The body is always in memory, so the request can be cloned
+        let result = http
+            .execute(request.try_clone().expect("body is not a stream"))
+            .await;
+        let error = match result {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => {
+                let status = response.status();
+                let error = format!("{} responded with status {}", request.url(), status);
+                if !is_retryable(status) {
+                    return Err(error);
+                }
+                error
+            }
+            Err(e) => format!("Request to {} failed: {}", request.url(), e),
+        };
+        if attempt >= retries {
+            return Err(error);
+        }
+        attempt += 1;
+        debug!(
+            "{}, retrying in {:?} ({}/{})",
+            error, backoff, attempt, retries
+        );
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+// Metadata of the message, properties as X-Pulsar-Property-<name>. Properties
+// that are not valid header names or values are left out.
+fn message_headers(msg: &ConsumedMessage) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    let mut insert = |name: &str, value: &str| match (
+        HeaderName::from_bytes(name.as_bytes()),
+        HeaderValue::from_str(value),
+    ) {
+        (Ok(name), Ok(value)) => {
+            headers.insert(name, value);
+        }
+        _ => debug!("Not sending {} as a header, it is not a valid header", name),
+    };
+    insert("X-Pulsar-Topic", msg.topic);
+    insert("X-Pulsar-Message-Id", &format_message_id(msg.message_id));
+    insert("X-Pulsar-Publish-Time", &msg.publish_time.to_string());
+    if let Some(key) = msg.key {
+        insert("X-Pulsar-Key", key);
+    }
+    if let Some(event_time) = msg.event_time {
+        insert("X-Pulsar-Event-Time", &event_time.to_string());
+    }
+    for property in msg.properties {
+        insert(
+            &format!("X-Pulsar-Property-{}", property.key),
+            &property.value,
+        );
+    }
+    headers
+}